@@ -8,7 +8,7 @@ use std::io::Write;
 use std::collections::HashSet;
 
 /// Threat level enumeration
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ThreatLevel {
     Info = 0,
     Warning = 1,
@@ -16,6 +16,17 @@ pub enum ThreatLevel {
     Emergency = 3,
 }
 
+impl std::fmt::Display for ThreatLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ThreatLevel::Info => "INFO",
+            ThreatLevel::Warning => "WARNING",
+            ThreatLevel::Critical => "CRITICAL",
+            ThreatLevel::Emergency => "EMERGENCY",
+        })
+    }
+}
+
 /// Threat type enumeration
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ThreatType {
@@ -26,6 +37,26 @@ pub enum ThreatType {
     SuspiciousConnection,
     AnomalousBehavior,
     IoCMatch,
+    APT,
+    Exploit,
+    Unknown,
+}
+
+impl std::fmt::Display for ThreatType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ThreatType::DDoS => "ddos",
+            ThreatType::Malware => "malware",
+            ThreatType::Phishing => "phishing",
+            ThreatType::BruteForce => "brute_force",
+            ThreatType::SuspiciousConnection => "suspicious_connection",
+            ThreatType::AnomalousBehavior => "anomalous_behavior",
+            ThreatType::IoCMatch => "ioc_match",
+            ThreatType::APT => "apt",
+            ThreatType::Exploit => "exploit",
+            ThreatType::Unknown => "unknown",
+        })
+    }
 }
 
 /// Threat evidence structure
@@ -80,41 +111,18 @@ impl BlocklistExporter {
                     file, 
                     "{} # {} - {} - {} - Agent: {}", 
                     ip,
-                    self.threat_level_to_string(evidence.threat_level),
-                    self.threat_type_to_string(&evidence.threat_type),
+                    evidence.threat_level,
+                    evidence.threat_type,
                     evidence.context,
                     evidence.agent_id
                 )?;
                 
-                println!("Added {} to blocklist: {} - {}", ip, self.threat_type_to_string(&evidence.threat_type), evidence.context);
+                println!("Added {} to blocklist: {} - {}", ip, evidence.threat_type, evidence.context);
             }
         }
         
         Ok(())
     }
-
-    /// Convert threat level to string
-    fn threat_level_to_string(&self, level: ThreatLevel) -> &'static str {
-        match level {
-            ThreatLevel::Info => "INFO",
-            ThreatLevel::Warning => "WARNING",
-            ThreatLevel::Critical => "CRITICAL",
-            ThreatLevel::Emergency => "EMERGENCY",
-        }
-    }
-
-    /// Convert threat type to string
-    fn threat_type_to_string(&self, threat_type: &ThreatType) -> &'static str {
-        match threat_type {
-            ThreatType::DDoS => "ddos",
-            ThreatType::Malware => "malware",
-            ThreatType::Phishing => "phishing",
-            ThreatType::BruteForce => "brute_force",
-            ThreatType::SuspiciousConnection => "suspicious_connection",
-            ThreatType::AnomalousBehavior => "anomalous_behavior",
-            ThreatType::IoCMatch => "ioc_match",
-        }
-    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -24,26 +24,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("✓ Fetched {} threats from upstream sources (simulated)", threats.len());
     
     // 4. 创建本地检测到的威胁证据
-    let local_evidence = ThreatEvidence {
-        id: format!("local-evidence-{}", 
-            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()),
-        timestamp: SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64,
-        source_ip: "203.0.113.42".to_string(),  // Example IP from RFC 5737
-        target_ip: "198.51.100.23".to_string(),  // Example IP from RFC 5737
-        threat_type: ThreatType::SuspiciousConnection,
-        threat_level: ThreatLevel::Warning,
-        context: "Suspicious connection pattern detected by local analyzer".to_string(),
-        evidence_hash: orasrs_agent::crypto::CryptoProvider::blake3_hash(b"local-network-flow-data"),
-        geolocation: "unknown".to_string(),
-        network_flow: "TCP SYN flood pattern".to_string(),
-        agent_id: "example-agent-001".to_string(),
-        reputation: 0.85,
-        compliance_tag: "global".to_string(),
-        region: "global".to_string(),
-    };
+    // Example IPs from RFC 5737
+    let local_evidence = ThreatEvidence::builder("203.0.113.42", ThreatType::SuspiciousConnection, ThreatLevel::Warning)
+        .id(&format!("local-evidence-{}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()))
+        .target_ip("198.51.100.23")
+        .context("Suspicious connection pattern detected by local analyzer")
+        .network_flow("TCP SYN flood pattern")
+        .agent_id("example-agent-001")
+        .reputation(0.85)
+        .hash_of(b"local-network-flow-data")
+        .build();
     
     println!("✓ Created local threat evidence: {}", local_evidence.id);
     
@@ -64,7 +54,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     // 8. 显示结果
     if !correlation_results.is_empty() {
-        let (_, consensus_result) = &correlation_results[0];
+        let (_, _, consensus_result) = &correlation_results[0];
         print_consensus_result(consensus_result);
         
         println!("\nEnhanced Threat Evidence Details:");
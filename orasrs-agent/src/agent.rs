@@ -2,41 +2,231 @@ use crate::{
     AgentConfig, 
     AgentStatus, 
     ThreatEvidence, 
+    allowlist::{Allowlist, SharedAllowlist},
     monitor::AgentMonitor, 
     analyzer::ThreatDetector, 
-    reporter::ThreatReporter, 
-    p2p::P2pClient, 
-    compliance::ComplianceEngine,
-    blocklist_exporter::{BlocklistExporter, start_blocklist_exporter},
+    reporter::{ReputationMetrics, ThreatReporter}, 
+    p2p::{ConnectionProbe, P2pClient, P2pNetwork, VerificationChannel, VerificationMessage},
+    compliance::{ComplianceEngine, ErasureReport, PolicyEvaluator, anonymization_prefixes, anonymize_ip_field},
+    blocklist_exporter::BlocklistExporter,
+    threat_intel::IndicatorStore,
+    evidence_store::EvidenceStore,
+    storage::{FsStorage, Storage},
     threat_intel_upstream::ThreatIntelAggregator,
-    consensus_verification::{ConsensusEngine, ConsensusConfig},
+    consensus_verification::{ConsensusEngine, ConsensusConfig, ConsensusResult, VerificationRequest},
+    correlation::TemporalCorrelator,
+    notarization::Notary,
     credibility_enhancement::{CredibilityEngine, CredibilityConfig},
+    clock::{Clock, SystemClock},
+    crypto::{CryptoProvider, Signer},
+    enrichment::{Enricher, EvidenceEnricher, NetworkEnricher},
+    metrics::{serve_metrics, MetricsCollector},
+    control::serve_control,
+    resources::{ResourceSampler, ResourceUsage},
     error::{AgentError, Result},
     ThreatLevel,
 };
-use tokio::sync::mpsc;
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
-use tokio::time::interval;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{mpsc, watch, Notify};
+use tokio::task::JoinHandle;
+use std::time::Duration;
+use tokio::time::{interval, interval_at, Instant};
+
+/// Number of submitted evidence items remembered for false positive reports
+const MAX_TRACKED_EVIDENCE: usize = 10_000;
+
+/// How often learned reputations decay toward their baseline
+const REPUTATION_DECAY_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How often consensus requests and verdicts are saved to disk
+const CONSENSUS_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often files past the compliance retention window are deleted
+const RETENTION_SWEEP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Periodic work of the status loop
+enum Maintenance {
+    Status,
+    Decay,
+    Checkpoint,
+    Retention,
+}
 
 /// Main OraSRS Agent implementation
 pub struct OrasrsAgent {
     pub config: AgentConfig,
     pub monitor: AgentMonitor,
     pub analyzer: ThreatDetector,
-    pub reporter: ThreatReporter,
-    pub p2p_client: P2pClient,
+    pub reporter: Option<ThreatReporter>, // Taken when the reporter task starts
+    pub reputation_metrics: Arc<ReputationMetrics>,
+    pub p2p_client: Box<dyn P2pNetwork>,
     pub compliance_engine: ComplianceEngine,
     pub threat_intel_aggregator: ThreatIntelAggregator,
-    pub consensus_engine: ConsensusEngine,
-    pub credibility_engine: CredibilityEngine,
+    pub consensus_engine: Arc<ConsensusEngine>,
+    pub credibility_engine: Arc<CredibilityEngine>,
+    pub storage: Arc<dyn Storage>,
+    pub indicator_store: Arc<IndicatorStore>,
+    pub evidence_store: EvidenceStore, // Published evidence, kept for data-subject requests
+    pub enricher: Option<EvidenceEnricher>,
+    pub notary: Option<Arc<Notary>>,
+    pub temporal_correlator: TemporalCorrelator,
     pub status: AgentStatus,
     pub running: bool,
+    clock: Arc<dyn Clock>,
+    started_at: i64, // Clock time the agent was created, uptime is measured from it
+    threat_count: Arc<AtomicU64>,
+    last_threat_report: Arc<Mutex<Option<i64>>>,
+    resource_usage: Arc<Mutex<ResourceUsage>>, // Latest sample taken by the status loop
+    metrics_addr: Option<SocketAddr>,
+    control_addr: Option<SocketAddr>,
+    shutdown: watch::Sender<bool>,
+    config_updates: watch::Sender<AgentConfig>, // Current config, for tasks that must follow updates
+    drain: Arc<Notify>,
+    tasks: Vec<JoinHandle<()>>,
+    blocklist_sender: Option<tokio::sync::mpsc::UnboundedSender<ThreatEvidence>>,
     blocklist_receiver: Option<tokio::sync::mpsc::UnboundedReceiver<ThreatEvidence>>,
+    blocklist_removal_sender: mpsc::UnboundedSender<String>,
+    blocklist_removal_receiver: Option<mpsc::UnboundedReceiver<String>>,
+    verification_requests: Option<mpsc::UnboundedReceiver<VerificationRequest>>, // Taken when the verification task starts
+    submitted_evidence: Mutex<(HashMap<String, ThreatEvidence>, VecDeque<String>)>,
+    allowlist: SharedAllowlist, // Shared with the reporter and blocklist exporter
+    agent_key: libp2p::identity::Keypair,
+    evidence_signer: Arc<dyn Signer>, // Signs evidence this agent publishes, shared with the reporter
 }
 
-impl OrasrsAgent {
-    /// Create a new OraSRS Agent instance
-    pub async fn new(config: AgentConfig) -> Result<Self> {
+/// Read-only view of the agent's status that can be moved into other tasks
+#[derive(Clone)]
+pub struct StatusHandle {
+    base: AgentStatus, // Fields not tracked live, as of when the handle was taken
+    clock: Arc<dyn Clock>,
+    started_at: i64,
+    threat_count: Arc<AtomicU64>,
+    last_threat_report: Arc<Mutex<Option<i64>>>,
+    resource_usage: Arc<Mutex<ResourceUsage>>,
+    reputation_metrics: Arc<ReputationMetrics>,
+    p2p_connected: ConnectionProbe,
+}
+
+impl StatusHandle {
+    /// Current agent status
+    pub fn snapshot(&self) -> AgentStatus {
+        let usage = *self.resource_usage.lock().unwrap();
+        AgentStatus {
+            uptime: uptime_since(self.clock.as_ref(), self.started_at),
+            threat_count: self.threat_count.load(Ordering::SeqCst),
+            reputation: self.reputation_metrics.reputation(),
+            last_threat_report: *self.last_threat_report.lock().unwrap(),
+            memory_usage: usage.memory,
+            cpu_usage: usage.cpu,
+            network_usage: usage.network,
+            p2p_connected: (self.p2p_connected)(),
+            ..self.base.clone()
+        }
+    }
+}
+
+/// Outcome of [`OrasrsAgent::stop`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    pub completed: usize, // Tasks that finished within the grace period
+    pub aborted: usize,   // Tasks aborted after the grace period ran out
+}
+
+/// Builder for configuring an [`OrasrsAgent`] programmatically
+///
+/// Lets embedders inject their own P2P client, clock, and evidence sinks, and
+/// defer connecting to the P2P network until [`OrasrsAgent::connect`] is called.
+pub struct AgentBuilder {
+    config: AgentConfig,
+    p2p_client: Option<Box<dyn P2pNetwork>>,
+    clock: Option<Arc<dyn Clock>>,
+    evidence_sinks: Vec<mpsc::UnboundedSender<ThreatEvidence>>,
+    policy_evaluator: Option<Arc<dyn PolicyEvaluator>>,
+    enricher: Option<Arc<dyn Enricher>>,
+    storage: Option<Arc<dyn Storage>>,
+    connect_on_build: bool,
+}
+
+impl AgentBuilder {
+    pub fn new(config: AgentConfig) -> Self {
+        Self {
+            config,
+            p2p_client: None,
+            clock: None,
+            evidence_sinks: Vec::new(),
+            policy_evaluator: None,
+            enricher: None,
+            storage: None,
+            connect_on_build: true,
+        }
+    }
+
+    /// Config the agent will be built with
+    pub(crate) fn config(&self) -> &AgentConfig {
+        &self.config
+    }
+
+    /// Use a custom P2P client instead of the libp2p-backed default
+    pub fn with_p2p_client(mut self, p2p_client: Box<dyn P2pNetwork>) -> Self {
+        self.p2p_client = Some(p2p_client);
+        self
+    }
+
+    /// Use a custom clock for status and timestamps
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Receive a copy of every detected threat evidence
+    pub fn with_evidence_sink(mut self, sink: mpsc::UnboundedSender<ThreatEvidence>) -> Self {
+        self.evidence_sinks.push(sink);
+        self
+    }
+
+    /// Delegate compliance decisions to an external policy evaluator
+    pub fn with_policy_evaluator(mut self, evaluator: Arc<dyn PolicyEvaluator>) -> Self {
+        self.policy_evaluator = Some(evaluator);
+        self
+    }
+
+    /// Enrich evidence using a custom enricher, regardless of `enrichment_enabled`
+    pub fn with_enricher(mut self, enricher: Arc<dyn Enricher>) -> Self {
+        self.enricher = Some(enricher);
+        self
+    }
+
+    /// Persist agent state in a custom storage backend instead of `data_dir`
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Skip connecting to the P2P network during `build`
+    pub fn defer_connection(mut self) -> Self {
+        self.connect_on_build = false;
+        self
+    }
+
+    /// Build the agent
+    pub async fn build(self) -> Result<OrasrsAgent> {
+        let AgentBuilder {
+            config,
+            p2p_client,
+            clock,
+            evidence_sinks,
+            policy_evaluator,
+            enricher,
+            storage,
+            connect_on_build,
+        } = self;
+        
+        // Reject invalid settings before any component is constructed
+        config.validate()?;
+
         // Create the main threat sender/receiver
         let (threat_sender_main, threat_receiver_main) = mpsc::unbounded_channel::<ThreatEvidence>();
         
@@ -44,15 +234,42 @@ impl OrasrsAgent {
         let (reporter_sender, threat_receiver_reporter) = mpsc::unbounded_channel::<ThreatEvidence>();
         let (blocklist_sender_internal, blocklist_receiver_for_exporter) = mpsc::unbounded_channel::<ThreatEvidence>();
         
+        // Keep a sender for manually submitted evidence
+        let blocklist_sender = if config.blocklist_export_enabled {
+            Some(blocklist_sender_internal.clone())
+        } else {
+            None
+        };
+        
         // Create a forwarding task to duplicate threat evidence
-        let _forwarder_task = tokio::spawn({
+        let drain = Arc::new(Notify::new());
+        let forwarder_task = tokio::spawn({
             let mut receiver = threat_receiver_main;
             let reporter_tx = reporter_sender;
             let blocklist_tx = blocklist_sender_internal;
             let blocklist_enabled = config.blocklist_export_enabled;
+            let drain = drain.clone();
             
             async move {
-                while let Some(evidence) = receiver.recv().await {
+                loop {
+                    let evidence = tokio::select! {
+                        evidence = receiver.recv() => evidence,
+                        _ = drain.notified() => {
+                            // Refuse new evidence but forward what is already queued;
+                            // the reporter exits once this task drops its sender
+                            receiver.close();
+                            continue;
+                        }
+                    };
+                    let Some(evidence) = evidence else {
+                        break;
+                    };
+                    
+                    // Send to any custom sinks
+                    for sink in &evidence_sinks {
+                        let _ = sink.send(evidence.clone());
+                    }
+
                     // Send to reporter
                     let _ = reporter_tx.send(evidence.clone());
                     
@@ -66,24 +283,59 @@ impl OrasrsAgent {
         
         // Initialize compliance engine first
         let mut compliance_engine = ComplianceEngine::new(&config);
+        if let Some(evaluator) = policy_evaluator {
+            compliance_engine.set_policy_evaluator(evaluator);
+        }
         compliance_engine.init_compliance()?;
         
         // Validate config compliance
         compliance_engine.validate_config_compliance(&config)?;
         
+        // Persistent state lives under data_dir unless a backend was supplied
+        let storage = storage.unwrap_or_else(|| {
+            Arc::new(FsStorage::new(config.storage_config.data_dir.join("state"))) as Arc<dyn Storage>
+        });
+        
+        // The agent key identifies us on the network and signs notarized batches and verdicts
+        let agent_key = CryptoProvider::agent_keypair(storage.as_ref()).await?;
+        
         // Initialize P2P client
-        let mut p2p_client = P2pClient::new(config.clone())?;
+        let p2p_client = match p2p_client {
+            Some(p2p_client) => p2p_client,
+            None => Box::new(P2pClient::with_keypair(config.clone(), agent_key.clone())?),
+        };
         
         // Initialize threat intelligence aggregator
-        let threat_intel_aggregator = ThreatIntelAggregator::new();
+        let threat_intel_aggregator = ThreatIntelAggregator::from_config(&config).with_storage(storage.clone());
         
-        // Initialize consensus engine
-        let consensus_config = ConsensusConfig::default();
-        let consensus_engine = ConsensusEngine::new(consensus_config, config.agent_id.clone());
+        // Open the local IoC database
+        let indicator_store = Arc::new(IndicatorStore::load(storage.as_ref()).await.unwrap_or_else(|e| {
+            log::warn!("Failed to load indicator store: {}", e);
+            IndicatorStore::new()
+        }));
+        let evidence_store = EvidenceStore::open(config.storage_config.data_dir.join("evidence.jsonl"), storage.as_ref()).await?;
         
         // Initialize credibility engine
         let credibility_config = CredibilityConfig::default();
-        let credibility_engine = CredibilityEngine::new(credibility_config);
+        let credibility_engine = Arc::new(CredibilityEngine::new(credibility_config));
+        if let Err(e) = credibility_engine.restore(storage.as_ref()).await {
+            log::warn!("Failed to restore credibility state: {}", e);
+        }
+        
+        let evidence_signer: Arc<dyn Signer> = Arc::from(CryptoProvider::signer(&config.crypto_config, agent_key.clone())?);
+        
+        // Initialize consensus engine
+        let consensus_config = ConsensusConfig::default();
+        let (verification_request_sender, verification_requests) = mpsc::unbounded_channel();
+        let consensus_engine = Arc::new(ConsensusEngine::new(consensus_config, config.agent_id.clone())
+            .with_request_broadcast(verification_request_sender)
+            .with_indicator_store(indicator_store.clone())
+            .with_credibility_engine(credibility_engine.clone())
+            .with_signer(CryptoProvider::signer(&config.crypto_config, agent_key.clone())?)
+            .with_evidence_signatures_required(config.crypto_config.require_evidence_signatures));
+        if let Err(e) = consensus_engine.restore_from(storage.as_ref()).await {
+            log::warn!("Failed to restore consensus state: {}", e);
+        }
         
         // Initialize components
         let monitor = AgentMonitor::new(
@@ -91,10 +343,33 @@ impl OrasrsAgent {
             config.enabled_modules.syscall,
             config.enabled_modules.tls_inspect,
             config.enabled_modules.geo_fence,
+            config.netflow_interface.clone(),
+            config.geo_fence.clone(),
             threat_sender_main,  // Send threats to the duplicator
-        );
+        )
+        .with_ddos_threshold(config.ddos_syn_rate_threshold)
+        .with_tls_indicators(config.tls_inspect.clone());
+        let monitor = match config.geoip_database {
+            Some(ref database) => monitor.with_geoip_database(database),
+            None => monitor,
+        };
+        let monitor = match config.yara_rules_path {
+            Some(ref path) => monitor.with_yara_rules(path)?,
+            None => monitor,
+        };
+        
+        let analyzer = match &config.detection_rules_file {
+            Some(path) => ThreatDetector::from_rules_file(path)?,
+            None => ThreatDetector::new(),
+        };
+        let analyzer = analyzer
+            .with_indicator_store(indicator_store.clone())
+            .with_ddos_threshold(config.ddos_syn_rate_threshold);
+        
+        // Get current time for uptime calculation
+        let clock = clock.unwrap_or_else(|| Arc::new(SystemClock));
         
-        let analyzer = ThreatDetector::new();
+        let allowlist = Arc::new(std::sync::RwLock::new(Allowlist::parse(&config.allowlist)?));
         
         // Create blocklist sender for the reporter to use (we'll pass None since we handle duplication separately)
         let reporter = ThreatReporter::new(
@@ -102,199 +377,467 @@ impl OrasrsAgent {
             config.clone(),
             threat_receiver_reporter,  // The reporter gets its own dedicated receiver
             None,  // We handle blocklist duplication separately
-        );
+        )
+        .with_clock(clock.clone())
+        .with_signer(evidence_signer.clone())
+        .with_allowlist(allowlist.clone());
+        // Without a configured context key, keep one in storage so contexts stay decryptable across restarts
+        let reporter = match config.crypto_config.context_encryption_key {
+            Some(_) => reporter,
+            None => reporter.with_context_key(CryptoProvider::stored_context_key(storage.as_ref()).await?),
+        };
+        let reputation_metrics = reporter.metrics();
         
-        // Get current time for uptime calculation
-        let start_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        // The notary continues the chain it saved before a restart
+        let notary = match config.notarization_enabled {
+            true => {
+                let notary = Notary::new(agent_key.clone(), config.notarization_batch_size)
+                    .with_clock(clock.clone())
+                    .with_storage(storage.clone());
+                notary.restore().await?;
+                Some(Arc::new(notary))
+            }
+            false => None,
+        };
+        let reporter = match notary {
+            Some(ref notary) => reporter.with_notary(notary.clone()),
+            None => reporter,
+        };
+        
+        // Set up the optional enrichment stage
+        let enricher = match enricher {
+            Some(enricher) => Some(enricher),
+            None if config.enrichment_enabled => Some(Arc::new(NetworkEnricher::new()) as Arc<dyn Enricher>),
+            None => None,
+        }
+        .map(|enricher| EvidenceEnricher::new(enricher, config.enrichment_cache_ttl).with_clock(clock.clone()));
+        let temporal_correlator = TemporalCorrelator::new(config.correlation_window);
+        let started_at = clock.now();
         
         let status = AgentStatus {
             agent_id: config.agent_id.clone(),
             version: env!("CARGO_PKG_VERSION").to_string(),
-            uptime: start_time,
+            uptime: 0,
             threat_count: 0,
             reputation: 1.0,
-            memory_usage: 0, // Will be updated by monitoring
-            cpu_usage: 0.0,  // Will be updated by monitoring
-            network_usage: 0, // Will be updated by monitoring
+            memory_usage: 0,
+            cpu_usage: 0.0,
+            network_usage: 0,
             last_threat_report: None,
             p2p_connected: false,
             compliance_mode: config.compliance_mode.clone(),
         };
         
-        let mut agent = Self {
+        let (blocklist_removal_sender, blocklist_removal_receiver) = mpsc::unbounded_channel::<String>();
+        
+        let blocklist_receiver = if config.blocklist_export_enabled {
+            Some(blocklist_receiver_for_exporter)
+        } else {
+            None
+        };
+
+        let mut agent = OrasrsAgent {
+            config_updates: watch::channel(config.clone()).0,
             config,
             monitor,
             analyzer,
-            reporter,
+            reporter: Some(reporter),
+            reputation_metrics,
             p2p_client,
             compliance_engine,
             threat_intel_aggregator,
             consensus_engine,
             credibility_engine,
+            storage,
+            indicator_store,
+            evidence_store,
+            enricher,
+            notary,
+            temporal_correlator,
             status,
             running: false,
-            blocklist_receiver: if config.blocklist_export_enabled {
-                Some(blocklist_receiver_for_exporter)
-            } else {
-                None
-            },
+            clock,
+            started_at,
+            threat_count: Arc::new(AtomicU64::new(0)),
+            last_threat_report: Arc::new(Mutex::new(None)),
+            resource_usage: Arc::new(Mutex::new(ResourceUsage::default())),
+            metrics_addr: None,
+            control_addr: None,
+            shutdown: watch::channel(false).0,
+            drain,
+            tasks: vec![forwarder_task],
+            blocklist_sender,
+            blocklist_receiver,
+            blocklist_removal_sender,
+            blocklist_removal_receiver: Some(blocklist_removal_receiver),
+            verification_requests: Some(verification_requests),
+            submitted_evidence: Mutex::new((HashMap::new(), VecDeque::new())),
+            allowlist,
+            evidence_signer,
+            agent_key,
         };
         
+        if connect_on_build {
+            agent.connect().await?;
+        }
+        
+        Ok(agent)
+    }
+}
+
+impl OrasrsAgent {
+    /// Create a new OraSRS Agent instance
+    pub async fn new(config: AgentConfig) -> Result<Self> {
+        AgentBuilder::new(config).build().await
+    }
+
+    /// Create a builder for configuring an agent
+    pub fn builder(config: AgentConfig) -> AgentBuilder {
+        AgentBuilder::new(config)
+    }
+
+    /// Connect to the P2P network and subscribe to threat intelligence
+    pub async fn connect(&mut self) -> Result<()> {
         // Connect to P2P network
-        agent.p2p_client.connect_bootstrap().await?;
-        agent.status.p2p_connected = agent.p2p_client.connected;
+        self.p2p_client.connect_bootstrap().await?;
+        self.status.p2p_connected = self.p2p_client.is_connected();
         
         // Subscribe to threat intelligence
-        agent.p2p_client.subscribe_threat_intel()?;
+        self.p2p_client.subscribe_threat_intel()?;
         
-        Ok(agent)
+        Ok(())
     }
     
     /// Start the agent
+    ///
+    /// Spawns the background services and returns; call [`OrasrsAgent::stop`]
+    /// to shut them down.
     pub async fn start(&mut self) -> Result<()> {
         log::info!("Starting OraSRS Agent v{}...", env!("CARGO_PKG_VERSION"));
         
         self.running = true;
+        self.shutdown.send_replace(false);
         
         // Start monitor
-        self.monitor.start_monitoring().await?;
+        let monitor_task = self.monitor.start_monitoring(self.shutdown.subscribe()).await?;
+        self.tasks.push(monitor_task);
         log::info!("Monitor started");
         
-        // Start reporter
-        let reporter_handle = tokio::spawn({
-            let mut reporter = std::mem::take(&mut self.reporter);
-            async move {
+        // Start reporter; it exits once the forwarder drains and drops its sender
+        if let Some(mut reporter) = self.reporter.take() {
+            self.tasks.push(tokio::spawn(async move {
                 if let Err(e) = reporter.start_reporting().await {
                     log::error!("Reporter error: {}", e);
                 }
-            }
-        });
-        log::info!("Reporter started");
+            }));
+            log::info!("Reporter started");
+        } else {
+            log::warn!("Reporter already started");
+        }
         
         // Start blocklist exporter if enabled in config
-        let blocklist_handle = if self.config.blocklist_export_enabled {
+        if self.config.blocklist_export_enabled {
             let blocklist_file = self.config.blocklist_file.clone().unwrap_or_else(|| "./blocklist.txt".to_string());
             let min_threat_level = self.config.blocklist_min_threat_level.unwrap_or(ThreatLevel::Warning);
             let export_interval = self.config.blocklist_export_interval.unwrap_or(300); // 5 minutes
             
             // Take the blocklist receiver from the agent
             if let Some(blocklist_receiver) = self.blocklist_receiver.take() {
-                Some(tokio::spawn({
+                let mut exporter = BlocklistExporter::new(blocklist_file, min_threat_level, export_interval)
+                    .with_allowlist(self.allowlist.clone())
+                    .with_signer(CryptoProvider::signer(&self.config.crypto_config, self.agent_key.clone())?,
+                                 self.config.agent_id.clone())
+                    .with_format(self.config.blocklist_format)
+                    .with_clock(self.clock.clone());
+                if let Some(ttl) = self.config.blocklist_entry_ttl {
+                    exporter = exporter.with_entry_ttl(ttl);
+                }
+                if let Some(threshold) = self.config.blocklist_aggregation_threshold {
+                    exporter = exporter.with_cidr_aggregation(threshold, self.config.blocklist_aggregate_mixed_types);
+                }
+                if let Some(removal_receiver) = self.blocklist_removal_receiver.take() {
+                    exporter = exporter.with_removal_queue(removal_receiver);
+                }
+                if let Some(delta_file) = self.config.blocklist_delta_file.clone() {
+                    exporter = exporter.with_delta_export(delta_file);
+                }
+                
+                self.tasks.push(tokio::spawn({
                     async move {
-                        if let Err(e) = start_blocklist_exporter(
-                            blocklist_file,
-                            min_threat_level,
-                            export_interval,
-                            blocklist_receiver
-                        ).await {
+                        if let Err(e) = exporter.start_export(blocklist_receiver).await {
                             log::error!("Blocklist exporter error: {}", e);
                         }
                     }
-                }))
+                }));
+                log::info!("Blocklist exporter started");
             } else {
                 log::warn!("Blocklist receiver not available");
-                None
             }
-        } else {
-            None
-        };
-        
-        if self.config.blocklist_export_enabled {
-            log::info!("Blocklist exporter started");
         }
         
         // Start threat intelligence aggregation
         self.start_threat_intel_aggregation().await?;
         log::info!("Threat intelligence aggregation started");
         
-        // Start status monitoring loop
-        let status_handle = tokio::spawn({
+        // Run evidence published by peers through the credibility pipeline
+        if let Some(mut incoming) = self.p2p_client.take_incoming_evidence() {
+            self.tasks.push(tokio::spawn({
+                let aggregator = self.threat_intel_aggregator.clone();
+                let consensus_engine = self.consensus_engine.clone();
+                let credibility_engine = self.credibility_engine.clone();
+                let mut shutdown = self.shutdown.subscribe();
+                
+                async move {
+                    loop {
+                        let evidence = tokio::select! {
+                            evidence = incoming.recv() => match evidence {
+                                Some(evidence) => evidence,
+                                None => break,
+                            },
+                            _ = shutdown.wait_for(|stopping| *stopping) => break,
+                        };
+                        
+                        let evidence_id = evidence.id.clone();
+                        if let Err(e) = enhance_evidence(&aggregator, &consensus_engine, &credibility_engine, evidence).await {
+                            log::warn!("Failed to process peer evidence {}: {}", evidence_id, e);
+                        }
+                    }
+                }
+            }));
+            log::info!("Peer evidence processing started");
+        }
+        
+        // Ask peers to verify our consensus requests and answer theirs
+        if let Some(channel) = self.p2p_client.take_verification_channel() {
+            if let Some(requests) = self.verification_requests.take() {
+                let signer = CryptoProvider::signer(&self.config.crypto_config, self.agent_key.clone())?;
+                self.tasks.push(tokio::spawn(exchange_verifications(
+                    self.consensus_engine.clone(),
+                    self.compliance_engine.clone(),
+                    signer,
+                    self.config_updates.subscribe(),
+                    channel,
+                    requests,
+                    self.shutdown.subscribe(),
+                )));
+                log::info!("Consensus verification exchange started");
+            }
+        }
+        
+        // Start status monitoring loop, which also decays and checkpoints learned reputations,
+        // saves consensus state so in-flight verifications survive a restart, and deletes
+        // data past the retention window of the current config
+        self.tasks.push(tokio::spawn({
             let mut interval = interval(Duration::from_secs(self.config.update_interval));
-            let agent_id = self.config.agent_id.clone();
-            let p2p_client = self.p2p_client.clone();
+            let mut decay = interval_at(Instant::now() + REPUTATION_DECAY_INTERVAL, REPUTATION_DECAY_INTERVAL);
+            let mut checkpoint = interval_at(Instant::now() + CONSENSUS_CHECKPOINT_INTERVAL, CONSENSUS_CHECKPOINT_INTERVAL);
+            let mut retention = interval_at(Instant::now() + RETENTION_SWEEP_INTERVAL, RETENTION_SWEEP_INTERVAL);
+            let compliance_engine = self.compliance_engine.clone();
+            let evidence_store = self.evidence_store.clone();
+            let credibility_engine = self.credibility_engine.clone();
+            let consensus_engine = self.consensus_engine.clone();
+            let storage = self.storage.clone();
             let mut status = self.status.clone();
-            let running = &self.running;
+            let clock = self.clock.clone();
+            let started_at = self.started_at;
+            let mut shutdown = self.shutdown.subscribe();
+            let reputation_metrics = self.reputation_metrics.clone();
+            let resource_usage = self.resource_usage.clone();
+            let mut sampler = ResourceSampler::new();
+            let mut over_limits = Vec::new();
+            let config_updates = self.config_updates.subscribe();
             
             async move {
                 loop {
-                    interval.tick().await;
-                    
-                    if !running {
-                        break;
+                    let due = tokio::select! {
+                        _ = interval.tick() => Maintenance::Status,
+                        _ = decay.tick() => Maintenance::Decay,
+                        _ = checkpoint.tick() => Maintenance::Checkpoint,
+                        _ = retention.tick() => Maintenance::Retention,
+                        _ = shutdown.wait_for(|stopping| *stopping) => break,
+                    };
+                    match due {
+                        Maintenance::Status => {
+                            refresh_status(&mut status, clock.as_ref(), started_at, &reputation_metrics);
+                            let usage = sampler.sample();
+                            *resource_usage.lock().unwrap() = usage;
+                            status.memory_usage = usage.memory;
+                            status.cpu_usage = usage.cpu;
+                            status.network_usage = usage.network;
+
+                            // Warn when a limit is first exceeded rather than on every sample
+                            let exceeded = exceeded_limits(&usage, &config_updates.borrow());
+                            if !exceeded.is_empty() && exceeded != over_limits {
+                                log::warn!(
+                                    "Agent exceeds its {} limit: memory {} bytes, CPU {:.1}%, network {} bytes/s",
+                                    exceeded.join(" and "), usage.memory, usage.cpu, usage.network
+                                );
+                            }
+                            over_limits = exceeded;
+                            log::debug!("Agent status updated: {:?}", status);
+                        }
+                        Maintenance::Decay => {
+                            credibility_engine.apply_decay().await;
+                            if let Err(e) = credibility_engine.persist(storage.as_ref()).await {
+                                log::warn!("Failed to checkpoint credibility state: {}", e);
+                            }
+                        }
+                        Maintenance::Checkpoint => {
+                            if let Err(e) = consensus_engine.checkpoint_to(storage.as_ref()).await {
+                                log::warn!("Failed to checkpoint consensus state: {}", e);
+                            }
+                        }
+                        Maintenance::Retention => {
+                            let config = config_updates.borrow().clone();
+                            let expire_state = |cutoff| {
+                                let (consensus_engine, storage) = (&consensus_engine, &storage);
+                                async move {
+                                    let expired = consensus_engine.expire_before(cutoff).await;
+                                    consensus_engine.checkpoint_to(storage.as_ref()).await?;
+                                    Ok(expired)
+                                }
+                            };
+                            if let Err(e) = compliance_engine.enforce_retention(&config, &evidence_store, expire_state).await {
+                                log::warn!("Failed to enforce data retention: {}", e);
+                            }
+                        }
                     }
-                    
-                    // Update status
-                    status.uptime = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs() - status.uptime;
-                    
-                    status.reputation = 0.95; // Placeholder - would come from reporter
-                    status.p2p_connected = p2p_client.connected;
-                    
-                    log::debug!("Agent status updated: {:?}", status);
                 }
             }
-        });
+        }));
         log::info!("Status monitoring started");
         
-        // Keep the agent running
-        if let Some(handle) = blocklist_handle {
-            tokio::try_join!(
-                async { Ok(reporter_handle.await?) },
-                async { handle.await.map_err(|e| AgentError::InternalError(e.to_string())) },
-                async { 
-                    status_handle.await.map_err(|e| AgentError::InternalError(e.to_string())) 
-                }
-            )?;
-        } else {
-            tokio::try_join!(
-                async { Ok(reporter_handle.await?) },
-                async { 
-                    status_handle.await.map_err(|e| AgentError::InternalError(e.to_string())) 
-                }
-            )?;
+        // Serve Prometheus metrics if enabled in config
+        if self.config.metrics_enabled {
+            let collector = MetricsCollector::new(
+                self.status_handle(),
+                self.threat_intel_aggregator.clone(),
+                self.consensus_engine.clone(),
+            );
+            let ip = self.config.metrics_bind_address
+                .parse::<IpAddr>()
+                .map_err(|e| AgentError::ConfigError(format!("Invalid metrics_bind_address: {}", e)))?;
+            let addr = SocketAddr::new(ip, self.config.metrics_port);
+            let (addr, task) = serve_metrics(addr, collector, self.shutdown.subscribe()).await?;
+            self.tasks.push(task);
+            self.metrics_addr = Some(addr);
+            log::info!("Metrics server listening on {}", addr);
+        }
+        
+        // Serve health and status checks if enabled in config
+        if self.config.control_enabled {
+            let ip = self.config.control_bind_address
+                .parse::<IpAddr>()
+                .map_err(|e| AgentError::ConfigError(format!("Invalid control_bind_address: {}", e)))?;
+            let addr = SocketAddr::new(ip, self.config.control_port);
+            let (addr, task) = serve_control(addr, self.status_handle(), self.shutdown.subscribe()).await?;
+            self.tasks.push(task);
+            self.control_addr = Some(addr);
+            log::info!("Control server listening on {}", addr);
         }
         
         Ok(())
     }
     
     /// Stop the agent
-    pub fn stop(&mut self) -> Result<()> {
+    ///
+    /// Stops accepting new evidence and drains what is already queued, waiting
+    /// up to `shutdown_grace_period` seconds for background tasks to finish
+    /// before aborting any that are still running.
+    pub async fn stop(&mut self) -> Result<ShutdownReport> {
         log::info!("Stopping OraSRS Agent...");
         self.running = false;
-        Ok(())
+        self.shutdown.send_replace(true);
+        self.drain.notify_one();
+        
+        // The exporter exits once every sender to it is dropped
+        self.blocklist_sender = None;
+        
+        let grace_period = Duration::from_secs(self.config.shutdown_grace_period);
+        let deadline = tokio::time::Instant::now() + grace_period;
+        let mut report = ShutdownReport::default();
+        
+        for mut handle in self.tasks.drain(..) {
+            if tokio::time::timeout_at(deadline, &mut handle).await.is_ok() {
+                report.completed += 1;
+            } else {
+                handle.abort();
+                report.aborted += 1;
+            }
+        }
+        let aborted = report.aborted;
+        
+        if aborted > 0 {
+            log::warn!("Aborted {} background task(s) still running after {}s shutdown grace period",
+                      aborted, grace_period.as_secs());
+        }
+        
+        // Nothing publishes any more, so the network can be left
+        self.p2p_client.disconnect().await;
+        self.status.p2p_connected = false;
+        
+        // Save learned state for the next run
+        if let Err(e) = self.indicator_store.persist(self.storage.as_ref()).await {
+            log::warn!("Failed to persist indicator store: {}", e);
+        }
+        if let Err(e) = self.credibility_engine.persist(self.storage.as_ref()).await {
+            log::warn!("Failed to persist credibility state: {}", e);
+        }
+        if let Err(e) = self.consensus_engine.checkpoint_to(self.storage.as_ref()).await {
+            log::warn!("Failed to checkpoint consensus state: {}", e);
+        }
+        
+        Ok(report)
     }
     
     /// Get current agent status
     pub fn get_status(&self) -> AgentStatus {
-        AgentStatus {
-            agent_id: self.config.agent_id.clone(),
-            version: self.status.version.clone(),
-            uptime: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() - self.status.uptime,
-            threat_count: self.status.threat_count,
-            reputation: self.reporter.get_reputation(),
-            memory_usage: self.status.memory_usage,
-            cpu_usage: self.status.cpu_usage,
-            network_usage: self.status.network_usage,
-            last_threat_report: self.status.last_threat_report,
-            p2p_connected: self.p2p_client.connected,
-            compliance_mode: self.status.compliance_mode.clone(),
+        self.status_handle().snapshot()
+    }
+    
+    /// Handle for reading the agent's status from other tasks
+    pub fn status_handle(&self) -> StatusHandle {
+        StatusHandle {
+            base: AgentStatus {
+                agent_id: self.config.agent_id.clone(),
+                ..self.status.clone()
+            },
+            clock: self.clock.clone(),
+            started_at: self.started_at,
+            threat_count: self.threat_count.clone(),
+            last_threat_report: self.last_threat_report.clone(),
+            resource_usage: self.resource_usage.clone(),
+            reputation_metrics: self.reputation_metrics.clone(),
+            p2p_connected: self.p2p_client.connection_probe(),
         }
     }
     
+    /// Public half of the agent key, used to verify what the agent signs
+    pub fn public_key(&self) -> libp2p::identity::PublicKey {
+        self.agent_key.public()
+    }
+    
+    /// Address the metrics server is listening on, once started
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+    
+    /// Address the control server is listening on, once started
+    pub fn control_addr(&self) -> Option<SocketAddr> {
+        self.control_addr
+    }
+    
     /// Update agent configuration
     pub fn update_config(&mut self, new_config: AgentConfig) -> Result<()> {
-        // Validate new config compliance
+        // Validate new config values and compliance
+        new_config.validate()?;
         self.compliance_engine.validate_config_compliance(&new_config)?;
         
         // Update config
+        *self.allowlist.write().unwrap() = Allowlist::parse(&new_config.allowlist)?;
+        self.monitor.geo_fence.update_config(new_config.geo_fence.clone());
+        self.monitor.tls_inspector.update_config(new_config.tls_inspect.clone());
+        self.config_updates.send_replace(new_config.clone());
         self.config = new_config;
         
         // Update status
@@ -305,22 +848,186 @@ impl OrasrsAgent {
     }
     
     /// Submit a threat evidence manually
-    pub async fn submit_threat_evidence(&self, mut evidence: ThreatEvidence) -> Result<()> {
+    ///
+    /// Correlation and blocklisting use the full IP when `internal_full_ip` is
+    /// set; compliance anonymization is applied only to the published evidence.
+    pub async fn submit_threat_evidence(&self, evidence: ThreatEvidence) -> Result<()> {
+        self.submit_threat_evidence_with_result(evidence).await.map(|_| ())
+    }
+    
+    /// Submit threat evidence and return it as enhanced, with any consensus result
+    ///
+    /// The returned evidence is the local copy carrying the credibility score,
+    /// before anonymization for publishing. Evidence about an allowlisted IP is
+    /// dropped and returned as given.
+    pub async fn submit_threat_evidence_with_result(
+        &self,
+        mut evidence: ThreatEvidence,
+    ) -> Result<(ThreatEvidence, Option<ConsensusResult>)> {
+        if *self.shutdown.borrow() {
+            return Err(AgentError::InternalError("Agent is shutting down".to_string()));
+        }
+        if !self.admit_evidence(&mut evidence)? {
+            return Ok((evidence, None));
+        }
+        
+        let (internal_evidence, source_ip, target_ip) = self.prepare_evidence(evidence).await?;
+        
+        // Enhance with credibility and consensus verification
+        let (enhanced_evidence, consensus_result) = self.enhance_threat_evidence(internal_evidence).await?;
+        
+        self.publish_evidence(&enhanced_evidence, source_ip, target_ip).await?;
+        Ok((enhanced_evidence, consensus_result))
+    }
+    
+    /// Submit several pieces of evidence, correlating them against one upstream snapshot
+    ///
+    /// Returns the enhanced evidence in submission order; evidence about
+    /// allowlisted IPs is dropped. Nothing is published if any item is invalid
+    /// or cannot be prepared for publishing. If publishing some items to peers
+    /// fails, the rest are still published and the first error is returned.
+    pub async fn submit_threat_evidence_batch(&self, evidences: Vec<ThreatEvidence>) -> Result<Vec<ThreatEvidence>> {
+        if *self.shutdown.borrow() {
+            return Err(AgentError::InternalError("Agent is shutting down".to_string()));
+        }
+        
+        let mut prepared = Vec::with_capacity(evidences.len());
+        let mut original_ips = Vec::with_capacity(evidences.len());
+        for mut evidence in evidences {
+            if !self.admit_evidence(&mut evidence)? {
+                continue;
+            }
+            let (internal_evidence, source_ip, target_ip) = self.prepare_evidence(evidence).await?;
+            prepared.push(internal_evidence);
+            original_ips.push((source_ip, target_ip));
+        }
+        
+        let enhanced = enhance_evidence_batch(
+            &self.threat_intel_aggregator,
+            &self.consensus_engine,
+            &self.credibility_engine,
+            prepared,
+        ).await?;
+        
+        // Prepare every outgoing copy first so a failure publishes none of the batch
+        let sealed = enhanced.iter()
+            .map(|evidence| self.seal_evidence(evidence))
+            .collect::<Result<Vec<_>>>()?;
+        
+        // Act on every item even if publishing one to peers fails
+        let mut publish_error = None;
+        for ((evidence, published_evidence), (source_ip, target_ip)) in enhanced.iter().zip(sealed).zip(original_ips) {
+            if let Err(e) = self.deliver_evidence(evidence, published_evidence, source_ip, target_ip).await {
+                log::warn!("Failed to publish evidence {}: {}", evidence.id, e);
+                publish_error.get_or_insert(e);
+            }
+        }
+        match publish_error {
+            Some(e) => Err(e),
+            None => Ok(enhanced),
+        }
+    }
+    
+    /// Fill in agent fields and validate, returning false for evidence about an allowlisted IP
+    fn admit_evidence(&self, evidence: &mut ThreatEvidence) -> Result<bool> {
         // Set agent-specific fields
         evidence.agent_id = self.config.agent_id.clone();
-        evidence.reputation = self.reporter.get_reputation();
+        evidence.reputation = self.reputation_metrics.reputation();
         evidence.compliance_tag = self.config.compliance_mode.clone();
         evidence.region = self.config.region.clone();
+        evidence.validate()?;
+        
+        // Never report our own critical infrastructure
+        if self.allowlist.read().unwrap().contains(&evidence.source_ip) {
+            log::warn!("Dropping evidence {} about allowlisted IP {}", evidence.id, evidence.source_ip);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+    
+    /// Enrich and correlate evidence, returning its internal form with the original source and target IPs
+    async fn prepare_evidence(&self, mut evidence: ThreatEvidence) -> Result<(ThreatEvidence, String, String)> {
+        // Attach hostname and netblock owner while the full IP is available
+        if let Some(ref enricher) = self.enricher {
+            enricher.enrich(&mut evidence).await;
+        }
         
-        // Process evidence according to compliance settings
-        let processed_evidence = self.compliance_engine
-            .process_evidence(evidence, &self.config)?;
+        // Link to earlier events from the same source or subnet
+        self.temporal_correlator.correlate(&mut evidence);
+        let (source_ip, target_ip) = (evidence.source_ip.clone(), evidence.target_ip.clone());
         
-        // Enhance with credibility and consensus verification
-        let enhanced_evidence = self.enhance_threat_evidence(processed_evidence).await?;
+        let internal_evidence = if self.config.internal_full_ip {
+            evidence
+        } else {
+            self.compliance_engine.process_evidence(evidence, &self.config)?
+        };
+        Ok((internal_evidence, source_ip, target_ip))
+    }
+    
+    /// Blocklist, record and publish enhanced evidence
+    async fn publish_evidence(&self, enhanced_evidence: &ThreatEvidence, source_ip: String, target_ip: String) -> Result<()> {
+        let published_evidence = self.seal_evidence(enhanced_evidence)?;
+        self.deliver_evidence(enhanced_evidence, published_evidence, source_ip, target_ip).await
+    }
+    
+    /// Process evidence according to compliance settings and sign it before it leaves the agent
+    fn seal_evidence(&self, enhanced_evidence: &ThreatEvidence) -> Result<ThreatEvidence> {
+        let mut published_evidence = self.compliance_engine
+            .process_evidence(enhanced_evidence.clone(), &self.config)?;
+        CryptoProvider::sign_evidence(&mut published_evidence, self.evidence_signer.as_ref())?;
+        Ok(published_evidence)
+    }
+    
+    /// Blocklist and record enhanced evidence, then publish its sealed copy to peers
+    async fn deliver_evidence(
+        &self,
+        enhanced_evidence: &ThreatEvidence,
+        published_evidence: ThreatEvidence,
+        source_ip: String,
+        target_ip: String,
+    ) -> Result<()> {
+        // Send to blocklist exporter if enabled
+        if let Some(ref sender) = self.blocklist_sender {
+            let _ = sender.send(enhanced_evidence.clone());
+        }
+        
+        // Remember the evidence so it can later be reported as a false positive
+        self.track_evidence(enhanced_evidence);
+        
+        // Keep a local record, found by data-subject requests through keys of the original IPs
+        let record = self.evidence_store.record(&source_ip, &target_ip, published_evidence.clone());
+        if let Err(e) = self.evidence_store.append(&record).await {
+            log::warn!("Failed to store evidence {}: {}", record.evidence.id, e);
+        }
         
-        // Publish to P2P network
-        self.p2p_client.publish_threat_evidence(&enhanced_evidence).await?;
+        // Hold back evidence while the reporter has paused publishing for low reputation
+        if self.config.pause_publishing_below_threshold && self.reputation_metrics.is_below_threshold() {
+            self.reputation_metrics.record_withheld();
+            log::debug!("Withholding evidence {} while reputation is below threshold", published_evidence.id);
+            self.update_threat_count();
+            return Ok(());
+        }
+        
+        // Publish to P2P network; without peers the evidence is only acted on locally
+        if self.p2p_client.is_connected() {
+            self.p2p_client.publish_threat_evidence(&published_evidence).await?;
+        } else {
+            log::warn!("Not connected to P2P network, evidence {} was not published", published_evidence.id);
+        }
+        
+        // Notarize it alongside the evidence the reporter publishes
+        if let Some(ref notary) = self.notary {
+            match notary.record(&published_evidence) {
+                // Save each sealed batch so its root survives a restart
+                Ok(Some(_)) => {
+                    if let Err(e) = notary.persist().await {
+                        log::warn!("Failed to persist notary state: {}", e);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => log::error!("Failed to notarize evidence {}: {}", published_evidence.id, e),
+            }
+        }
         
         // Update status
         self.update_threat_count();
@@ -328,70 +1035,131 @@ impl OrasrsAgent {
         Ok(())
     }
     
-    /// Update threat count in status
-    fn update_threat_count(&mut self) {
-        self.status.threat_count += 1;
-        self.status.last_threat_report = Some(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64
-        );
+    /// Erase a data subject's IP from every store holding it
+    ///
+    /// Covers stored evidence, verification state and its checkpoint, local
+    /// and upstream indicators, buffered events and the exported blocklist.
+    pub async fn handle_gdpr_deletion(&self, subject_ip: &str) -> Result<ErasureReport> {
+        self.compliance_engine
+            .handle_gdpr_deletion(&self.evidence_store, subject_ip, self.erase_subject(subject_ip))
+            .await
     }
     
-    /// Enhance threat evidence with credibility and consensus verification
-    async fn enhance_threat_evidence(&self, evidence: ThreatEvidence) -> Result<ThreatEvidence> {
-        log::debug!("Enhancing threat evidence: {}", evidence.id);
+    /// Erase a data subject's IP from every store but the evidence store
+    async fn erase_subject(&self, subject_ip: &str) -> Result<ErasureReport> {
+        let Ok(ip) = subject_ip.trim().parse::<IpAddr>() else {
+            return Ok(ErasureReport::default());
+        };
         
-        // First, check if there are any upstream threats that correlate with this evidence
-        let upstream_threats = self.threat_intel_aggregator.fetch_all_sources().await.unwrap_or_default();
+        let consensus_requests = self.consensus_engine.forget_ip(ip).await;
+        self.consensus_engine.checkpoint_to(self.storage.as_ref()).await?;
         
-        // Perform correlation between local evidence and upstream threats
-        let correlation_results = self.consensus_engine
-            .process_evidence_correlation(&[evidence.clone()], &upstream_threats)
-            .await?;
+        let local_indicator = self.indicator_store.remove(&ip.to_string()).is_some();
+        self.indicator_store.persist(self.storage.as_ref()).await?;
+        let indicators = usize::from(local_indicator) + self.threat_intel_aggregator.forget_ip(ip);
         
-        // Extract consensus confidence from correlation results
-        let consensus_confidence = if !correlation_results.is_empty() {
-            Some(correlation_results[0].1.confidence_score)
-        } else {
-            None
-        };
+        let mut buffered_events = self.temporal_correlator.forget_ip(ip);
+        {
+            let mut tracked = self.submitted_evidence.lock().unwrap();
+            let (by_id, order) = &mut *tracked;
+            let before = by_id.len();
+            by_id.retain(|_, evidence| !evidence.concerns_ip(ip));
+            order.retain(|id| by_id.contains_key(id));
+            buffered_events += before - by_id.len();
+        }
         
-        // Enhance with credibility engine
-        let enhanced_evidence = self.credibility_engine
-            .enhance_threat_evidence(evidence, consensus_confidence)
-            .await?;
+        if self.config.blocklist_export_enabled {
+            let _ = self.blocklist_removal_sender.send(ip.to_string());
+        }
+        
+        Ok(ErasureReport { consensus_requests, indicators, buffered_events, ..ErasureReport::default() })
+    }
+    
+    /// Report that submitted evidence was a false positive
+    ///
+    /// Removes the source IP from the blocklist, lowers the reputation of the
+    /// source IP, and broadcasts a dispute if `broadcast_disputes` is set. The
+    /// reporting agent's reputation is only lowered when it is not this agent.
+    pub async fn report_false_positive(&self, evidence_id: &str) -> Result<()> {
+        let evidence = self.submitted_evidence.lock().unwrap().0.get(evidence_id).cloned()
+            .ok_or_else(|| AgentError::InternalError(format!("Unknown evidence: {}", evidence_id)))?;
+        
+        if self.config.blocklist_export_enabled {
+            let _ = self.blocklist_removal_sender.send(evidence.source_ip.clone());
+        }
         
-        // Update credibility based on the correlation results
-        if !correlation_results.is_empty() {
-            let (_, consensus_result) = &correlation_results[0];
-            // Update credibility based on consensus result
-            self.credibility_engine.update_credibility(
-                &enhanced_evidence, 
-                consensus_result.consensus_verdict
-            ).await?;
+        if evidence.agent_id == self.config.agent_id {
+            self.credibility_engine.update_evidence_credibility(&evidence, false).await?;
+        } else {
+            self.credibility_engine.update_credibility(&evidence, false).await?;
+        }
+        
+        if self.config.broadcast_disputes {
+            self.p2p_client.publish_dispute(evidence_id, "false positive").await?;
         }
         
-        log::info!("Enhanced threat evidence {} with credibility score: {:.2}", 
-                  enhanced_evidence.id, enhanced_evidence.reputation);
+        log::info!("Reported evidence {} from {} as a false positive", evidence_id, evidence.source_ip);
+        Ok(())
+    }
+    
+    /// Remember submitted evidence, forgetting the oldest beyond the limit
+    fn track_evidence(&self, evidence: &ThreatEvidence) {
+        let mut tracked = self.submitted_evidence.lock().unwrap();
+        let (by_id, order) = &mut *tracked;
         
-        Ok(enhanced_evidence)
+        if by_id.insert(evidence.id.clone(), evidence.clone()).is_none() {
+            order.push_back(evidence.id.clone());
+        }
+        while order.len() > MAX_TRACKED_EVIDENCE {
+            if let Some(oldest) = order.pop_front() {
+                by_id.remove(&oldest);
+            }
+        }
+    }
+    
+    /// Update threat count in status
+    fn update_threat_count(&self) {
+        self.threat_count.fetch_add(1, Ordering::SeqCst);
+        *self.last_threat_report.lock().unwrap() = Some(self.clock.now());
+    }
+    
+    /// Enhance threat evidence with credibility and consensus verification
+    async fn enhance_threat_evidence(&self, evidence: ThreatEvidence) -> Result<(ThreatEvidence, Option<ConsensusResult>)> {
+        enhance_evidence(
+            &self.threat_intel_aggregator,
+            &self.consensus_engine,
+            &self.credibility_engine,
+            evidence,
+        ).await
     }
     
     /// Start the threat intelligence aggregation service
-    pub async fn start_threat_intel_aggregation(&self) -> Result<()> {
+    pub async fn start_threat_intel_aggregation(&mut self) -> Result<()> {
         log::info!("Starting threat intelligence aggregation service...");
         
         // Spawn a background task to periodically fetch upstream threat intelligence
-        tokio::spawn({
+        self.tasks.push(tokio::spawn({
             let aggregator = self.threat_intel_aggregator.clone();
+            let indicator_store = self.indicator_store.clone();
+            let storage = self.storage.clone();
+            let mut shutdown = self.shutdown.subscribe();
             async move {
                 loop {
-                    match aggregator.fetch_all_sources().await {
+                    let fetched = tokio::select! {
+                        fetched = aggregator.fetch_all_sources() => fetched,
+                        _ = shutdown.wait_for(|stopping| *stopping) => break,
+                    };
+                    match fetched {
                         Ok(threats) => {
                             log::info!("Fetched {} upstream threats", threats.len());
-                            // Could process these threats further if needed
+                            
+                            // Keep the local IoC database current
+                            let added = indicator_store.ingest(&threats);
+                            let expired = indicator_store.purge_expired();
+                            log::debug!("Indicator store: {} added, {} expired", added, expired);
+                            if let Err(e) = indicator_store.persist(storage.as_ref()).await {
+                                log::warn!("Failed to persist indicator store: {}", e);
+                            }
                         }
                         Err(e) => {
                             log::error!("Error fetching upstream threat intelligence: {}", e);
@@ -399,83 +1167,1403 @@ impl OrasrsAgent {
                     }
                     
                     // Wait for the configured interval before next fetch
-                    tokio::time::sleep(tokio::time::Duration::from_secs(300)).await; // 5 minutes
+                    tokio::select! {
+                        _ = tokio::time::sleep(tokio::time::Duration::from_secs(300)) => {} // 5 minutes
+                        _ = shutdown.wait_for(|stopping| *stopping) => break,
+                    }
                 }
             }
-        });
+        }));
         
         Ok(())
     }
 }
 
-// Note: OrasrsAgent does not implement Clone because it contains non-cloneable elements like receivers.
-// Instead, components that need access to the agent should receive references or use Arc<Mutex<OrasrsAgent>> if needed.
-
-impl ComplianceEngine {
-    /// Process evidence according to compliance settings
-    pub fn process_evidence(&self, mut evidence: ThreatEvidence, config: &AgentConfig) -> Result<ThreatEvidence> {
-        // Apply privacy settings based on privacy level
-        match config.privacy_level {
-            1 => { // GDPR: anonymize to /24
-                evidence.source_ip = self.anonymize_ip(&evidence.source_ip, 24);
-                evidence.target_ip = self.anonymize_ip(&evidence.target_ip, 24);
+/// Correlate evidence with upstream threats and score it with the credibility engine
+async fn enhance_evidence(
+    aggregator: &ThreatIntelAggregator,
+    consensus_engine: &ConsensusEngine,
+    credibility_engine: &CredibilityEngine,
+    evidence: ThreatEvidence,
+) -> Result<(ThreatEvidence, Option<ConsensusResult>)> {
+    log::debug!("Enhancing threat evidence: {}", evidence.id);
+    
+    // Correlate against the upstream threats fetched by the aggregation loop
+    let upstream_threats = aggregator.known_threats();
+    
+    // Perform correlation between local evidence and upstream threats
+    let correlation_results = consensus_engine
+        .process_evidence_correlation(std::slice::from_ref(&evidence), &upstream_threats)
+        .await?;
+    
+    // Extract consensus confidence from correlation results
+    let consensus_confidence = if !correlation_results.is_empty() {
+        Some(correlation_results[0].2.confidence_score)
+    } else {
+        None
+    };
+    
+    // Enhance with credibility engine
+    let enhanced_evidence = credibility_engine
+        .enhance_threat_evidence(evidence, consensus_confidence)
+        .await?;
+    
+    // Update credibility based on the correlation results
+    let consensus_result = correlation_results.into_iter().next().map(|(_, _, result)| result);
+    if let Some(ref consensus_result) = consensus_result {
+        // Update credibility based on consensus result
+        credibility_engine.update_credibility(
+            &enhanced_evidence, 
+            consensus_result.consensus_verdict
+        ).await?;
+    }
+    
+    log::info!("Enhanced threat evidence {} with credibility score: {:.2}", 
+              enhanced_evidence.id, enhanced_evidence.reputation);
+    
+    Ok((enhanced_evidence, consensus_result))
+}
+
+/// Enhance a batch of evidence against a single snapshot of upstream threats
+///
+/// Each item's credibility uses the consensus result of its own correlation
+/// with upstream, if it correlated with any.
+async fn enhance_evidence_batch(
+    aggregator: &ThreatIntelAggregator,
+    consensus_engine: &ConsensusEngine,
+    credibility_engine: &CredibilityEngine,
+    evidences: Vec<ThreatEvidence>,
+) -> Result<Vec<ThreatEvidence>> {
+    if evidences.is_empty() {
+        return Ok(Vec::new());
+    }
+    let upstream_threats = aggregator.known_threats();
+    let correlation_results = consensus_engine
+        .process_evidence_correlation(&evidences, &upstream_threats)
+        .await?;
+    
+    // Each item takes the first decided consensus among its correlated pairs
+    let mut consensus_results: Vec<Option<ConsensusResult>> = vec![None; evidences.len()];
+    for (index, _, result) in correlation_results {
+        consensus_results[index].get_or_insert(result);
+    }
+    
+    let enhanced = credibility_engine
+        .batch_enhance_threat_evidence(evidences.into_iter()
+            .zip(&consensus_results)
+            .map(|(evidence, result)| (evidence, result.as_ref().map(|result| result.confidence_score)))
+            .collect())
+        .await?;
+    
+    for (evidence, result) in enhanced.iter().zip(&consensus_results) {
+        if let Some(result) = result {
+            credibility_engine.update_credibility(evidence, result.consensus_verdict).await?;
+        }
+    }
+    
+    log::info!("Enhanced a batch of {} threat evidence items", enhanced.len());
+    Ok(enhanced)
+}
+
+/// Broadcast submitted verification requests, verify peers' requests and collect their responses
+///
+/// Requests are anonymized under the current compliance settings and signed
+/// as their emitter before they leave the agent; the local copy keeps the
+/// full evidence.
+async fn exchange_verifications(
+    consensus_engine: Arc<ConsensusEngine>,
+    compliance_engine: ComplianceEngine,
+    signer: Box<dyn Signer>,
+    config: watch::Receiver<AgentConfig>,
+    channel: VerificationChannel,
+    mut requests: mpsc::UnboundedReceiver<VerificationRequest>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let VerificationChannel { mut incoming, outgoing } = channel;
+    loop {
+        let message = tokio::select! {
+            request = requests.recv() => match request {
+                Some(mut request) => {
+                    let processed = compliance_engine.process_evidence(request.evidence.clone(), &config.borrow())
+                        .and_then(|mut evidence| {
+                            CryptoProvider::sign_evidence(&mut evidence, signer.as_ref())?;
+                            Ok(evidence)
+                        });
+                    match processed {
+                        Ok(evidence) => {
+                            request.evidence = evidence;
+                            let _ = outgoing.send(VerificationMessage::Request(Box::new(request)));
+                        }
+                        Err(e) => log::warn!("Not broadcasting verification request {}: {}", request.request_id, e),
+                    }
+                    continue;
+                }
+                None => break,
             },
-            2 => { // CCPA: anonymize to /16
-                evidence.source_ip = self.anonymize_ip(&evidence.source_ip, 16);
-                evidence.target_ip = self.anonymize_ip(&evidence.target_ip, 16);
+            message = incoming.recv() => match message {
+                Some(message) => message,
+                None => break,
             },
-            3 => { // China: full IP allowed
-                // No anonymization needed
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+        };
+        
+        match message {
+            VerificationMessage::Request(request) => match consensus_engine.verify_evidence(&request).await {
+                Ok(response) => {
+                    let _ = outgoing.send(VerificationMessage::Response(response));
+                }
+                Err(e) => log::warn!("Failed to verify request {} from {}: {}", request.request_id, request.requesting_agent, e),
             },
-            _ => { // Global: anonymize to /16
-                evidence.source_ip = self.anonymize_ip(&evidence.source_ip, 16);
-                evidence.target_ip = self.anonymize_ip(&evidence.target_ip, 16);
+            VerificationMessage::Response(response) => consensus_engine.record_response(response).await,
+        }
+    }
+}
+
+/// Seconds elapsed since `started_at`, never negative if the clock steps back
+fn uptime_since(clock: &dyn Clock, started_at: i64) -> u64 {
+    (clock.now() - started_at).max(0) as u64
+}
+
+/// Update the periodically reported status fields
+fn refresh_status(status: &mut AgentStatus, clock: &dyn Clock, started_at: i64, reputation_metrics: &ReputationMetrics) {
+    status.uptime = uptime_since(clock, started_at);
+    status.reputation = reputation_metrics.reputation();
+}
+
+/// Names of the configured resource limits `usage` is over
+fn exceeded_limits(usage: &ResourceUsage, config: &AgentConfig) -> Vec<&'static str> {
+    let mut exceeded = Vec::new();
+    if usage.memory > config.max_memory {
+        exceeded.push("memory");
+    }
+    if usage.cpu > config.cpu_limit {
+        exceeded.push("CPU");
+    }
+    if usage.network > config.network_limit as u64 {
+        exceeded.push("network");
+    }
+    exceeded
+}
+
+// Note: OrasrsAgent does not implement Clone because it contains non-cloneable elements like receivers.
+// Instead, components that need access to the agent should receive references or use Arc<Mutex<OrasrsAgent>> if needed.
+
+impl ComplianceEngine {
+    /// Process evidence according to compliance settings
+    pub fn process_evidence(&self, mut evidence: ThreatEvidence, config: &AgentConfig) -> Result<ThreatEvidence> {
+        // Apply privacy settings based on privacy level
+        if let Some((ipv4_prefix, ipv6_prefix)) = anonymization_prefixes(config.privacy_level) {
+            if config.anonymize_fields.source() && !self.is_anonymization_exempt(&evidence.source_ip) {
+                evidence.source_ip = anonymize_ip_field(&evidence.source_ip, ipv4_prefix, ipv6_prefix);
+                // Reverse DNS and netblock owner would identify the address the prefix hides
+                evidence.source_hostname = None;
+                evidence.source_owner = None;
+            }
+            if config.anonymize_fields.target() && !self.is_anonymization_exempt(&evidence.target_ip) {
+                evidence.target_ip = anonymize_ip_field(&evidence.target_ip, ipv4_prefix, ipv6_prefix);
             }
         }
 
         Ok(evidence)
     }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::p2p::NetworkStatus;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
 
-    /// Anonymize IP address to specified subnet size
-    fn anonymize_ip(&self, ip: &str, subnet_bits: u8) -> String {
-        // This is a simplified IP anonymization
-        // In a real implementation, we'd use proper IP address manipulation
-        if subnet_bits >= 32 {
-            return ip.to_string(); // No anonymization
-        }
-
-        // For IPv4, anonymize the last octet(s) based on subnet_bits
-        if ip.contains('.') {
-            let octets: Vec<&str> = ip.split('.').collect();
-            if octets.len() == 4 {
-                let keep_octets = match subnet_bits {
-                    0..=8 => 1,
-                    9..=16 => 2,
-                    17..=24 => 3,
-                    _ => 4, // Don't anonymize if >= 24
-                };
-                
-                if keep_octets >= 4 {
-                    return ip.to_string(); // No anonymization needed
-                }
-                
-                let mut result = String::new();
-                for i in 0..4 {
-                    if i < keep_octets {
-                        result.push_str(octets[i]);
-                    } else {
-                        result.push_str("0");
-                    }
-                    
-                    if i < 3 {
-                        result.push('.');
-                    }
+    fn test_signer() -> Box<dyn Signer> {
+        Box::new(crate::crypto::Ed25519Signer::new(libp2p::identity::Keypair::generate_ed25519()))
+    }
+
+    /// P2P client that records calls and never touches the network
+    struct MockP2pClient {
+        connect_calls: Arc<AtomicUsize>,
+        published: Arc<std::sync::Mutex<Vec<ThreatEvidence>>>,
+        disputes: Arc<std::sync::Mutex<Vec<String>>>,
+        failing_publishes: Arc<AtomicUsize>, // Number of upcoming publishes that fail
+        connected: bool,
+    }
+
+    impl MockP2pClient {
+        fn new(connect_calls: Arc<AtomicUsize>) -> Self {
+            Self {
+                connect_calls,
+                published: Arc::new(std::sync::Mutex::new(Vec::new())),
+                disputes: Arc::new(std::sync::Mutex::new(Vec::new())),
+                failing_publishes: Arc::new(AtomicUsize::new(0)),
+                connected: false,
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl P2pNetwork for MockP2pClient {
+        async fn connect_bootstrap(&mut self) -> Result<()> {
+            self.connect_calls.fetch_add(1, Ordering::SeqCst);
+            self.connected = true;
+            Ok(())
+        }
+
+        fn subscribe_threat_intel(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn publish_threat_evidence(&self, evidence: &ThreatEvidence) -> Result<()> {
+            if self.failing_publishes.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1)).is_ok() {
+                return Err(AgentError::P2pError("Mock publish failure".to_string()));
+            }
+            self.published.lock().unwrap().push(evidence.clone());
+            Ok(())
+        }
+
+        async fn publish_dispute(&self, evidence_id: &str, _reason: &str) -> Result<()> {
+            self.disputes.lock().unwrap().push(evidence_id.to_string());
+            Ok(())
+        }
+
+        fn is_connected(&self) -> bool {
+            self.connected
+        }
+
+        async fn disconnect(&mut self) {
+            self.connected = false;
+        }
+
+        fn get_network_status(&self) -> NetworkStatus {
+            NetworkStatus {
+                connected: self.connected,
+                peer_id: "mock-peer".to_string(),
+                connections: 0,
+                reputation: 1.0,
+                last_seen: 0,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_builder_with_mock_p2p_client() {
+        let connect_calls = Arc::new(AtomicUsize::new(0));
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+
+        let mut config = AgentConfig::default();
+        config.agent_id = "test-builder-agent".to_string();
+
+        let mut agent = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(MockP2pClient::new(connect_calls.clone())))
+            .with_clock(clock.clone())
+            .defer_connection()
+            .build()
+            .await
+            .unwrap();
+
+        // Connection is deferred until explicitly requested
+        assert_eq!(connect_calls.load(Ordering::SeqCst), 0);
+        assert!(!agent.get_status().p2p_connected);
+
+        agent.connect().await.unwrap();
+        assert_eq!(connect_calls.load(Ordering::SeqCst), 1);
+        assert!(agent.get_status().p2p_connected);
+
+        // Uptime follows the injected clock
+        clock.advance(42);
+        assert_eq!(agent.get_status().uptime, 42);
+    }
+
+    #[tokio::test]
+    async fn test_blocklist_gets_full_ip_while_published_is_anonymized() {
+        let mut config = AgentConfig::default();
+        config.privacy_level = 1; // Anonymize to /24 on publish
+        config.blocklist_export_enabled = true;
+
+        let p2p_client = MockP2pClient::new(Arc::new(AtomicUsize::new(0)));
+        let published = p2p_client.published.clone();
+
+        let mut agent = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(p2p_client))
+            .build()
+            .await
+            .unwrap();
+
+        // Keep the test offline
+        for source in agent.threat_intel_aggregator.get_sources_config() {
+            agent.threat_intel_aggregator.set_source_enabled(&source.name, false).unwrap();
+        }
+        let mut blocklist_receiver = agent.blocklist_receiver.take().unwrap();
+
+        let evidence = ThreatEvidence {
+            id: "test-full-ip".to_string(),
+            timestamp: 1_700_000_000,
+            source_ip: "203.0.113.57".to_string(),
+            target_ip: "10.0.0.12".to_string(),
+            threat_type: crate::ThreatType::Malware,
+            threat_level: ThreatLevel::Critical,
+            context: "Test threat".to_string(),
+            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"test-full-ip"),
+            geolocation: "unknown".to_string(),
+            network_flow: "TCP".to_string(),
+            agent_id: String::new(),
+            reputation: 1.0,
+            compliance_tag: String::new(),
+            region: String::new(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        };
+        agent.submit_threat_evidence(evidence).await.unwrap();
+
+        let blocklisted = blocklist_receiver.recv().await.unwrap();
+        assert_eq!(blocklisted.source_ip, "203.0.113.57");
+        assert_eq!(blocklisted.target_ip, "10.0.0.12");
+
+        let published = published.lock().unwrap();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].source_ip, "203.0.113.0");
+        assert_eq!(published[0].target_ip, "10.0.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_submissions_are_withheld_while_publishing_is_paused() {
+        let config = AgentConfig { pause_publishing_below_threshold: true, ..AgentConfig::default() };
+        let p2p_client = MockP2pClient::new(Arc::new(AtomicUsize::new(0)));
+        let published = p2p_client.published.clone();
+        let agent = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(p2p_client))
+            .build()
+            .await
+            .unwrap();
+        for source in agent.threat_intel_aggregator.get_sources_config() {
+            agent.threat_intel_aggregator.set_source_enabled(&source.name, false).unwrap();
+        }
+        let evidence = |source_ip: &str| {
+            ThreatEvidence::builder(source_ip, crate::ThreatType::Malware, ThreatLevel::Critical)
+                .target_ip("10.0.0.12")
+                .context("Test threat")
+                .build()
+        };
+
+        agent.reputation_metrics.set_below_threshold(true);
+        agent.submit_threat_evidence(evidence("203.0.113.57")).await.unwrap();
+        assert!(published.lock().unwrap().is_empty());
+        assert_eq!(agent.reputation_metrics.withheld_evidence(), 1);
+
+        // Recovery resumes publishing
+        agent.reputation_metrics.set_below_threshold(false);
+        agent.submit_threat_evidence(evidence("203.0.113.58")).await.unwrap();
+        assert_eq!(published.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stop_aborts_hanging_task_after_grace_period() {
+        /// Sets a flag when the task's future is dropped
+        struct DropFlag(Arc<AtomicBool>);
+
+        impl Drop for DropFlag {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let mut config = AgentConfig::default();
+        config.shutdown_grace_period = 1;
+
+        let mut agent = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .defer_connection()
+            .build()
+            .await
+            .unwrap();
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        agent.tasks.push(tokio::spawn({
+            let guard = DropFlag(dropped.clone());
+            async move {
+                let _guard = guard;
+                std::future::pending::<()>().await;
+            }
+        }));
+
+        let started = std::time::Instant::now();
+        tokio::time::timeout(Duration::from_secs(5), agent.stop())
+            .await
+            .expect("shutdown should complete after the grace period")
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+
+        // Give the runtime a moment to drop the aborted task
+        tokio::task::yield_now().await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(dropped.load(Ordering::SeqCst));
+        assert!(agent.tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_submit_through_arc_updates_threat_count() {
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let agent = OrasrsAgent::builder(AgentConfig::default())
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .with_clock(clock.clone())
+            .build()
+            .await
+            .unwrap();
+
+        // Keep the test offline
+        for source in agent.threat_intel_aggregator.get_sources_config() {
+            agent.threat_intel_aggregator.set_source_enabled(&source.name, false).unwrap();
+        }
+
+        let agent = Arc::new(agent);
+        assert_eq!(agent.get_status().threat_count, 0);
+        assert_eq!(agent.get_status().last_threat_report, None);
+
+        let submit = |id: &str| {
+            let agent = agent.clone();
+            let evidence = ThreatEvidence {
+                id: id.to_string(),
+                timestamp: 1_700_000_000,
+                source_ip: "203.0.113.9".to_string(),
+                target_ip: "10.0.0.1".to_string(),
+                threat_type: crate::ThreatType::Malware,
+                threat_level: ThreatLevel::Critical,
+                context: "Test threat".to_string(),
+                evidence_hash: crate::crypto::CryptoProvider::blake3_hash(id.as_bytes()),
+                geolocation: "unknown".to_string(),
+                network_flow: "TCP".to_string(),
+                agent_id: String::new(),
+                reputation: 1.0,
+                compliance_tag: String::new(),
+                region: String::new(),
+                expires_at: None,
+                source_hostname: None,
+                source_owner: None,
+                campaign_id: None,
+                source_network: None,
+                signer_key: None,
+                signature: None,
+            };
+            tokio::spawn(async move { agent.submit_threat_evidence(evidence).await })
+        };
+
+        let (first, second) = tokio::join!(submit("arc-1"), submit("arc-2"));
+        first.unwrap().unwrap();
+        second.unwrap().unwrap();
+
+        let status = agent.get_status();
+        assert_eq!(status.threat_count, 2);
+        assert_eq!(status.last_threat_report, Some(1_700_000_000));
+    }
+
+    #[test]
+    fn test_anonymize_fields_selects_ips() {
+        use crate::config::AnonymizeFields;
+
+        let cases = [
+            (AnonymizeFields::SourceOnly, "203.0.113.0", "10.0.0.12", None),
+            (AnonymizeFields::TargetOnly, "203.0.113.57", "10.0.0.0", Some("scanner.example.net")),
+            (AnonymizeFields::Both, "203.0.113.0", "10.0.0.0", None),
+        ];
+
+        for (fields, expected_source, expected_target, expected_hostname) in cases {
+            let mut config = AgentConfig::default();
+            config.privacy_level = 1; // Anonymize to /24
+            config.anonymize_fields = fields;
+            let engine = ComplianceEngine::new(&config);
+
+            let evidence = ThreatEvidence {
+                id: "test-anonymize".to_string(),
+                timestamp: 1_700_000_000,
+                source_ip: "203.0.113.57".to_string(),
+                target_ip: "10.0.0.12".to_string(),
+                threat_type: crate::ThreatType::Malware,
+                threat_level: ThreatLevel::Critical,
+                context: "Test threat".to_string(),
+                evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"test-anonymize"),
+                geolocation: "unknown".to_string(),
+                network_flow: "TCP".to_string(),
+                agent_id: String::new(),
+                reputation: 1.0,
+                compliance_tag: String::new(),
+                region: String::new(),
+                expires_at: None,
+                source_hostname: Some("scanner.example.net".to_string()),
+                source_owner: Some("EXAMPLE-NET".to_string()),
+                campaign_id: None,
+                source_network: None,
+                signer_key: None,
+                signature: None,
+            };
+
+            let processed = engine.process_evidence(evidence, &config).unwrap();
+            assert_eq!(processed.source_ip, expected_source, "{:?}", fields);
+            assert_eq!(processed.target_ip, expected_target, "{:?}", fields);
+            // Enrichment of an anonymized source is dropped with its address
+            assert_eq!(processed.source_hostname.as_deref(), expected_hostname, "{:?}", fields);
+            assert_eq!(processed.source_owner.is_some(), expected_hostname.is_some(), "{:?}", fields);
+        }
+    }
+
+    #[test]
+    fn test_anonymization_exemptions_keep_ip_intact() {
+        let mut config = AgentConfig::default();
+        config.privacy_level = 1; // Anonymize to /24
+        config.anonymization_exemptions = vec!["10.0.0.12".to_string(), "not-an-ip".to_string()];
+        let mut engine = ComplianceEngine::new(&config);
+        engine.add_anonymization_exemption("198.51.100.8".parse().unwrap());
+
+        let evidence = |source_ip: &str| ThreatEvidence {
+            id: "test-exempt".to_string(),
+            timestamp: 1_700_000_000,
+            source_ip: source_ip.to_string(),
+            target_ip: "10.0.0.12".to_string(),
+            threat_type: crate::ThreatType::Malware,
+            threat_level: ThreatLevel::Critical,
+            context: "Test threat".to_string(),
+            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"test-exempt"),
+            geolocation: "unknown".to_string(),
+            network_flow: "TCP".to_string(),
+            agent_id: String::new(),
+            reputation: 1.0,
+            compliance_tag: String::new(),
+            region: String::new(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        };
+
+        // Exempted sensor IPs are kept while others are anonymized under the same mode
+        let processed = engine.process_evidence(evidence("203.0.113.57"), &config).unwrap();
+        assert_eq!(processed.source_ip, "203.0.113.0");
+        assert_eq!(processed.target_ip, "10.0.0.12");
+
+        let processed = engine.process_evidence(evidence("198.51.100.8"), &config).unwrap();
+        assert_eq!(processed.source_ip, "198.51.100.8");
+    }
+
+    #[tokio::test]
+    async fn test_report_false_positive() {
+        let mut config = AgentConfig::default();
+        config.blocklist_export_enabled = true;
+        config.broadcast_disputes = true;
+
+        let p2p_client = MockP2pClient::new(Arc::new(AtomicUsize::new(0)));
+        let disputes = p2p_client.disputes.clone();
+
+        let mut agent = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(p2p_client))
+            .build()
+            .await
+            .unwrap();
+
+        // Keep the test offline
+        for source in agent.threat_intel_aggregator.get_sources_config() {
+            agent.threat_intel_aggregator.set_source_enabled(&source.name, false).unwrap();
+        }
+
+        let blocklist_path = std::env::temp_dir()
+            .join(format!("orasrs-false-positive-{}.txt", uuid::Uuid::new_v4()));
+        let mut exporter = BlocklistExporter::new(blocklist_path.to_string_lossy().to_string(), ThreatLevel::Info, 300)
+            .with_removal_queue(agent.blocklist_removal_receiver.take().unwrap());
+        let blocklist_receiver = agent.blocklist_receiver.take().unwrap();
+        let exporter_task = tokio::spawn(async move { exporter.start_export(blocklist_receiver).await });
+
+        let evidence = ThreatEvidence {
+            id: "false-positive".to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+            source_ip: "203.0.113.99".to_string(),
+            target_ip: "10.0.0.1".to_string(),
+            threat_type: crate::ThreatType::SuspiciousConnection,
+            threat_level: ThreatLevel::Critical,
+            context: "Test threat".to_string(),
+            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"false-positive"),
+            geolocation: "unknown".to_string(),
+            network_flow: "TCP".to_string(),
+            agent_id: String::new(),
+            reputation: 1.0,
+            compliance_tag: String::new(),
+            region: String::new(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        };
+        agent.submit_threat_evidence(evidence).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(std::fs::read_to_string(&blocklist_path).unwrap().contains("203.0.113.99"));
+
+        let before = agent.credibility_engine.get_metrics().await;
+        assert_eq!(before.total_sources_tracked, 0);
+
+        agent.report_false_positive("false-positive").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(!std::fs::read_to_string(&blocklist_path).unwrap().contains("203.0.113.99"));
+        // The local agent is not penalized for its own report, only the source IP
+        let after = agent.credibility_engine.get_metrics().await;
+        assert_eq!(after.total_sources_tracked, 0);
+        let ip_reputations = agent.credibility_engine.ip_reputations(usize::MAX).await;
+        let (_, ip_reputation) = ip_reputations.iter().find(|(ip, _)| ip == "203.0.113.99").unwrap();
+        assert!(*ip_reputation < 0.5); // Below the default for unknown IPs
+        assert_eq!(*disputes.lock().unwrap(), vec!["false-positive".to_string()]);
+
+        assert!(agent.report_false_positive("missing").await.is_err());
+
+        exporter_task.abort();
+        let _ = std::fs::remove_file(&blocklist_path);
+    }
+
+    #[tokio::test]
+    async fn test_gdpr_deletion_erases_subject_from_every_store() {
+        let dir = std::env::temp_dir().join(format!("orasrs-agent-gdpr-{}", uuid::Uuid::new_v4()));
+        let config = AgentConfig {
+            compliance_mode: "gdpr".to_string(),
+            storage_config: crate::config::StorageConfig {
+                data_dir: dir.clone(),
+                ..crate::config::StorageConfig::default()
+            },
+            ..AgentConfig::default()
+        };
+        let agent = OrasrsAgent::builder(config.clone())
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .with_storage(Arc::new(crate::storage::MemoryStorage::new()))
+            .build()
+            .await
+            .unwrap();
+
+        for ip in ["203.0.113.7", "198.51.100.1"] {
+            let evidence = ThreatEvidence::builder(ip, crate::ThreatType::BruteForce, ThreatLevel::Warning).build();
+            agent.consensus_engine.submit_for_verification(evidence.clone()).await.unwrap();
+            agent.evidence_store.append(&agent.evidence_store.record(ip, "local", evidence)).await.unwrap();
+            agent.indicator_store.add(crate::threat_intel::Indicator::new(
+                ip,
+                crate::threat_intel::IndicatorType::Ip,
+                crate::ThreatType::BruteForce,
+                ThreatLevel::Warning,
+                "test",
+            ));
+        }
+
+        let report = agent.handle_gdpr_deletion("203.0.113.7").await.unwrap();
+        assert_eq!(report, ErasureReport { evidence_records: 1, consensus_requests: 1, indicators: 1, buffered_events: 0, audited: true });
+
+        let checkpoint = agent.storage.get(crate::consensus_verification::STORAGE_KEY).await.unwrap().unwrap();
+        let checkpoint = String::from_utf8(checkpoint).unwrap();
+        assert!(!checkpoint.contains("203.0.113.7"));
+        assert!(checkpoint.contains("198.51.100.1"));
+        assert!(!agent.indicator_store.is_known_ip("203.0.113.7"));
+        assert!(agent.indicator_store.is_known_ip("198.51.100.1"));
+        assert_eq!(agent.evidence_store.load().await.unwrap().len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_submit_rejects_invalid_evidence() {
+        let p2p_client = MockP2pClient::new(Arc::new(AtomicUsize::new(0)));
+        let published = p2p_client.published.clone();
+
+        let agent = OrasrsAgent::builder(AgentConfig::default())
+            .with_p2p_client(Box::new(p2p_client))
+            .build()
+            .await
+            .unwrap();
+
+        let evidence = ThreatEvidence {
+            id: "test-invalid".to_string(),
+            timestamp: 1_700_000_000,
+            source_ip: "unknown".to_string(),
+            target_ip: "10.0.0.1".to_string(),
+            threat_type: crate::ThreatType::Malware,
+            threat_level: ThreatLevel::Critical,
+            context: "Test threat".to_string(),
+            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"test-invalid"),
+            geolocation: "unknown".to_string(),
+            network_flow: "TCP".to_string(),
+            agent_id: String::new(),
+            reputation: 1.0,
+            compliance_tag: String::new(),
+            region: String::new(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        };
+        let result = agent.submit_threat_evidence(evidence).await;
+
+        assert!(matches!(result, Err(AgentError::InvalidEvidence(_))));
+        assert!(published.lock().unwrap().is_empty());
+        assert_eq!(agent.get_status().threat_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_state_survives_restart_through_storage() {
+        let storage: Arc<dyn Storage> = Arc::new(crate::storage::MemoryStorage::new());
+        let config = AgentConfig { notarization_enabled: true, notarization_batch_size: 1, ..AgentConfig::default() };
+
+        let mut agent = OrasrsAgent::builder(config.clone())
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .with_storage(storage.clone())
+            .build()
+            .await
+            .unwrap();
+        for source in agent.threat_intel_aggregator.get_sources_config() {
+            agent.threat_intel_aggregator.set_source_enabled(&source.name, false).unwrap();
+        }
+        agent.indicator_store.add(crate::threat_intel::Indicator::new(
+            "203.0.113.44",
+            crate::threat_intel::IndicatorType::Ip,
+            crate::ThreatType::IoCMatch,
+            ThreatLevel::Warning,
+            "test",
+        ));
+        let evidence = ThreatEvidence {
+            id: "test-restart".to_string(),
+            timestamp: 1_700_000_000,
+            source_ip: "203.0.113.44".to_string(),
+            target_ip: "10.0.0.1".to_string(),
+            threat_type: crate::ThreatType::Malware,
+            threat_level: ThreatLevel::Critical,
+            context: "Test threat".to_string(),
+            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"test-restart"),
+            geolocation: "unknown".to_string(),
+            network_flow: "TCP".to_string(),
+            agent_id: "peer-agent".to_string(),
+            reputation: 1.0,
+            compliance_tag: String::new(),
+            region: String::new(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        };
+        agent.credibility_engine.update_credibility(&evidence, true).await.unwrap();
+        // Submitted evidence is notarized like the reporter's
+        agent.submit_threat_evidence(ThreatEvidence { id: "test-notarized".to_string(), ..evidence.clone() }).await.unwrap();
+        let batches = agent.notary.as_ref().unwrap().batches();
+        assert_eq!(batches.len(), 1);
+        agent.stop().await.unwrap();
+
+        let restarted = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .with_storage(storage)
+            .build()
+            .await
+            .unwrap();
+        assert!(restarted.indicator_store.is_known_ip("203.0.113.44"));
+        // The agent key and notarized batches carry over, so earlier signatures still verify
+        assert_eq!(restarted.public_key(), agent.public_key());
+        assert_eq!(restarted.notary.as_ref().unwrap().batches(), batches);
+        batches[0].verify_signature().unwrap();
+        let before = agent.credibility_engine.get_metrics().await;
+        let after = restarted.credibility_engine.get_metrics().await;
+        assert_eq!(after.total_sources_tracked, 1);
+        assert_eq!(after.avg_source_reputation, before.avg_source_reputation);
+    }
+
+    #[tokio::test]
+    async fn test_stop_drains_queued_evidence() {
+        let mut config = AgentConfig::default();
+        config.enabled_modules.geo_fence = false;
+        config.update_interval = 1;
+        config.shutdown_grace_period = 2;
+
+        let mut agent = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .build()
+            .await
+            .unwrap();
+
+        // Keep the test offline
+        for source in agent.threat_intel_aggregator.get_sources_config() {
+            agent.threat_intel_aggregator.set_source_enabled(&source.name, false).unwrap();
+        }
+        agent.start().await.unwrap();
+
+        let threat_sender = agent.monitor.get_threat_sender();
+        for i in 0..5 {
+            let evidence = ThreatEvidence {
+                id: format!("test-drain-{}", i),
+                timestamp: 1_700_000_000,
+                source_ip: "203.0.113.60".to_string(),
+                target_ip: "10.0.0.1".to_string(),
+                threat_type: crate::ThreatType::Malware,
+                threat_level: ThreatLevel::Critical,
+                context: "Test threat".to_string(),
+                evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"test-drain"),
+                geolocation: "unknown".to_string(),
+                network_flow: "TCP".to_string(),
+                agent_id: String::new(),
+                reputation: 1.0,
+                compliance_tag: String::new(),
+                region: String::new(),
+                expires_at: None,
+                source_hostname: None,
+                source_owner: None,
+                campaign_id: None,
+                source_network: None,
+                signer_key: None,
+                signature: None,
+            };
+            threat_sender.send(evidence).unwrap();
+        }
+
+        agent.stop().await.unwrap();
+        assert_eq!(agent.reputation_metrics.published_evidence(), 5);
+
+        // Nothing new is accepted once draining has started
+        assert!(threat_sender.send(ThreatEvidence {
+            id: "test-drain-late".to_string(),
+            timestamp: 1_700_000_000,
+            source_ip: "203.0.113.61".to_string(),
+            target_ip: "10.0.0.1".to_string(),
+            threat_type: crate::ThreatType::Malware,
+            threat_level: ThreatLevel::Critical,
+            context: "Test threat".to_string(),
+            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"test-drain-late"),
+            geolocation: "unknown".to_string(),
+            network_flow: "TCP".to_string(),
+            agent_id: String::new(),
+            reputation: 1.0,
+            compliance_tag: String::new(),
+            region: String::new(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        }).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_peer_verification_reaches_requester() {
+        let mut config = AgentConfig::default();
+        config.p2p_config.bootstrap_nodes = Vec::new();
+        config.p2p_config.listen_port = 0;
+
+        let mut requester_client = P2pClient::new(config.clone()).unwrap();
+        requester_client.connect_bootstrap().await.unwrap();
+        requester_client.subscribe_threat_intel().unwrap();
+        let addr = requester_client.listen_addrs()
+            .into_iter()
+            .find(|addr| addr.to_string().starts_with("/ip4/127.0.0.1/"))
+            .expect("listening on loopback");
+
+        let mut verifier_config = config.clone();
+        verifier_config.p2p_config.bootstrap_nodes = vec![format!("{}/p2p/{}", addr, requester_client.peer_id)];
+        let verifier_key = libp2p::identity::Keypair::generate_ed25519();
+        let mut verifier_client = P2pClient::with_keypair(verifier_config, verifier_key.clone()).unwrap();
+        verifier_client.connect_bootstrap().await.unwrap();
+        verifier_client.subscribe_threat_intel().unwrap();
+
+        let (shutdown, _) = watch::channel(false);
+        let (request_sender, requests) = mpsc::unbounded_channel();
+        let requester = Arc::new(ConsensusEngine::new(ConsensusConfig { min_verifiers: 1, ..ConsensusConfig::default() }, "requester".to_string())
+            .with_request_broadcast(request_sender));
+        tokio::spawn(exchange_verifications(
+            requester.clone(),
+            ComplianceEngine::new(&config),
+            test_signer(),
+            watch::channel(config.clone()).1,
+            requester_client.take_verification_channel().unwrap(),
+            requests,
+            shutdown.subscribe(),
+        ));
+        let (_verifier_requests, requests) = mpsc::unbounded_channel();
+        // Responses are only accepted when signed by the peer that sends them
+        let verifier = Arc::new(ConsensusEngine::new(ConsensusConfig::default(), "verifier".to_string())
+            .with_signer(Box::new(crate::crypto::Ed25519Signer::new(verifier_key))));
+        tokio::spawn(exchange_verifications(
+            verifier,
+            ComplianceEngine::new(&config),
+            test_signer(),
+            watch::channel(config.clone()).1,
+            verifier_client.take_verification_channel().unwrap(),
+            requests,
+            shutdown.subscribe(),
+        ));
+
+        let evidence = ThreatEvidence {
+            id: "peer-verified".to_string(),
+            timestamp: 1_700_000_000,
+            source_ip: "203.0.113.5".to_string(),
+            target_ip: "10.0.0.1".to_string(),
+            threat_type: crate::ThreatType::Malware,
+            threat_level: ThreatLevel::Critical,
+            context: "Beacon to c2.evil.example".to_string(),
+            evidence_hash: "hash".to_string(),
+            geolocation: "unknown".to_string(),
+            network_flow: "TCP".to_string(),
+            agent_id: "requester".to_string(),
+            reputation: 0.9,
+            compliance_tag: "global".to_string(),
+            region: "test-region".to_string(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        };
+
+        // Requests are lost until the subscriptions have propagated, so keep asking
+        let result = tokio::time::timeout(Duration::from_secs(20), async {
+            loop {
+                let request = requester.submit_for_verification(evidence.clone()).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                if let Ok(result) = requester.check_consensus(&request.request_id).await {
+                    return result;
                 }
-                return result;
             }
+        })
+        .await
+        .expect("peer response collected");
+
+        assert_eq!(result.evidence_id, "peer-verified");
+        assert_eq!(result.total_verifiers, 1);
+        assert!(result.verified_by.iter().chain(&result.disputed_by).all(|agent| agent == "verifier"));
+        shutdown.send_replace(true);
+    }
+
+    #[tokio::test]
+    async fn test_verification_requests_are_anonymized_before_broadcast() {
+        let config = AgentConfig::default();
+        assert!(config.internal_full_ip);
+
+        let (shutdown, _) = watch::channel(false);
+        let (request_sender, requests) = mpsc::unbounded_channel();
+        let engine = Arc::new(ConsensusEngine::new(ConsensusConfig::default(), "requester".to_string())
+            .with_request_broadcast(request_sender));
+        let (_incoming_sender, incoming) = mpsc::unbounded_channel();
+        let (outgoing, mut sent) = mpsc::unbounded_channel();
+        tokio::spawn(exchange_verifications(
+            engine.clone(),
+            ComplianceEngine::new(&config),
+            test_signer(),
+            watch::channel(config.clone()).1,
+            VerificationChannel { incoming, outgoing },
+            requests,
+            shutdown.subscribe(),
+        ));
+
+        let evidence = ThreatEvidence::builder("203.0.113.5", crate::ThreatType::Malware, ThreatLevel::Critical)
+            .target_ip("10.0.0.1")
+            .build();
+        let request = engine.submit_for_verification(evidence).await.unwrap();
+        assert_eq!(request.evidence.source_ip, "203.0.113.5");
+
+        let Some(VerificationMessage::Request(broadcast)) = sent.recv().await else {
+            panic!("request broadcast");
+        };
+        assert_eq!(broadcast.request_id, request.request_id);
+        assert_eq!(broadcast.evidence.source_ip, "203.0.0.0");
+        assert_eq!(broadcast.evidence.target_ip, "10.0.0.0");
+        shutdown.send_replace(true);
+    }
+
+    #[tokio::test]
+    async fn test_stop_completes_background_tasks() {
+        let blocklist_file = std::env::temp_dir().join(format!("orasrs-shutdown-{}.txt", uuid::Uuid::new_v4()));
+        let mut config = AgentConfig::default();
+        config.update_interval = 3600;
+        config.shutdown_grace_period = 5;
+        config.blocklist_export_enabled = true;
+        config.blocklist_file = Some(blocklist_file.to_string_lossy().to_string());
+        config.blocklist_min_threat_level = Some(ThreatLevel::Warning);
+
+        let mut agent = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .build()
+            .await
+            .unwrap();
+
+        // Keep the test offline
+        for source in agent.threat_intel_aggregator.get_sources_config() {
+            agent.threat_intel_aggregator.set_source_enabled(&source.name, false).unwrap();
         }
+        agent.start().await.unwrap();
+        assert!(agent.p2p_client.is_connected());
 
-        // For IPv6 or malformed IPs, return a placeholder
-        "0.0.0.0".to_string()
+        // Forwarder, monitor, reporter, exporter, intel aggregation and status loop
+        let report = agent.stop().await.unwrap();
+        assert_eq!(report, ShutdownReport { completed: 6, aborted: 0 });
+        assert!(agent.tasks.is_empty());
+        assert!(!agent.p2p_client.is_connected());
+
+        let _ = std::fs::remove_file(&blocklist_file);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_status_uptime_tracks_elapsed_time() {
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let agent = OrasrsAgent::builder(AgentConfig::default())
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .with_clock(clock.clone())
+            .defer_connection()
+            .build()
+            .await
+            .unwrap();
+
+        // Each status tick reports time since start rather than a delta of the last value
+        let mut status = agent.status.clone();
+        clock.advance(30);
+        refresh_status(&mut status, clock.as_ref(), agent.started_at, &agent.reputation_metrics);
+        let first = status.uptime;
+        clock.advance(45);
+        refresh_status(&mut status, clock.as_ref(), agent.started_at, &agent.reputation_metrics);
+
+        assert_eq!(first, 30);
+        assert_eq!(status.uptime, 75);
+        assert_eq!(agent.get_status().uptime, 75);
+
+        // A clock stepping backwards does not underflow
+        clock.set(1_600_000_000);
+        assert_eq!(agent.get_status().uptime, 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_status_loop_samples_resource_usage() {
+        let mut agent = OrasrsAgent::builder(AgentConfig::default())
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .build()
+            .await
+            .unwrap();
+        for source in agent.threat_intel_aggregator.get_sources_config() {
+            agent.threat_intel_aggregator.set_source_enabled(&source.name, false).unwrap();
+        }
+        assert_eq!(agent.get_status().memory_usage, 0);
+
+        // The status interval ticks once on start
+        agent.start().await.unwrap();
+        let sampled = tokio::time::timeout(Duration::from_secs(5), async {
+            while agent.get_status().memory_usage == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        agent.stop().await.unwrap();
+
+        assert!(sampled.is_ok());
+        let usage = ResourceUsage { memory: agent.get_status().memory_usage, ..ResourceUsage::default() };
+        assert!(usage.memory > 0);
+        assert_eq!(exceeded_limits(&usage, &AgentConfig { max_memory: usage.memory, ..AgentConfig::default() }), Vec::<&str>::new());
+        assert_eq!(exceeded_limits(&usage, &AgentConfig { max_memory: usage.memory - 1, ..AgentConfig::default() }), vec!["memory"]);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_exports_agent_metrics() {
+        let config = AgentConfig { metrics_enabled: true, metrics_port: 0, ..AgentConfig::default() };
+        let mut agent = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .build()
+            .await
+            .unwrap();
+
+        // Keep the test offline
+        for source in agent.threat_intel_aggregator.get_sources_config() {
+            agent.threat_intel_aggregator.set_source_enabled(&source.name, false).unwrap();
+        }
+        agent.start().await.unwrap();
+        agent.update_threat_count();
+
+        let addr = agent.metrics_addr().expect("metrics server should be listening");
+        assert!(addr.ip().is_loopback());
+        let response = reqwest::get(format!("http://127.0.0.1:{}/metrics", addr.port())).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = response.text().await.unwrap();
+
+        for name in [
+            "orasrs_threats_total",
+            "orasrs_reputation",
+            "orasrs_p2p_connected",
+            "orasrs_upstream_threats_fetched_total",
+            "orasrs_consensus_requests",
+            "orasrs_memory_usage_bytes",
+            "orasrs_cpu_usage_percent",
+        ] {
+            assert!(body.contains(&format!("# TYPE {} ", name)), "missing {} in:\n{}", name, body);
+        }
+        assert!(body.contains("orasrs_threats_total 1\n"), "{}", body);
+
+        let missing = reqwest::get(format!("http://127.0.0.1:{}/other", addr.port())).await.unwrap();
+        assert_eq!(missing.status(), 404);
+
+        agent.stop().await.unwrap();
+    }
+
+    async fn start_control_agent(connect: bool) -> OrasrsAgent {
+        let config = AgentConfig { control_enabled: true, control_port: 0, ..AgentConfig::default() };
+        let mut builder = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .with_clock(Arc::new(MockClock::new(1_700_000_000)));
+        if !connect {
+            builder = builder.defer_connection();
+        }
+        let mut agent = builder.build().await.unwrap();
+
+        // Keep the test offline
+        for source in agent.threat_intel_aggregator.get_sources_config() {
+            agent.threat_intel_aggregator.set_source_enabled(&source.name, false).unwrap();
+        }
+        agent.start().await.unwrap();
+        agent
+    }
+
+    #[tokio::test]
+    async fn test_control_endpoints_report_live_agent() {
+        let mut agent = start_control_agent(true).await;
+        agent.update_threat_count();
+        let url = format!("http://{}", agent.control_addr().expect("control server should be listening"));
+
+        let health = reqwest::get(format!("{}/health", url)).await.unwrap();
+        assert_eq!(health.status(), 200);
+
+        let response = reqwest::get(format!("{}/status", url)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::to_value(agent.get_status()).unwrap());
+        assert_eq!(body["threat_count"], 1);
+
+        let report = agent.stop().await.unwrap();
+        assert_eq!(report.aborted, 0);
+        assert!(reqwest::get(format!("{}/health", url)).await.is_err(), "server should stop with the agent");
+    }
+
+    #[tokio::test]
+    async fn test_health_is_unavailable_without_p2p() {
+        let mut agent = start_control_agent(false).await;
+        let url = format!("http://{}", agent.control_addr().unwrap());
+
+        let health = reqwest::get(format!("{}/health", url)).await.unwrap();
+        assert_eq!(health.status(), 503);
+
+        agent.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_allowlisted_evidence_is_not_published() {
+        let config = AgentConfig {
+            allowlist: vec!["192.0.2.0/24".to_string()],
+            blocklist_export_enabled: true,
+            ..AgentConfig::default()
+        };
+        let p2p_client = MockP2pClient::new(Arc::new(AtomicUsize::new(0)));
+        let published = p2p_client.published.clone();
+        let mut agent = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(p2p_client))
+            .build()
+            .await
+            .unwrap();
+        let mut blocklist_receiver = agent.blocklist_receiver.take().unwrap();
+
+        let evidence = |id: &str, source_ip: &str| ThreatEvidence {
+            id: id.to_string(),
+            timestamp: 1_700_000_000,
+            source_ip: source_ip.to_string(),
+            target_ip: "10.0.0.12".to_string(),
+            threat_type: crate::ThreatType::BruteForce,
+            threat_level: ThreatLevel::Critical,
+            context: "Test threat".to_string(),
+            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(id.as_bytes()),
+            geolocation: "unknown".to_string(),
+            network_flow: "TCP".to_string(),
+            agent_id: String::new(),
+            reputation: 1.0,
+            compliance_tag: String::new(),
+            region: String::new(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        };
+        agent.submit_threat_evidence(evidence("resolver", "192.0.2.53")).await.unwrap();
+        agent.submit_threat_evidence(evidence("scanner", "203.0.113.9")).await.unwrap();
+
+        let published: Vec<String> = published.lock().unwrap().iter().map(|e| e.id.clone()).collect();
+        assert_eq!(published, vec!["scanner"]);
+        assert_eq!(blocklist_receiver.recv().await.unwrap().id, "scanner");
+        assert!(blocklist_receiver.try_recv().is_err());
+        assert_eq!(agent.get_status().threat_count, 1);
+
+        // A config update reaches every holder of the allowlist, the reporter and exporter included
+        let shared_allowlist = agent.allowlist.clone();
+        agent.update_config(AgentConfig { allowlist: vec!["203.0.113.0/24".to_string()], ..agent.config.clone() }).unwrap();
+        assert!(shared_allowlist.read().unwrap().contains("203.0.113.9"));
+        agent.submit_threat_evidence(evidence("rescan", "203.0.113.9")).await.unwrap();
+        assert!(blocklist_receiver.try_recv().is_err());
+        assert_eq!(agent.get_status().threat_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_bootstrap_nodes_fail_agent_creation() {
+        let mut config = AgentConfig::default();
+        config.p2p_config.bootstrap_nodes = vec![
+            "/ip4/127.0.0.1/tcp/1".to_string(),
+            "/ip4/127.0.0.1/tcp/2".to_string(),
+            "not-a-multiaddr".to_string(),
+        ];
+        let mut p2p_config = config.clone();
+        p2p_config.p2p_config.listen_port = 0;
+
+        let result = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(P2pClient::new(p2p_config).unwrap()))
+            .build()
+            .await;
+        assert!(matches!(result, Err(AgentError::BootstrapFailed { tried: 2 })));
+    }
+
+    #[tokio::test]
+    async fn test_submit_with_result_returns_consensus() {
+        let mut agent = OrasrsAgent::builder(AgentConfig::default())
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .build()
+            .await
+            .unwrap();
+        let evidence = || ThreatEvidence::builder("192.168.1.100", crate::ThreatType::Malware, ThreatLevel::Critical).build();
+
+        // Without upstream threats nothing is correlated
+        let (enhanced, consensus) = agent.submit_threat_evidence_with_result(evidence()).await.unwrap();
+        assert!(enhanced.context.contains("[CREDIBILITY:"));
+        assert!(consensus.is_none());
+
+        // Upstream knows the source IP; with no peers answering, verification expires at once
+        agent.threat_intel_aggregator = ThreatIntelAggregator::new().with_simulated_feeds();
+        agent.threat_intel_aggregator.set_source_enabled("CISA_AIS", true).unwrap();
+        agent.threat_intel_aggregator.fetch_all_sources().await.unwrap();
+        let consensus_config = ConsensusConfig { verification_timeout: 0, ..ConsensusConfig::default() };
+        agent.consensus_engine = Arc::new(ConsensusEngine::new(consensus_config, agent.config.agent_id.clone()));
+
+        let (enhanced, consensus) = agent.submit_threat_evidence_with_result(evidence()).await.unwrap();
+        assert!(enhanced.context.contains("[CREDIBILITY:"));
+        assert_eq!(enhanced.source_ip, "192.168.1.100");
+        assert_eq!(consensus.unwrap().status, crate::consensus_verification::VerificationStatus::Expired);
+    }
+
+    #[tokio::test]
+    async fn test_batch_submit_correlates_against_one_snapshot() {
+        let p2p_client = MockP2pClient::new(Arc::new(AtomicUsize::new(0)));
+        let published = p2p_client.published.clone();
+        let mut agent = OrasrsAgent::builder(AgentConfig::default())
+            .with_p2p_client(Box::new(p2p_client))
+            .build()
+            .await
+            .unwrap();
+
+        // Count verification requests, expiring them at once since no peers answer
+        agent.threat_intel_aggregator = ThreatIntelAggregator::new().with_simulated_feeds();
+        agent.threat_intel_aggregator.set_source_enabled("CISA_AIS", true).unwrap();
+        agent.threat_intel_aggregator.fetch_all_sources().await.unwrap();
+        let (request_sender, mut requests) = mpsc::unbounded_channel();
+        let consensus_config = ConsensusConfig { verification_timeout: 0, ..ConsensusConfig::default() };
+        agent.consensus_engine = Arc::new(ConsensusEngine::new(consensus_config, agent.config.agent_id.clone())
+            .with_request_broadcast(request_sender));
+
+        let fetched = agent.threat_intel_aggregator.threats_fetched();
+        let evidences = ["192.168.1.100", "203.0.113.7", "198.51.100.9"].into_iter()
+            .map(|ip| ThreatEvidence::builder(ip, crate::ThreatType::Malware, ThreatLevel::Critical).build())
+            .collect();
+        let enhanced = agent.submit_threat_evidence_batch(evidences).await.unwrap();
+        
+        // The batch reads the snapshot of the one earlier fetch without contacting upstream
+        assert_eq!(agent.threat_intel_aggregator.threats_fetched(), fetched);
+
+        assert_eq!(enhanced.len(), 3);
+        assert!(enhanced.iter().all(|evidence| evidence.context.contains("[CREDIBILITY:")));
+        assert_eq!(published.lock().unwrap().len(), 3);
+        assert_eq!(agent.get_status().threat_count, 3);
+
+        // Only the correlated pair is verified; uncorrelated upstream threats are not resubmitted
+        let mut submitted = 0;
+        while requests.try_recv().is_ok() {
+            submitted += 1;
+        }
+        assert_eq!(submitted, 1);
+    }
+
+    #[tokio::test]
+    async fn test_enhancement_reads_one_snapshot_within_ttl() {
+        let mut agent = OrasrsAgent::builder(AgentConfig::default())
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .build()
+            .await
+            .unwrap();
+        agent.threat_intel_aggregator = ThreatIntelAggregator::new().with_simulated_feeds().with_snapshot_cache(30);
+        agent.threat_intel_aggregator.set_source_enabled("CISA_AIS", true).unwrap();
+        agent.threat_intel_aggregator.fetch_all_sources().await.unwrap();
+        let snapshot = agent.threat_intel_aggregator.known_threats();
+
+        // Correlating with upstream leaves consensus undecided without failing the submission
+        for _ in 0..2 {
+            let evidence = ThreatEvidence::builder("192.168.1.100", crate::ThreatType::Malware, ThreatLevel::Critical).build();
+            let (enhanced, consensus) = agent.submit_threat_evidence_with_result(evidence).await.unwrap();
+            assert!(enhanced.context.contains("[CREDIBILITY:"));
+            assert!(consensus.is_none());
+        }
+
+        // Both enhancements used the same snapshot and verified only their correlated pair
+        assert!(Arc::ptr_eq(&snapshot, &agent.threat_intel_aggregator.known_threats()));
+        assert_eq!(agent.consensus_engine.in_flight_requests().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_publish_failure_still_acts_on_every_item() {
+        let mut p2p_client = MockP2pClient::new(Arc::new(AtomicUsize::new(0)));
+        let published = p2p_client.published.clone();
+        p2p_client.failing_publishes.store(1, Ordering::SeqCst);
+        p2p_client.connected = true;
+        let agent = OrasrsAgent::builder(AgentConfig::default())
+            .with_p2p_client(Box::new(p2p_client))
+            .build()
+            .await
+            .unwrap();
+
+        let evidences = ["203.0.113.7", "198.51.100.9", "192.0.2.44"].into_iter()
+            .map(|ip| ThreatEvidence::builder(ip, crate::ThreatType::Malware, ThreatLevel::Critical).build())
+            .collect();
+        assert!(agent.submit_threat_evidence_batch(evidences).await.is_err());
+
+        // The first publish failed, yet the items after it were still published
+        assert_eq!(published.lock().unwrap().len(), 2);
+        assert_eq!(agent.get_status().threat_count, 2);
+    }
+
+    #[cfg(feature = "geoip")]
+    #[tokio::test]
+    async fn test_geoip_database_reaches_geo_fence() {
+        let config = AgentConfig {
+            geoip_database: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/geoip-test.mmdb").to_string()),
+            ..AgentConfig::default()
+        };
+        let agent = OrasrsAgent::builder(config)
+            .with_p2p_client(Box::new(MockP2pClient::new(Arc::new(AtomicUsize::new(0)))))
+            .build()
+            .await
+            .unwrap();
+        assert!(agent.monitor.geo_fence.has_database());
+        assert_eq!(agent.monitor.geo_fence.lookup("81.2.69.142").map(|(country, _)| country), Some("GB".to_string()));
+    }
+}
@@ -0,0 +1,88 @@
+//! Addresses that must never be blocked or reported as threats
+
+use crate::error::{AgentError, Result};
+use ipnetwork::IpNetwork;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+/// Allowlist shared by the components consulting it, replaced on config updates
+pub type SharedAllowlist = Arc<RwLock<Allowlist>>;
+
+/// Set of allowlisted IPs and CIDR ranges
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Allowlist {
+    networks: Vec<IpNetwork>, // Single IPs are stored as /32 or /128 networks
+}
+
+impl Allowlist {
+    /// Parse a list of IPs and CIDR ranges
+    pub fn parse(entries: &[String]) -> Result<Self> {
+        let networks = entries
+            .iter()
+            .map(|entry| {
+                entry.trim().parse::<IpNetwork>().map_err(|e| {
+                    AgentError::ConfigError(format!("allowlist entry {:?} is not an IP or CIDR range: {}", entry, e))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { networks })
+    }
+
+    /// Whether an IP is allowlisted; values that are not IPs never are
+    pub fn contains(&self, ip: &str) -> bool {
+        ip.parse::<IpAddr>()
+            .map(|ip| self.networks.iter().any(|network| network.contains(ip)))
+            .unwrap_or(false)
+    }
+
+    /// Whether any allowlisted address falls inside `network`
+    pub fn overlaps(&self, network: IpNetwork) -> bool {
+        self.networks
+            .iter()
+            .any(|allowed| allowed.contains(network.network()) || network.contains(allowed.network()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist(entries: &[&str]) -> Allowlist {
+        Allowlist::parse(&entries.iter().map(|entry| entry.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_matches_ips_and_ranges() {
+        let allowlist = allowlist(&["192.0.2.53", "198.51.100.0/24", "2001:db8::/32"]);
+
+        assert!(allowlist.contains("192.0.2.53"));
+        assert!(!allowlist.contains("192.0.2.54"));
+        assert!(allowlist.contains("198.51.100.7"));
+        assert!(!allowlist.contains("198.51.101.7"));
+        assert!(allowlist.contains("2001:db8::1"));
+        assert!(!allowlist.contains("2001:db9::1"));
+        assert!(!allowlist.contains("local"));
+    }
+
+    #[test]
+    fn test_overlapping_ranges() {
+        let allowlist = allowlist(&["192.0.2.53", "10.0.0.0/8"]);
+
+        assert!(allowlist.overlaps("192.0.2.0/24".parse().unwrap()));
+        assert!(allowlist.overlaps("10.1.2.0/24".parse().unwrap()));
+        assert!(!allowlist.overlaps("192.0.3.0/24".parse().unwrap()));
+        assert!(!allowlist.overlaps("2001:db8::/32".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_rejects_invalid_entries() {
+        for entry in ["resolver.example", "10.0.0.0/33", ""] {
+            let result = Allowlist::parse(&[entry.to_string()]);
+            assert!(matches!(result, Err(AgentError::ConfigError(_))), "{:?}", entry);
+        }
+    }
+}
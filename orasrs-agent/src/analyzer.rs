@@ -1,82 +1,176 @@
-use crate::{ThreatEvidence, ThreatType, ThreatLevel, error::{AgentError, Result}};
+use crate::{ThreatEvidence, ThreatType, ThreatLevel, threat_intel::IndicatorStore, error::{AgentError, Result}};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Threat detection engine
 pub struct ThreatDetector {
-    /// Behavior baseline for anomaly detection
-    behavior_baseline: HashMap<String, f64>,
+    /// Per-entity metric history for anomaly detection
+    behavior_analyzer: BehaviorAnalyzer,
     
     /// Known threat indicators
     threat_indicators: Vec<String>,
     
     /// Detection rules
     detection_rules: Vec<DetectionRule>,
+    
+    /// Local IoC database
+    indicator_store: Arc<IndicatorStore>,
+    
+    /// SYNs per second from one source that count as a flood
+    syn_rate_threshold: f64,
+    
+    /// Recent SYNs per source IP
+    syn_rates: RateTracker,
+    
+    /// Sources currently above the threshold, so a flood is reported once
+    flooding_sources: HashSet<String>,
+    
+    /// YARA rules scanning payloads
+    #[cfg(feature = "yara")]
+    yara: Option<Arc<YaraMatcher>>,
 }
 
 impl ThreatDetector {
+    /// Detector applying the bundled default rules
     pub fn new() -> Self {
+        Self::with_rules(parse_rules(DEFAULT_RULES).expect("bundled detection rules are valid"))
+    }
+
+    /// Detector applying the rules of a TOML rules file instead of the defaults
+    pub fn from_rules_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())?;
+        let rules = parse_rules(&content).map_err(|e| {
+            AgentError::ConfigError(format!("Invalid detection rules file {}: {}", path.as_ref().display(), e))
+        })?;
+        Ok(Self::with_rules(rules))
+    }
+
+    fn with_rules(detection_rules: Vec<DetectionRule>) -> Self {
         Self {
-            behavior_baseline: HashMap::new(),
+            behavior_analyzer: BehaviorAnalyzer::new(DEFAULT_ANOMALY_Z_SCORE),
             threat_indicators: vec![
                 "suspicious_user_agent".to_string(),
                 "abnormal_request_pattern".to_string(),
                 "known_malicious_ip".to_string(),
             ],
-            detection_rules: vec![
-                DetectionRule {
-                    name: "ddos_protection".to_string(),
-                    condition: "request_rate > 100/sec".to_string(),
-                    threat_type: ThreatType::DDoS,
-                    threat_level: ThreatLevel::Critical,
-                },
-                DetectionRule {
-                    name: "malware_detection".to_string(),
-                    condition: "file_hash_in_ioc".to_string(),
-                    threat_type: ThreatType::Malware,
-                    threat_level: ThreatLevel::Critical,
-                },
-                DetectionRule {
-                    name: "suspicious_connection".to_string(),
-                    condition: "connection_to_known_bad_ip".to_string(),
-                    threat_type: ThreatType::SuspiciousConnection,
-                    threat_level: ThreatLevel::Warning,
-                },
-            ],
+            detection_rules,
+            indicator_store: Arc::new(IndicatorStore::new()),
+            syn_rate_threshold: DEFAULT_SYN_RATE_THRESHOLD,
+            syn_rates: RateTracker::new(SYN_RATE_WINDOW_SECS),
+            flooding_sources: HashSet::new(),
+            #[cfg(feature = "yara")]
+            yara: None,
+        }
+    }
+
+    /// Use a shared local IoC store for indicator matching
+    pub fn with_indicator_store(mut self, indicator_store: Arc<IndicatorStore>) -> Self {
+        self.indicator_store = indicator_store;
+        self
+    }
+
+    /// Scan payloads with YARA rules
+    #[cfg(feature = "yara")]
+    pub fn with_yara_matcher(mut self, matcher: Arc<YaraMatcher>) -> Self {
+        self.yara = Some(matcher);
+        self
+    }
+
+    /// Scan a payload sent from `source_ip` to `target_ip`, returning evidence per matching YARA rule
+    #[cfg(feature = "yara")]
+    pub fn detect_threats_from_payload(&mut self, source_ip: &str, target_ip: &str, payload: &[u8]) -> Vec<ThreatEvidence> {
+        let Some(matcher) = &self.yara else {
+            return Vec::new();
+        };
+        let matched = match matcher.scan(payload) {
+            Ok(matched) => matched,
+            Err(e) => {
+                log::warn!("YARA scan of payload from {} failed: {}", source_ip, e);
+                return Vec::new();
+            }
+        };
+        
+        matched
+            .into_iter()
+            .map(|rule| {
+                ThreatEvidence::builder(source_ip, ThreatType::Malware, ThreatLevel::Critical)
+                    .target_ip(target_ip)
+                    .context(format!("Matched YARA rule: {}", rule))
+                    .network_flow(format!("payload {} -> {} ({} bytes)", source_ip, target_ip, payload.len()))
+                    .hash_of(payload)
+                    .build()
+            })
+            .collect()
+    }
+
+    /// Payload scanning requires the yara feature; nothing is detected without it
+    #[cfg(not(feature = "yara"))]
+    pub fn detect_threats_from_payload(&mut self, _source_ip: &str, _target_ip: &str, _payload: &[u8]) -> Vec<ThreatEvidence> {
+        Vec::new()
+    }
+
+    /// Report a SYN flood once a source exceeds `syn_rate` SYNs per second
+    pub fn with_ddos_threshold(mut self, syn_rate: f64) -> Self {
+        self.syn_rate_threshold = syn_rate;
+        self
+    }
+
+    /// Count `count` SYNs from `source_ip` to `target_ip` observed at `observed_at`
+    ///
+    /// Returns DDoS evidence when the source's rate over the last
+    /// `SYN_RATE_WINDOW_SECS` crosses the threshold; a source is reported
+    /// again only after its rate has dropped back below it.
+    pub fn observe_syns(&mut self, source_ip: &str, target_ip: &str, count: u64, observed_at: Instant) -> Option<ThreatEvidence> {
+        let rate = self.syn_rates.record(source_ip, count, observed_at);
+        if rate <= self.syn_rate_threshold {
+            self.flooding_sources.remove(source_ip);
+            return None;
         }
+        if !self.flooding_sources.insert(source_ip.to_string()) {
+            return None;
+        }
+        
+        Some(
+            ThreatEvidence::builder(source_ip, ThreatType::DDoS, ThreatLevel::Critical)
+                .target_ip(target_ip)
+                .context(format!(
+                    "Possible SYN flood: {:.1} SYNs/sec over {}s exceeds {:.1}/sec",
+                    rate, SYN_RATE_WINDOW_SECS, self.syn_rate_threshold
+                ))
+                .network_flow(format!("SYN {} -> {}", source_ip, target_ip))
+                .build(),
+        )
+    }
+
+    /// Forget SYN rates of sources quiet for a whole window before `now`
+    pub fn prune_syn_rates(&mut self, now: Instant) {
+        self.syn_rates.prune(now);
+        let syn_rates = &self.syn_rates;
+        self.flooding_sources.retain(|source| syn_rates.events.contains_key(source));
+    }
+
+    /// Evidence for each detection rule matching `data`
+    fn apply_rules(&self, data: &str, source_ip: &str) -> Vec<ThreatEvidence> {
+        self.detection_rules
+            .iter()
+            .filter(|rule| rule.matches(data))
+            .map(|rule| {
+                ThreatEvidence::builder(source_ip, rule.threat_type.clone(), rule.threat_level)
+                    .context(format!("Triggered rule: {}", rule.name))
+                    .network_flow(data)
+                    .build()
+            })
+            .collect()
     }
 
     /// Detect threats from network flow data
     pub fn detect_threats_from_flow(&mut self, flow_data: &str) -> Vec<ThreatEvidence> {
-        let mut detected_threats = Vec::new();
-        
         // Apply detection rules
-        for rule in &self.detection_rules {
-            if self.evaluate_rule(rule, flow_data) {
-                let threat = ThreatEvidence {
-                    id: uuid::Uuid::new_v4().to_string(),
-                    timestamp: SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs() as i64,
-                    source_ip: "unknown".to_string(), // Would be extracted from flow_data
-                    target_ip: "local".to_string(),
-                    threat_type: rule.threat_type.clone(),
-                    threat_level: rule.threat_level,
-                    context: format!("Triggered rule: {}", rule.name),
-                    evidence_hash: crate::crypto::CryptoProvider::blake3_hash(flow_data.as_bytes()),
-                    geolocation: "unknown".to_string(),
-                    network_flow: flow_data.to_string(),
-                    agent_id: "agent".to_string(), // Will be set by agent
-                    reputation: 1.0, // Will be set by agent
-                    compliance_tag: "global".to_string(), // Will be set by agent
-                    region: "unknown".to_string(),
-                };
-                
-                detected_threats.push(threat);
-            }
-        }
+        let mut detected_threats = self.apply_rules(flow_data, "unknown"); // Source would be extracted from flow_data
         
         // Check against known threat indicators
         for indicator in &self.threat_indicators {
@@ -99,42 +193,46 @@ impl ThreatDetector {
                     reputation: 1.0, // Will be set by agent
                     compliance_tag: "global".to_string(), // Will be set by agent
                     region: "unknown".to_string(),
+                    expires_at: None,
+                    source_hostname: None,
+                    source_owner: None,
+                    campaign_id: None,
+                    source_network: None,
+                    signer_key: None,
+                    signature: None,
                 };
                 
                 detected_threats.push(threat);
             }
         }
         
-        detected_threats
-    }
-
-    /// Detect anomalies in behavior
-    pub fn detect_behavior_anomalies(&mut self, behavior_data: &str) -> Vec<ThreatEvidence> {
-        let mut detected_threats = Vec::new();
-        
-        // Calculate behavior score
-        let behavior_score = self.calculate_behavior_score(behavior_data);
-        
-        // If score is significantly different from baseline, flag as anomaly
-        if behavior_score > 0.8 {  // Threshold for anomaly detection
+        // Check against the local IoC database
+        for indicator in self.indicator_store.matches_in(flow_data) {
             let threat = ThreatEvidence {
                 id: uuid::Uuid::new_v4().to_string(),
                 timestamp: SystemTime::now()
                     .duration_since(UNIX_EPOCH)
                     .unwrap()
                     .as_secs() as i64,
-                source_ip: "local".to_string(),
+                source_ip: "unknown".to_string(),
                 target_ip: "local".to_string(),
-                threat_type: ThreatType::AnomalousBehavior,
-                threat_level: ThreatLevel::Info,
-                context: format!("Behavior anomaly detected: score={:.2}", behavior_score),
-                evidence_hash: crate::crypto::CryptoProvider::blake3_hash(behavior_data.as_bytes()),
-                geolocation: "local".to_string(),
-                network_flow: behavior_data.to_string(),
+                threat_type: ThreatType::IoCMatch,
+                threat_level: indicator.threat_level,
+                context: format!("Matched local IoC: {} (source: {})", indicator.value, indicator.source),
+                evidence_hash: crate::crypto::CryptoProvider::blake3_hash(flow_data.as_bytes()),
+                geolocation: "unknown".to_string(),
+                network_flow: flow_data.to_string(),
                 agent_id: "agent".to_string(), // Will be set by agent
                 reputation: 1.0, // Will be set by agent
                 compliance_tag: "global".to_string(), // Will be set by agent
-                region: "local".to_string(),
+                region: "unknown".to_string(),
+                expires_at: None,
+                source_hostname: None,
+                source_owner: None,
+                campaign_id: None,
+                source_network: None,
+                signer_key: None,
+                signature: None,
             };
             
             detected_threats.push(threat);
@@ -143,54 +241,267 @@ impl ThreatDetector {
         detected_threats
     }
 
-    /// Evaluate a detection rule against data
-    fn evaluate_rule(&self, rule: &DetectionRule, data: &str) -> bool {
-        // Simple pattern matching for demonstration
-        // In a real implementation, this would be more sophisticated
-        match rule.name.as_str() {
-            "ddos_protection" => data.contains("high_request_rate"),
-            "malware_detection" => data.contains("malicious_hash"),
-            "suspicious_connection" => data.contains("known_bad_ip"),
-            _ => false,
-        }
+    /// Apply detection rules to local behavior data, e.g. command lines
+    pub fn detect_threats_from_behavior(&mut self, behavior_data: &str) -> Vec<ThreatEvidence> {
+        self.apply_rules(behavior_data, "local")
     }
 
-    /// Calculate behavior score based on data
-    fn calculate_behavior_score(&mut self, behavior_data: &str) -> f64 {
-        // Simple scoring for demonstration
-        // In a real implementation, this would use ML models
-        let current_behavior = behavior_data.len() as f64;
-        
-        // Update baseline
-        let key = "default".to_string();
-        let baseline = self.behavior_baseline.entry(key).or_insert_with(|| current_behavior * 0.9);
-        
-        // Calculate deviation from baseline
-        let deviation = (current_behavior - *baseline).abs() / (*baseline + 1.0);
+    /// Detect an anomalous value of an entity's metric, e.g. requests per minute of a process
+    ///
+    /// Values are compared with the entity's earlier history, so nothing is
+    /// flagged until `MIN_BEHAVIOR_HISTORY` values have been seen.
+    pub fn detect_behavior_anomalies(&mut self, entity: &str, metric: f64) -> Vec<ThreatEvidence> {
+        let mut detected_threats = Vec::new();
         
-        // Update baseline with weighted average
-        *baseline = *baseline * 0.9 + current_behavior * 0.1;
+        if let Some(z_score) = self.behavior_analyzer.detect_anomaly(entity, metric) {
+            let threat = ThreatEvidence::builder("local", ThreatType::AnomalousBehavior, ThreatLevel::Info)
+                .context(format!("Behavior anomaly in {}: value={} z-score={:.2}", entity, metric, z_score))
+                .network_flow(format!("{} {}", entity, metric))
+                .location("local")
+                .build();
+            
+            detected_threats.push(threat);
+        }
         
-        deviation
+        detected_threats
     }
 }
 
-/// Detection rule structure
+/// SYNs per second from one source reported as a flood unless configured
+pub const DEFAULT_SYN_RATE_THRESHOLD: f64 = 100.0;
+
+/// Length of the window over which SYN rates are measured, in seconds
+pub const SYN_RATE_WINDOW_SECS: f64 = 10.0;
+
+/// Sliding-window event counter per key
+///
+/// Events are timed with the monotonic clock, so wall-clock steps neither
+/// expire a window early nor keep it open.
 #[derive(Debug, Clone)]
+pub struct RateTracker {
+    window: Duration,
+    
+    /// Recent (time, count) pairs per key
+    events: HashMap<String, VecDeque<(Instant, u64)>>,
+}
+
+impl RateTracker {
+    pub fn new(window_secs: f64) -> Self {
+        Self {
+            window: Duration::from_secs_f64(window_secs.max(1.0)),
+            events: HashMap::new(),
+        }
+    }
+
+    /// Record `count` events for `key` at `at`, returning its events per second over the window
+    pub fn record(&mut self, key: &str, count: u64, at: Instant) -> f64 {
+        let recent = self.events.entry(key.to_string()).or_default();
+        recent.push_back((at, count));
+        while matches!(recent.front(), Some(&(oldest, _)) if at.saturating_duration_since(oldest) >= self.window) {
+            recent.pop_front();
+        }
+        recent.iter().map(|&(_, count)| count).sum::<u64>() as f64 / self.window.as_secs_f64()
+    }
+
+    /// Forget keys without events in the window ending at `now`
+    pub fn prune(&mut self, now: Instant) {
+        let window = self.window;
+        self.events.retain(|_, recent| matches!(recent.back(), Some(&(latest, _)) if now.saturating_duration_since(latest) < window));
+    }
+}
+
+/// Detection rules bundled with the agent
+pub const DEFAULT_RULES: &str = include_str!("../rules/default.toml");
+
+/// How a rule's pattern is matched against data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchType {
+    /// Data contains the pattern as a substring
+    #[default]
+    Contains,
+    /// Data matches the pattern as a regular expression
+    Regex,
+}
+
+/// Detection rule structure
+///
+/// Regex patterns are compiled when the rule is loaded, so an invalid
+/// pattern fails loading instead of never matching.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RuleDefinition")]
 pub struct DetectionRule {
     pub name: String,
-    pub condition: String,
+    pub pattern: String,
+    pub match_type: MatchType,
     pub threat_type: ThreatType,
     pub threat_level: ThreatLevel,
+    regex: Option<Regex>,
+}
+
+impl DetectionRule {
+    pub fn new(
+        name: impl Into<String>,
+        pattern: impl Into<String>,
+        match_type: MatchType,
+        threat_type: ThreatType,
+        threat_level: ThreatLevel,
+    ) -> Result<Self> {
+        let name = name.into();
+        let pattern = pattern.into();
+        let regex = match match_type {
+            MatchType::Contains => None,
+            MatchType::Regex => Some(Regex::new(&pattern).map_err(|e| {
+                AgentError::ConfigError(format!("Detection rule {} has an invalid regex {:?}: {}", name, pattern, e))
+            })?),
+        };
+        Ok(Self { name, pattern, match_type, threat_type, threat_level, regex })
+    }
+
+    /// Whether the rule's pattern matches `data`
+    pub fn matches(&self, data: &str) -> bool {
+        match &self.regex {
+            Some(regex) => regex.is_match(data),
+            None => data.contains(&self.pattern),
+        }
+    }
+}
+
+/// Detection rule as written in a rules file
+#[derive(Deserialize)]
+struct RuleDefinition {
+    name: String,
+    pattern: String,
+    #[serde(default)]
+    match_type: MatchType,
+    threat_type: ThreatType,
+    threat_level: ThreatLevel,
+}
+
+impl TryFrom<RuleDefinition> for DetectionRule {
+    type Error = AgentError;
+
+    fn try_from(rule: RuleDefinition) -> Result<Self> {
+        Self::new(rule.name, rule.pattern, rule.match_type, rule.threat_type, rule.threat_level)
+    }
+}
+
+#[derive(Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<DetectionRule>,
+}
+
+/// Parse detection rules from TOML `[[rules]]` tables
+pub fn parse_rules(content: &str) -> Result<Vec<DetectionRule>> {
+    toml::from_str::<RulesFile>(content)
+        .map(|file| file.rules)
+        .map_err(|e| AgentError::ConfigError(e.message().to_string()))
+}
+
+/// Seconds a single YARA scan may take
+#[cfg(feature = "yara")]
+const YARA_SCAN_TIMEOUT_SECS: i32 = 10;
+
+/// Payload scanner using compiled YARA rules (requires the yara feature)
+#[cfg(feature = "yara")]
+pub struct YaraMatcher {
+    rules: yara::Rules,
+}
+
+#[cfg(feature = "yara")]
+impl YaraMatcher {
+    /// Compile rules from YARA source text
+    pub fn from_source(source: &str) -> Result<Self> {
+        let compiler = yara::Compiler::new()
+            .map_err(yara_error)?
+            .add_rules_str(source)
+            .map_err(yara_error)?;
+        Self::compile(compiler)
+    }
+
+    /// Compile a rules file, or every `.yar` and `.yara` file in a directory
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let files = if path.is_dir() {
+            let mut files: Vec<_> = std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| matches!(file.extension().and_then(|ext| ext.to_str()), Some("yar" | "yara")))
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+        if files.is_empty() {
+            return Err(AgentError::ThreatDetectionError(format!("No YARA rules found in {}", path.display())));
+        }
+        
+        let mut compiler = yara::Compiler::new().map_err(yara_error)?;
+        for file in &files {
+            compiler = compiler.add_rules_file(file).map_err(|e| {
+                AgentError::ThreatDetectionError(format!("Failed to compile YARA rules {}: {}", file.display(), e))
+            })?;
+        }
+        Self::compile(compiler)
+    }
+
+    fn compile(compiler: yara::Compiler) -> Result<Self> {
+        Ok(Self { rules: compiler.compile_rules().map_err(yara_error)? })
+    }
+
+    /// Identifiers of the rules matching `data`
+    pub fn scan(&self, data: &[u8]) -> Result<Vec<String>> {
+        let matches = self.rules.scan_mem(data, YARA_SCAN_TIMEOUT_SECS).map_err(yara_error)?;
+        Ok(matches.iter().map(|rule| rule.identifier.to_string()).collect())
+    }
+}
+
+#[cfg(feature = "yara")]
+impl std::fmt::Debug for YaraMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("YaraMatcher").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "yara")]
+fn yara_error(e: impl std::fmt::Display) -> AgentError {
+    AgentError::ThreatDetectionError(format!("YARA error: {}", e))
+}
+
+/// Standard deviations from the mean beyond which a metric is anomalous
+pub const DEFAULT_ANOMALY_Z_SCORE: f64 = 3.0;
+
+/// Values an entity needs before its metrics can be flagged
+pub const MIN_BEHAVIOR_HISTORY: usize = 10;
+
+/// Values kept per entity
+const MAX_BEHAVIOR_HISTORY: usize = 100;
+
+/// Entities tracked before the least recently seen tenth is dropped
+const MAX_BEHAVIOR_ENTITIES: usize = 10_000;
+
+/// Smallest standard deviation used, as a fraction of the mean, so a history
+/// that barely varies does not turn every small change into an anomaly
+const MIN_STD_DEV_FRACTION: f64 = 0.05;
+
+/// Recent values of one entity
+#[derive(Debug, Default)]
+struct EntityHistory {
+    values: VecDeque<f64>,
+    last_seen: u64, // Tick of the latest value
 }
 
 /// Behavior analyzer
 pub struct BehaviorAnalyzer {
     /// Historical behavior data
-    history: HashMap<String, Vec<f64>>,
+    history: HashMap<String, EntityHistory>,
     
     /// Anomaly detection threshold
     threshold: f64,
+
+    /// Count of recorded values, ordering entities by recency
+    tick: u64,
 }
 
 impl BehaviorAnalyzer {
@@ -198,30 +509,462 @@ impl BehaviorAnalyzer {
         Self {
             history: HashMap::new(),
             threshold,
+            tick: 0,
         }
     }
 
     /// Analyze behavior and detect anomalies
     pub fn analyze_behavior(&mut self, entity: &str, metric: f64) -> bool {
-        let history = self.history.entry(entity.to_string()).or_insert_with(Vec::new);
+        self.detect_anomaly(entity, metric).is_some()
+    }
+
+    /// Record a metric, returning its z-score if it is an anomaly
+    ///
+    /// The z-score is taken against the values recorded before this one, with
+    /// the standard deviation floored at a fraction of the mean so it stays finite.
+    pub fn detect_anomaly(&mut self, entity: &str, metric: f64) -> Option<f64> {
+        if !self.history.contains_key(entity) && self.history.len() >= MAX_BEHAVIOR_ENTITIES {
+            self.evict_least_recent();
+        }
+        self.tick += 1;
+        let history = self.history.entry(entity.to_string()).or_default();
+        let z_score = z_score(&history.values, metric);
         
-        // Keep last 100 data points
-        if history.len() >= 100 {
-            history.remove(0);
+        // Keep the most recent data points
+        if history.values.len() >= MAX_BEHAVIOR_HISTORY {
+            history.values.pop_front();
         }
+        history.values.push_back(metric);
+        history.last_seen = self.tick;
         
-        history.push(metric);
+        z_score.filter(|z| z.abs() > self.threshold)
+    }
+
+    /// Drop the least recently seen tenth of the tracked entities
+    fn evict_least_recent(&mut self) {
+        let mut last_seen: Vec<u64> = self.history.values().map(|history| history.last_seen).collect();
+        let index = last_seen.len() / 10;
+        let (_, cutoff, _) = last_seen.select_nth_unstable(index);
+        let cutoff = *cutoff;
+        self.history.retain(|_, history| history.last_seen > cutoff);
+    }
+}
+
+/// Z-score of `metric` against `history`, once it is long enough
+fn z_score(history: &VecDeque<f64>, metric: f64) -> Option<f64> {
+    if history.len() < MIN_BEHAVIOR_HISTORY {
+        return None; // Not enough data points
+    }
+    
+    let mean = history.iter().sum::<f64>() / history.len() as f64;
+    let variance = history.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / history.len() as f64;
+    let std_dev = variance.sqrt().max(mean.abs() * MIN_STD_DEV_FRACTION).max(f64::EPSILON);
+    
+    Some((metric - mean) / std_dev)
+}
+
+/// Shortest subdomain (labels left of the base domain) checked for entropy
+const MIN_TUNNEL_SUBDOMAIN_LENGTH: usize = 24;
+
+/// DNS tunneling detector
+///
+/// Flags queries whose subdomain looks like encoded data and base domains
+/// receiving an abnormal number of queries within a time window.
+pub struct DnsTunnelDetector {
+    /// Shannon entropy (bits per character) above which a subdomain is suspicious
+    entropy_threshold: f64,
+    
+    /// Queries to one base domain within the window that count as abnormal
+    query_threshold: usize,
+    
+    /// Length of the query volume window in seconds
+    window_secs: i64,
+    
+    /// Recent query timestamps per base domain
+    queries: HashMap<String, VecDeque<i64>>,
+}
+
+impl DnsTunnelDetector {
+    pub fn new(entropy_threshold: f64, query_threshold: usize, window_secs: i64) -> Self {
+        Self {
+            entropy_threshold,
+            query_threshold: query_threshold.max(1),
+            window_secs,
+            queries: HashMap::new(),
+        }
+    }
+
+    /// Check a DNS query made by a client, returning evidence for anything suspicious
+    pub fn observe_query(&mut self, client_ip: &str, query: &str, timestamp: i64) -> Vec<ThreatEvidence> {
+        let mut detected_threats = Vec::new();
         
-        // Calculate mean and std dev
-        if history.len() < 10 {
-            return false; // Not enough data points
+        let query = query.trim_end_matches('.').to_lowercase();
+        let labels: Vec<&str> = query.split('.').filter(|label| !label.is_empty()).collect();
+        if labels.len() < 2 {
+            return detected_threats;
         }
         
-        let mean = history.iter().sum::<f64>() / history.len() as f64;
-        let variance = history.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / history.len() as f64;
-        let std_dev = variance.sqrt();
+        // Naive base domain: the last two labels
+        let base_domain = labels[labels.len() - 2..].join(".");
+        let subdomain: String = labels[..labels.len() - 2].concat();
+        
+        // Encoded payloads make long, high-entropy subdomains
+        if subdomain.len() >= MIN_TUNNEL_SUBDOMAIN_LENGTH {
+            let entropy = shannon_entropy(&subdomain);
+            if entropy >= self.entropy_threshold {
+                detected_threats.push(dns_evidence(
+                    client_ip,
+                    &query,
+                    timestamp,
+                    ThreatType::SuspiciousConnection,
+                    format!("Possible DNS tunneling to {}: high-entropy subdomain ({:.2} bits/char)", base_domain, entropy),
+                ));
+            }
+        }
         
-        // Check if current metric is an anomaly
-        (metric - mean).abs() > self.threshold * std_dev
+        // Track query volume per base domain within the window
+        let recent = self.queries.entry(base_domain.clone()).or_default();
+        recent.push_back(timestamp);
+        while matches!(recent.front(), Some(&oldest) if timestamp - oldest >= self.window_secs) {
+            recent.pop_front();
+        }
+        
+        // Alert once when the threshold is crossed, not on every query after
+        if recent.len() == self.query_threshold {
+            detected_threats.push(dns_evidence(
+                client_ip,
+                &query,
+                timestamp,
+                ThreatType::AnomalousBehavior,
+                format!("Abnormal DNS query volume to {}: {} queries in {}s", base_domain, recent.len(), self.window_secs),
+            ));
+        }
+        
+        detected_threats
+    }
+}
+
+impl Default for DnsTunnelDetector {
+    fn default() -> Self {
+        Self::new(3.7, 100, 60)
     }
-}
\ No newline at end of file
+}
+
+/// Shannon entropy of a string in bits per character
+fn shannon_entropy(data: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in data.chars() {
+        *counts.entry(c).or_insert(0) += 1;
+    }
+    
+    let len = data.chars().count() as f64;
+    counts.values()
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn dns_evidence(client_ip: &str, query: &str, timestamp: i64, threat_type: ThreatType, context: String) -> ThreatEvidence {
+    ThreatEvidence {
+        id: uuid::Uuid::new_v4().to_string(),
+        timestamp,
+        source_ip: client_ip.to_string(),
+        target_ip: "local".to_string(),
+        threat_type,
+        threat_level: ThreatLevel::Warning,
+        context,
+        evidence_hash: crate::crypto::CryptoProvider::blake3_hash(query.as_bytes()),
+        geolocation: "unknown".to_string(),
+        network_flow: format!("DNS {}", query),
+        agent_id: "agent".to_string(), // Will be set by agent
+        reputation: 1.0, // Will be set by agent
+        compliance_tag: "global".to_string(), // Will be set by agent
+        region: "unknown".to_string(),
+        expires_at: None,
+        source_hostname: None,
+        source_owner: None,
+        campaign_id: None,
+        source_network: None,
+        signer_key: None,
+        signature: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn test_high_entropy_subdomain_is_flagged() {
+        let mut detector = DnsTunnelDetector::default();
+
+        let threats = detector.observe_query(
+            "10.0.0.5",
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b.2b0b822cd15d6c15b0f00a08.tunnel.example.",
+            NOW,
+        );
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].threat_type, ThreatType::SuspiciousConnection);
+        assert!(threats[0].context.contains("tunnel.example"));
+
+        let threats = detector.observe_query("10.0.0.5", "nbswy3dpeb3w64tmmqqgc3tdnbqxi.exfil.net", NOW);
+        assert_eq!(threats.len(), 1);
+    }
+
+    #[test]
+    fn test_normal_queries_are_not_flagged() {
+        let mut detector = DnsTunnelDetector::default();
+
+        for query in ["www.google.com", "clients4.google.com", "mail-attachment-server.us-east-1.example.com", "localhost", "example.org."] {
+            assert!(detector.observe_query("10.0.0.5", query, NOW).is_empty(), "{} was flagged", query);
+        }
+    }
+
+    #[test]
+    fn test_query_volume_is_flagged_once_per_burst() {
+        let mut detector = DnsTunnelDetector::new(3.7, 50, 60);
+
+        let mut alerts = Vec::new();
+        for i in 0..80 {
+            alerts.extend(detector.observe_query("10.0.0.6", &format!("q{}.chatty.net", i), NOW + i / 10));
+        }
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].threat_type, ThreatType::AnomalousBehavior);
+        assert!(alerts[0].context.contains("chatty.net"));
+
+        // The same number of queries spread over time stays under the threshold
+        let mut detector = DnsTunnelDetector::new(3.7, 50, 60);
+        for i in 0..80 {
+            assert!(detector.observe_query("10.0.0.6", &format!("q{}.steady.net", i), NOW + i * 2).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_syn_flood_is_detected_only_above_threshold() {
+        let mut detector = ThreatDetector::new().with_ddos_threshold(50.0);
+        let start = Instant::now();
+
+        // 40 SYNs/sec for ten seconds stays under the threshold
+        for i in 0..10 {
+            assert!(detector.observe_syns("203.0.113.7", "192.0.2.10", 40, start + Duration::from_secs(i)).is_none());
+        }
+
+        // 120 SYNs/sec from another source crosses it once the window fills
+        let mut alerts = Vec::new();
+        for i in 0..10 {
+            alerts.extend(detector.observe_syns("198.51.100.9", "192.0.2.10", 120, start + Duration::from_secs(i)));
+        }
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].threat_type, ThreatType::DDoS);
+        assert_eq!(alerts[0].source_ip, "198.51.100.9");
+        assert!(alerts[0].context.contains("60.0 SYNs/sec"), "{}", alerts[0].context);
+    }
+
+    #[test]
+    fn test_flood_is_reported_again_after_subsiding() {
+        let mut detector = ThreatDetector::new().with_ddos_threshold(10.0);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(detector.observe_syns("198.51.100.9", "local", 500, at(0)).is_some());
+        assert!(detector.observe_syns("198.51.100.9", "local", 500, at(1)).is_none());
+
+        // Once the burst leaves the window the rate drops below the threshold
+        assert!(detector.observe_syns("198.51.100.9", "local", 1, at(30)).is_none());
+        assert!(detector.observe_syns("198.51.100.9", "local", 500, at(31)).is_some());
+    }
+
+    #[test]
+    fn test_rate_tracker_slides_its_window() {
+        let mut tracker = RateTracker::new(10.0);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(tracker.record("a", 50, at(0)), 5.0);
+        assert_eq!(tracker.record("a", 50, at(5)), 10.0);
+        assert_eq!(tracker.record("a", 20, at(10)), 7.0);
+        assert_eq!(tracker.record("b", 10, at(10)), 1.0);
+
+        tracker.prune(at(25));
+        assert!(tracker.events.is_empty());
+        assert_eq!(tracker.record("a", 10, at(25)), 1.0);
+    }
+
+    #[test]
+    fn test_default_rules_fire_on_matching_flows() {
+        let mut detector = ThreatDetector::new();
+        assert_eq!(detector.detection_rules.len(), 2);
+
+        let threats = detector.detect_threats_from_flow("tcp 10.0.0.5 -> 192.0.2.10 known_bad_ip");
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].threat_type, ThreatType::SuspiciousConnection);
+        assert!(threats[0].context.contains("suspicious_connection"));
+    }
+
+    #[test]
+    fn test_rules_are_loaded_from_file() {
+        let path = std::env::temp_dir().join(format!("orasrs-rules-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"
+[[rules]]
+name = "scanner_user_agent"
+pattern = 'User-Agent:\s*(sqlmap|nikto)'
+match_type = "regex"
+threat_type = "Exploit"
+threat_level = "Warning"
+
+[[rules]]
+name = "telnet"
+pattern = "dport=23 "
+threat_type = "SuspiciousConnection"
+threat_level = "Info"
+"#).unwrap();
+        let mut detector = ThreatDetector::from_rules_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let threats = detector.detect_threats_from_flow("GET / HTTP/1.1 User-Agent: sqlmap/1.7");
+        assert_eq!(threats.len(), 1);
+        assert_eq!((threats[0].threat_type.clone(), threats[0].threat_level), (ThreatType::Exploit, ThreatLevel::Warning));
+
+        let threats = detector.detect_threats_from_flow("flow proto=tcp dport=23 packets=4");
+        assert_eq!(threats.len(), 1);
+        assert!(threats[0].context.contains("telnet"));
+
+        // The default rules are replaced, not extended
+        assert!(detector.detect_threats_from_flow("known_bad_ip").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_rules_file_is_rejected() {
+        for content in ["[[rules]]\nname = \"x\"", "[[rules]]\nname = \"x\"\npattern = \"y\"\nthreat_type = \"Nope\"\nthreat_level = \"Info\""] {
+            assert!(matches!(parse_rules(content), Err(AgentError::ConfigError(_))), "{}", content);
+        }
+    }
+
+    #[test]
+    fn test_regex_rule_matches_flow_and_behavior_data() {
+        let rule = DetectionRule::new(
+            "scanner_user_agent", r"User-Agent:\s*(sqlmap|nikto)", MatchType::Regex, ThreatType::Exploit, ThreatLevel::Warning,
+        ).unwrap();
+        assert!(rule.matches("GET /?id=1 HTTP/1.1\r\nUser-Agent:   nikto/2.5"));
+        assert!(!rule.matches("GET / HTTP/1.1\r\nUser-Agent: curl/8.4"));
+        assert!(!rule.matches("sqlmap")); // No header
+
+        let mut detector = ThreatDetector::with_rules(vec![rule]);
+        assert_eq!(detector.detect_threats_from_flow("User-Agent: sqlmap/1.7").len(), 1);
+        assert!(detector.detect_threats_from_flow("User-Agent: Mozilla/5.0").is_empty());
+
+        let threats = detector.detect_threats_from_behavior("curl -H 'User-Agent: sqlmap' http://192.0.2.10/");
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].source_ip, "local");
+    }
+
+    #[test]
+    fn test_invalid_regex_fails_rule_loading() {
+        let result = DetectionRule::new("broken", "(sqlmap", MatchType::Regex, ThreatType::Exploit, ThreatLevel::Warning);
+        assert!(matches!(result, Err(AgentError::ConfigError(ref message)) if message.contains("broken")), "{:?}", result);
+
+        // The same pattern is a plain string for substring rules
+        assert!(DetectionRule::new("literal", "(sqlmap", MatchType::Contains, ThreatType::Exploit, ThreatLevel::Warning).is_ok());
+
+        let content = "[[rules]]\nname = \"broken\"\npattern = \"(sqlmap\"\nmatch_type = \"regex\"\nthreat_type = \"Exploit\"\nthreat_level = \"Warning\"";
+        match parse_rules(content) {
+            Err(AgentError::ConfigError(message)) => assert!(message.contains("invalid regex"), "{}", message),
+            other => panic!("expected the rule to be rejected, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "yara")]
+    #[test]
+    fn test_yara_rule_matches_payload() {
+        let matcher = YaraMatcher::from_source(r#"
+rule test_marker {
+    strings:
+        $marker = { 4F 52 41 53 52 53 2D 54 45 53 54 }
+    condition:
+        $marker
+}
+"#).unwrap();
+        let mut detector = ThreatDetector::new().with_yara_matcher(Arc::new(matcher));
+
+        let threats = detector.detect_threats_from_payload("203.0.113.7", "192.0.2.10", b"GET /ORASRS-TEST HTTP/1.1");
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].threat_type, ThreatType::Malware);
+        assert!(threats[0].context.contains("test_marker"), "{}", threats[0].context);
+        assert_eq!(threats[0].source_ip, "203.0.113.7");
+
+        assert!(detector.detect_threats_from_payload("203.0.113.7", "192.0.2.10", b"GET / HTTP/1.1").is_empty());
+        assert!(YaraMatcher::from_source("rule broken {").is_err());
+    }
+
+    #[cfg(not(feature = "yara"))]
+    #[test]
+    fn test_payload_scanning_requires_feature() {
+        assert!(ThreatDetector::new().detect_threats_from_payload("203.0.113.7", "local", b"ORASRS-TEST").is_empty());
+    }
+
+    #[test]
+    fn test_outlier_in_stable_series_is_flagged() {
+        let mut detector = ThreatDetector::new();
+
+        for i in 0..30 {
+            let metric = 100.0 + (i % 5) as f64; // Varies between 100 and 104
+            assert!(detector.detect_behavior_anomalies("nginx", metric).is_empty(), "value {} flagged", metric);
+        }
+
+        let threats = detector.detect_behavior_anomalies("nginx", 400.0);
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].threat_type, ThreatType::AnomalousBehavior);
+        assert!(threats[0].context.contains("nginx: value=400 z-score="), "{}", threats[0].context);
+
+        // Other entities keep their own history
+        assert!(detector.detect_behavior_anomalies("sshd", 400.0).is_empty());
+    }
+
+    #[test]
+    fn test_anomalies_need_minimum_history() {
+        let mut analyzer = BehaviorAnalyzer::new(DEFAULT_ANOMALY_Z_SCORE);
+
+        for _ in 0..MIN_BEHAVIOR_HISTORY - 1 {
+            assert_eq!(analyzer.detect_anomaly("cron", 1.0), None);
+        }
+        // Still too little history when the outlier arrives
+        assert_eq!(analyzer.detect_anomaly("cron", 50.0), None);
+
+        let mut analyzer = BehaviorAnalyzer::new(DEFAULT_ANOMALY_Z_SCORE);
+        for _ in 0..MIN_BEHAVIOR_HISTORY {
+            assert_eq!(analyzer.detect_anomaly("cron", 1.0), None);
+        }
+        assert_eq!(analyzer.detect_anomaly("cron", 1.0), None);
+        assert_eq!(analyzer.detect_anomaly("cron", 0.0), Some(-1.0 / MIN_STD_DEV_FRACTION));
+    }
+
+    #[test]
+    fn test_constant_history_uses_std_dev_floor() {
+        let mut analyzer = BehaviorAnalyzer::new(DEFAULT_ANOMALY_Z_SCORE);
+        for _ in 0..MIN_BEHAVIOR_HISTORY {
+            analyzer.detect_anomaly("cron", 100.0);
+        }
+        // A change within the floor is not an anomaly, a large one is and stays finite
+        assert_eq!(analyzer.detect_anomaly("cron", 101.0), None);
+        let z = analyzer.detect_anomaly("cron", 200.0).unwrap();
+        assert!(z.is_finite() && z > DEFAULT_ANOMALY_Z_SCORE, "{}", z);
+    }
+
+    #[test]
+    fn test_behavior_history_is_bounded() {
+        let mut analyzer = BehaviorAnalyzer::new(DEFAULT_ANOMALY_Z_SCORE);
+        analyzer.detect_anomaly("first", 1.0);
+        for index in 0..MAX_BEHAVIOR_ENTITIES {
+            analyzer.detect_anomaly(&format!("entity-{}", index), 1.0);
+            analyzer.detect_anomaly("first", 1.0); // Kept recent
+        }
+        assert!(analyzer.history.len() <= MAX_BEHAVIOR_ENTITIES);
+        assert!(analyzer.history.contains_key("first"));
+        assert!(!analyzer.history.contains_key("entity-0"));
+        assert!(analyzer.history.contains_key(&format!("entity-{}", MAX_BEHAVIOR_ENTITIES - 1)));
+    }
+}
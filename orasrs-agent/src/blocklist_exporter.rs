@@ -1,16 +1,113 @@
-use crate::{ThreatEvidence, ThreatLevel, ThreatType, error::{AgentError, Result}};
-use std::collections::HashSet;
+use crate::{ThreatEvidence, ThreatLevel, ThreatType, allowlist::SharedAllowlist, clock::{Clock, SystemClock}, crypto::{CryptoProvider, Signer}, error::{AgentError, Result}};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::io::{Write, BufWriter};
-use std::time::{SystemTime, UNIX_EPOCH};
+use ipnetwork::{IpNetwork, Ipv4Network};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+use std::sync::Arc;
 use tokio::sync::mpsc;
+use tokio::time::{interval, Duration};
+
+/// Name of the ipset / nftables set entries are added to
+const SET_NAME: &str = "orasrs_blocklist";
+
+/// Name of the nftables set IPv6 entries are added to
+const SET_NAME_V6: &str = "orasrs_blocklist6";
+
+/// Syntax of the exported blocklist file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlocklistFormat {
+    /// One `ip # comment` line per entry
+    #[default]
+    PlainWithComments,
+    /// `add orasrs_blocklist <ip>` lines for `ipset restore`
+    IpsetRestore,
+    /// `iptables -A INPUT -s <ip> -j DROP` commands, using ip6tables for IPv6
+    IptablesRules,
+    /// `add element inet filter orasrs_blocklist { <ip> }` lines for `nft -f`, after
+    /// statements creating the table and sets; IPv6 goes to `orasrs_blocklist6`
+    NftablesSet,
+    /// JSON array of entry objects, rewritten in full on every change
+    Json,
+    /// CSV with a header row
+    Csv,
+}
+
+/// Header row of the CSV format
+const CSV_HEADER: &str = "ip,threat_type,threat_level,context,agent_id,timestamp";
+
+/// Signed statement of the blocklist content, written next to it as `<file>.sig`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlocklistManifest {
+    pub file_hash: String, // Hex blake3 hash of the blocklist file
+    pub generated_at: i64,
+    pub agent_id: String,
+    pub signer: String,    // Hex protobuf-encoded public key
+    pub signature: String, // Hex signature over the other fields
+}
+
+impl BlocklistManifest {
+    /// Bytes covered by the signature
+    fn signing_payload(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&(&self.file_hash, self.generated_at, &self.agent_id, &self.signer))?)
+    }
+}
+
+/// A single blocklist entry and the evidence that caused it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlocklistEntry {
+    pub ip: String,
+    pub threat_level: ThreatLevel,
+    pub threat_type: ThreatType,
+    pub context: String,
+    pub agent_id: String,
+    pub timestamp: i64,
+    #[serde(skip)]
+    pub added_at: i64, // Time the IP was listed, for expiry
+}
+
+/// A listed entry as kept in `<file>.entries.json`, with the time it was listed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredEntry {
+    #[serde(flatten)]
+    entry: BlocklistEntry,
+    added_at: i64,
+}
+
+impl BlocklistEntry {
+    fn from_evidence(ip: &str, evidence: &ThreatEvidence, added_at: i64) -> Self {
+        Self {
+            ip: ip.to_string(),
+            threat_level: evidence.threat_level,
+            threat_type: evidence.threat_type.clone(),
+            context: evidence.context.clone(),
+            agent_id: evidence.agent_id.clone(),
+            timestamp: evidence.timestamp,
+            added_at,
+        }
+    }
+}
 
 /// Blocklist exporter to convert threat evidence to blocklist.txt format
 pub struct BlocklistExporter {
     blocklist_file: String,
-    threat_cache: HashSet<String>,  // Cache to avoid duplicate IPs
+    threat_cache: HashMap<String, BlocklistEntry>,  // Cache to avoid duplicate IPs
     min_threat_level: ThreatLevel,  // Minimum threat level to include in blocklist
     export_interval: u64,           // Export interval in seconds
+    last_modified: i64,             // Time the entry set last changed
+    removal_queue: Option<mpsc::UnboundedReceiver<String>>, // IPs to drop from the blocklist
+    delta_file: Option<String>,     // File receiving only the changes of each export
+    last_exported: BTreeSet<String>, // IPs listed as of the previous delta export
+    format: BlocklistFormat,
+    entry_ttl: Option<u64>,          // Seconds an entry stays listed, forever if unset
+    clock: Arc<dyn Clock>,
+    allowlist: SharedAllowlist,      // Addresses never blocklisted
+    aggregation_threshold: Option<usize>, // IPs from one /24 needed to list the /24 instead
+    aggregate_mixed_types: bool,     // Whether a /24 may combine different threat types
+    signer: Option<(Box<dyn Signer>, String)>, // Scheme and agent ID signing the manifest
 }
 
 impl BlocklistExporter {
@@ -18,85 +115,584 @@ impl BlocklistExporter {
     pub fn new(blocklist_file: String, min_threat_level: ThreatLevel, export_interval: u64) -> Self {
         Self {
             blocklist_file,
-            threat_cache: HashSet::new(),
+            threat_cache: HashMap::new(),
             min_threat_level,
             export_interval,
+            last_modified: SystemClock.now(),
+            removal_queue: None,
+            delta_file: None,
+            last_exported: BTreeSet::new(),
+            format: BlocklistFormat::default(),
+            entry_ttl: None,
+            clock: Arc::new(SystemClock),
+            allowlist: SharedAllowlist::default(),
+            aggregation_threshold: None,
+            aggregate_mixed_types: false,
+            signer: None,
         }
     }
 
+    /// Sign each written blocklist, recording the signature in `<file>.sig`
+    pub fn with_signer(mut self, signer: Box<dyn Signer>, agent_id: String) -> Self {
+        self.signer = Some((signer, agent_id));
+        self
+    }
+
+    /// Check a blocklist file against its `.sig` manifest and the expected signer
+    ///
+    /// Returns false if the file was modified or the manifest was not signed by
+    /// `public_key` under `scheme`, and an error if either file can't be read.
+    pub fn verify(path: &str, scheme: &dyn Signer, public_key: &[u8]) -> Result<bool> {
+        let content = std::fs::read(path)?;
+        let manifest: BlocklistManifest = serde_json::from_slice(&std::fs::read(manifest_path(path))?)?;
+
+        if blake3::hash(&content).to_hex().as_str() != manifest.file_hash {
+            return Ok(false);
+        }
+        if CryptoProvider::hex_decode(&manifest.signer)? != public_key {
+            return Ok(false);
+        }
+        let signature = CryptoProvider::hex_decode(&manifest.signature)?;
+        scheme.verify(&manifest.signing_payload()?, &signature, public_key)
+    }
+
+    /// List a whole /24 once at least `threshold` of its IPs are listed
+    ///
+    /// IPs with different threat types are only combined if `mixed_types` is set,
+    /// and a /24 containing an allowlisted address is never listed. Ranges are
+    /// only formed when rendering; the individual IPs stay listed and are what
+    /// `<file>.entries.json` restores after a restart.
+    pub fn with_cidr_aggregation(mut self, threshold: usize, mixed_types: bool) -> Self {
+        self.aggregation_threshold = Some(threshold);
+        self.aggregate_mixed_types = mixed_types;
+        self
+    }
+
+    /// Never list IPs covered by the allowlist, following updates made through the shared handle
+    pub fn with_allowlist(mut self, allowlist: SharedAllowlist) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    /// Drop entries listed for longer than `ttl` seconds at each export
+    pub fn with_entry_ttl(mut self, ttl: u64) -> Self {
+        self.entry_ttl = Some(ttl);
+        self
+    }
+
+    /// Use a custom clock for entry ages, e.g. a mock clock in tests
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.last_modified = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    /// Write the blocklist in the given syntax instead of plain commented lines
+    pub fn with_format(mut self, format: BlocklistFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Accept IPs to remove from the blocklist while exporting
+    pub fn with_removal_queue(mut self, removal_queue: mpsc::UnboundedReceiver<String>) -> Self {
+        self.removal_queue = Some(removal_queue);
+        self
+    }
+
+    /// Also write a delta file listing only entries added (`+`) or removed (`-`) since the last export
+    pub fn with_delta_export(mut self, delta_file: String) -> Self {
+        self.delta_file = Some(delta_file);
+        self
+    }
+
     /// Start the blocklist export service
     pub async fn start_export(&mut self, mut evidence_queue: mpsc::UnboundedReceiver<ThreatEvidence>) -> Result<()> {
         log::info!("Starting blocklist export service...");
-        
-        // Initialize the blocklist file
+
+        // Pick up the existing blocklist, or start a new one
         self.initialize_blocklist_file()?;
-        
-        while let Some(evidence) = evidence_queue.recv().await {
-            // Check if threat level is high enough for blocklist
-            if evidence.threat_level as u8 >= self.min_threat_level as u8 {
-                // Add source IP to blocklist if not already present
-                if self.threat_cache.insert(evidence.source_ip.clone()) {
-                    self.add_to_blocklist(&evidence.source_ip, &evidence)?;
+
+        // New entries are written by the periodic rewrite, which keeps the file sorted
+        let mut export_tick = interval(Duration::from_secs(self.export_interval.max(1)));
+        let mut dirty = false;
+        let mut removal_queue = self.removal_queue.take();
+
+        loop {
+            tokio::select! {
+                maybe_evidence = evidence_queue.recv() => {
+                    let Some(evidence) = maybe_evidence else { break };
+                    if self.process_evidence(&evidence)? {
+                        dirty = true;
+                    }
+                }
+                Some(ip) = recv_removal(&mut removal_queue) => {
+                    // Removal rewrites the whole file, so nothing is left pending
+                    if self.remove_from_blocklist(&ip)? {
+                        dirty = false;
+                    }
+                }
+                _ = export_tick.tick() => {
+                    if self.expire_entries() > 0 {
+                        dirty = true;
+                    }
+                    if dirty || self.delta_pending() {
+                        self.export_blocklist()?;
+                        dirty = false;
+                    }
                 }
             }
         }
-        
+
+        if dirty || self.delta_pending() {
+            self.export_blocklist()?;
+        }
+
         Ok(())
     }
 
-    /// Initialize the blocklist file with header
-    fn initialize_blocklist_file(&self) -> Result<()> {
-        let mut file = File::create(&self.blocklist_file)?;
-        
-        // Write header information
-        writeln!(file, "# OraSRS Agent Blocklist")?;
-        writeln!(file, "# Generated: {}", chrono::Utc::now().to_rfc3339())?;
-        writeln!(file, "# Contains IP addresses detected as threats by OraSRS Agent")?;
-        writeln!(file, "# Minimum threat level: {:?}", self.min_threat_level)?;
-        writeln!(file, "")?;
-        
+    /// Process a single evidence, returning whether a new entry was added
+    ///
+    /// The entry reaches the file at the next export.
+    fn process_evidence(&mut self, evidence: &ThreatEvidence) -> Result<bool> {
+        // Check if threat level is high enough for blocklist
+        if self.allowlist.read().unwrap().contains(&evidence.source_ip) {
+            log::warn!("Not blocklisting allowlisted IP {} from evidence {}", evidence.source_ip, evidence.id);
+            return Ok(false);
+        }
+
+        if evidence.threat_level as u8 >= self.min_threat_level as u8 {
+            // Add source IP to blocklist if not already present
+            if self.insert_entry(&evidence.source_ip, evidence) {
+                log::info!("Added {} to blocklist: {} - {}", evidence.source_ip,
+                          evidence.threat_type, evidence.context);
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Record an entry in the cache, returning false if the IP was already present
+    fn insert_entry(&mut self, ip: &str, evidence: &ThreatEvidence) -> bool {
+        if self.threat_cache.contains_key(ip) {
+            return false;
+        }
+
+        let now = self.clock.now();
+        self.threat_cache.insert(ip.to_string(), BlocklistEntry::from_evidence(ip, evidence, now));
+        self.last_modified = now;
+        true
+    }
+
+    /// Load the entries saved with each rewrite, returning how many were added or None if there are none
+    fn restore_stored_entries(&mut self) -> Result<Option<usize>> {
+        let path = entries_path(&self.blocklist_file);
+        let stored: Vec<StoredEntry> = match std::fs::read(&path) {
+            Ok(content) => match serde_json::from_slice(&content) {
+                Ok(stored) => stored,
+                Err(e) => {
+                    log::warn!("Ignoring unreadable blocklist entries {}: {}", path, e);
+                    return Ok(None);
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut restored = 0;
+        for StoredEntry { mut entry, added_at } in stored {
+            if entry.ip.parse::<IpNetwork>().is_err() || self.threat_cache.contains_key(&entry.ip) {
+                continue;
+            }
+            entry.added_at = added_at;
+            self.threat_cache.insert(entry.ip.clone(), entry);
+            restored += 1;
+        }
+        Ok(Some(restored))
+    }
+
+    /// Load the entries listed in a blocklist file's content, returning how many were added
+    fn restore_entries(&mut self, content: &str) -> usize {
+        let now = self.clock.now();
+        let entries: Vec<BlocklistEntry> = if self.format == BlocklistFormat::Json {
+            match serde_json::from_str::<Vec<BlocklistEntry>>(content) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    log::warn!("Ignoring unreadable blocklist {}: {}", self.blocklist_file, e);
+                    Vec::new()
+                }
+            }
+        } else {
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#') && *line != CSV_HEADER)
+                .filter_map(|line| {
+                    let entry = self.parse_line(line);
+                    if entry.is_none() {
+                        log::debug!("Skipping unrecognized blocklist line: {}", line);
+                    }
+                    entry
+                })
+                .collect()
+        };
+
+        let mut restored = 0;
+        for mut entry in entries {
+            if entry.ip.parse::<IpNetwork>().is_err() || self.threat_cache.contains_key(&entry.ip) {
+                continue;
+            }
+            entry.added_at = now;
+            self.threat_cache.insert(entry.ip.clone(), entry);
+            restored += 1;
+        }
+        restored
+    }
+
+    /// Parse one line of the configured format; metadata the format lacks is left empty
+    fn parse_line(&self, line: &str) -> Option<BlocklistEntry> {
+        let entry = |ip: &str| BlocklistEntry {
+            ip: ip.to_string(),
+            threat_level: self.min_threat_level,
+            threat_type: ThreatType::Unknown,
+            context: String::new(),
+            agent_id: String::new(),
+            timestamp: 0,
+            added_at: 0,
+        };
+
+        match self.format {
+            BlocklistFormat::PlainWithComments => {
+                let (ip, comment) = line.split_once(" # ").unwrap_or((line, ""));
+                let mut restored = entry(ip.trim());
+                if let Some((details, agent_id)) = comment.rsplit_once(" - Agent: ") {
+                    let mut parts = details.splitn(3, " - ");
+                    if let (Some(level), Some(threat_type), Some(context)) = (parts.next(), parts.next(), parts.next()) {
+                        restored.threat_level = parse_threat_level(level).unwrap_or(restored.threat_level);
+                        restored.threat_type = parse_threat_type(threat_type).unwrap_or(ThreatType::Unknown);
+                        restored.context = context.to_string();
+                        restored.agent_id = agent_id.to_string();
+                    }
+                }
+                Some(restored)
+            }
+            BlocklistFormat::IpsetRestore => {
+                let mut tokens = line.split_whitespace();
+                match (tokens.next(), tokens.next(), tokens.next()) {
+                    (Some("add"), Some(SET_NAME), Some(ip)) => Some(entry(ip)),
+                    _ => None,
+                }
+            }
+            BlocklistFormat::IptablesRules => {
+                let mut tokens = line.split_whitespace();
+                tokens.find(|token| *token == "-s")?;
+                tokens.next().map(entry)
+            }
+            BlocklistFormat::NftablesSet => {
+                let (_, rest) = line.strip_prefix("add element ")?.split_once('{')?;
+                let (ip, _) = rest.split_once('}')?;
+                Some(entry(ip.trim()))
+            }
+            BlocklistFormat::Csv => {
+                let fields = parse_csv_row(line);
+                let [ip, threat_type, threat_level, context, agent_id, timestamp] = fields.as_slice() else {
+                    return None;
+                };
+                Some(BlocklistEntry {
+                    threat_type: parse_threat_type(threat_type).unwrap_or(ThreatType::Unknown),
+                    threat_level: parse_threat_level(threat_level).unwrap_or(self.min_threat_level),
+                    context: context.clone(),
+                    agent_id: agent_id.clone(),
+                    timestamp: timestamp.parse().unwrap_or(0),
+                    ..entry(ip)
+                })
+            }
+            BlocklistFormat::Json => None,
+        }
+    }
+
+    /// Drop entries older than the TTL, returning how many expired
+    fn expire_entries(&mut self) -> usize {
+        let Some(ttl) = self.entry_ttl else {
+            return 0;
+        };
+
+        let now = self.clock.now();
+        let before = self.threat_cache.len();
+        self.threat_cache.retain(|_, entry| now - entry.added_at < ttl as i64);
+        let expired = before - self.threat_cache.len();
+        if expired > 0 {
+            self.last_modified = now;
+            log::info!("Expired {} blocklist entries older than {}s", expired, ttl);
+        }
+        expired
+    }
+
+    /// Remove an IP and rewrite the file, returning whether it was listed
+    pub fn remove_from_blocklist(&mut self, ip: &str) -> Result<bool> {
+        if self.threat_cache.remove(ip).is_none() {
+            return Ok(false);
+        }
+
+        self.last_modified = self.clock.now();
+        self.rewrite_blocklist()?;
+
+        log::info!("Removed {} from blocklist", ip);
+        Ok(true)
+    }
+
+    /// Restore entries from an existing blocklist file, or create it with a header
+    ///
+    /// Entries are read from `<file>.entries.json`, which keeps when each was
+    /// listed, and from the blocklist itself if that is missing. An existing
+    /// file is left as is until the next export.
+    fn initialize_blocklist_file(&mut self) -> Result<()> {
+        if let Some(restored) = self.restore_stored_entries()? {
+            self.last_exported = self.threat_cache.keys().cloned().collect();
+            log::info!("Restored {} entries of blocklist {}", restored, self.blocklist_file);
+            if Path::new(&self.blocklist_file).exists() {
+                return Ok(());
+            }
+            return self.rewrite_blocklist();
+        }
+
+        match std::fs::read_to_string(&self.blocklist_file) {
+            Ok(content) if !content.trim().is_empty() => {
+                let restored = self.restore_entries(&content);
+                self.last_exported = self.threat_cache.keys().cloned().collect();
+                log::info!("Restored {} entries from blocklist {}", restored, self.blocklist_file);
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        self.rewrite_blocklist()
+    }
+
+    /// Write the header comment block
+    fn write_header<W: Write>(&self, writer: &mut W, generated: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        // Structured formats have no room for comments
+        match self.format {
+            BlocklistFormat::Json => return Ok(()),
+            BlocklistFormat::Csv => {
+                writeln!(writer, "{}", CSV_HEADER)?;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        writeln!(writer, "# OraSRS Agent Blocklist")?;
+        writeln!(writer, "# Generated: {}", generated.to_rfc3339())?;
+        writeln!(writer, "# Contains IP addresses detected as threats by OraSRS Agent")?;
+        writeln!(writer, "# Minimum threat level: {:?}", self.min_threat_level)?;
+        match self.format {
+            BlocklistFormat::PlainWithComments => {}
+            BlocklistFormat::IpsetRestore => writeln!(writer, "# Load with: ipset restore -exist < <file>")?,
+            BlocklistFormat::IptablesRules => writeln!(writer, "# Apply with: sh <file>")?,
+            BlocklistFormat::NftablesSet => writeln!(writer, "# Load with: nft -f <file>")?,
+            BlocklistFormat::Json | BlocklistFormat::Csv => {}
+        }
+        writeln!(writer)?;
+
+        // `add` creates the table and sets unless they exist, so the file can be loaded again
+        if self.format == BlocklistFormat::NftablesSet {
+            writeln!(writer, "add table inet filter")?;
+            writeln!(writer, "add set inet filter {} {{ type ipv4_addr; flags interval; }}", SET_NAME)?;
+            writeln!(writer, "add set inet filter {} {{ type ipv6_addr; flags interval; }}", SET_NAME_V6)?;
+        }
+
         Ok(())
     }
 
-    /// Add an IP to the blocklist file
-    fn add_to_blocklist(&mut self, ip: &str, evidence: &ThreatEvidence) -> Result<()> {
-        let file = std::fs::OpenOptions::new()
-            .append(true)
-            .open(&self.blocklist_file)?;
-        
-        let mut writer = BufWriter::new(file);
-        
-        // Write the IP with comment about the threat
-        writeln!(
-            writer, 
-            "{} # {} - {} - {} - Agent: {}", 
-            ip,
-            self.threat_level_to_string(evidence.threat_level),
-            self.threat_type_to_string(&evidence.threat_type),
-            evidence.context,
-            evidence.agent_id
-        )?;
-        
-        writer.flush()?;
-        
-        log::info!("Added {} to blocklist: {} - {}", ip, self.threat_type_to_string(&evidence.threat_type), evidence.context);
-        
+    /// Rewrite the whole blocklist file with entries sorted by IP
+    pub fn rewrite_blocklist(&self) -> Result<()> {
+        // Use the last change time so rewriting an unchanged set is byte-identical
+        let generated = chrono::DateTime::from_timestamp(self.last_modified, 0)
+            .ok_or_else(|| AgentError::InternalError(format!("Invalid timestamp: {}", self.last_modified)))?;
+
+        let mut content = Vec::new();
+        self.write_header(&mut content, generated)?;
+        if self.format == BlocklistFormat::Json {
+            serde_json::to_writer_pretty(&mut content, &self.output_entries())?;
+            writeln!(content)?;
+        } else {
+            for entry in self.output_entries() {
+                writeln!(content, "{}", self.format_entry(&entry))?;
+            }
+        }
+
+        // Save the entries first, so a listed IP is never missing from them
+        let stored: Vec<StoredEntry> = self.sorted_entries()
+            .into_iter()
+            .map(|entry| StoredEntry { entry: entry.clone(), added_at: entry.added_at })
+            .collect();
+        let stored = serde_json::to_vec(&stored)?;
+        write_atomically(&entries_path(&self.blocklist_file), |writer| Ok(writer.write_all(&stored)?))?;
+
+        write_atomically(&self.blocklist_file, |writer| Ok(writer.write_all(&content)?))?;
+        self.write_manifest(&content)?;
+
+        log::debug!("Rewrote blocklist with {} entries", self.threat_cache.len());
+
         Ok(())
     }
 
-    /// Convert threat level to string
-    fn threat_level_to_string(&self, level: ThreatLevel) -> &'static str {
-        match level {
-            ThreatLevel::Info => "INFO",
-            ThreatLevel::Warning => "WARNING",
-            ThreatLevel::Critical => "CRITICAL",
-            ThreatLevel::Emergency => "EMERGENCY",
+    /// Write the signed manifest for freshly written blocklist content
+    fn write_manifest(&self, content: &[u8]) -> Result<()> {
+        let Some((signer, agent_id)) = &self.signer else {
+            return Ok(());
+        };
+
+        let mut manifest = BlocklistManifest {
+            file_hash: blake3::hash(content).to_hex().to_string(),
+            generated_at: self.last_modified,
+            agent_id: agent_id.clone(),
+            signer: CryptoProvider::hex_encode(&signer.public_key()),
+            signature: String::new(),
+        };
+        let signature = signer.sign(&manifest.signing_payload()?)?;
+        manifest.signature = CryptoProvider::hex_encode(&signature);
+
+        let manifest = serde_json::to_vec_pretty(&manifest)?;
+        write_atomically(&manifest_path(&self.blocklist_file), |writer| Ok(writer.write_all(&manifest)?))
+    }
+
+    /// Rewrite the full blocklist and, if enabled, the delta file
+    pub fn export_blocklist(&mut self) -> Result<()> {
+        self.rewrite_blocklist()?;
+        if self.delta_file.is_some() {
+            self.write_delta()?;
         }
+        Ok(())
     }
 
-    /// Convert threat type to string
-    fn threat_type_to_string(&self, threat_type: &ThreatType) -> &'static str {
-        threat_type.as_ref()
+    /// Whether the listed IPs differ from those of the previous delta export
+    fn delta_pending(&self) -> bool {
+        self.delta_file.is_some()
+            && (self.last_exported.len() != self.threat_cache.len()
+                || self.last_exported.iter().any(|ip| !self.threat_cache.contains_key(ip)))
+    }
+
+    /// Overwrite the delta file with the changes since the previous delta export
+    fn write_delta(&mut self) -> Result<()> {
+        let Some(delta_file) = &self.delta_file else {
+            return Ok(());
+        };
+
+        let added: Vec<&BlocklistEntry> = self.sorted_entries()
+            .into_iter()
+            .filter(|entry| !self.last_exported.contains(&entry.ip))
+            .collect();
+        let mut removed: Vec<&String> = self.last_exported
+            .iter()
+            .filter(|ip| !self.threat_cache.contains_key(*ip))
+            .collect();
+        removed.sort_by_cached_key(|ip| ip_sort_key(ip));
+
+        write_atomically(delta_file, |writer| {
+            writeln!(writer, "# OraSRS Agent Blocklist Delta")?;
+            writeln!(writer, "# Generated: {}", chrono::Utc::now().to_rfc3339())?;
+            writeln!(writer, "# Lines starting with + were added, - were removed since the previous export")?;
+            writeln!(writer)?;
+            for entry in &added {
+                writeln!(writer, "+{}", self.format_commented_entry(entry))?;
+            }
+            for ip in &removed {
+                writeln!(writer, "-{}", ip)?;
+            }
+            Ok(())
+        })?;
+
+        log::debug!("Wrote blocklist delta: {} added, {} removed", added.len(), removed.len());
+
+        self.last_exported = self.threat_cache.keys().cloned().collect();
+        Ok(())
+    }
+
+    /// Entries as written to the blocklist, with dense /24s collapsed if enabled
+    fn output_entries(&self) -> Vec<BlocklistEntry> {
+        let Some(threshold) = self.aggregation_threshold else {
+            return self.sorted_entries().into_iter().cloned().collect();
+        };
+
+        let mut subnets: BTreeMap<u32, Vec<&BlocklistEntry>> = BTreeMap::new();
+        let mut entries = Vec::new();
+        for entry in self.threat_cache.values() {
+            match entry.ip.parse::<Ipv4Addr>() {
+                Ok(ip) => subnets.entry(u32::from(ip) & 0xffff_ff00).or_default().push(entry),
+                Err(_) => entries.push(entry.clone()),
+            }
+        }
+
+        for (network, members) in subnets {
+            let network = Ipv4Network::new(Ipv4Addr::from(network), 24).expect("24 is a valid IPv4 prefix");
+            let same_type = members.iter().all(|member| member.threat_type == members[0].threat_type);
+            if members.len() >= threshold
+                && (same_type || self.aggregate_mixed_types)
+                && !self.allowlist.read().unwrap().overlaps(IpNetwork::V4(network))
+            {
+                // A range restored from a blocklist written without saved entries already covers them
+                if !self.threat_cache.contains_key(&network.to_string()) {
+                    log::debug!("Aggregating {} blocklist entries into {}", members.len(), network);
+                    entries.push(aggregate_entries(network, &members));
+                }
+            } else {
+                entries.extend(members.into_iter().cloned());
+            }
+        }
+
+        entries.sort_by_cached_key(|entry| ip_sort_key(&entry.ip));
+        entries
+    }
+
+    /// Get the cached entries ordered numerically by IP (IPv4 before IPv6)
+    fn sorted_entries(&self) -> Vec<&BlocklistEntry> {
+        let mut entries: Vec<&BlocklistEntry> = self.threat_cache.values().collect();
+        entries.sort_by_cached_key(|entry| ip_sort_key(&entry.ip));
+        entries
+    }
+
+    /// Format a blocklist line in the configured syntax
+    fn format_entry(&self, entry: &BlocklistEntry) -> String {
+        match self.format {
+            BlocklistFormat::PlainWithComments => self.format_commented_entry(entry),
+            BlocklistFormat::IpsetRestore => format!("add {} {}", SET_NAME, entry.ip),
+            BlocklistFormat::IptablesRules => {
+                let command = if is_ipv6_entry(&entry.ip) { "ip6tables" } else { "iptables" };
+                format!("{} -A INPUT -s {} -j DROP", command, entry.ip)
+            }
+            BlocklistFormat::NftablesSet => {
+                let set = if is_ipv6_entry(&entry.ip) { SET_NAME_V6 } else { SET_NAME };
+                format!("add element inet filter {} {{ {} }}", set, entry.ip)
+            }
+            BlocklistFormat::Json => serde_json::to_string(entry).unwrap_or_default(),
+            BlocklistFormat::Csv => [
+                entry.ip.clone(),
+                format!("{:?}", entry.threat_type),
+                format!("{:?}", entry.threat_level),
+                entry.context.clone(),
+                entry.agent_id.clone(),
+                entry.timestamp.to_string(),
+            ]
+            .iter()
+            .map(|field| csv_field(field))
+            .collect::<Vec<_>>()
+            .join(","),
+        }
+    }
+
+    /// Format a blocklist line with a comment about the threat
+    fn format_commented_entry(&self, entry: &BlocklistEntry) -> String {
+        format!(
+            "{} # {} - {} - {} - Agent: {}",
+            entry.ip,
+            entry.threat_level,
+            entry.threat_type,
+            entry.context,
+            entry.agent_id
+        )
     }
 
     /// Get current reputation
@@ -105,6 +701,122 @@ impl BlocklistExporter {
     }
 }
 
+/// Path of the signed manifest accompanying a blocklist file
+fn manifest_path(blocklist_file: &str) -> String {
+    format!("{}.sig", blocklist_file)
+}
+
+/// Path of the entries saved alongside a blocklist file
+fn entries_path(blocklist_file: &str) -> String {
+    format!("{}.entries.json", blocklist_file)
+}
+
+/// Write a file through a temporary file in the same directory that is then
+/// renamed into place, so readers never see it half-written
+fn write_atomically(path: &str, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+    let path = Path::new(path);
+    let file_name = path.file_name()
+        .ok_or_else(|| AgentError::ConfigError(format!("Invalid blocklist path: {}", path.display())))?;
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    let written = write(&mut writer).and_then(|()| {
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        Ok(())
+    });
+    drop(writer);
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Split a CSV row written by [`csv_field`] back into its fields
+fn parse_csv_row(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Parse a threat level as written by any blocklist format, ignoring case
+fn parse_threat_level(name: &str) -> Option<ThreatLevel> {
+    [ThreatLevel::Info, ThreatLevel::Warning, ThreatLevel::Critical, ThreatLevel::Emergency]
+        .into_iter()
+        .find(|level| name.eq_ignore_ascii_case(&format!("{:?}", level)))
+}
+
+/// Parse a threat type written either by name or as its variant
+fn parse_threat_type(name: &str) -> Option<ThreatType> {
+    ThreatType::from_name(name)
+        .or_else(|| ThreatType::ALL.into_iter().find(|threat_type| format!("{:?}", threat_type) == name))
+}
+
+/// Combine the entries of one /24 into a single entry listing the whole range
+fn aggregate_entries(network: Ipv4Network, members: &[&BlocklistEntry]) -> BlocklistEntry {
+    let distinct = |field: fn(&BlocklistEntry) -> &str| {
+        members.iter().map(|member| field(member)).collect::<BTreeSet<_>>().into_iter().collect::<Vec<_>>()
+    };
+    let same_type = members.iter().all(|member| member.threat_type == members[0].threat_type);
+
+    BlocklistEntry {
+        ip: network.to_string(),
+        threat_level: members.iter().map(|member| member.threat_level).max().unwrap_or(ThreatLevel::Info),
+        threat_type: if same_type { members[0].threat_type.clone() } else { ThreatType::Unknown },
+        context: format!("{} IPs aggregated: {}", members.len(), distinct(|member| &member.context).join("; ")),
+        agent_id: distinct(|member| &member.agent_id).join(","),
+        timestamp: members.iter().map(|member| member.timestamp).max().unwrap_or(0),
+        added_at: members.iter().map(|member| member.added_at).min().unwrap_or(0),
+    }
+}
+
+/// Sort key ordering IPs and CIDR ranges numerically by address;
+/// unparseable values sort last, lexicographically
+fn ip_sort_key(ip: &str) -> (bool, Option<IpAddr>, String) {
+    let address = ip.split_once('/').map_or(ip, |(address, _)| address);
+    let parsed = address.parse::<IpAddr>().ok();
+    (parsed.is_none(), parsed, ip.to_string())
+}
+
+/// Whether an entry's IP or CIDR range is IPv6
+fn is_ipv6_entry(ip: &str) -> bool {
+    ip.parse::<IpNetwork>().is_ok_and(|network| network.is_ipv6())
+}
+
+/// Receive the next IP to remove, or wait forever if there is no removal queue
+async fn recv_removal(removal_queue: &mut Option<mpsc::UnboundedReceiver<String>>) -> Option<String> {
+    match removal_queue {
+        Some(queue) => queue.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
 /// Function to create and start a blocklist exporter
 pub async fn start_blocklist_exporter(
     blocklist_file: String,
@@ -114,4 +826,545 @@ pub async fn start_blocklist_exporter(
 ) -> Result<()> {
     let mut exporter = BlocklistExporter::new(blocklist_file, min_threat_level, export_interval);
     exporter.start_export(evidence_queue).await
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allowlist::Allowlist;
+    use crate::crypto::Ed25519Signer;
+    use libp2p::identity;
+    use crate::clock::MockClock;
+    use crate::test_evidence;
+
+    impl BlocklistExporter {
+        /// IPs of the lines of `content` that parse as entries
+        fn parse_listed_ips(&self, content: &str) -> Vec<String> {
+            content
+                .lines()
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| self.parse_line(line).expect("every line should parse").ip)
+                .collect()
+        }
+    }
+
+    fn temp_blocklist_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("orasrs-{}-{}.txt", name, uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Remove a blocklist with the files written alongside it
+    fn remove_blocklist_files(path: &str) {
+        for file in [path.to_string(), entries_path(path), manifest_path(path)] {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    fn test_rewrite_is_deterministic() {
+        let ips = ["10.0.0.20", "2001:db8::1", "9.9.9.9", "10.0.0.3", "192.168.1.1", "::1"];
+        let path = temp_blocklist_path("deterministic");
+
+        let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300);
+        for ip in ips {
+            exporter.insert_entry(ip, &test_evidence(ip));
+        }
+
+        exporter.rewrite_blocklist().unwrap();
+        let first = std::fs::read(&path).unwrap();
+        exporter.rewrite_blocklist().unwrap();
+        let second = std::fs::read(&path).unwrap();
+        assert_eq!(first, second);
+
+        // Entries are sorted numerically, IPv4 before IPv6
+        let content = String::from_utf8(first).unwrap();
+        let listed: Vec<&str> = content
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(listed, vec!["9.9.9.9", "10.0.0.3", "10.0.0.20", "192.168.1.1", "::1", "2001:db8::1"]);
+
+        // Insertion order does not affect the output
+        let mut reversed = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300);
+        for ip in ips.iter().rev() {
+            reversed.insert_entry(ip, &test_evidence(ip));
+        }
+        reversed.last_modified = exporter.last_modified;
+        reversed.rewrite_blocklist().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+
+        remove_blocklist_files(&path);
+    }
+
+    #[test]
+    fn test_remove_from_blocklist() {
+        let path = temp_blocklist_path("remove");
+        let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300);
+        exporter.initialize_blocklist_file().unwrap();
+        for ip in ["10.0.0.1", "10.0.0.2"] {
+            assert!(exporter.process_evidence(&test_evidence(ip)).unwrap());
+        }
+
+        assert!(exporter.remove_from_blocklist("10.0.0.1").unwrap());
+        assert!(!exporter.remove_from_blocklist("10.0.0.1").unwrap());
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("10.0.0.1 "));
+        assert!(content.contains("10.0.0.2 "));
+
+        remove_blocklist_files(&path);
+    }
+
+    #[test]
+    fn test_delta_export_contains_only_changes() {
+        let path = temp_blocklist_path("delta-full");
+        let delta_path = temp_blocklist_path("delta");
+        let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_delta_export(delta_path.clone());
+        exporter.initialize_blocklist_file().unwrap();
+
+        let delta_lines = || -> Vec<String> {
+            std::fs::read_to_string(&delta_path)
+                .unwrap()
+                .lines()
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.split(' ').next().unwrap().to_string())
+                .collect()
+        };
+
+        // First cycle: everything is new
+        for ip in ["10.0.0.1", "10.0.0.2"] {
+            exporter.process_evidence(&test_evidence(ip)).unwrap();
+        }
+        assert!(exporter.delta_pending());
+        exporter.export_blocklist().unwrap();
+        assert_eq!(delta_lines(), vec!["+10.0.0.1", "+10.0.0.2"]);
+        assert!(!exporter.delta_pending());
+
+        // Second cycle: only the addition and the removal are emitted
+        exporter.process_evidence(&test_evidence("10.0.0.3")).unwrap();
+        exporter.remove_from_blocklist("10.0.0.1").unwrap();
+        exporter.export_blocklist().unwrap();
+        assert_eq!(delta_lines(), vec!["+10.0.0.3", "-10.0.0.1"]);
+
+        // The full blocklist is still written alongside
+        let full = std::fs::read_to_string(&path).unwrap();
+        assert!(full.contains("10.0.0.2 ") && full.contains("10.0.0.3 "));
+        assert!(!full.contains("10.0.0.1 "));
+
+        remove_blocklist_files(&path);
+        let _ = std::fs::remove_file(&delta_path);
+    }
+
+    #[test]
+    fn test_formats_render_expected_syntax() {
+        let header = "# OraSRS Agent Blocklist\n\
+                      # Generated: 2023-11-14T22:13:20+00:00\n\
+                      # Contains IP addresses detected as threats by OraSRS Agent\n\
+                      # Minimum threat level: Warning\n";
+        let cases = [
+            (BlocklistFormat::PlainWithComments, "",
+             "203.0.113.5 # CRITICAL - malware - Test threat - Agent: test-agent\n"),
+            (BlocklistFormat::IpsetRestore, "# Load with: ipset restore -exist < <file>\n",
+             "add orasrs_blocklist 203.0.113.5\n"),
+            (BlocklistFormat::IptablesRules, "# Apply with: sh <file>\n",
+             "iptables -A INPUT -s 203.0.113.5 -j DROP\n"),
+            (BlocklistFormat::NftablesSet, "# Load with: nft -f <file>\n",
+             "add table inet filter\n\
+              add set inet filter orasrs_blocklist { type ipv4_addr; flags interval; }\n\
+              add set inet filter orasrs_blocklist6 { type ipv6_addr; flags interval; }\n\
+              add element inet filter orasrs_blocklist { 203.0.113.5 }\n"),
+        ];
+
+        for (format, usage, entry) in cases {
+            let path = temp_blocklist_path("format");
+            let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300).with_format(format);
+            exporter.insert_entry("203.0.113.5", &test_evidence("203.0.113.5"));
+            exporter.last_modified = 1_700_000_000;
+            exporter.rewrite_blocklist().unwrap();
+
+            let expected = format!("{}{}\n{}", header, usage, entry);
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), expected, "{:?}", format);
+            remove_blocklist_files(&path);
+        }
+    }
+
+    #[test]
+    fn test_firewall_formats_split_address_families() {
+        for (format, v4, v6) in [
+            (BlocklistFormat::IptablesRules,
+             "iptables -A INPUT -s 203.0.113.5 -j DROP", "ip6tables -A INPUT -s 2001:db8::1 -j DROP"),
+            (BlocklistFormat::NftablesSet,
+             "add element inet filter orasrs_blocklist { 203.0.113.5 }",
+             "add element inet filter orasrs_blocklist6 { 2001:db8::1 }"),
+        ] {
+            let path = temp_blocklist_path("family");
+            let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300).with_format(format);
+            exporter.initialize_blocklist_file().unwrap();
+            for ip in ["203.0.113.5", "2001:db8::1"] {
+                exporter.process_evidence(&test_evidence(ip)).unwrap();
+            }
+            exporter.export_blocklist().unwrap();
+
+            let content = std::fs::read_to_string(&path).unwrap();
+            let lines: Vec<&str> = content.lines().filter(|line| line.contains(" -s ") || line.starts_with("add element")).collect();
+            assert_eq!(lines, vec![v4, v6], "{:?}", format);
+
+            // The set creation statements are not mistaken for entries
+            std::fs::remove_file(entries_path(&path)).unwrap();
+            let mut restarted = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300).with_format(format);
+            restarted.initialize_blocklist_file().unwrap();
+            assert_eq!(restarted.threat_cache.len(), 2, "{:?}", format);
+            remove_blocklist_files(&path);
+        }
+    }
+
+    #[test]
+    fn test_json_stays_valid_across_additions() {
+        let path = temp_blocklist_path("json");
+        let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_format(BlocklistFormat::Json);
+        exporter.initialize_blocklist_file().unwrap();
+
+        let read_entries = || -> Vec<BlocklistEntry> {
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+        };
+        assert!(read_entries().is_empty());
+
+        for ip in ["10.0.0.2", "10.0.0.1"] {
+            assert!(exporter.process_evidence(&test_evidence(ip)).unwrap());
+        }
+        exporter.export_blocklist().unwrap();
+        let entries = read_entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].ip, "10.0.0.1");
+        assert_eq!(entries[1].ip, "10.0.0.2");
+        for entry in &entries {
+            assert_eq!(entry.threat_type, ThreatType::Malware);
+            assert_eq!(entry.threat_level, ThreatLevel::Critical);
+            assert_eq!(entry.context, "Test threat");
+            assert_eq!(entry.agent_id, "test-agent");
+            assert_eq!(entry.timestamp, 1_700_000_000);
+        }
+
+        exporter.remove_from_blocklist("10.0.0.1").unwrap();
+        assert_eq!(read_entries().iter().map(|e| e.ip.as_str()).collect::<Vec<_>>(), vec!["10.0.0.2"]);
+
+        remove_blocklist_files(&path);
+    }
+
+    #[test]
+    fn test_csv_has_header_and_quoted_fields() {
+        let path = temp_blocklist_path("csv");
+        let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_format(BlocklistFormat::Csv);
+        exporter.initialize_blocklist_file().unwrap();
+
+        let mut evidence = test_evidence("203.0.113.5");
+        evidence.context = "Beacon to \"c2\", port 443".to_string();
+        exporter.process_evidence(&evidence).unwrap();
+        exporter.export_blocklist().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "ip,threat_type,threat_level,context,agent_id,timestamp\n\
+             203.0.113.5,Malware,Critical,\"Beacon to \"\"c2\"\", port 443\",test-agent,1700000000\n"
+        );
+
+        remove_blocklist_files(&path);
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let path = temp_blocklist_path("ttl");
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_entry_ttl(3600)
+            .with_clock(clock.clone());
+        exporter.initialize_blocklist_file().unwrap();
+
+        exporter.process_evidence(&test_evidence("10.0.0.1")).unwrap();
+        clock.advance(1800);
+        exporter.process_evidence(&test_evidence("10.0.0.2")).unwrap();
+
+        // Only the first entry has outlived the TTL
+        clock.advance(1800);
+        assert_eq!(exporter.expire_entries(), 1);
+        exporter.export_blocklist().unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("10.0.0.1 "));
+        assert!(content.contains("10.0.0.2 "));
+
+        // An expired IP is listed again when seen again
+        assert!(exporter.process_evidence(&test_evidence("10.0.0.1")).unwrap());
+        exporter.export_blocklist().unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().contains("10.0.0.1 "));
+
+        // A restart keeps the time each entry was listed, so the TTL does not start over
+        let mut restarted = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_entry_ttl(3600)
+            .with_clock(clock.clone());
+        restarted.initialize_blocklist_file().unwrap();
+        assert_eq!(restarted.threat_cache.len(), 2);
+        clock.advance(1800);
+        assert_eq!(restarted.expire_entries(), 1);
+        assert!(restarted.threat_cache.contains_key("10.0.0.1"));
+
+        remove_blocklist_files(&path);
+    }
+
+    #[test]
+    fn test_restart_restores_listed_ips() {
+        for format in [
+            BlocklistFormat::PlainWithComments,
+            BlocklistFormat::IpsetRestore,
+            BlocklistFormat::IptablesRules,
+            BlocklistFormat::NftablesSet,
+            BlocklistFormat::Json,
+            BlocklistFormat::Csv,
+        ] {
+            let path = temp_blocklist_path("restore");
+            let mut first = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300).with_format(format);
+            first.initialize_blocklist_file().unwrap();
+            for ip in ["10.0.0.1", "2001:db8::1"] {
+                first.process_evidence(&test_evidence(ip)).unwrap();
+            }
+            first.export_blocklist().unwrap();
+            let written = std::fs::read_to_string(&path).unwrap();
+
+            // A restarted exporter keeps the file and knows what it lists
+            let mut second = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300).with_format(format);
+            second.initialize_blocklist_file().unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), written, "{:?}", format);
+            assert_eq!(second.threat_cache.len(), 2, "{:?}", format);
+
+            // Previously listed IPs are not emitted again, new ones are
+            assert!(!second.process_evidence(&test_evidence("10.0.0.1")).unwrap(), "{:?}", format);
+            assert!(second.process_evidence(&test_evidence("10.0.0.2")).unwrap(), "{:?}", format);
+            second.export_blocklist().unwrap();
+            let content = std::fs::read_to_string(&path).unwrap();
+            assert!(content.starts_with(&written[..written.find('\n').unwrap()]), "{:?}", format);
+            assert_eq!(content.matches("10.0.0.1").count(), 1, "{:?}", format);
+            assert_eq!(content.matches("10.0.0.2").count(), 1, "{:?}", format);
+
+            remove_blocklist_files(&path);
+        }
+    }
+
+    #[test]
+    fn test_restore_keeps_entry_metadata() {
+        for format in [BlocklistFormat::PlainWithComments, BlocklistFormat::Json, BlocklistFormat::Csv] {
+            let path = temp_blocklist_path("restore-metadata");
+            let mut first = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300).with_format(format);
+            first.initialize_blocklist_file().unwrap();
+            let mut evidence = test_evidence("203.0.113.5");
+            evidence.context = "Beacon - \"c2\", port 443".to_string();
+            first.process_evidence(&evidence).unwrap();
+            first.export_blocklist().unwrap();
+
+            // Without the saved entries the metadata is read back from the blocklist itself
+            std::fs::remove_file(entries_path(&path)).unwrap();
+            let mut second = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300).with_format(format);
+            second.initialize_blocklist_file().unwrap();
+            let mut restored = second.threat_cache["203.0.113.5"].clone();
+            restored.added_at = 0;
+            let mut expected = BlocklistEntry::from_evidence("203.0.113.5", &evidence, 0);
+            if format == BlocklistFormat::PlainWithComments {
+                expected.timestamp = 0; // Not part of the plain format
+            }
+            assert_eq!(restored, expected, "{:?}", format);
+
+            remove_blocklist_files(&path);
+        }
+    }
+
+    #[test]
+    fn test_allowlisted_ips_are_skipped() {
+        let path = temp_blocklist_path("allowlist");
+        let allowlist = Allowlist::parse(&["192.0.2.53".to_string(), "198.51.100.0/24".to_string()]).unwrap();
+        let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_allowlist(Arc::new(std::sync::RwLock::new(allowlist)));
+        exporter.initialize_blocklist_file().unwrap();
+
+        // Exact match
+        assert!(!exporter.process_evidence(&test_evidence("192.0.2.53")).unwrap());
+        // Inside the CIDR range
+        assert!(!exporter.process_evidence(&test_evidence("198.51.100.200")).unwrap());
+        // Neighbours of both are still listed
+        assert!(exporter.process_evidence(&test_evidence("192.0.2.54")).unwrap());
+        assert!(exporter.process_evidence(&test_evidence("198.51.101.1")).unwrap());
+        exporter.export_blocklist().unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("192.0.2.53 ") && !content.contains("198.51.100.200 "));
+        assert_eq!(exporter.threat_cache.len(), 2);
+
+        remove_blocklist_files(&path);
+    }
+
+    #[test]
+    fn test_export_replaces_file_atomically() {
+        let path = temp_blocklist_path("atomic");
+        let delta_path = temp_blocklist_path("atomic-delta");
+        let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_delta_export(delta_path.clone());
+        exporter.initialize_blocklist_file().unwrap();
+
+        let ips: Vec<String> = (1..=200).rev().map(|i| format!("10.0.{}.{}", i / 100, i % 100)).collect();
+        for ip in ips.iter().chain(ips.iter()) {
+            exporter.process_evidence(&test_evidence(ip)).unwrap();
+        }
+
+        // Nothing is written until the export cycle
+        let before = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(exporter.parse_listed_ips(&before), Vec::<String>::new());
+
+        exporter.export_blocklist().unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.ends_with('\n'));
+        let listed = exporter.parse_listed_ips(&content);
+        assert_eq!(listed.len(), content.lines().filter(|line| !line.is_empty() && !line.starts_with('#')).count());
+
+        // Every IP appears once, in numeric order
+        let mut expected = ips.clone();
+        expected.sort_by_cached_key(|ip| ip_sort_key(ip));
+        assert_eq!(listed, expected);
+
+        // The temporary files were renamed away
+        for written in [&path, &delta_path] {
+            let written = Path::new(written);
+            let tmp = written.with_file_name(format!(".{}.tmp", written.file_name().unwrap().to_string_lossy()));
+            assert!(!tmp.exists());
+        }
+
+        remove_blocklist_files(&path);
+        let _ = std::fs::remove_file(&delta_path);
+    }
+
+    fn aggregated_lines(exporter: &BlocklistExporter) -> Vec<String> {
+        exporter.output_entries().iter().map(|entry| exporter.format_entry(entry)).collect()
+    }
+
+    #[test]
+    fn test_cidr_aggregation_at_threshold() {
+        let path = temp_blocklist_path("aggregate");
+        let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_cidr_aggregation(3, false);
+
+        // Two IPs from the /24 stay individual
+        for ip in ["203.0.113.7", "203.0.113.200", "198.51.100.1"] {
+            exporter.insert_entry(ip, &test_evidence(ip));
+        }
+        assert_eq!(aggregated_lines(&exporter), vec![
+            "198.51.100.1 # CRITICAL - malware - Test threat - Agent: test-agent",
+            "203.0.113.7 # CRITICAL - malware - Test threat - Agent: test-agent",
+            "203.0.113.200 # CRITICAL - malware - Test threat - Agent: test-agent",
+        ]);
+
+        // The third collapses them into the range
+        let mut evidence = test_evidence("203.0.113.9");
+        evidence.threat_level = ThreatLevel::Emergency;
+        evidence.context = "Port scan".to_string();
+        exporter.insert_entry("203.0.113.9", &evidence);
+        assert_eq!(aggregated_lines(&exporter), vec![
+            "198.51.100.1 # CRITICAL - malware - Test threat - Agent: test-agent",
+            "203.0.113.0/24 # EMERGENCY - malware - 3 IPs aggregated: Port scan; Test threat - Agent: test-agent",
+        ]);
+
+        // The file carries the range while the cache keeps every IP
+        exporter.export_blocklist().unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("203.0.113.0/24 # "));
+        assert!(!content.contains("203.0.113.7 "));
+        assert_eq!(exporter.threat_cache.len(), 4);
+
+        // A restart restores the individual IPs rather than the range
+        let mut restarted = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_cidr_aggregation(3, false);
+        restarted.initialize_blocklist_file().unwrap();
+        assert_eq!(restarted.threat_cache.len(), 4);
+        assert!(!restarted.threat_cache.contains_key("203.0.113.0/24"));
+        assert_eq!(aggregated_lines(&restarted), aggregated_lines(&exporter));
+
+        // So removing one of them drops the range below the threshold again
+        assert!(restarted.remove_from_blocklist("203.0.113.9").unwrap());
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("203.0.113.0/24"));
+        assert!(content.contains("203.0.113.7 ") && content.contains("203.0.113.200 "));
+
+        // A range read back from the file alone is not listed twice
+        std::fs::remove_file(entries_path(&path)).unwrap();
+        let mut from_file = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_cidr_aggregation(3, false);
+        std::fs::write(&path, "203.0.113.0/24 # CRITICAL - malware - 3 IPs aggregated: Test threat - Agent: test-agent\n").unwrap();
+        from_file.initialize_blocklist_file().unwrap();
+        for ip in ["203.0.113.7", "203.0.113.8", "203.0.113.9"] {
+            from_file.insert_entry(ip, &test_evidence(ip));
+        }
+        assert_eq!(aggregated_lines(&from_file).iter().filter(|line| line.starts_with("203.0.113.0/24")).count(), 1);
+
+        remove_blocklist_files(&path);
+    }
+
+    #[test]
+    fn test_cidr_aggregation_respects_types_and_allowlist() {
+        let mut mixed = test_evidence("203.0.113.3");
+        mixed.threat_type = ThreatType::BruteForce;
+
+        let mut exporter = BlocklistExporter::new(temp_blocklist_path("aggregate-types"), ThreatLevel::Warning, 300)
+            .with_cidr_aggregation(3, false);
+        for ip in ["203.0.113.1", "203.0.113.2"] {
+            exporter.insert_entry(ip, &test_evidence(ip));
+        }
+        exporter.insert_entry("203.0.113.3", &mixed);
+        assert_eq!(exporter.output_entries().len(), 3);
+
+        // Conflicting types are only combined when configured to
+        exporter.aggregate_mixed_types = true;
+        let entries = exporter.output_entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].ip, "203.0.113.0/24");
+        assert_eq!(entries[0].threat_type, ThreatType::Unknown);
+
+        // A range covering an allowlisted address is never listed
+        *exporter.allowlist.write().unwrap() = Allowlist::parse(&["203.0.113.53".to_string()]).unwrap();
+        assert_eq!(exporter.output_entries().len(), 3);
+    }
+
+    #[test]
+    fn test_signed_manifest_round_trip() {
+        let path = temp_blocklist_path("signed");
+        let keypair = identity::Keypair::generate_ed25519();
+        let public_key = keypair.public().encode_protobuf();
+        let mut exporter = BlocklistExporter::new(path.clone(), ThreatLevel::Warning, 300)
+            .with_signer(Box::new(Ed25519Signer::new(keypair.clone())), "test-agent".to_string());
+        let scheme = Ed25519Signer::new(keypair);
+        exporter.initialize_blocklist_file().unwrap();
+        for ip in ["10.0.0.1", "10.0.0.2"] {
+            exporter.process_evidence(&test_evidence(ip)).unwrap();
+        }
+        exporter.export_blocklist().unwrap();
+
+        let manifest: BlocklistManifest =
+            serde_json::from_str(&std::fs::read_to_string(manifest_path(&path)).unwrap()).unwrap();
+        assert_eq!(manifest.agent_id, "test-agent");
+        assert_eq!(manifest.generated_at, exporter.last_modified);
+        assert!(BlocklistExporter::verify(&path, &scheme, &public_key).unwrap());
+
+        // Another key is not accepted as the signer
+        let other = identity::Keypair::generate_ed25519().public().encode_protobuf();
+        assert!(!BlocklistExporter::verify(&path, &scheme, &other).unwrap());
+
+        // Changing a single byte breaks verification
+        let mut content = std::fs::read(&path).unwrap();
+        let last = content.len() - 2;
+        content[last] ^= 0x01;
+        std::fs::write(&path, &content).unwrap();
+        assert!(!BlocklistExporter::verify(&path, &scheme, &public_key).unwrap());
+
+        remove_blocklist_files(&path);
+    }
+}
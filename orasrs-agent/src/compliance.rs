@@ -1,10 +1,115 @@
-use crate::{AgentConfig, error::{AgentError, Result}};
+use crate::{AgentConfig, config::GeoFenceConfig, error::{AgentError, Result}, evidence_store::EvidenceStore, is_target_placeholder, monitor::GeoFenceMonitor};
+use libp2p::{Multiaddr, multiaddr::Protocol};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Policy query used to decide whether a data processing action is allowed
+pub const PROCESSING_POLICY_QUERY: &str = "data.orasrs.compliance.allow_processing";
+
+/// Policy query used to decide whether an agent configuration is allowed
+pub const CONFIG_POLICY_QUERY: &str = "data.orasrs.compliance.allow_config";
+
+/// IPv4 and IPv6 prefix lengths kept when anonymizing at a privacy level, or None for full IPs
+pub fn anonymization_prefixes(privacy_level: u8) -> Option<(u8, u8)> {
+    match privacy_level {
+        1 => Some((24, 64)), // GDPR: anonymize to /24 and /64
+        2 => Some((16, 48)), // CCPA: anonymize to /16 and /48
+        3 => None,           // China: full IP allowed
+        _ => Some((16, 48)), // Global: anonymize to /16 and /48
+    }
+}
+
+/// Zero all bits of an IP beyond the prefix length for its family
+pub fn mask_ip(ip: IpAddr, ipv4_prefix: u8, ipv6_prefix: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX.checked_shl(32 - ipv4_prefix.min(32) as u32).unwrap_or(0);
+            IpAddr::V4((u32::from(v4) & mask).into())
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX.checked_shl(128 - ipv6_prefix.min(128) as u32).unwrap_or(0);
+            IpAddr::V6((u128::from(v6) & mask).into())
+        }
+    }
+}
+
+/// Anonymize an IP to its network address, e.g. `2001:db8::1` at /64 becomes `2001:db8::`
+pub fn anonymize_ip(ip: &str, ipv4_prefix: u8, ipv6_prefix: u8) -> Result<String> {
+    let parsed: IpAddr = ip.trim().parse().map_err(|_| {
+        AgentError::ComplianceError(format!("Cannot anonymize malformed IP: {}", ip))
+    })?;
+    Ok(mask_ip(parsed, ipv4_prefix, ipv6_prefix).to_string())
+}
+
+/// What an IP field holding neither an IP nor a placeholder is published as
+const REDACTED_IP: &str = "redacted";
+
+/// Anonymize an evidence IP field
+///
+/// Placeholders such as `unknown` or `local` pass through unchanged, and any
+/// other value that is not an IP is redacted rather than published.
+pub fn anonymize_ip_field(value: &str, ipv4_prefix: u8, ipv6_prefix: u8) -> String {
+    if value == "unknown" || is_target_placeholder(value) {
+        return value.to_string();
+    }
+    anonymize_ip(value, ipv4_prefix, ipv6_prefix).unwrap_or_else(|_| REDACTED_IP.to_string())
+}
+
+/// Parse configured anonymization exemptions, skipping any that are not IPs
+pub fn parse_anonymization_exemptions(exemptions: &[String]) -> HashSet<IpAddr> {
+    exemptions
+        .iter()
+        .filter_map(|ip| match ip.parse::<IpAddr>() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                log::warn!("Ignoring invalid anonymization exemption: {}", ip);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Whether an IP is one of the given anonymization exemptions
+pub fn is_anonymization_exempt(exemptions: &HashSet<IpAddr>, ip: &str) -> bool {
+    ip.parse::<IpAddr>()
+        .map(|ip| exemptions.contains(&ip))
+        .unwrap_or(false)
+}
+
+/// External compliance policy evaluator
+///
+/// Lets compliance rules live outside the binary, e.g. as rego policies
+/// evaluated by an embedded or remote OPA engine.
+pub trait PolicyEvaluator: Send + Sync {
+    /// Evaluate a policy query against an input document, returning whether it is allowed
+    fn evaluate(&self, query: &str, input: &serde_json::Value) -> Result<bool>;
+}
+
+/// Days data is retained in a region
+fn retention_days(region: &str) -> u32 {
+    match region.to_lowercase().as_str() {
+        "cn" | "china" => 180, // China requires 180 days
+        _ => 30,               // Standard GDPR and CCPA
+    }
+}
+
+/// Directory under `data_dir` holding live agent state, which is pruned per record rather than swept
+const STATE_DIR: &str = "state";
+
+/// Directory under `data_dir` holding the compliance audit log, which outlives the retention window
+const AUDIT_DIR: &str = "audit";
 
 /// Compliance engine for OraSRS Agent
+#[derive(Clone)]
 pub struct ComplianceEngine {
     pub region: String,
     pub compliance_mode: String,
@@ -13,6 +118,9 @@ pub struct ComplianceEngine {
     pub gdpr_compliant: bool,
     pub ccpa_compliant: bool,
     pub china_compliant: bool,
+    policy_evaluator: Option<Arc<dyn PolicyEvaluator>>,
+    anonymization_exemptions: HashSet<IpAddr>, // Own infrastructure IPs kept intact
+    audit_log: AuditLog,
 }
 
 impl ComplianceEngine {
@@ -24,6 +132,8 @@ impl ComplianceEngine {
             _ => (true, true, false), // Default to GDPR + CCPA compliance
         };
 
+        let anonymization_exemptions = parse_anonymization_exemptions(&config.anonymization_exemptions);
+
         Self {
             region: config.region.clone(),
             compliance_mode: config.compliance_mode.clone(),
@@ -32,9 +142,27 @@ impl ComplianceEngine {
             gdpr_compliant,
             ccpa_compliant,
             china_compliant,
+            policy_evaluator: None,
+            anonymization_exemptions,
+            audit_log: AuditLog::new(config.storage_config.data_dir.join(AUDIT_DIR).join("compliance.jsonl")),
         }
     }
 
+    /// Delegate compliance decisions to an external policy evaluator
+    pub fn set_policy_evaluator(&mut self, evaluator: Arc<dyn PolicyEvaluator>) {
+        self.policy_evaluator = Some(evaluator);
+    }
+
+    /// Keep an IP intact in processed evidence regardless of compliance mode
+    pub fn add_anonymization_exemption(&mut self, ip: IpAddr) {
+        self.anonymization_exemptions.insert(ip);
+    }
+
+    /// Whether an IP is exempt from anonymization
+    pub fn is_anonymization_exempt(&self, ip: &str) -> bool {
+        is_anonymization_exempt(&self.anonymization_exemptions, ip)
+    }
+
     /// Initialize compliance settings based on region
     pub fn init_compliance(&mut self) -> Result<()> {
         log::info!("Initializing compliance engine for region: {}", self.region);
@@ -45,26 +173,26 @@ impl ComplianceEngine {
                 self.china_compliant = true;
                 self.gdpr_compliant = false;
                 self.ccpa_compliant = false;
-                self.data_retention_days = 180; // China requires 180 days
+                self.data_retention_days = retention_days(&self.region);
             },
             "eu" | "europe" => {
                 self.gdpr_compliant = true;
                 self.ccpa_compliant = false;
                 self.china_compliant = false;
-                self.data_retention_days = 30; // Standard GDPR
+                self.data_retention_days = retention_days(&self.region);
             },
             "us" | "usa" => {
                 self.ccpa_compliant = true;
                 self.gdpr_compliant = false;
                 self.china_compliant = false;
-                self.data_retention_days = 30; // Standard CCPA
+                self.data_retention_days = retention_days(&self.region);
             },
             _ => {
                 // Global default - try to comply with all where possible
                 self.gdpr_compliant = true;
                 self.ccpa_compliant = true;
                 self.china_compliant = false;
-                self.data_retention_days = 30;
+                self.data_retention_days = retention_days(&self.region);
             }
         }
 
@@ -76,6 +204,22 @@ impl ComplianceEngine {
 
     /// Check if data processing is compliant
     pub fn is_processing_compliant(&self, data_type: &str, data: &str) -> bool {
+        if let Some(evaluator) = &self.policy_evaluator {
+            let input = serde_json::json!({
+                "compliance_mode": self.compliance_mode,
+                "region": self.region,
+                "privacy_level": self.privacy_level,
+                "data_type": data_type,
+                "data": data,
+            });
+
+            // Fail closed if the policy can't be evaluated
+            return evaluator.evaluate(PROCESSING_POLICY_QUERY, &input).unwrap_or_else(|e| {
+                log::error!("Compliance policy evaluation failed: {}", e);
+                false
+            });
+        }
+
         match self.compliance_mode.as_str() {
             "gdpr" => self.check_gdpr_compliance(data_type, data),
             "ccpa" => self.check_ccpa_compliance(data_type, data),
@@ -122,36 +266,104 @@ impl ComplianceEngine {
         matches!(data_type, "network_flow" | "threat_evidence" | "anonymized_data")
     }
 
+    /// Delete data older than the retention window of `config`'s region
+    ///
+    /// Records in the evidence store are removed by their own timestamp, and
+    /// `expire_state` is given the cutoff to do the same for records kept
+    /// under `data_dir/state`, returning how many it removed. Other files
+    /// under `data_dir` are deleted by modification time, except the audit log.
+    pub async fn enforce_retention<F, Fut>(&self, config: &AgentConfig, store: &EvidenceStore, expire_state: F) -> Result<RetentionReport>
+    where
+        F: FnOnce(i64) -> Fut,
+        Fut: Future<Output = Result<usize>>,
+    {
+        let days = retention_days(&config.region);
+        let max_age = Duration::from_secs(u64::from(days) * 24 * 3600);
+        let cutoff = SystemTime::now().checked_sub(max_age).unwrap_or(SystemTime::UNIX_EPOCH);
+        let cutoff_secs = cutoff.duration_since(SystemTime::UNIX_EPOCH).map(|age| age.as_secs() as i64).unwrap_or(0);
+
+        let evidence_records = store.remove_older_than(cutoff_secs).await?;
+        let state_records = expire_state(cutoff_secs).await?;
+
+        let data_dir = config.storage_config.data_dir.clone();
+        let keep = vec![data_dir.join(STATE_DIR), data_dir.join(AUDIT_DIR), store.path().to_path_buf()];
+        let files = tokio::task::spawn_blocking(move || remove_files_older_than(&data_dir, cutoff, &keep))
+            .await
+            .map_err(|e| AgentError::InternalError(format!("Retention sweep failed: {}", e)))??;
+
+        let report = RetentionReport { files, evidence_records, state_records };
+        if report != RetentionReport::default() {
+            let data_dir = config.storage_config.data_dir.display().to_string();
+            log::info!("Retention of {} days {} from {}", days, report, data_dir);
+            // The files are gone either way, so a failed audit write does not fail the sweep
+            if let Err(e) = self.audit_log.append("retention", &data_dir, &report.to_string()).await {
+                log::error!("Failed to audit retention sweep of {}: {}", data_dir, e);
+            }
+        }
+        Ok(report)
+    }
+
     /// Handle GDPR data deletion request
-    pub fn handle_gdpr_deletion(&self, data_id: &str) -> Result<()> {
+    ///
+    /// Removes stored evidence whose source or target IP, before anonymization,
+    /// is `subject_ip`, then runs `erase_elsewhere` to erase the subject from
+    /// the other stores holding them. Nothing is erased unless GDPR applies.
+    /// The audit log names the subject by its key in the store rather than by IP.
+    /// A deletion that succeeded but could not be audited still returns its
+    /// report, with `audited` false.
+    pub async fn handle_gdpr_deletion<F>(&self, store: &EvidenceStore, subject_ip: &str, erase_elsewhere: F) -> Result<ErasureReport>
+    where
+        F: Future<Output = Result<ErasureReport>>,
+    {
         if !self.gdpr_compliant {
-            return Ok(());
+            return Ok(ErasureReport::default());
         }
 
-        log::info!("Processing GDPR deletion request for data: {}", data_id);
-        
-        // In a real implementation, this would delete user data
-        // For now, we'll just log the request
-        println!("GDPR deletion request processed for: {}", data_id);
-        
-        Ok(())
+        log::info!("Processing GDPR deletion request");
+        let subject = store.subject_key(subject_ip).unwrap_or_else(|| subject_ip.to_string());
+        let result = match store.remove_ip(subject_ip).await {
+            Ok(evidence_records) => erase_elsewhere.await.map(|report| ErasureReport { evidence_records, ..report }),
+            Err(e) => Err(e),
+        };
+        let outcome = match &result {
+            Ok(report) => report.to_string(),
+            Err(e) => format!("failed: {}", e),
+        };
+        let audited = self.audit_log.append("gdpr_deletion", &subject, &outcome).await;
+
+        let mut report = result?;
+        log::info!("GDPR deletion {}", report);
+        match audited {
+            Ok(_) => report.audited = true,
+            Err(e) => log::error!("GDPR deletion of {} succeeded but could not be audited: {}", subject, e),
+        }
+        Ok(report)
     }
 
     /// Handle CCPA "Do Not Sell" request
-    pub fn handle_ccpa_do_not_sell(&self, user_id: &str) -> Result<()> {
+    pub async fn handle_ccpa_do_not_sell(&self, user_id: &str) -> Result<()> {
         if !self.ccpa_compliant {
             return Ok(());
         }
 
         log::info!("Processing CCPA Do Not Sell request for user: {}", user_id);
         
-        // In a real implementation, this would update user preferences
-        // For now, we'll just log the request
-        println!("CCPA Do Not Sell request processed for: {}", user_id);
+        // Evidence is never sold, so the opt-out only needs to be recorded
+        self.audit_log.append("ccpa_do_not_sell", user_id, "recorded").await?;
         
         Ok(())
     }
 
+    /// The log of compliance actions taken by this engine
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit_log
+    }
+
+    /// Whether the audit log is intact, with every entry chained to the one before
+    pub async fn verify_audit_chain(&self) -> Result<bool> {
+        self.audit_log.verify().await
+    }
+
     /// Generate compliance report
     pub fn generate_compliance_report(&self) -> ComplianceReport {
         ComplianceReport {
@@ -170,16 +382,25 @@ impl ComplianceEngine {
 
     /// Validate that the agent configuration is compliant
     pub fn validate_config_compliance(&self, config: &AgentConfig) -> Result<()> {
-        if self.china_compliant {
-            // In China, data must be stored locally and not transferred abroad
-            if config.p2p_config.bootstrap_nodes.iter().any(|node| {
-                // Simplified check - in real implementation would check actual IP locations
-                node.contains("foreign") || node.contains("overseas")
-            }) {
+        if let Some(evaluator) = &self.policy_evaluator {
+            let input = serde_json::json!({
+                "compliance_mode": self.compliance_mode,
+                "region": self.region,
+                "config": config,
+            });
+
+            if !evaluator.evaluate(CONFIG_POLICY_QUERY, &input)? {
                 return Err(AgentError::ComplianceError(
-                    "China compliance: Cannot connect to foreign nodes".to_string()
+                    "Configuration rejected by compliance policy".to_string()
                 ));
             }
+
+            return Ok(());
+        }
+
+        if self.china_compliant {
+            // In China, data must be stored locally and not transferred abroad
+            validate_bootstrap_regions(config)?;
         }
 
         if self.gdpr_compliant {
@@ -195,6 +416,44 @@ impl ComplianceEngine {
     }
 }
 
+/// What a retention pass removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionReport {
+    pub files: usize,
+    pub evidence_records: usize,
+    pub state_records: usize, // Records pruned from agent state, such as verification requests
+}
+
+impl std::fmt::Display for RetentionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "removed {} files, {} evidence records and {} state records",
+            self.files, self.evidence_records, self.state_records
+        )
+    }
+}
+
+/// What a GDPR deletion removed from each store holding the subject
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ErasureReport {
+    pub evidence_records: usize,
+    pub consensus_requests: usize, // Verification requests, with their cached results
+    pub indicators: usize,         // Local and upstream threat indicators
+    pub buffered_events: usize,    // Correlation events and evidence tracked for disputes
+    pub audited: bool,             // Whether the deletion was recorded in the audit log
+}
+
+impl std::fmt::Display for ErasureReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "removed {} evidence records, {} verification requests, {} indicators and {} buffered events",
+            self.evidence_records, self.consensus_requests, self.indicators, self.buffered_events
+        )
+    }
+}
+
 /// Compliance report structure
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ComplianceReport {
@@ -218,4 +477,485 @@ pub struct DataDeletionRequest {
     pub request_type: String, // "gdpr", "ccpa", etc.
     pub timestamp: i64,
     pub status: String, // "pending", "completed", "failed"
-}
\ No newline at end of file
+}
+
+/// Reject bootstrap nodes located outside China
+///
+/// Nodes whose country can't be resolved, because no GeoIP database is
+/// available or the address has no IP, need `allow_unverified_nodes`.
+fn validate_bootstrap_regions(config: &AgentConfig) -> Result<()> {
+    let locator = config.geoip_database.as_ref()
+        .map(|path| GeoFenceMonitor::new(false, GeoFenceConfig::default()).with_database(path))
+        .filter(GeoFenceMonitor::has_database);
+
+    for node in &config.p2p_config.bootstrap_nodes {
+        let country = bootstrap_ip(node)
+            .zip(locator.as_ref())
+            .and_then(|(ip, locator)| locator.lookup(&ip.to_string()))
+            .map(|(country, _)| country)
+            .filter(|country| !country.is_empty());
+        match country {
+            Some(country) if country.eq_ignore_ascii_case("CN") => {}
+            Some(country) => {
+                return Err(AgentError::ComplianceError(format!(
+                    "China compliance: bootstrap node {} is located in {}", node, country
+                )));
+            }
+            None if config.allow_unverified_nodes => {
+                log::warn!("China compliance: region of bootstrap node {} is unverified", node);
+            }
+            None => {
+                return Err(AgentError::ComplianceError(format!(
+                    "China compliance: cannot verify the region of bootstrap node {}; \
+                     configure geoip_database or set allow_unverified_nodes", node
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// IP address of a bootstrap multiaddr, if it has one
+fn bootstrap_ip(node: &str) -> Option<IpAddr> {
+    node.parse::<Multiaddr>().ok()?.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+/// One compliance action in the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: i64,
+    pub action: String,  // "gdpr_deletion", "ccpa_do_not_sell", "retention"
+    pub subject: String, // IP, user or directory the action applied to
+    pub result: String,
+    pub prev_hash: String, // Empty for the first entry
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// BLAKE3 over the previous entry's hash and this entry's fields
+    fn compute_hash(&self) -> String {
+        let timestamp = self.timestamp.to_string();
+        let mut hasher = blake3::Hasher::new();
+        for field in [&self.prev_hash, &timestamp, &self.action, &self.subject, &self.result] {
+            // Length-prefix fields so moving bytes between them changes the hash
+            hasher.update(&(field.len() as u64).to_le_bytes());
+            hasher.update(field.as_bytes());
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+}
+
+/// Append-only log of compliance actions, stored as JSON lines
+///
+/// Each entry's hash covers the hash of the entry before it, so editing,
+/// removing or reordering entries breaks the chain.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    last_hash: Arc<Mutex<Option<String>>>, // Hash of the newest entry, read from disk when unknown
+}
+
+impl AuditLog {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            last_hash: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Append an entry chained to the newest one
+    pub async fn append(&self, action: &str, subject: &str, result: &str) -> Result<AuditEntry> {
+        let mut last_hash = self.last_hash.lock().await;
+        let prev_hash = match last_hash.take() {
+            Some(hash) => hash,
+            None => self.entries().await?.last().map(|entry| entry.hash.clone()).unwrap_or_default(),
+        };
+
+        let mut entry = AuditEntry {
+            timestamp: chrono::Utc::now().timestamp(),
+            action: action.to_string(),
+            subject: subject.to_string(),
+            result: result.to_string(),
+            prev_hash,
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+
+        *last_hash = Some(entry.hash.clone());
+        Ok(entry)
+    }
+
+    /// All entries, oldest first
+    pub async fn entries(&self) -> Result<Vec<AuditEntry>> {
+        let contents = match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(Into::into))
+            .collect()
+    }
+
+    /// Whether every entry matches its hash and links to the entry before it
+    pub async fn verify(&self) -> Result<bool> {
+        let entries = match self.entries().await {
+            Ok(entries) => entries,
+            Err(AgentError::SerializationError(_)) => return Ok(false), // A mangled line is tampering too
+            Err(e) => return Err(e),
+        };
+
+        let mut prev_hash = String::new();
+        for entry in entries {
+            if entry.prev_hash != prev_hash || entry.hash != entry.compute_hash() {
+                return Ok(false);
+            }
+            prev_hash = entry.hash;
+        }
+        Ok(true)
+    }
+}
+
+/// Remove regular files under `dir` modified before `cutoff`, skipping the `keep` directories
+fn remove_files_older_than(dir: &Path, cutoff: SystemTime, keep: &[PathBuf]) -> Result<usize> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?; // Symlinks are neither followed nor removed
+        if file_type.is_dir() && !keep.contains(&path) {
+            removed += remove_files_older_than(&path, cutoff, keep)?;
+        } else if file_type.is_file() && !keep.contains(&path) && entry.metadata()?.modified()? < cutoff {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StorageConfig;
+
+    /// Policy that denies processing of a single data type and any GDPR config
+    struct DenyPolicy {
+        denied_data_type: String,
+    }
+
+    impl PolicyEvaluator for DenyPolicy {
+        fn evaluate(&self, query: &str, input: &serde_json::Value) -> Result<bool> {
+            match query {
+                PROCESSING_POLICY_QUERY => Ok(input["data_type"] != self.denied_data_type.as_str()),
+                CONFIG_POLICY_QUERY => Ok(input["config"]["compliance_mode"] != "gdpr"),
+                _ => Err(AgentError::ComplianceError(format!("Unknown query: {}", query))),
+            }
+        }
+    }
+
+    #[test]
+    fn test_policy_evaluator_blocks_processing() {
+        let config = AgentConfig::default();
+        let mut engine = ComplianceEngine::new(&config);
+
+        // The built-in rules allow threat evidence in global mode
+        assert!(engine.is_processing_compliant("threat_evidence", "1.2.3.4"));
+
+        engine.set_policy_evaluator(Arc::new(DenyPolicy {
+            denied_data_type: "threat_evidence".to_string(),
+        }));
+        assert!(!engine.is_processing_compliant("threat_evidence", "1.2.3.4"));
+        assert!(engine.is_processing_compliant("network_flow", "flow"));
+    }
+
+    #[test]
+    fn test_policy_evaluator_rejects_config() {
+        let mut config = AgentConfig::default();
+        let mut engine = ComplianceEngine::new(&config);
+        engine.set_policy_evaluator(Arc::new(DenyPolicy {
+            denied_data_type: "none".to_string(),
+        }));
+
+        assert!(engine.validate_config_compliance(&config).is_ok());
+
+        config.compliance_mode = "gdpr".to_string();
+        assert!(matches!(
+            engine.validate_config_compliance(&config),
+            Err(AgentError::ComplianceError(_))
+        ));
+    }
+
+    #[test]
+    fn test_anonymize_ipv4_and_ipv6() {
+        assert_eq!(anonymize_ip("203.0.113.57", 24, 64).unwrap(), "203.0.113.0");
+        assert_eq!(anonymize_ip("203.0.113.57", 16, 48).unwrap(), "203.0.0.0");
+        assert_eq!(anonymize_ip("2001:db8::1", 24, 64).unwrap(), "2001:db8::");
+        assert_eq!(anonymize_ip("2001:db8:aaaa:bbbb:1::1", 24, 64).unwrap(), "2001:db8:aaaa:bbbb::");
+        assert_eq!(anonymize_ip("2001:db8:aaaa:bbbb:1::1", 16, 48).unwrap(), "2001:db8:aaaa::");
+        assert_eq!(anonymize_ip("203.0.113.57", 32, 128).unwrap(), "203.0.113.57");
+    }
+
+    #[test]
+    fn test_anonymize_malformed_ip_is_an_error() {
+        for ip in ["", "unknown", "203.0.113.300", "2001:db8::zz"] {
+            assert!(matches!(anonymize_ip(ip, 24, 64), Err(AgentError::ComplianceError(_))), "{:?}", ip);
+        }
+    }
+
+    #[test]
+    fn test_anonymize_ip_field_keeps_placeholders_and_redacts_garbage() {
+        assert_eq!(anonymize_ip_field("203.0.113.57", 24, 64), "203.0.113.0");
+        for placeholder in ["unknown", "local", "global"] {
+            assert_eq!(anonymize_ip_field(placeholder, 24, 64), placeholder);
+        }
+        for value in ["", "host.example.com", "203.0.113.300"] {
+            assert_eq!(anonymize_ip_field(value, 24, 64), "redacted", "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn test_anonymize_prefix_boundaries() {
+        let cases = [
+            (0, "0.0.0.0", "::"),
+            (8, "203.0.0.0", "2000::"),
+            (16, "203.0.0.0", "2001::"),
+            (24, "203.0.113.0", "2001:d00::"),
+            (32, "203.0.113.57", "2001:db8::"),
+        ];
+        for (bits, expected_v4, expected_v6) in cases {
+            assert_eq!(anonymize_ip("203.0.113.57", bits, bits).unwrap(), expected_v4, "/{}", bits);
+            assert_eq!(anonymize_ip("2001:db8:1::1", bits, bits).unwrap(), expected_v6, "/{}", bits);
+        }
+
+        // Prefixes longer than the address family keep the address intact
+        assert_eq!(anonymize_ip("203.0.113.57", 40, 128).unwrap(), "203.0.113.57");
+        assert_eq!(anonymize_ip("2001:db8:1::1", 32, 200).unwrap(), "2001:db8:1::1");
+    }
+
+    #[tokio::test]
+    async fn test_retention_removes_only_old_data() {
+        let dir = std::env::temp_dir().join(format!("orasrs-retention-{}", uuid::Uuid::new_v4()));
+        let mut config = AgentConfig {
+            region: "eu".to_string(),
+            storage_config: StorageConfig {
+                data_dir: dir.clone(),
+                ..StorageConfig::default()
+            },
+            ..AgentConfig::default()
+        };
+        let engine = ComplianceEngine::new(&config);
+        let store = EvidenceStore::new(dir.join("evidence.jsonl"), [7; 32]);
+
+        let old = SystemTime::now() - Duration::from_secs(31 * 24 * 3600);
+        let old_secs = chrono::Utc::now().timestamp() - 31 * 24 * 3600;
+        for (id, timestamp) in [("old", old_secs), ("new", chrono::Utc::now().timestamp())] {
+            let evidence = crate::ThreatEvidence::builder("203.0.113.0", crate::ThreatType::SuspiciousConnection, crate::ThreatLevel::Warning)
+                .id(id)
+                .timestamp(timestamp)
+                .build();
+            store.append(&store.record("203.0.113.7", "local", evidence)).await.unwrap();
+        }
+
+        let write = |path: &Path, modified: Option<SystemTime>| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let file = fs::File::create(path).unwrap();
+            if let Some(modified) = modified {
+                file.set_modified(modified).unwrap();
+            }
+        };
+        write(&dir.join("evidence-old.json"), Some(old));
+        write(&dir.join("logs/agent-old.log"), Some(old));
+        write(&dir.join("evidence-new.json"), None);
+        write(&dir.join("logs/agent-new.log"), None);
+        write(&dir.join("state/checkpoint"), Some(old));
+        write(&dir.join("audit/compliance.jsonl"), Some(old));
+
+        let mut state_cutoff = 0;
+        let expire_state = |cutoff| {
+            state_cutoff = cutoff;
+            async { Ok(3) }
+        };
+        let report = engine.enforce_retention(&config, &store, expire_state).await.unwrap();
+        assert_eq!(report, RetentionReport { files: 2, evidence_records: 1, state_records: 3 });
+        assert!((state_cutoff - (old_secs + 24 * 3600)).abs() < 60);
+        let remaining = store.load().await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].evidence.id, "new");
+        assert!(!dir.join("evidence-old.json").exists());
+        assert!(!dir.join("logs/agent-old.log").exists());
+        assert!(dir.join("evidence-new.json").exists());
+        assert!(dir.join("logs/agent-new.log").exists());
+        assert!(dir.join("state/checkpoint").exists());
+        assert!(dir.join("audit/compliance.jsonl").exists());
+
+        // The region of the config passed in decides the window, so a longer one keeps everything,
+        // and a missing directory is not an error
+        config.region = "cn".to_string();
+        write(&dir.join("evidence-old.json"), Some(old));
+        let report = engine.enforce_retention(&config, &store, |_| async { Ok(0) }).await.unwrap();
+        assert_eq!(report, RetentionReport::default());
+        fs::remove_dir_all(&dir).unwrap();
+        let report = engine.enforce_retention(&config, &store, |_| async { Ok(0) }).await.unwrap();
+        assert_eq!(report, RetentionReport::default());
+    }
+
+    #[tokio::test]
+    async fn test_gdpr_deletion_removes_only_subject_evidence() {
+        let dir = std::env::temp_dir().join(format!("orasrs-gdpr-{}", uuid::Uuid::new_v4()));
+        let store = EvidenceStore::new(dir.join("evidence.jsonl"), [7; 32]);
+        let config = AgentConfig {
+            compliance_mode: "gdpr".to_string(),
+            storage_config: StorageConfig {
+                data_dir: dir.clone(),
+                ..StorageConfig::default()
+            },
+            ..AgentConfig::default()
+        };
+        let engine = ComplianceEngine::new(&config);
+
+        for ip in ["203.0.113.7", "198.51.100.1"] {
+            let evidence = crate::ThreatEvidence::builder("203.0.113.0", crate::ThreatType::SuspiciousConnection, crate::ThreatLevel::Warning)
+                .id(ip)
+                .build();
+            store.append(&store.record(ip, "local", evidence)).await.unwrap();
+        }
+
+        let elsewhere = async { Ok(ErasureReport { indicators: 2, ..ErasureReport::default() }) };
+        let report = engine.handle_gdpr_deletion(&store, "203.0.113.7", elsewhere).await.unwrap();
+        assert_eq!(report, ErasureReport { evidence_records: 1, indicators: 2, audited: true, ..ErasureReport::default() });
+        let remaining = store.load().await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].evidence.id, "198.51.100.1");
+        assert!(report.audited);
+        let entry = &engine.audit_log().entries().await.unwrap()[0];
+        assert_eq!(entry.subject, store.subject_key("203.0.113.7").unwrap());
+        assert_eq!(entry.result, report.to_string());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unaudited_gdpr_deletion_still_reports_removal() {
+        let dir = std::env::temp_dir().join(format!("orasrs-gdpr-{}", uuid::Uuid::new_v4()));
+        let store = EvidenceStore::new(dir.join("evidence.jsonl"), [7; 32]);
+        let config = AgentConfig {
+            compliance_mode: "gdpr".to_string(),
+            storage_config: StorageConfig {
+                data_dir: dir.clone(),
+                ..StorageConfig::default()
+            },
+            ..AgentConfig::default()
+        };
+        let engine = ComplianceEngine::new(&config);
+
+        let evidence = crate::ThreatEvidence::builder("203.0.113.0", crate::ThreatType::SuspiciousConnection, crate::ThreatLevel::Warning).build();
+        store.append(&store.record("203.0.113.7", "local", evidence)).await.unwrap();
+        // A file in place of the audit directory makes every append fail
+        fs::write(dir.join(AUDIT_DIR), b"").unwrap();
+
+        let report = engine.handle_gdpr_deletion(&store, "203.0.113.7", async { Ok(ErasureReport::default()) }).await.unwrap();
+        assert_eq!(report, ErasureReport { evidence_records: 1, audited: false, ..ErasureReport::default() });
+        assert!(store.load().await.unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_audit_chain_detects_tampering() {
+        let dir = std::env::temp_dir().join(format!("orasrs-audit-{}", uuid::Uuid::new_v4()));
+        let config = AgentConfig {
+            storage_config: StorageConfig {
+                data_dir: dir.clone(),
+                ..StorageConfig::default()
+            },
+            ..AgentConfig::default()
+        };
+        let engine = ComplianceEngine::new(&config);
+        assert!(engine.verify_audit_chain().await.unwrap());
+
+        engine.handle_ccpa_do_not_sell("user-1").await.unwrap();
+        engine.handle_ccpa_do_not_sell("user-2").await.unwrap();
+        // A fresh engine continues the chain from disk
+        ComplianceEngine::new(&config).handle_ccpa_do_not_sell("user-3").await.unwrap();
+
+        let entries = engine.audit_log().entries().await.unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].prev_hash, entries[1].hash);
+        assert!(engine.verify_audit_chain().await.unwrap());
+
+        let path = dir.join(AUDIT_DIR).join("compliance.jsonl");
+        let original = fs::read_to_string(&path).unwrap();
+        fs::write(&path, original.replacen("user-2", "user-9", 1)).unwrap();
+        assert!(!engine.verify_audit_chain().await.unwrap());
+
+        // Dropping an entry breaks the link to the next one
+        let without_middle: Vec<&str> = original.lines().enumerate().filter(|(i, _)| *i != 1).map(|(_, line)| line).collect();
+        fs::write(&path, without_middle.join("\n")).unwrap();
+        assert!(!engine.verify_audit_chain().await.unwrap());
+
+        fs::write(&path, "not json\n").unwrap();
+        assert!(!engine.verify_audit_chain().await.unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn china_config(bootstrap_nodes: &[&str]) -> AgentConfig {
+        let mut config = AgentConfig {
+            compliance_mode: "china".to_string(),
+            ..AgentConfig::default()
+        };
+        config.p2p_config.bootstrap_nodes = bootstrap_nodes.iter().map(|node| node.to_string()).collect();
+        config
+    }
+
+    #[test]
+    fn test_china_compliance_requires_verified_nodes() {
+        let mut config = china_config(&["/ip4/175.16.199.1/tcp/4001", "/dns4/boot.example/tcp/4001"]);
+        let engine = ComplianceEngine::new(&config);
+
+        let result = engine.validate_config_compliance(&config);
+        assert!(matches!(result, Err(AgentError::ComplianceError(_))));
+
+        config.allow_unverified_nodes = true;
+        assert!(engine.validate_config_compliance(&config).is_ok());
+    }
+
+    #[cfg(feature = "geoip")]
+    #[test]
+    fn test_china_compliance_rejects_out_of_region_nodes() {
+        let database = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/geoip-test.mmdb");
+        let mut config = china_config(&["/ip4/175.16.199.1/tcp/4001/p2p/12D3KooWCeV2JWivXqakX9ZR53z32k7Z4FwKjZ7y6zY6o2Rr5v5o"]);
+        config.geoip_database = Some(database.to_string());
+        let engine = ComplianceEngine::new(&config);
+        assert!(engine.validate_config_compliance(&config).is_ok());
+
+        config.p2p_config.bootstrap_nodes.push("/ip4/81.2.69.142/tcp/4001".to_string());
+        let result = engine.validate_config_compliance(&config);
+        assert!(matches!(result, Err(AgentError::ComplianceError(message)) if message.contains("GB")));
+
+        // Opting in to unverified nodes doesn't admit nodes known to be abroad
+        config.allow_unverified_nodes = true;
+        assert!(engine.validate_config_compliance(&config).is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::path::PathBuf;
-use crate::{ThreatLevel};
+use crate::{ThreatLevel, allowlist::Allowlist, blocklist_exporter::BlocklistFormat, error::{AgentError, Result}};
 
 /// Agent configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,13 +19,16 @@ pub struct AgentConfig {
     /// Compliance mode (gdpr, ccpa, china, global)
     pub compliance_mode: String,
     
-    /// Maximum memory usage in bytes (default: 5MB)
+    /// Maximum memory usage in bytes (default: 64MB)
     pub max_memory: usize,
     
     /// CPU usage limit as percentage (default: 5)
     pub cpu_limit: f64,
     
-    /// Network usage limit in bytes/sec (default: 10KB)
+    /// Network usage limit in bytes/sec (default: 1MB)
+    ///
+    /// Measured across the agent's network namespace, so on a shared host
+    /// it covers other processes' traffic too.
     pub network_limit: usize,
     
     /// Enabled monitoring modules
@@ -55,6 +60,306 @@ pub struct AgentConfig {
     
     /// Blocklist export interval in seconds
     pub blocklist_export_interval: Option<u64>,
+    
+    /// File receiving only the blocklist entries added or removed at each export
+    #[serde(default)]
+    pub blocklist_delta_file: Option<String>,
+    
+    /// Syntax of the exported blocklist file
+    #[serde(default)]
+    pub blocklist_format: BlocklistFormat,
+    
+    /// Seconds an IP stays on the blocklist; entries never expire if unset
+    #[serde(default)]
+    pub blocklist_entry_ttl: Option<u64>,
+    
+    /// IPs from one /24 needed to list the whole /24 instead; no aggregation if unset
+    #[serde(default)]
+    pub blocklist_aggregation_threshold: Option<usize>,
+    
+    /// Whether a /24 may be aggregated from IPs with different threat types
+    #[serde(default)]
+    pub blocklist_aggregate_mixed_types: bool,
+    
+    /// Keep full IPs for local correlation and blocklisting, anonymizing only on publish
+    #[serde(default = "default_internal_full_ip")]
+    pub internal_full_ip: bool,
+    
+    /// Seconds to wait for background tasks on shutdown before aborting them
+    #[serde(default = "default_shutdown_grace_period")]
+    pub shutdown_grace_period: u64,
+    
+    /// Whether to attach reverse DNS and netblock owner to evidence
+    #[serde(default)]
+    pub enrichment_enabled: bool,
+    
+    /// Seconds to cache enrichment lookups per IP
+    #[serde(default = "default_enrichment_cache_ttl")]
+    pub enrichment_cache_ttl: u64,
+    
+    /// Which IP fields privacy anonymization applies to
+    #[serde(default)]
+    pub anonymize_fields: AnonymizeFields,
+    
+    /// Whether false positive reports are broadcast to peers as disputes
+    #[serde(default)]
+    pub broadcast_disputes: bool,
+    
+    /// Whether to stop publishing while reputation is below `reputation_threshold`
+    #[serde(default)]
+    pub pause_publishing_below_threshold: bool,
+    
+    /// Seconds during which evidence from the same source or subnet joins one campaign
+    #[serde(default = "default_correlation_window")]
+    pub correlation_window: u64,
+    
+    /// Own infrastructure IPs never anonymized, since they are not personal data
+    #[serde(default)]
+    pub anonymization_exemptions: Vec<String>,
+    
+    /// IPs and CIDR ranges never blocklisted or published as threats, e.g. own resolvers
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+    
+    /// Network interface captured by netflow monitoring; the default device if unset
+    #[serde(default)]
+    pub netflow_interface: Option<String>,
+    
+    /// TOML file of detection rules replacing the bundled defaults
+    #[serde(default)]
+    pub detection_rules_file: Option<String>,
+    
+    /// YARA rules file, or directory of `.yar` files, scanning captured payloads (requires the yara feature)
+    #[serde(default)]
+    pub yara_rules_path: Option<String>,
+    
+    /// SYNs per second from one source reported as a DDoS
+    #[serde(default = "default_ddos_syn_rate_threshold")]
+    pub ddos_syn_rate_threshold: f64,
+    
+    /// Regions and ASNs flagged by the geographic fence
+    #[serde(default)]
+    pub geo_fence: GeoFenceConfig,
+    
+    /// MaxMind `.mmdb` database locating bootstrap nodes for China compliance and IPs checked by the geographic fence
+    #[serde(default)]
+    pub geoip_database: Option<String>,
+    
+    /// Accept bootstrap nodes whose region can't be verified under China compliance
+    #[serde(default)]
+    pub allow_unverified_nodes: bool,
+    
+    /// Server names and JA3 fingerprints flagged by TLS inspection
+    #[serde(default)]
+    pub tls_inspect: TlsInspectConfig,
+    
+    /// Whether published evidence is hash-chained and notarized in signed Merkle batches
+    #[serde(default)]
+    pub notarization_enabled: bool,
+    
+    /// Number of evidence items per notarized batch
+    #[serde(default = "default_notarization_batch_size")]
+    pub notarization_batch_size: usize,
+    
+    /// Whether to serve Prometheus metrics over HTTP at `/metrics`
+    #[serde(default)]
+    pub metrics_enabled: bool,
+    
+    /// IP address the metrics server binds to
+    #[serde(default = "default_metrics_bind_address")]
+    pub metrics_bind_address: String,
+    
+    /// Port of the metrics server
+    #[serde(default = "default_metrics_port")]
+    pub metrics_port: u16,
+    
+    /// Whether to serve `/health` and `/status` over HTTP
+    #[serde(default)]
+    pub control_enabled: bool,
+    
+    /// IP address the control server binds to
+    #[serde(default = "default_control_bind_address")]
+    pub control_bind_address: String,
+    
+    /// Port of the control server
+    #[serde(default = "default_control_port")]
+    pub control_port: u16,
+    
+    /// Upstream threat intelligence feeds; when set they replace the built-in CISA AIS source
+    #[serde(default)]
+    pub upstream_sources: Vec<UpstreamSourceConfigFile>,
+    
+    /// Seconds a snapshot of known upstream threats used for enhancement may lag behind newly fetched indicators
+    #[serde(default = "default_upstream_cache_ttl")]
+    pub upstream_cache_ttl: u64,
+}
+
+impl AgentConfig {
+    /// Check that field values are in range, naming the first invalid field
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=4).contains(&self.privacy_level) {
+            return Err(AgentError::ConfigError(format!(
+                "privacy_level must be between 1 and 4, got {}", self.privacy_level
+            )));
+        }
+        if !(self.cpu_limit > 0.0 && self.cpu_limit <= 100.0) {
+            return Err(AgentError::ConfigError(format!(
+                "cpu_limit must be greater than 0 and at most 100, got {}", self.cpu_limit
+            )));
+        }
+        if self.p2p_config.listen_port == 0 {
+            return Err(AgentError::ConfigError("p2p_config.listen_port must not be 0".to_string()));
+        }
+        if self.p2p_config.listen_address.parse::<IpAddr>().is_err() {
+            return Err(AgentError::ConfigError(format!(
+                "p2p_config.listen_address must be an IP address, got {:?}", self.p2p_config.listen_address
+            )));
+        }
+        if self.blocklist_export_enabled && self.blocklist_min_threat_level.is_none() {
+            return Err(AgentError::ConfigError(
+                "blocklist_min_threat_level must be set when blocklist_export_enabled is true".to_string()
+            ));
+        }
+        if self.metrics_bind_address.parse::<IpAddr>().is_err() {
+            return Err(AgentError::ConfigError(format!(
+                "metrics_bind_address must be an IP address, got {:?}", self.metrics_bind_address
+            )));
+        }
+        if self.control_bind_address.parse::<IpAddr>().is_err() {
+            return Err(AgentError::ConfigError(format!(
+                "control_bind_address must be an IP address, got {:?}", self.control_bind_address
+            )));
+        }
+        if !(self.ddos_syn_rate_threshold > 0.0 && self.ddos_syn_rate_threshold.is_finite()) {
+            return Err(AgentError::ConfigError(format!(
+                "ddos_syn_rate_threshold must be a positive number, got {}", self.ddos_syn_rate_threshold
+            )));
+        }
+        if let Some(threshold) = self.blocklist_aggregation_threshold {
+            if !(2..=256).contains(&threshold) {
+                return Err(AgentError::ConfigError(format!(
+                    "blocklist_aggregation_threshold must be between 2 and 256, got {}", threshold
+                )));
+            }
+        }
+        Allowlist::parse(&self.allowlist)?;
+        match self.geo_fence.mode {
+            GeoFenceMode::Allowlist if self.geo_fence.allowed_regions.is_empty() => {
+                return Err(AgentError::ConfigError(
+                    "geo_fence.allowed_regions must be set when geo_fence.mode is allowlist".to_string()
+                ));
+            }
+            GeoFenceMode::Blocklist if !self.geo_fence.allowed_regions.is_empty() => {
+                return Err(AgentError::ConfigError(
+                    "geo_fence.allowed_regions requires geo_fence.mode = \"allowlist\"".to_string()
+                ));
+            }
+            _ => {}
+        }
+        let mut source_names = HashSet::new();
+        for source in &self.upstream_sources {
+            if source.url.is_empty() {
+                return Err(AgentError::ConfigError(format!("upstream_sources.{} has no url", source.name)));
+            }
+            if !source_names.insert(source.name.as_str()) {
+                return Err(AgentError::ConfigError(format!("upstream_sources name {} is used twice", source.name)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// IP fields subject to privacy anonymization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnonymizeFields {
+    /// Only the external source IP
+    SourceOnly,
+    /// Only the target IP
+    TargetOnly,
+    /// Both source and target IPs
+    #[default]
+    Both,
+}
+
+impl AnonymizeFields {
+    pub fn source(&self) -> bool {
+        matches!(self, AnonymizeFields::SourceOnly | AnonymizeFields::Both)
+    }
+
+    pub fn target(&self) -> bool {
+        matches!(self, AnonymizeFields::TargetOnly | AnonymizeFields::Both)
+    }
+}
+
+fn default_internal_full_ip() -> bool {
+    true
+}
+
+fn default_shutdown_grace_period() -> u64 {
+    10
+}
+
+fn default_enrichment_cache_ttl() -> u64 {
+    3600
+}
+
+fn default_correlation_window() -> u64 {
+    600
+}
+
+fn default_upstream_cache_ttl() -> u64 {
+    30
+}
+
+fn default_ddos_syn_rate_threshold() -> f64 {
+    crate::analyzer::DEFAULT_SYN_RATE_THRESHOLD
+}
+
+/// Upstream threat intelligence feed as written in the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpstreamSourceConfigFile {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub auth_token: Option<String>,
+    #[serde(default = "default_source_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_source_update_interval")]
+    pub update_interval: u64, // in seconds
+    #[serde(default)]
+    pub threat_level_mapping: HashMap<String, ThreatLevel>,
+    #[serde(default)]
+    pub taxii_collection: Option<String>, // Poll this TAXII 2.1 collection under `url`
+}
+
+fn default_source_enabled() -> bool {
+    true
+}
+
+fn default_source_update_interval() -> u64 {
+    300 // 5 minutes
+}
+
+fn default_notarization_batch_size() -> usize {
+    64
+}
+
+fn default_metrics_bind_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_metrics_port() -> u16 {
+    9464
+}
+
+fn default_control_bind_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_control_port() -> u16 {
+    9465
 }
 
 /// Monitoring modules configuration
@@ -66,13 +371,85 @@ pub struct ModuleConfig {
     pub geo_fence: bool,
 }
 
+/// How the geographic fence decides which regions to flag
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GeoFenceMode {
+    /// Flag connections from `blocked_regions`
+    #[default]
+    Blocklist,
+    /// Flag connections from any region outside `allowed_regions`
+    Allowlist,
+}
+
+/// Geographic fence configuration; nothing is flagged unless listed here
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeoFenceConfig {
+    #[serde(default)]
+    pub mode: GeoFenceMode,
+    /// ISO country codes whose connections are flagged in blocklist mode
+    #[serde(default)]
+    pub blocked_regions: Vec<String>,
+    /// ISO country codes allowed in allowlist mode
+    #[serde(default)]
+    pub allowed_regions: Vec<String>,
+    /// Autonomous system numbers whose connections are flagged
+    #[serde(default)]
+    pub suspicious_asns: Vec<u32>,
+}
+
+/// Known-bad TLS indicators; nothing is flagged unless listed here
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TlsInspectConfig {
+    /// Server names whose connections are flagged, including their subdomains
+    #[serde(default)]
+    pub blocked_sni: Vec<String>,
+    /// JA3 fingerprints (MD5 hex) of clients whose connections are flagged
+    #[serde(default)]
+    pub blocked_ja3: Vec<String>,
+}
+
 /// P2P network configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct P2pConfig {
     pub bootstrap_nodes: Vec<String>,
+    #[serde(default = "default_listen_address")]
+    pub listen_address: String,    // IP address the swarm listens on; all interfaces by default
     pub listen_port: u16,
     pub max_connections: usize,
     pub reconnect_interval: u64,
+    #[serde(default = "default_max_message_age")]
+    pub max_message_age: u64,     // Seconds before signed evidence is considered stale
+    #[serde(default = "default_replay_cache_size")]
+    pub replay_cache_size: usize, // Number of recent nonces remembered
+    #[serde(default = "default_protocol_version")]
+    pub protocol_version: u32,     // Highest wire protocol version spoken
+    #[serde(default = "default_protocol_version")]
+    pub min_protocol_version: u32, // Oldest wire protocol version accepted from peers
+    #[serde(default)]
+    pub mdns_enabled: bool,        // Discover peers on the local network via mDNS
+    #[serde(default = "default_verification_rate_limit")]
+    pub verification_rate_limit: u32, // Verification requests answered per peer per minute
+}
+
+fn default_listen_address() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_max_message_age() -> u64 {
+    300
+}
+
+fn default_replay_cache_size() -> usize {
+    10_000
+}
+
+fn default_protocol_version() -> u32 {
+    crate::p2p::PROTOCOL_VERSION
+}
+
+fn default_verification_rate_limit() -> u32 {
+    60
 }
 
 /// Cryptographic configuration
@@ -82,6 +459,12 @@ pub struct CryptoConfig {
     pub sm2_private_key: Option<String>,
     pub sm2_public_key: Option<String>,
     pub encryption_algorithm: String,  // "sm4" or "aes256"
+    /// Hex-encoded 32-byte key for evidence context encryption; a random key is generated if unset
+    #[serde(default)]
+    pub context_encryption_key: Option<String>,
+    /// Reject peer evidence without a valid emitter signature
+    #[serde(default)]
+    pub require_evidence_signatures: bool,
 }
 
 /// Local storage configuration
@@ -100,9 +483,9 @@ impl Default for AgentConfig {
             region: "auto".to_string(),
             privacy_level: 2,  // Default to GDPR level
             compliance_mode: "global".to_string(),
-            max_memory: 5 * 1024 * 1024, // 5MB
+            max_memory: 64 * 1024 * 1024, // 64MB
             cpu_limit: 5.0,
-            network_limit: 10 * 1024, // 10KB
+            network_limit: 1024 * 1024, // 1MB
             enabled_modules: ModuleConfig::default(),
             p2p_config: P2pConfig::default(),
             crypto_config: CryptoConfig::default(),
@@ -113,6 +496,39 @@ impl Default for AgentConfig {
             blocklist_file: Some("./blocklist.txt".to_string()),
             blocklist_min_threat_level: Some(crate::ThreatLevel::Warning),
             blocklist_export_interval: Some(300), // 5 minutes
+            blocklist_delta_file: None,
+            blocklist_format: BlocklistFormat::default(),
+            blocklist_entry_ttl: None,
+            blocklist_aggregation_threshold: None,
+            blocklist_aggregate_mixed_types: false,
+            internal_full_ip: true,
+            shutdown_grace_period: 10,
+            enrichment_enabled: false,
+            enrichment_cache_ttl: 3600, // 1 hour
+            anonymize_fields: AnonymizeFields::Both,
+            broadcast_disputes: false,
+            pause_publishing_below_threshold: false,
+            correlation_window: 600, // 10 minutes
+            anonymization_exemptions: Vec::new(),
+            allowlist: Vec::new(),
+            netflow_interface: None,
+            detection_rules_file: None,
+            yara_rules_path: None,
+            ddos_syn_rate_threshold: crate::analyzer::DEFAULT_SYN_RATE_THRESHOLD,
+            geo_fence: GeoFenceConfig::default(),
+            geoip_database: None,
+            allow_unverified_nodes: false,
+            tls_inspect: TlsInspectConfig::default(),
+            notarization_enabled: false,
+            notarization_batch_size: 64,
+            metrics_enabled: false,
+            metrics_bind_address: default_metrics_bind_address(),
+            metrics_port: 9464,
+            control_enabled: false,
+            control_bind_address: default_control_bind_address(),
+            control_port: 9465,
+            upstream_sources: Vec::new(),
+            upstream_cache_ttl: 30,
         }
     }
 }
@@ -135,9 +551,16 @@ impl Default for P2pConfig {
                 "/ip4/159.138.224.180/tcp/4001/p2p/12D3KooWCeV2JWivXqakX9ZR53z32k7Z4FwKjZ7y6zY6o2Rr5v5o".to_string(),
                 "/ip4/159.138.224.181/tcp/4001/p2p/12D3KooWCeV2JWivXqakX9ZR53z32k7Z4FwKjZ7y6zY6o2Rr5v5p".to_string(),
             ],
+            listen_address: default_listen_address(),
             listen_port: 4001,
             max_connections: 50,
             reconnect_interval: 30,
+            max_message_age: 300, // 5 minutes
+            replay_cache_size: 10_000,
+            protocol_version: crate::p2p::PROTOCOL_VERSION,
+            min_protocol_version: crate::p2p::PROTOCOL_VERSION,
+            mdns_enabled: false,
+            verification_rate_limit: 60,
         }
     }
 }
@@ -149,6 +572,8 @@ impl Default for CryptoConfig {
             sm2_private_key: None,
             sm2_public_key: None,
             encryption_algorithm: "aes256".to_string(),
+            context_encryption_key: None,
+            require_evidence_signatures: false,
         }
     }
 }
@@ -162,4 +587,139 @@ impl Default for StorageConfig {
             encryption_enabled: true,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rejected(config: AgentConfig, field: &str) {
+        match config.validate() {
+            Err(AgentError::ConfigError(message)) => assert!(message.contains(field), "{}", message),
+            other => panic!("expected {} to be rejected, got {:?}", field, other),
+        }
+    }
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert!(AgentConfig::default().validate().is_ok());
+
+        let mut config = AgentConfig::default();
+        config.privacy_level = 4;
+        config.cpu_limit = 100.0;
+        config.blocklist_export_enabled = true;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_privacy_level() {
+        for level in [0, 5] {
+            assert_rejected(AgentConfig { privacy_level: level, ..AgentConfig::default() }, "privacy_level");
+        }
+    }
+
+    #[test]
+    fn test_invalid_cpu_limit() {
+        for limit in [0.0, -1.0, 100.5, f64::NAN] {
+            assert_rejected(AgentConfig { cpu_limit: limit, ..AgentConfig::default() }, "cpu_limit");
+        }
+    }
+
+    #[test]
+    fn test_invalid_ddos_syn_rate_threshold() {
+        for threshold in [0.0, -5.0, f64::NAN, f64::INFINITY] {
+            assert_rejected(
+                AgentConfig { ddos_syn_rate_threshold: threshold, ..AgentConfig::default() },
+                "ddos_syn_rate_threshold",
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_metrics_bind_address() {
+        for address in ["", "localhost", "127.0.0.1:9464"] {
+            assert_rejected(
+                AgentConfig { metrics_bind_address: address.to_string(), ..AgentConfig::default() },
+                "metrics_bind_address",
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_control_bind_address() {
+        for address in ["", "localhost", "127.0.0.1:9465"] {
+            assert_rejected(
+                AgentConfig { control_bind_address: address.to_string(), ..AgentConfig::default() },
+                "control_bind_address",
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_aggregation_threshold() {
+        for threshold in [0, 1, 257] {
+            let config = AgentConfig { blocklist_aggregation_threshold: Some(threshold), ..AgentConfig::default() };
+            assert_rejected(config, "blocklist_aggregation_threshold");
+        }
+    }
+
+    #[test]
+    fn test_geo_fence_mode_must_match_regions() {
+        let allowlist_without_regions = GeoFenceConfig { mode: GeoFenceMode::Allowlist, ..GeoFenceConfig::default() };
+        let config = AgentConfig { geo_fence: allowlist_without_regions, ..AgentConfig::default() };
+        assert_rejected(config, "geo_fence.allowed_regions");
+
+        let regions_without_mode = GeoFenceConfig { allowed_regions: vec!["CN".to_string()], ..GeoFenceConfig::default() };
+        let config = AgentConfig { geo_fence: regions_without_mode, ..AgentConfig::default() };
+        assert_rejected(config, "geo_fence.allowed_regions");
+    }
+
+    #[test]
+    fn test_invalid_allowlist_entry() {
+        let config = AgentConfig { allowlist: vec!["10.0.0.0/8".to_string(), "resolver".to_string()], ..AgentConfig::default() };
+        assert_rejected(config, "allowlist");
+    }
+
+    #[test]
+    fn test_zero_listen_port() {
+        let mut config = AgentConfig::default();
+        config.p2p_config.listen_port = 0;
+        assert_rejected(config, "listen_port");
+    }
+
+    #[test]
+    fn test_invalid_listen_address() {
+        for address in ["", "localhost", "/ip4/0.0.0.0"] {
+            let mut config = AgentConfig::default();
+            config.p2p_config.listen_address = address.to_string();
+            assert_rejected(config, "listen_address");
+        }
+    }
+
+    #[test]
+    fn test_blocklist_export_needs_min_threat_level() {
+        let mut config = AgentConfig::default();
+        config.blocklist_min_threat_level = None;
+        assert!(config.validate().is_ok());
+
+        config.blocklist_export_enabled = true;
+        assert_rejected(config, "blocklist_min_threat_level");
+    }
+
+    #[test]
+    fn test_upstream_source_names_must_be_unique() {
+        let source: UpstreamSourceConfigFile = serde_json::from_value(serde_json::json!({
+            "name": "feed",
+            "url": "https://feeds.example.com/ips.txt",
+        }))
+        .unwrap();
+        assert!(source.enabled);
+        assert_eq!(source.update_interval, 300);
+
+        let mut config = AgentConfig { upstream_sources: vec![source.clone()], ..AgentConfig::default() };
+        assert!(config.validate().is_ok());
+
+        config.upstream_sources.push(source);
+        assert_rejected(config, "upstream_sources");
+    }
+}
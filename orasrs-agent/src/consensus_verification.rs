@@ -1,11 +1,23 @@
-use crate::{ThreatEvidence, ThreatType, ThreatLevel, error::{AgentError, Result}};
+use crate::{ThreatEvidence, ThreatType, ThreatLevel, credibility_enhancement::CredibilityEngine, error::{AgentError, Result}, storage::Storage};
+use crate::compliance::mask_ip;
+use crate::threat_intel::{self, IndicatorStore, IndicatorType};
+use crate::crypto::{CryptoProvider, Ed25519Signer, Signer};
+use libp2p::identity;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH, Duration};
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 use tokio::time::sleep;
 use uuid::Uuid;
 
+/// Prefix lengths within which evidence sources count as the same subnet
+const CORRELATION_IPV4_PREFIX: u8 = 24;
+const CORRELATION_IPV6_PREFIX: u8 = 64;
+
 /// Consensus verification configuration
 #[derive(Debug, Clone)]
 pub struct ConsensusConfig {
@@ -14,6 +26,10 @@ pub struct ConsensusConfig {
     pub reputation_threshold: f64,    // Minimum reputation threshold for valid verification
     pub consensus_threshold: f64,     // Percentage of verifiers needed for consensus (0.0-1.0)
     pub max_consensus_attempts: u32,  // Maximum number of consensus attempts before giving up
+    pub context_similarity: ContextSimilarity, // Algorithm used to compare evidence contexts
+    pub context_similarity_threshold: f64,     // Minimum similarity for contexts to correlate (0.0-1.0)
+    pub max_pending_requests: usize,  // Maximum number of in-flight verification requests
+    pub threat_type_accuracy_weight: f64, // Confidence swing from the agent's historical accuracy per threat type
 }
 
 impl Default for ConsensusConfig {
@@ -24,10 +40,71 @@ impl Default for ConsensusConfig {
             reputation_threshold: 0.7,     // 70% reputation threshold
             consensus_threshold: 0.6,      // 60% consensus needed
             max_consensus_attempts: 5,
+            context_similarity: ContextSimilarity::Jaccard,
+            context_similarity_threshold: 0.5,
+            max_pending_requests: 1000,
+            threat_type_accuracy_weight: 0.4,
+        }
+    }
+}
+
+/// Similarity measure over the terms of two evidence contexts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContextSimilarity {
+    /// Shared distinct terms over all distinct terms
+    Jaccard,
+    /// Cosine of the term-frequency vectors
+    Cosine,
+}
+
+impl ContextSimilarity {
+    /// Score the similarity of two texts between 0.0 and 1.0
+    pub fn score(&self, a: &str, b: &str) -> f64 {
+        let terms_a = tokenize(a);
+        let terms_b = tokenize(b);
+        if terms_a.is_empty() || terms_b.is_empty() {
+            return 0.0;
+        }
+
+        match self {
+            ContextSimilarity::Jaccard => {
+                let set_a: HashSet<&str> = terms_a.iter().map(String::as_str).collect();
+                let set_b: HashSet<&str> = terms_b.iter().map(String::as_str).collect();
+                let intersection = set_a.intersection(&set_b).count();
+                let union = set_a.union(&set_b).count();
+                intersection as f64 / union as f64
+            }
+            ContextSimilarity::Cosine => {
+                let freq_a = term_frequencies(&terms_a);
+                let freq_b = term_frequencies(&terms_b);
+                let dot: f64 = freq_a
+                    .iter()
+                    .filter_map(|(term, count)| freq_b.get(term).map(|other| count * other))
+                    .sum();
+                let norm_a = freq_a.values().map(|c| c * c).sum::<f64>().sqrt();
+                let norm_b = freq_b.values().map(|c| c * c).sum::<f64>().sqrt();
+                dot / (norm_a * norm_b)
+            }
         }
     }
 }
 
+/// Split text into lowercase alphanumeric terms
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+        .collect()
+}
+
+fn term_frequencies(terms: &[String]) -> HashMap<&str, f64> {
+    let mut frequencies = HashMap::new();
+    for term in terms {
+        *frequencies.entry(term.as_str()).or_insert(0.0) += 1.0;
+    }
+    frequencies
+}
+
 /// Verification request structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VerificationRequest {
@@ -52,7 +129,17 @@ pub struct VerificationResponse {
     pub confidence: f64,               // Confidence level of the verification (0.0-1.0)
     pub justification: String,         // Reason for the verdict
     pub timestamp: i64,
-    pub signature: String,             // Digital signature of the verifying agent
+    #[serde(default)]
+    pub signer: Vec<u8>,               // Protobuf-encoded public key of the verifying agent
+    #[serde(default)]
+    pub signature: Vec<u8>,            // Signature over `signing_payload`
+}
+
+impl VerificationResponse {
+    /// Bytes covered by the signature
+    fn signing_payload(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&(&self.request_id, &self.evidence_id, &self.verifying_agent, self.verdict, self.confidence))?)
+    }
 }
 
 /// Verification status
@@ -67,12 +154,34 @@ pub enum VerificationStatus {
     ConsensusFailed,
 }
 
+impl VerificationStatus {
+    /// Whether the request is still awaiting a consensus decision
+    pub fn is_in_flight(&self) -> bool {
+        matches!(self, VerificationStatus::Pending | VerificationStatus::InProgress)
+    }
+}
+
 /// Consensus verification engine
 pub struct ConsensusEngine {
     config: ConsensusConfig,
     pending_requests: RwLock<HashMap<String, VerificationRequest>>,
     verification_cache: RwLock<HashMap<String, ConsensusResult>>,
+    indicator_store: Arc<IndicatorStore>,
+    credibility_engine: Option<Arc<CredibilityEngine>>,
     local_agent_id: String,
+    signer: Box<dyn Signer>,
+    require_evidence_signatures: bool, // Refuse to confirm evidence without a valid emitter signature
+    request_broadcast: Option<mpsc::UnboundedSender<VerificationRequest>>, // Receives submitted requests to send to peers
+}
+
+/// Storage key for persisted consensus state
+pub(crate) const STORAGE_KEY: &str = "consensus/state";
+
+/// Consensus state saved across restarts
+#[derive(Serialize, Deserialize)]
+struct ConsensusState {
+    pending_requests: HashMap<String, VerificationRequest>,
+    verification_cache: HashMap<String, ConsensusResult>,
 }
 
 /// Result of consensus verification
@@ -85,7 +194,9 @@ pub struct ConsensusResult {
     pub disputed_by: Vec<String>,      // Agents that disputed the threat
     pub total_verifiers: usize,        // Total number of verifiers
     pub consensus_percentage: f64,     // Percentage of verifiers that agreed
+    pub weighted_consensus_percentage: f64, // Share of verifier reputation behind the threat, decides the verdict
     pub timestamp: i64,
+    pub status: VerificationStatus,    // ConsensusReached, ConsensusFailed, or Expired
 }
 
 impl ConsensusEngine {
@@ -94,10 +205,45 @@ impl ConsensusEngine {
             config,
             pending_requests: RwLock::new(HashMap::new()),
             verification_cache: RwLock::new(HashMap::new()),
+            indicator_store: Arc::new(IndicatorStore::new()),
+            credibility_engine: None,
             local_agent_id,
+            signer: Box::new(Ed25519Signer::new(identity::Keypair::generate_ed25519())),
+            require_evidence_signatures: false,
+            request_broadcast: None,
         }
     }
 
+    /// Sign and verify verification responses with the given scheme
+    pub fn with_signer(mut self, signer: Box<dyn Signer>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// Dispute evidence whose emitter signature is missing or invalid under this engine's scheme
+    pub fn with_evidence_signatures_required(mut self, required: bool) -> Self {
+        self.require_evidence_signatures = required;
+        self
+    }
+
+    /// Use a shared local IoC store for known-threat lookups
+    pub fn with_indicator_store(mut self, indicator_store: Arc<IndicatorStore>) -> Self {
+        self.indicator_store = indicator_store;
+        self
+    }
+
+    /// Send every submitted request to `sender` so it can be broadcast to peers
+    pub fn with_request_broadcast(mut self, sender: mpsc::UnboundedSender<VerificationRequest>) -> Self {
+        self.request_broadcast = Some(sender);
+        self
+    }
+
+    /// Weight local verification by the historical accuracy tracked per threat type
+    pub fn with_credibility_engine(mut self, credibility_engine: Arc<CredibilityEngine>) -> Self {
+        self.credibility_engine = Some(credibility_engine);
+        self
+    }
+
     /// Submit evidence for consensus verification
     pub async fn submit_for_verification(&self, evidence: ThreatEvidence) -> Result<VerificationRequest> {
         let request_id = format!("consensus-{}", Uuid::new_v4());
@@ -113,17 +259,31 @@ impl ConsensusEngine {
             requesting_agent: self.local_agent_id.clone(),
             timestamp,
             verification_threshold: self.config.min_verifiers,
-            verifiers: Vec::new(),        // Will be populated by the consensus mechanism
+            verifiers: Vec::new(),        // Filled in as peers respond
             responses: Vec::new(),
             status: VerificationStatus::Pending,
         };
 
-        // Store the request
+        // Store the request, refusing it if too many are already in flight
         {
             let mut requests = self.pending_requests.write().await;
+            if requests.len() >= self.config.max_pending_requests {
+                // Finished requests no longer count against the limit
+                requests.retain(|_, request| request.status.is_in_flight());
+            }
+            if requests.len() >= self.config.max_pending_requests {
+                return Err(AgentError::InternalError(format!(
+                    "Too many in-flight consensus requests (limit {})",
+                    self.config.max_pending_requests
+                )));
+            }
             requests.insert(request_id.clone(), verification_request.clone());
         }
 
+        if let Some(broadcast) = &self.request_broadcast {
+            let _ = broadcast.send(verification_request.clone());
+        }
+
         log::info!("Submitted evidence {} for consensus verification", evidence.id);
         
         Ok(verification_request)
@@ -139,7 +299,7 @@ impl ConsensusEngine {
         // Perform local verification of the evidence
         let (verdict, confidence, justification) = self.local_verify_evidence(&request.evidence).await;
 
-        let response = VerificationResponse {
+        let mut response = VerificationResponse {
             request_id: request.request_id.clone(),
             evidence_id: request.evidence_id.clone(),
             verifying_agent: self.local_agent_id.clone(),
@@ -147,21 +307,14 @@ impl ConsensusEngine {
             confidence,
             justification,
             timestamp,
-            signature: self.sign_verification_response(&request.request_id, verdict, confidence)?,
+            signer: self.signer.public_key(),
+            signature: Vec::new(),
         };
+        response.signature = self.sign_verification_response(&response)?;
 
-        // Update the request with our response
-        {
-            let mut requests = self.pending_requests.write().await;
-            if let Some(mut req) = requests.get_mut(&request.request_id) {
-                req.responses.push(response.clone());
-                
-                // Update status based on responses
-                if req.responses.len() >= req.verification_threshold as usize {
-                    req.status = VerificationStatus::InProgress;
-                }
-            }
-        }
+        // Track requests originated by other agents so our response is retained
+        self.track_request(request).await;
+        self.record_response(response.clone()).await;
 
         log::info!("Submitted verification response for evidence {}: verdict={}, confidence={}", 
                   request.evidence_id, verdict, confidence);
@@ -169,8 +322,51 @@ impl ConsensusEngine {
         Ok(response)
     }
 
+    /// Start tracking a request received from another agent, unless it is already known
+    ///
+    /// Requests beyond `max_pending_requests` are not tracked.
+    async fn track_request(&self, request: &VerificationRequest) {
+        let mut requests = self.pending_requests.write().await;
+        if requests.contains_key(&request.request_id) {
+            return;
+        }
+        if requests.len() >= self.config.max_pending_requests {
+            requests.retain(|_, request| request.status.is_in_flight());
+        }
+        if requests.len() >= self.config.max_pending_requests {
+            log::warn!("Not tracking consensus request {}: too many in-flight requests", request.request_id);
+            return;
+        }
+        requests.insert(request.request_id.clone(), request.clone());
+    }
+
+    /// Attach a verification response to its pending request
+    ///
+    /// Signatures are checked when consensus is computed, not here, so only
+    /// valid responses from distinct verifiers move the request on.
+    pub async fn record_response(&self, response: VerificationResponse) {
+        let mut requests = self.pending_requests.write().await;
+        if let Some(req) = requests.get_mut(&response.request_id) {
+            if !req.verifiers.contains(&response.verifying_agent) {
+                req.verifiers.push(response.verifying_agent.clone());
+            }
+            req.responses.push(response);
+            
+            // Update status based on responses
+            if req.status == VerificationStatus::Pending
+                && self.valid_responses(req).len() >= req.verification_threshold as usize
+            {
+                req.status = VerificationStatus::InProgress;
+            }
+        }
+    }
+
     /// Perform local verification of evidence
     async fn local_verify_evidence(&self, evidence: &ThreatEvidence) -> (bool, f64, String) {
+        if self.require_evidence_signatures && !CryptoProvider::has_valid_evidence_signature(evidence, self.signer.as_ref()) {
+            return (false, 0.0, "Missing or invalid evidence signature".to_string());
+        }
+
         // Check if this evidence matches known threat patterns
         let mut confidence = 0.5; // Base confidence
         let mut justification = String::new();
@@ -189,7 +385,7 @@ impl ConsensusEngine {
             ThreatLevel::Emergency => confidence += 0.3,
         }
 
-        // Check if source IP is in known threat databases (simulated)
+        // Check if source IP is in the local IoC database
         if self.is_known_threat_ip(&evidence.source_ip).await {
             confidence += 0.3;
             justification.push_str("Known threat IP; ");
@@ -204,6 +400,13 @@ impl ConsensusEngine {
             _ => confidence += 0.05,
         }
 
+        // Favour threat types our past reports of have held up
+        if let Some(credibility_engine) = &self.credibility_engine {
+            let accuracy = credibility_engine.get_threat_type_accuracy(&evidence.threat_type).await;
+            confidence += (accuracy - 0.5) * self.config.threat_type_accuracy_weight;
+            justification.push_str(&format!("{:?} accuracy: {:.2}; ", evidence.threat_type, accuracy));
+        }
+
         // Ensure confidence is within bounds
         confidence = confidence.max(0.0).min(1.0);
 
@@ -215,32 +418,36 @@ impl ConsensusEngine {
         (verdict, confidence, justification)
     }
 
-    /// Check if an IP is in known threat databases (simulated)
-    async fn is_known_threat_ip(&self, ip: &str) -> bool {
-        // In a real implementation, this would check against threat intelligence feeds
-        // For now, we'll simulate by checking against a small list of known bad IPs
-        let known_threat_ips = [
-            "192.168.1.100",
-            "10.0.0.10",
-            "8.8.8.8",  // Example IP for testing
-        ];
-
-        known_threat_ips.contains(&ip)
+    /// Check if an IP is, or falls inside a network, in the local IoC database
+    pub async fn is_known_threat_ip(&self, ip: &str) -> bool {
+        self.indicator_store.is_known_ip(ip)
     }
 
     /// Check for consensus on a verification request
     pub async fn check_consensus(&self, request_id: &str) -> Result<ConsensusResult> {
         let requests = self.pending_requests.read().await;
         let request = requests.get(request_id)
-            .ok_or_else(|| AgentError::InternalError(format!("Verification request {} not found", request_id)))?
+            .ok_or_else(|| AgentError::VerificationNotFound(request_id.to_string()))?
             .clone();
         drop(requests);
 
-        let responses = &request.responses;
+        // Requests left unanswered past the timeout expire rather than being decided
+        let age = now_secs() - request.timestamp;
+        if request.status == VerificationStatus::Expired
+            || (request.status.is_in_flight() && age >= self.config.verification_timeout as i64)
+        {
+            return Ok(self.expire_request(&request).await);
+        }
+
+        let responses = self.valid_responses(&request);
         let total_responses = responses.len();
         
-        if total_responses == 0 {
-            return Err(AgentError::InternalError("No verification responses received".to_string()));
+        // Too few verifiers cannot decide, however they voted
+        let required = (request.verification_threshold as usize).max(1);
+        if total_responses < required {
+            return Err(AgentError::InternalError(format!(
+                "Only {} of {} required verification responses received", total_responses, required
+            )));
         }
 
         // Calculate consensus
@@ -250,7 +457,23 @@ impl ConsensusEngine {
         
         let disputed_count = total_responses - verified_count;
         let consensus_percentage = verified_count as f64 / total_responses as f64;
-        let consensus_verdict = consensus_percentage >= self.config.consensus_threshold;
+        
+        // Weight each verdict by the verifier's reputation so low-trust nodes cannot outvote trusted ones
+        let mut weighted_verified = 0.0;
+        let mut weighted_total = 0.0;
+        for resp in &responses {
+            let weight = self.verifier_reputation(&resp.verifying_agent).await;
+            weighted_total += weight;
+            if resp.verdict {
+                weighted_verified += weight;
+            }
+        }
+        let weighted_consensus_percentage = if weighted_total > 0.0 {
+            weighted_verified / weighted_total
+        } else {
+            0.0
+        };
+        let consensus_verdict = weighted_consensus_percentage >= self.config.consensus_threshold;
 
         let verified_by: Vec<String> = responses.iter()
             .filter(|resp| resp.verdict)
@@ -279,21 +502,20 @@ impl ConsensusEngine {
             disputed_by,
             total_verifiers: total_responses,
             consensus_percentage,
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64,
+            weighted_consensus_percentage,
+            timestamp: now_secs(),
+            status: if consensus_verdict {
+                VerificationStatus::ConsensusReached
+            } else {
+                VerificationStatus::ConsensusFailed
+            },
         };
 
         // Update request status
         {
             let mut requests = self.pending_requests.write().await;
             if let Some(req) = requests.get_mut(request_id) {
-                req.status = if consensus_verdict {
-                    VerificationStatus::ConsensusReached
-                } else {
-                    VerificationStatus::ConsensusFailed
-                };
+                req.status = consensus_result.status.clone();
             }
         }
 
@@ -306,16 +528,99 @@ impl ConsensusEngine {
         Ok(consensus_result)
     }
 
+    /// Wait until a request has enough responses to decide, or expire it after `timeout`
+    pub async fn await_consensus(&self, request_id: &str, timeout: Duration) -> Result<ConsensusResult> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let request = self.pending_requests.read().await
+                .get(request_id)
+                .cloned()
+                .ok_or_else(|| AgentError::VerificationNotFound(request_id.to_string()))?;
+
+            if !request.status.is_in_flight()
+                || self.valid_responses(&request).len() >= request.verification_threshold as usize
+            {
+                return self.check_consensus(request_id).await;
+            }
+
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return Ok(self.expire_request(&request).await);
+            }
+            sleep((deadline - now).min(Duration::from_millis(100))).await;
+        }
+    }
+
+    /// Mark a request expired and report the responses it had gathered without a verdict
+    async fn expire_request(&self, request: &VerificationRequest) -> ConsensusResult {
+        if let Some(req) = self.pending_requests.write().await.get_mut(&request.request_id) {
+            req.status = VerificationStatus::Expired;
+        }
+        log::warn!("Verification request {} for evidence {} expired with {} of {} responses",
+                  request.request_id, request.evidence_id, request.responses.len(), request.verification_threshold);
+
+        let responses = self.valid_responses(request);
+        ConsensusResult {
+            evidence_id: request.evidence_id.clone(),
+            consensus_verdict: false,
+            confidence_score: 0.0,
+            verified_by: responses.iter().filter(|resp| resp.verdict).map(|resp| resp.verifying_agent.clone()).collect(),
+            disputed_by: responses.iter().filter(|resp| !resp.verdict).map(|resp| resp.verifying_agent.clone()).collect(),
+            total_verifiers: responses.len(),
+            consensus_percentage: 0.0,
+            weighted_consensus_percentage: 0.0,
+            timestamp: now_secs(),
+            status: VerificationStatus::Expired,
+        }
+    }
+
+    /// Responses whose signature checks out, one per verifier
+    ///
+    /// Forged or tampered verdicts do not count, and a verifier answering more
+    /// than once, under the same agent ID or key, counts with its first answer only.
+    fn valid_responses<'a>(&self, request: &'a VerificationRequest) -> Vec<&'a VerificationResponse> {
+        let mut agents = HashSet::new();
+        let mut keys = HashSet::new();
+        request.responses
+            .iter()
+            .filter(|resp| {
+                if !self.verify_response_signature(resp) {
+                    log::warn!("Discarding verification response from {} with an invalid signature", resp.verifying_agent);
+                    return false;
+                }
+                let first_agent = agents.insert(resp.verifying_agent.as_str());
+                let first_key = keys.insert(resp.signer.as_slice());
+                if !(first_agent && first_key) {
+                    log::warn!("Discarding repeated verification response from {}", resp.verifying_agent);
+                }
+                first_agent && first_key
+            })
+            .collect()
+    }
+
+    /// Reputation used to weight a verifier's verdict, equal for all without a credibility engine
+    async fn verifier_reputation(&self, agent_id: &str) -> f64 {
+        match &self.credibility_engine {
+            Some(credibility_engine) => credibility_engine.get_source_reputation(agent_id).await,
+            None => 1.0,
+        }
+    }
+
     /// Process multiple evidence items for consensus (used for local + upstream correlation)
+    ///
+    /// Each correlated local and upstream pair is submitted for verification
+    /// as combined evidence. Only pairs whose consensus is already decided
+    /// are returned, with the index of their local item; the rest are
+    /// decided as peers respond.
     pub async fn process_evidence_correlation(
         &self,
         local_evidence: &[ThreatEvidence],
         upstream_evidence: &[ThreatEvidence],
-    ) -> Result<Vec<(ThreatEvidence, ConsensusResult)>> {
+    ) -> Result<Vec<(usize, ThreatEvidence, ConsensusResult)>> {
         let mut correlated_results = Vec::new();
 
         // Correlate local and upstream evidence
-        for local_item in local_evidence {
+        for (local_index, local_item) in local_evidence.iter().enumerate() {
             for upstream_item in upstream_evidence {
                 // Check if these items are related (same IP, same threat pattern, etc.)
                 if self.is_correlated_evidence(local_item, upstream_item) {
@@ -324,36 +629,35 @@ impl ConsensusEngine {
                     
                     // Submit for consensus verification
                     let verification_request = self.submit_for_verification(combined_evidence.clone()).await?;
-                    let consensus_result = self.check_consensus(&verification_request.request_id).await?;
-                    
-                    correlated_results.push((combined_evidence, consensus_result));
+                    match self.check_consensus(&verification_request.request_id).await {
+                        Ok(consensus_result) => correlated_results.push((local_index, combined_evidence, consensus_result)),
+                        Err(e) => log::debug!("Consensus on {} not decided yet: {}", combined_evidence.id, e),
+                    }
                 }
             }
         }
 
-        // Also process upstream evidence individually
-        for upstream_item in upstream_evidence {
-            if !local_evidence.iter().any(|local| {
-                self.is_correlated_evidence(local, upstream_item)
-            }) {
-                // Submit for consensus verification
-                let verification_request = self.submit_for_verification(upstream_item.clone()).await?;
-                let consensus_result = self.check_consensus(&verification_request.request_id).await?;
-                
-                correlated_results.push((upstream_item.clone(), consensus_result));
-            }
-        }
-
         Ok(correlated_results)
     }
 
     /// Check if two evidence items are correlated
     fn is_correlated_evidence(&self, evidence1: &ThreatEvidence, evidence2: &ThreatEvidence) -> bool {
-        // Check if they have the same source IP
+        // Check if they have the same source IP or subnet
         if !evidence1.source_ip.is_empty() && !evidence2.source_ip.is_empty() {
             if evidence1.source_ip == evidence2.source_ip {
                 return true;
             }
+            if let (Some(subnet1), Some(subnet2)) = (source_subnet(evidence1), source_subnet(evidence2)) {
+                if subnet1 == subnet2 {
+                    return true;
+                }
+            }
+        }
+
+        // Check if they share a hash, domain or URL
+        let indicators1 = evidence_indicators(evidence1);
+        if !indicators1.is_empty() && !indicators1.is_disjoint(&evidence_indicators(evidence2)) {
+            return true;
         }
 
         // Check if they have similar threat patterns
@@ -365,7 +669,8 @@ impl ConsensusEngine {
 
         // Check if they have similar context
         if !evidence1.context.is_empty() && !evidence2.context.is_empty() {
-            if evidence1.context.contains(&evidence2.context) || evidence2.context.contains(&evidence1.context) {
+            let similarity = self.config.context_similarity.score(&evidence1.context, &evidence2.context);
+            if similarity >= self.config.context_similarity_threshold {
                 return true;
             }
         }
@@ -385,7 +690,7 @@ impl ConsensusEngine {
             threat_type: if evidence1.threat_type != ThreatType::Unknown { evidence1.threat_type.clone() } else { evidence2.threat_type.clone() },
             threat_level: std::cmp::max(evidence1.threat_level, evidence2.threat_level), // Take higher threat level
             context: format!("{} | Combined with upstream: {}", evidence1.context, evidence2.context),
-            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(
+            evidence_hash: CryptoProvider::blake3_hash(
                 format!("{}-{}", evidence1.evidence_hash, evidence2.evidence_hash).as_bytes()
             ),
             geolocation: if !evidence1.geolocation.is_empty() { evidence1.geolocation.clone() } else { evidence2.geolocation.clone() },
@@ -394,15 +699,31 @@ impl ConsensusEngine {
             reputation: (evidence1.reputation + evidence2.reputation) / 2.0, // Average reputation
             compliance_tag: evidence1.compliance_tag.clone(), // Use first evidence compliance tag
             region: evidence1.region.clone(), // Use first evidence region
+            expires_at: evidence1.expires_at.or(evidence2.expires_at),
+            source_hostname: evidence1.source_hostname.clone().or_else(|| evidence2.source_hostname.clone()),
+            source_owner: evidence1.source_owner.clone().or_else(|| evidence2.source_owner.clone()),
+            campaign_id: evidence1.campaign_id.clone().or_else(|| evidence2.campaign_id.clone()),
+            source_network: evidence1.source_network.clone().or_else(|| evidence2.source_network.clone()),
+            signer_key: None,
+            signature: None,
         }
     }
 
     /// Sign a verification response
-    fn sign_verification_response(&self, request_id: &str, verdict: bool, confidence: f64) -> Result<String> {
-        // In a real implementation, this would create a cryptographic signature
-        // For now, we'll create a simple hash-based signature
-        let signature_data = format!("{}-{}-{:.2}-{}", request_id, verdict, confidence, self.local_agent_id);
-        Ok(crate::crypto::CryptoProvider::blake3_hash(signature_data.as_bytes()))
+    fn sign_verification_response(&self, response: &VerificationResponse) -> Result<Vec<u8>> {
+        self.signer.sign(&response.signing_payload()?)
+    }
+
+    /// Whether a response was signed by the holder of its `signer` key under this engine's scheme
+    pub fn verify_response_signature(&self, response: &VerificationResponse) -> bool {
+        response.signing_payload()
+            .and_then(|payload| self.signer.verify(&payload, &response.signature, &response.signer))
+            .unwrap_or(false)
+    }
+
+    /// Number of verification requests still awaiting consensus
+    pub async fn in_flight_requests(&self) -> usize {
+        self.pending_requests.read().await.values().filter(|request| request.status.is_in_flight()).count()
     }
 
     /// Get cached verification results
@@ -419,23 +740,175 @@ impl ConsensusEngine {
             .unwrap()
             .as_secs() as i64;
         
+        let before = requests.len();
         requests.retain(|_, request| {
             // Keep requests that are not expired (older than verification_timeout seconds)
             now - request.timestamp < self.config.verification_timeout as i64
         });
 
-        log::debug!("Cleaned up {} old verification requests", 
-                   requests.len() - self.pending_requests.read().await.len());
+        log::debug!("Cleaned up {} old verification requests", before - requests.len());
 
         Ok(())
     }
 
+    /// Drop verification requests and cached results timestamped before `cutoff`
+    ///
+    /// Returns the number of entries dropped. Checkpoint afterwards
+    /// to drop them from saved state too.
+    pub async fn expire_before(&self, cutoff: i64) -> usize {
+        let mut requests = self.pending_requests.write().await;
+        let before = requests.len();
+        requests.retain(|_, request| request.timestamp >= cutoff);
+        let mut expired = before - requests.len();
+        drop(requests);
+
+        let mut cache = self.verification_cache.write().await;
+        let before = cache.len();
+        cache.retain(|_, result| result.timestamp >= cutoff);
+        expired += before - cache.len();
+        expired
+    }
+
+    /// Drop verification requests about `ip`, and their cached results
+    ///
+    /// Returns the number of requests dropped. Checkpoint afterwards
+    /// to erase them from saved state too.
+    pub async fn forget_ip(&self, ip: IpAddr) -> usize {
+        let mut requests = self.pending_requests.write().await;
+        let evidence_ids: Vec<String> = requests
+            .values()
+            .filter(|request| request.evidence.concerns_ip(ip))
+            .map(|request| request.evidence_id.clone())
+            .collect();
+        requests.retain(|_, request| !request.evidence.concerns_ip(ip));
+        drop(requests);
+
+        let mut cache = self.verification_cache.write().await;
+        for evidence_id in &evidence_ids {
+            cache.remove(evidence_id);
+        }
+        evidence_ids.len()
+    }
+
+    /// Save verification requests and cached results to a JSON file
+    ///
+    /// The state is written to a temporary file in the same directory that is
+    /// then renamed into place, so a crash mid-write keeps the previous checkpoint.
+    pub async fn checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content = self.state_json().await?;
+        let file_name = path.file_name()
+            .ok_or_else(|| AgentError::ConfigError(format!("Invalid consensus state path: {}", path.display())))?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+        let written = async {
+            let mut file = tokio::fs::File::create(&tmp_path).await?;
+            file.write_all(&content).await?;
+            file.sync_all().await
+        }.await;
+        if let Err(e) = written {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(e.into());
+        }
+        tokio::fs::rename(&tmp_path, path).await?;
+        Ok(())
+    }
+
+    /// Load state saved by `checkpoint`, returning false if there was none
+    ///
+    /// Requests already past the verification timeout are dropped. A missing
+    /// or corrupt file leaves the engine's state untouched.
+    pub async fn restore<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = path.as_ref();
+        let content = match tokio::fs::read(path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("No consensus state at {}, starting fresh", path.display());
+                return Ok(false);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(self.apply_state_json(&content, &path.display().to_string()).await)
+    }
+
+    /// Save the same state as `checkpoint` to a storage backend
+    pub async fn checkpoint_to(&self, storage: &dyn Storage) -> Result<()> {
+        storage.put(STORAGE_KEY, self.state_json().await?).await
+    }
+
+    /// Restore state saved by `checkpoint_to`, returning false if there was none
+    ///
+    /// Requests already past the verification timeout are dropped. Missing
+    /// or corrupt state leaves the engine's state untouched.
+    pub async fn restore_from(&self, storage: &dyn Storage) -> Result<bool> {
+        let Some(content) = storage.get(STORAGE_KEY).await? else {
+            log::info!("No stored consensus state, starting fresh");
+            return Ok(false);
+        };
+        Ok(self.apply_state_json(&content, &format!("storage key {}", STORAGE_KEY)).await)
+    }
+
+    async fn state_json(&self) -> Result<Vec<u8>> {
+        let state = ConsensusState {
+            pending_requests: self.pending_requests.read().await.clone(),
+            verification_cache: self.verification_cache.read().await.clone(),
+        };
+        Ok(serde_json::to_vec(&state)?)
+    }
+
+    /// Apply serialized state, warning and returning false if it is corrupt
+    async fn apply_state_json(&self, content: &[u8], origin: &str) -> bool {
+        let state: ConsensusState = match serde_json::from_slice(content) {
+            Ok(state) => state,
+            Err(e) => {
+                log::warn!("Ignoring corrupt consensus state at {}: {}", origin, e);
+                return false;
+            }
+        };
+        
+        let now = now_secs();
+        let timeout = self.config.verification_timeout as i64;
+        let mut requests = self.pending_requests.write().await;
+        let before = requests.len();
+        requests.extend(state.pending_requests.into_iter().filter(|(_, request)| now - request.timestamp < timeout));
+        log::info!("Restored {} verification requests and {} cached results",
+                   requests.len() - before, state.verification_cache.len());
+        self.verification_cache.write().await.extend(state.verification_cache);
+        true
+    }
+
     /// Get current configuration
     pub fn get_config(&self) -> ConsensusConfig {
         self.config.clone()
     }
 }
 
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// Subnet of the evidence source used for correlation, /24 for IPv4 and /64 for IPv6
+fn source_subnet(evidence: &ThreatEvidence) -> Option<IpAddr> {
+    let ip = evidence.source_ip.trim().parse::<IpAddr>().ok()?;
+    Some(mask_ip(ip, CORRELATION_IPV4_PREFIX, CORRELATION_IPV6_PREFIX))
+}
+
+/// Hash, domain and URL indicators named by the evidence
+fn evidence_indicators(evidence: &ThreatEvidence) -> HashSet<(IndicatorType, String)> {
+    let mut indicators = threat_intel::extract_indicators(&evidence.context);
+    indicators.extend(threat_intel::extract_indicators(&evidence.network_flow));
+    if let Some(hostname) = evidence.source_hostname.as_deref().map(str::trim).filter(|hostname| !hostname.is_empty()) {
+        indicators.insert((IndicatorType::Domain, hostname.trim_end_matches('.').to_lowercase()));
+    }
+    indicators
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -466,16 +939,504 @@ mod tests {
             threat_type: crate::ThreatType::Malware,
             threat_level: crate::ThreatLevel::Critical,
             context: "Test threat evidence".to_string(),
-            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"test-data"),
+            evidence_hash: CryptoProvider::blake3_hash(b"test-data"),
             geolocation: "unknown".to_string(),
             network_flow: "TCP".to_string(),
             agent_id: "test-agent".to_string(),
             reputation: 0.9,
             compliance_tag: "global".to_string(),
             region: "test-region".to_string(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
         };
 
         let result = engine.submit_for_verification(evidence).await;
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_known_threat_ip_uses_indicator_store() {
+        use crate::threat_intel::{Indicator, IndicatorType};
+
+        let store = Arc::new(IndicatorStore::new());
+        let engine = ConsensusEngine::new(ConsensusConfig::default(), "test-agent".to_string())
+            .with_indicator_store(store.clone());
+
+        assert!(!engine.is_known_threat_ip("203.0.113.9").await);
+        store.add(Indicator::new("203.0.113.9", IndicatorType::Ip, ThreatType::IoCMatch, ThreatLevel::Warning, "test"));
+        assert!(engine.is_known_threat_ip("203.0.113.9").await);
+    }
+
+    fn create_context_evidence(source_ip: &str, network_flow: &str, context: &str) -> ThreatEvidence {
+        ThreatEvidence {
+            id: format!("test-{}", source_ip),
+            timestamp: 1_700_000_000,
+            source_ip: source_ip.to_string(),
+            target_ip: "10.0.0.1".to_string(),
+            threat_type: crate::ThreatType::Malware,
+            threat_level: crate::ThreatLevel::Critical,
+            context: context.to_string(),
+            evidence_hash: CryptoProvider::blake3_hash(context.as_bytes()),
+            geolocation: "unknown".to_string(),
+            network_flow: network_flow.to_string(),
+            agent_id: "test-agent".to_string(),
+            reputation: 0.9,
+            compliance_tag: "global".to_string(),
+            region: "test-region".to_string(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_correlation_reports_local_index() {
+        // No peers answer, so requests expire at once and each correlation is decided
+        let config = ConsensusConfig { verification_timeout: 0, ..ConsensusConfig::default() };
+        let engine = ConsensusEngine::new(config, "test-agent".to_string());
+        let local = [
+            create_context_evidence("203.0.113.7", "tcp:443", "TLS beacon"),
+            create_context_evidence("192.168.1.100", "tcp:22", "SSH brute force"),
+        ];
+        let upstream = [create_context_evidence("192.168.1.100", "tcp:22", "Known scanner")];
+
+        let correlations = engine.process_evidence_correlation(&local, &upstream).await.unwrap();
+        assert_eq!(correlations.len(), 1);
+        assert_eq!(correlations[0].0, 1);
+        assert_eq!(engine.in_flight_requests().await, 0);
+    }
+
+    #[test]
+    fn test_reworded_contexts_correlate() {
+        for algorithm in [ContextSimilarity::Jaccard, ContextSimilarity::Cosine] {
+            let config = ConsensusConfig {
+                context_similarity: algorithm,
+                ..ConsensusConfig::default()
+            };
+            let engine = ConsensusEngine::new(config, "test-agent".to_string());
+
+            let local = create_context_evidence("198.51.100.1", "TCP", "Emotet malware beacon to command and control server");
+            let upstream = create_context_evidence("198.51.100.2", "UDP", "command and control server beacon from Emotet malware");
+            assert!(engine.is_correlated_evidence(&local, &upstream), "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_unrelated_contexts_do_not_correlate() {
+        for algorithm in [ContextSimilarity::Jaccard, ContextSimilarity::Cosine] {
+            let config = ConsensusConfig {
+                context_similarity: algorithm,
+                ..ConsensusConfig::default()
+            };
+            let engine = ConsensusEngine::new(config, "test-agent".to_string());
+
+            // A short context contained in a longer one is no longer enough
+            let local = create_context_evidence("198.51.100.1", "TCP", "scan");
+            let upstream = create_context_evidence("203.0.113.2", "UDP", "Phishing page hosted on compromised scan server");
+            assert!(!engine.is_correlated_evidence(&local, &upstream), "{:?}", algorithm);
+
+            let local = create_context_evidence("198.51.100.1", "TCP", "SSH brute force login attempts");
+            let upstream = create_context_evidence("203.0.113.2", "UDP", "DNS amplification flood against resolver");
+            assert!(!engine.is_correlated_evidence(&local, &upstream), "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_shared_indicators_correlate() {
+        let engine = ConsensusEngine::new(ConsensusConfig::default(), "test-agent".to_string());
+
+        let local = create_context_evidence("198.51.100.1", "TCP", "Dropper 44d88612fea8a8f36de82e1278abb02f written to disk");
+        let upstream = create_context_evidence("203.0.113.2", "UDP", "Feed entry: sample 44D88612FEA8A8F36DE82E1278ABB02F");
+        assert!(engine.is_correlated_evidence(&local, &upstream));
+
+        let local = create_context_evidence("198.51.100.1", "TCP", "Beacon to c2.evil.example");
+        let upstream = create_context_evidence("203.0.113.2", "UDP", "Phishing kit at https://c2.evil.example/login");
+        assert!(engine.is_correlated_evidence(&local, &upstream));
+    }
+
+    #[test]
+    fn test_same_subnet_correlates() {
+        let engine = ConsensusEngine::new(ConsensusConfig::default(), "test-agent".to_string());
+
+        let local = create_context_evidence("198.51.100.1", "TCP", "SSH brute force login attempts");
+        let upstream = create_context_evidence("198.51.100.200", "UDP", "DNS amplification flood against resolver");
+        assert!(engine.is_correlated_evidence(&local, &upstream));
+
+        let upstream = create_context_evidence("198.51.101.1", "UDP", "DNS amplification flood against resolver");
+        assert!(!engine.is_correlated_evidence(&local, &upstream));
+    }
+
+    #[test]
+    fn test_unrelated_evidence_does_not_correlate() {
+        let engine = ConsensusEngine::new(ConsensusConfig::default(), "test-agent".to_string());
+
+        let local = create_context_evidence("198.51.100.1", "TCP", "Dropper 44d88612fea8a8f36de82e1278abb02f via bad.example");
+        let upstream = create_context_evidence("203.0.113.2", "UDP", "Sample e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855 via other.example");
+        assert!(!engine.is_correlated_evidence(&local, &upstream));
+
+        // Empty fields never match each other
+        let mut empty = create_context_evidence("", "", "");
+        empty.source_hostname = Some(String::new());
+        assert!(!engine.is_correlated_evidence(&empty, &empty.clone()));
+    }
+
+    #[test]
+    fn test_context_similarity_scores() {
+        assert_eq!(ContextSimilarity::Jaccard.score("a b c", "c b a"), 1.0);
+        assert_eq!(ContextSimilarity::Jaccard.score("a b", "c d"), 0.0);
+        assert!((ContextSimilarity::Cosine.score("Malware beacon", "malware, beacon!") - 1.0).abs() < 1e-9);
+        assert_eq!(ContextSimilarity::Cosine.score("", "anything"), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_peer_response_to_foreign_request_is_recorded() {
+        let requester = ConsensusEngine::new(ConsensusConfig::default(), "requester".to_string());
+        let peer = ConsensusEngine::new(ConsensusConfig::default(), "peer".to_string());
+
+        let evidence = create_context_evidence("198.51.100.1", "TCP", "Beacon to c2.evil.example");
+        let request = requester.submit_for_verification(evidence).await.unwrap();
+        let response = peer.verify_evidence(&request).await.unwrap();
+
+        let requests = peer.pending_requests.read().await;
+        let tracked = requests.get(&request.request_id).expect("peer should track the request");
+        assert_eq!(tracked.requesting_agent, "requester");
+        assert_eq!(tracked.responses.len(), 1);
+        assert_eq!(tracked.responses[0].verifying_agent, "peer");
+        assert_eq!(tracked.responses[0].signature, response.signature);
+    }
+
+    #[tokio::test]
+    async fn test_pending_request_limit() {
+        let config = ConsensusConfig {
+            max_pending_requests: 3,
+            ..ConsensusConfig::default()
+        };
+        let engine = ConsensusEngine::new(config, "test-agent".to_string());
+
+        let mut request_ids = Vec::new();
+        for i in 0..3 {
+            let evidence = create_context_evidence(&format!("198.51.100.{}", i), "TCP", "Test threat");
+            request_ids.push(engine.submit_for_verification(evidence).await.unwrap().request_id);
+        }
+
+        let overflow = create_context_evidence("198.51.100.50", "TCP", "Test threat");
+        assert!(engine.submit_for_verification(overflow.clone()).await.is_err());
+        assert_eq!(engine.pending_requests.read().await.len(), 3);
+
+        // Once a request finishes, its slot is freed
+        engine.pending_requests.write().await
+            .get_mut(&request_ids[0]).unwrap()
+            .status = VerificationStatus::ConsensusReached;
+        assert!(engine.submit_for_verification(overflow).await.is_ok());
+        assert_eq!(engine.pending_requests.read().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_threat_type_accuracy_weights_confidence() {
+        let credibility_engine = Arc::new(CredibilityEngine::new(Default::default()));
+        let engine = ConsensusEngine::new(ConsensusConfig::default(), "test-agent".to_string())
+            .with_credibility_engine(credibility_engine.clone());
+
+        let mut reliable = create_context_evidence("203.0.113.20", "TCP", "Test threat");
+        reliable.threat_type = ThreatType::Phishing;
+        reliable.threat_level = ThreatLevel::Info;
+        let unreliable = ThreatEvidence {
+            threat_type: ThreatType::BruteForce,
+            ..reliable.clone()
+        };
+
+        // Both types start at the same default accuracy
+        let (_, before_reliable, _) = engine.local_verify_evidence(&reliable).await;
+        let (_, before_unreliable, _) = engine.local_verify_evidence(&unreliable).await;
+        assert_eq!(before_reliable, before_unreliable);
+
+        for _ in 0..5 {
+            credibility_engine.update_credibility(&reliable, true).await.unwrap();
+            credibility_engine.update_credibility(&unreliable, false).await.unwrap();
+        }
+
+        let (_, reliable_confidence, justification) = engine.local_verify_evidence(&reliable).await;
+        let (_, unreliable_confidence, _) = engine.local_verify_evidence(&unreliable).await;
+        assert!(reliable_confidence > before_reliable);
+        assert!(unreliable_confidence < before_unreliable);
+        assert!(justification.contains("Phishing accuracy: 1.00"));
+    }
+
+    #[tokio::test]
+    async fn test_response_signatures_are_verified() {
+        let engine = ConsensusEngine::new(ConsensusConfig { min_verifiers: 1, ..ConsensusConfig::default() }, "requester".to_string());
+        let verifier_key = identity::Keypair::generate_ed25519();
+        let verifier = ConsensusEngine::new(ConsensusConfig::default(), "verifier".to_string())
+            .with_signer(Box::new(Ed25519Signer::new(verifier_key.clone())));
+
+        let request = engine
+            .submit_for_verification(create_context_evidence("203.0.113.30", "TCP", "Test threat"))
+            .await
+            .unwrap();
+        let response = verifier.verify_evidence(&request).await.unwrap();
+        assert_eq!(response.signer, verifier_key.public().encode_protobuf());
+        assert!(engine.verify_response_signature(&response));
+        let mut wrong_signer = response.clone();
+        wrong_signer.signer = identity::Keypair::generate_ed25519().public().encode_protobuf();
+        assert!(!engine.verify_response_signature(&wrong_signer));
+
+        engine.record_response(response).await;
+        assert_eq!(engine.check_consensus(&request.request_id).await.unwrap().total_verifiers, 1);
+    }
+
+    #[tokio::test]
+    async fn test_tampered_verdict_is_discarded() {
+        let engine = ConsensusEngine::new(ConsensusConfig { min_verifiers: 1, ..ConsensusConfig::default() }, "requester".to_string());
+        let verifier = ConsensusEngine::new(ConsensusConfig::default(), "verifier".to_string());
+
+        let request = engine
+            .submit_for_verification(create_context_evidence("203.0.113.31", "TCP", "Test threat"))
+            .await
+            .unwrap();
+        let honest = verifier.verify_evidence(&request).await.unwrap();
+        let mut impersonated = honest.clone();
+        impersonated.verifying_agent = "other-verifier".to_string();
+        assert!(!engine.verify_response_signature(&impersonated));
+        let mut tampered = verifier.verify_evidence(&request).await.unwrap();
+        tampered.verdict = !tampered.verdict;
+        tampered.verifying_agent = "forger".to_string();
+        assert!(!engine.verify_response_signature(&tampered));
+
+        engine.record_response(honest.clone()).await;
+        engine.record_response(tampered).await;
+        let result = engine.check_consensus(&request.request_id).await.unwrap();
+        assert_eq!(result.total_verifiers, 1);
+        assert!(!result.verified_by.contains(&"forger".to_string()));
+        assert!(!result.disputed_by.contains(&"forger".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_consensus_needs_min_distinct_verifiers() {
+        let engine = ConsensusEngine::new(ConsensusConfig { min_verifiers: 2, ..ConsensusConfig::default() }, "requester".to_string());
+        let request = engine
+            .submit_for_verification(create_context_evidence("203.0.113.32", "TCP", "Test threat"))
+            .await
+            .unwrap();
+
+        // One verifier answering repeatedly, or under another agent ID with the same key, is still one verifier
+        let verifier = ConsensusEngine::new(ConsensusConfig::default(), "verifier".to_string());
+        let first = verifier.verify_evidence(&request).await.unwrap();
+        let mut renamed = first.clone();
+        renamed.verifying_agent = "verifier-alias".to_string();
+        renamed.signature = verifier.sign_verification_response(&renamed).unwrap();
+        for response in [first.clone(), first, renamed] {
+            engine.record_response(response).await;
+        }
+        assert!(engine.check_consensus(&request.request_id).await.is_err());
+        assert_eq!(engine.pending_requests.read().await[&request.request_id].status, VerificationStatus::Pending);
+
+        engine.record_response(signed_response(&request, "second-verifier", true).await).await;
+        let result = engine.check_consensus(&request.request_id).await.unwrap();
+        assert_eq!(result.total_verifiers, 2);
+    }
+
+    async fn signed_response(request: &VerificationRequest, agent_id: &str, verdict: bool) -> VerificationResponse {
+        let verifier = ConsensusEngine::new(ConsensusConfig::default(), agent_id.to_string());
+        let mut response = verifier.verify_evidence(request).await.unwrap();
+        response.verdict = verdict;
+        response.signature = verifier.sign_verification_response(&response).unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_trusted_verifier_outweighs_low_reputation_disputers() {
+        let credibility_engine = Arc::new(CredibilityEngine::new(Default::default()));
+        credibility_engine.set_source_reputation("trusted", 0.95).await;
+        credibility_engine.set_source_reputation("spam-1", 0.3).await;
+        credibility_engine.set_source_reputation("spam-2", 0.3).await;
+        let engine = ConsensusEngine::new(ConsensusConfig::default(), "requester".to_string())
+            .with_credibility_engine(credibility_engine);
+
+        let request = engine
+            .submit_for_verification(create_context_evidence("203.0.113.40", "TCP", "Test threat"))
+            .await
+            .unwrap();
+        engine.record_response(signed_response(&request, "trusted", true).await).await;
+        engine.record_response(signed_response(&request, "spam-1", false).await).await;
+        engine.record_response(signed_response(&request, "spam-2", false).await).await;
+
+        let result = engine.check_consensus(&request.request_id).await.unwrap();
+        assert!(result.consensus_verdict);
+        assert_eq!(result.total_verifiers, 3);
+        assert_eq!(result.verified_by, vec!["trusted".to_string()]);
+        assert_eq!(result.disputed_by.len(), 2);
+        assert!((result.consensus_percentage - 1.0 / 3.0).abs() < 1e-9);
+        assert!((result.weighted_consensus_percentage - 0.95 / 1.55).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_request_expires() {
+        let engine = ConsensusEngine::new(ConsensusConfig::default(), "requester".to_string());
+        let request = engine
+            .submit_for_verification(create_context_evidence("203.0.113.50", "TCP", "Test threat"))
+            .await
+            .unwrap();
+
+        let result = engine.await_consensus(&request.request_id, Duration::from_millis(200)).await.unwrap();
+        assert_eq!(result.status, VerificationStatus::Expired);
+        assert!(!result.consensus_verdict);
+        assert_eq!(result.total_verifiers, 0);
+        assert_eq!(engine.pending_requests.read().await[&request.request_id].status, VerificationStatus::Expired);
+
+        // Late responses do not revive an expired request
+        engine.record_response(signed_response(&request, "late", true).await).await;
+        assert_eq!(engine.check_consensus(&request.request_id).await.unwrap().status, VerificationStatus::Expired);
+    }
+
+    #[tokio::test]
+    async fn test_check_consensus_expires_old_requests() {
+        let config = ConsensusConfig {
+            verification_timeout: 0,
+            ..ConsensusConfig::default()
+        };
+        let engine = ConsensusEngine::new(config, "requester".to_string());
+        let request = engine
+            .submit_for_verification(create_context_evidence("203.0.113.51", "TCP", "Test threat"))
+            .await
+            .unwrap();
+        engine.record_response(signed_response(&request, "verifier", true).await).await;
+
+        let result = engine.check_consensus(&request.request_id).await.unwrap();
+        assert_eq!(result.status, VerificationStatus::Expired);
+        assert_eq!(result.verified_by, vec!["verifier".to_string()]);
+        assert!(engine.get_cached_result(&request.evidence_id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_evidence_is_disputed_when_signatures_required() {
+        let engine = ConsensusEngine::new(ConsensusConfig::default(), "verifier".to_string())
+            .with_evidence_signatures_required(true);
+        let emitter = Ed25519Signer::new(identity::Keypair::generate_ed25519());
+
+        let mut signed = create_context_evidence("203.0.113.40", "TCP", "Test threat");
+        CryptoProvider::sign_evidence(&mut signed, &emitter).unwrap();
+        let (verdict, confidence, _) = engine.local_verify_evidence(&signed).await;
+        assert!(verdict);
+        assert!(confidence > 0.5);
+
+        let mut tampered = signed.clone();
+        tampered.source_ip = "203.0.113.41".to_string();
+        let unsigned = create_context_evidence("203.0.113.40", "TCP", "Test threat");
+        for evidence in [tampered, unsigned] {
+            let (verdict, confidence, justification) = engine.local_verify_evidence(&evidence).await;
+            assert!(!verdict);
+            assert_eq!(confidence, 0.0);
+            assert!(justification.contains("signature"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_restores_into_fresh_engine() {
+        let path = std::env::temp_dir().join(format!("orasrs-consensus-{}.json", uuid::Uuid::new_v4()));
+        let engine = ConsensusEngine::new(ConsensusConfig { min_verifiers: 1, ..ConsensusConfig::default() }, "requester".to_string());
+
+        let decided = engine
+            .submit_for_verification(create_context_evidence("203.0.113.60", "TCP", "Test threat"))
+            .await
+            .unwrap();
+        engine.record_response(signed_response(&decided, "verifier", true).await).await;
+        let result = engine.check_consensus(&decided.request_id).await.unwrap();
+        assert_eq!(result.status, VerificationStatus::ConsensusReached);
+
+        let in_flight = engine
+            .submit_for_verification(create_context_evidence("203.0.113.61", "TCP", "Test threat"))
+            .await
+            .unwrap();
+        let mut stale = engine
+            .submit_for_verification(create_context_evidence("203.0.113.62", "TCP", "Test threat"))
+            .await
+            .unwrap();
+        stale.timestamp -= 3600;
+        engine.pending_requests.write().await.insert(stale.request_id.clone(), stale.clone());
+        std::fs::write(&path, b"previous checkpoint").unwrap();
+        engine.checkpoint(&path).await.unwrap();
+
+        // The previous checkpoint is replaced through a temporary file
+        let tmp_path = path.with_file_name(format!(".{}.tmp", path.file_name().unwrap().to_string_lossy()));
+        assert!(!tmp_path.exists());
+
+        let restored = ConsensusEngine::new(ConsensusConfig::default(), "requester".to_string());
+        assert!(restored.restore(&path).await.unwrap());
+        let _ = std::fs::remove_file(&path);
+
+        let cached = restored.get_cached_result(&decided.evidence_id).await.unwrap();
+        assert!(cached.consensus_verdict);
+        assert_eq!(cached.verified_by, vec!["verifier".to_string()]);
+
+        // In-flight requests resume; those past their timeout are dropped
+        let requests = restored.pending_requests.read().await;
+        assert!(requests.contains_key(&in_flight.request_id));
+        assert!(!requests.contains_key(&stale.request_id));
+    }
+
+    #[tokio::test]
+    async fn test_missing_or_corrupt_checkpoint_starts_clean() {
+        let engine = ConsensusEngine::new(ConsensusConfig::default(), "requester".to_string());
+        let path = std::env::temp_dir().join(format!("orasrs-consensus-{}.json", uuid::Uuid::new_v4()));
+        assert!(!engine.restore(&path).await.unwrap());
+
+        std::fs::write(&path, b"{ not json").unwrap();
+        assert!(!engine.restore(&path).await.unwrap());
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(engine.in_flight_requests().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_storage_checkpoint_round_trip() {
+        let storage = crate::storage::MemoryStorage::new();
+        let engine = ConsensusEngine::new(ConsensusConfig::default(), "requester".to_string());
+        assert!(!engine.restore_from(&storage).await.unwrap());
+
+        let request = engine
+            .submit_for_verification(create_context_evidence("203.0.113.63", "TCP", "Test threat"))
+            .await
+            .unwrap();
+        engine.checkpoint_to(&storage).await.unwrap();
+
+        let restored = ConsensusEngine::new(ConsensusConfig::default(), "requester".to_string());
+        assert!(restored.restore_from(&storage).await.unwrap());
+        assert!(restored.pending_requests.read().await.contains_key(&request.request_id));
+
+        storage.put(STORAGE_KEY, b"{ not json".to_vec()).await.unwrap();
+        let fresh = ConsensusEngine::new(ConsensusConfig::default(), "requester".to_string());
+        assert!(!fresh.restore_from(&storage).await.unwrap());
+        assert_eq!(fresh.in_flight_requests().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_expire_before_drops_old_requests_and_results() {
+        let engine = ConsensusEngine::new(ConsensusConfig { min_verifiers: 1, ..ConsensusConfig::default() }, "requester".to_string());
+        let decided = engine
+            .submit_for_verification(create_context_evidence("203.0.113.60", "TCP", "Test threat"))
+            .await
+            .unwrap();
+        engine.record_response(signed_response(&decided, "verifier", true).await).await;
+        engine.check_consensus(&decided.request_id).await.unwrap();
+        let recent = engine
+            .submit_for_verification(create_context_evidence("203.0.113.61", "TCP", "Test threat"))
+            .await
+            .unwrap();
+
+        let now = chrono::Utc::now().timestamp();
+        assert_eq!(engine.expire_before(now - 60).await, 0);
+
+        engine.pending_requests.write().await.get_mut(&recent.request_id).unwrap().timestamp = now + 3600;
+        assert_eq!(engine.expire_before(now + 60).await, 2);
+        assert!(engine.get_cached_result(&decided.evidence_id).await.is_none());
+        assert_eq!(engine.in_flight_requests().await, 1);
+    }
+}
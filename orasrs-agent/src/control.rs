@@ -0,0 +1,44 @@
+//! Control endpoints reporting a running agent's health and status
+
+use crate::{
+    agent::StatusHandle,
+    error::Result,
+    http_server::{self, HttpResponse},
+};
+use hyper::{Method, StatusCode};
+use std::net::SocketAddr;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Serve `/health` and `/status` on `addr` until `shutdown` turns true
+///
+/// `/health` answers 200 while the agent runs and is connected to the P2P
+/// network and 503 otherwise; `/status` returns the agent status as JSON.
+pub async fn serve_control(
+    addr: SocketAddr,
+    status: StatusHandle,
+    shutdown: watch::Receiver<bool>,
+) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let stopping = shutdown.clone();
+    http_server::serve(addr, shutdown, move |method, path| {
+        let response = match (method, path.as_str()) {
+            (Method::GET, "/health") => health(&status, !*stopping.borrow()),
+            (Method::GET, "/status") => match serde_json::to_string(&status.snapshot()) {
+                Ok(body) => HttpResponse::new(StatusCode::OK, "application/json", body),
+                Err(e) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "text/plain", format!("{}\n", e)),
+            },
+            _ => HttpResponse::not_found(),
+        };
+        async move { response }
+    })
+    .await
+}
+
+/// Health check response
+fn health(status: &StatusHandle, running: bool) -> HttpResponse {
+    if running && status.snapshot().p2p_connected {
+        HttpResponse::new(StatusCode::OK, "text/plain", "ok\n")
+    } else {
+        HttpResponse::new(StatusCode::SERVICE_UNAVAILABLE, "text/plain", "unavailable\n")
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{ThreatEvidence, compliance::mask_ip};
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Maximum number of recent events kept for correlation
+const MAX_BUFFERED_EVENTS: usize = 10_000;
+
+/// A recent event remembered by the correlator
+#[derive(Debug, Clone)]
+struct CorrelatedEvent {
+    source_ip: String,
+    subnet: Option<IpAddr>,
+    timestamp: i64,
+    campaign_id: String,
+}
+
+/// Links evidence to earlier related events within a time window
+///
+/// Evidence from the same source IP, or failing that the same subnet, seen
+/// within `window_secs` of an earlier event joins that event's campaign;
+/// otherwise a new campaign is started.
+pub struct TemporalCorrelator {
+    window_secs: i64,
+    ipv4_prefix: u8,
+    ipv6_prefix: u8,
+    events: Mutex<VecDeque<CorrelatedEvent>>,
+}
+
+impl TemporalCorrelator {
+    /// Create a correlator grouping IPv4 /24 and IPv6 /64 subnets
+    pub fn new(window_secs: u64) -> Self {
+        Self {
+            window_secs: window_secs as i64,
+            ipv4_prefix: 24,
+            ipv6_prefix: 64,
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Set the prefix lengths used to decide whether two IPs share a subnet
+    pub fn with_subnet_prefixes(mut self, ipv4_prefix: u8, ipv6_prefix: u8) -> Self {
+        self.ipv4_prefix = ipv4_prefix.min(32);
+        self.ipv6_prefix = ipv6_prefix.min(128);
+        self
+    }
+
+    /// Assign the evidence to a campaign, returning the campaign ID
+    pub fn correlate(&self, evidence: &mut ThreatEvidence) -> String {
+        let subnet = evidence.source_ip
+            .parse::<IpAddr>()
+            .ok()
+            .map(|ip| mask_ip(ip, self.ipv4_prefix, self.ipv6_prefix));
+
+        let mut events = self.events.lock().unwrap();
+        let cutoff = evidence.timestamp - self.window_secs;
+        events.retain(|event| event.timestamp >= cutoff);
+
+        // Prefer the most recent event from the same IP over one from the same subnet
+        let same_ip = events.iter().rev().find(|event| event.source_ip == evidence.source_ip);
+        let same_subnet = || {
+            subnet.and_then(|subnet| events.iter().rev().find(|event| event.subnet == Some(subnet)))
+        };
+        let campaign_id = same_ip
+            .or_else(same_subnet)
+            .map(|event| event.campaign_id.clone())
+            .unwrap_or_else(|| format!("campaign-{}", Uuid::new_v4()));
+
+        events.push_back(CorrelatedEvent {
+            source_ip: evidence.source_ip.clone(),
+            subnet,
+            timestamp: evidence.timestamp,
+            campaign_id: campaign_id.clone(),
+        });
+        while events.len() > MAX_BUFFERED_EVENTS {
+            events.pop_front();
+        }
+
+        evidence.campaign_id = Some(campaign_id.clone());
+        campaign_id
+    }
+
+    /// Number of events currently held in the window
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every buffered event from `ip`, returning how many were dropped
+    pub fn forget_ip(&self, ip: IpAddr) -> usize {
+        let mut events = self.events.lock().unwrap();
+        let before = events.len();
+        events.retain(|event| event.source_ip.parse::<IpAddr>() != Ok(ip));
+        before - events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_evidence;
+
+    fn create_test_evidence(ip: &str, timestamp: i64) -> ThreatEvidence {
+        ThreatEvidence { timestamp, ..test_evidence(ip) }
+    }
+
+    #[test]
+    fn test_events_minutes_apart_share_campaign() {
+        let correlator = TemporalCorrelator::new(600);
+        let start = 1_700_000_000;
+
+        let mut first = create_test_evidence("203.0.113.9", start);
+        let mut second = create_test_evidence("203.0.113.9", start + 120);
+        let campaign = correlator.correlate(&mut first);
+        assert_eq!(correlator.correlate(&mut second), campaign);
+        assert_eq!(first.campaign_id, second.campaign_id);
+
+        // Same subnet joins the campaign, an unrelated source does not
+        assert_eq!(correlator.correlate(&mut create_test_evidence("203.0.113.200", start + 180)), campaign);
+        assert_ne!(correlator.correlate(&mut create_test_evidence("198.51.100.1", start + 180)), campaign);
+    }
+
+    #[test]
+    fn test_events_outside_window_start_new_campaign() {
+        let correlator = TemporalCorrelator::new(300);
+        let start = 1_700_000_000;
+
+        let campaign = correlator.correlate(&mut create_test_evidence("2001:db8::1", start));
+        assert_eq!(correlator.correlate(&mut create_test_evidence("2001:db8::2", start + 60)), campaign);
+
+        // Older events are dropped once they fall out of the window
+        let later = correlator.correlate(&mut create_test_evidence("2001:db8::1", start + 1000));
+        assert_ne!(later, campaign);
+        assert_eq!(correlator.len(), 1);
+    }
+}
@@ -1,8 +1,16 @@
-use crate::{ThreatEvidence, ThreatLevel, error::{AgentError, Result}};
+use crate::{ThreatEvidence, ThreatLevel, error::Result, storage::Storage};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 
+/// Reputation of a source with no history, and the baseline sources decay toward
+const DEFAULT_SOURCE_REPUTATION: f64 = 0.7;
+
+/// Reputation of an IP with no history, and the baseline IPs decay toward
+const DEFAULT_IP_REPUTATION: f64 = 0.5;
+
 /// Credibility enhancement engine
 pub struct CredibilityEngine {
     /// Track source reputation scores
@@ -44,6 +52,18 @@ pub struct CredibilityConfig {
     
     /// Time window for recency factor in seconds
     pub recency_time_window: u64,
+    
+    /// Weight of each verification result in a source's reputation (0.0-1.0)
+    pub source_learning_rate: f64,
+    
+    /// Weight of each verification result in an IP's reputation (0.0-1.0)
+    pub ip_learning_rate: f64,
+    
+    /// Lowest reputation a source or IP can reach
+    pub reputation_floor: f64,
+    
+    /// Highest reputation a source or IP can reach
+    pub reputation_ceiling: f64,
 }
 
 impl Default for CredibilityConfig {
@@ -57,6 +77,10 @@ impl Default for CredibilityConfig {
             medium_confidence_threshold: 0.6,
             reputation_decay_factor: 0.99,
             recency_time_window: 86400, // 24 hours
+            source_learning_rate: 0.1,
+            ip_learning_rate: 0.05,
+            reputation_floor: 0.0,
+            reputation_ceiling: 1.0,
         }
     }
 }
@@ -121,25 +145,20 @@ impl CredibilityEngine {
         // Update source reputation
         {
             let mut source_reputation = self.source_reputation.write().await;
-            let current_rep = source_reputation.entry(evidence.agent_id.clone()).or_insert(0.7); // Default to 0.7
-            
-            if is_accurate {
-                *current_rep = (*current_rep * 0.9 + 1.0 * 0.1).min(1.0); // Boost with 10% weight
-            } else {
-                *current_rep = (*current_rep * 0.9 + 0.0 * 0.1).max(0.0); // Reduce with 10% weight
-            }
+            let current_rep = source_reputation.entry(evidence.agent_id.clone()).or_insert(DEFAULT_SOURCE_REPUTATION);
+            *current_rep = self.nudge_reputation(*current_rep, is_accurate, self.config.source_learning_rate);
         }
 
+        self.update_evidence_credibility(evidence, is_accurate).await
+    }
+
+    /// Update IP reputation and threat type accuracy, leaving the reporting source's reputation unchanged
+    pub async fn update_evidence_credibility(&self, evidence: &ThreatEvidence, is_accurate: bool) -> Result<()> {
         // Update IP reputation
         {
             let mut ip_reputation = self.ip_reputation.write().await;
-            let current_rep = ip_reputation.entry(evidence.source_ip.clone()).or_insert(0.5); // Default to 0.5
-            
-            if is_accurate {
-                *current_rep = (*current_rep * 0.95 + 1.0 * 0.05).min(1.0); // Small update for IP
-            } else {
-                *current_rep = (*current_rep * 0.95 + 0.0 * 0.05).max(0.0);
-            }
+            let current_rep = ip_reputation.entry(evidence.source_ip.clone()).or_insert(DEFAULT_IP_REPUTATION);
+            *current_rep = self.nudge_reputation(*current_rep, is_accurate, self.config.ip_learning_rate);
         }
 
         // Update threat type accuracy
@@ -157,8 +176,37 @@ impl CredibilityEngine {
         Ok(())
     }
 
+    /// Move a reputation toward the ceiling or floor by the learning rate
+    fn nudge_reputation(&self, current: f64, is_accurate: bool, learning_rate: f64) -> f64 {
+        let target = if is_accurate {
+            self.config.reputation_ceiling
+        } else {
+            self.config.reputation_floor
+        };
+        (current * (1.0 - learning_rate) + target * learning_rate)
+            .max(self.config.reputation_floor)
+            .min(self.config.reputation_ceiling)
+    }
+
+    /// Pull every tracked reputation toward its neutral baseline by `reputation_decay_factor`
+    ///
+    /// Called periodically so trust earned or lost long ago fades unless renewed.
+    pub async fn apply_decay(&self) {
+        let factor = self.config.reputation_decay_factor;
+        let decay = |reputation: &mut f64, baseline: f64| {
+            *reputation = baseline + (*reputation - baseline) * factor;
+        };
+
+        for reputation in self.source_reputation.write().await.values_mut() {
+            decay(reputation, DEFAULT_SOURCE_REPUTATION);
+        }
+        for reputation in self.ip_reputation.write().await.values_mut() {
+            decay(reputation, DEFAULT_IP_REPUTATION);
+        }
+    }
+
     /// Get source reputation
-    async fn get_source_reputation(&self, source_id: &str) -> f64 {
+    pub async fn get_source_reputation(&self, source_id: &str) -> f64 {
         let source_reputation = self.source_reputation.read().await;
         
         // For upstream sources, provide a default high reputation
@@ -166,17 +214,25 @@ impl CredibilityEngine {
             return 0.9; // High trust for upstream feeds
         }
         
-        *source_reputation.get(source_id).unwrap_or(&0.7) // Default to 0.7
+        *source_reputation.get(source_id).unwrap_or(&DEFAULT_SOURCE_REPUTATION)
+    }
+
+    /// Pin a source's reputation, clamped to the configured floor and ceiling
+    pub async fn set_source_reputation(&self, source_id: &str, reputation: f64) {
+        let reputation = reputation
+            .max(self.config.reputation_floor)
+            .min(self.config.reputation_ceiling);
+        self.source_reputation.write().await.insert(source_id.to_string(), reputation);
     }
 
     /// Get IP reputation
     async fn get_ip_reputation(&self, ip: &str) -> f64 {
         let ip_reputation = self.ip_reputation.read().await;
-        *ip_reputation.get(ip).unwrap_or(&0.5) // Default to 0.5
+        *ip_reputation.get(ip).unwrap_or(&DEFAULT_IP_REPUTATION)
     }
 
     /// Get threat type accuracy
-    async fn get_threat_type_accuracy(&self, threat_type: &crate::ThreatType) -> f64 {
+    pub async fn get_threat_type_accuracy(&self, threat_type: &crate::ThreatType) -> f64 {
         let threat_type_accuracy = self.threat_type_accuracy.read().await;
         let threat_type_key = format!("{:?}", threat_type);
         
@@ -208,7 +264,7 @@ impl CredibilityEngine {
         let credibility_score = self.calculate_credibility_score(&evidence, consensus_confidence).await?;
         
         // Adjust threat level based on credibility score
-        let adjusted_threat_level = self.adjust_threat_level_by_credential(&evidence.threat_level, credibility_score);
+        let adjusted_threat_level = self.adjust_threat_level_by_credential(evidence.threat_level, credibility_score);
         
         // Update the evidence with credibility-enhanced information
         evidence.threat_level = adjusted_threat_level;
@@ -256,6 +312,100 @@ impl CredibilityEngine {
         Ok(enhanced_evidences)
     }
 
+    /// Save reputation and accuracy state to a JSON file
+    pub async fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let content = self.state_json().await?;
+        if let Some(parent) = path.as_ref().parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, content).await?;
+        Ok(())
+    }
+
+    /// Load state saved by `save_to`, returning false if there was none
+    ///
+    /// A missing or corrupt file leaves the engine's state untouched.
+    pub async fn load_from<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = path.as_ref();
+        let content = match tokio::fs::read(path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("No credibility state at {}, starting fresh", path.display());
+                return Ok(false);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(self.apply_state_json(&content, &path.display().to_string()).await)
+    }
+
+    /// Save the same state as `save_to` to a storage backend
+    pub async fn persist(&self, storage: &dyn Storage) -> Result<()> {
+        storage.put(STORAGE_KEY, self.state_json().await?).await
+    }
+
+    /// Restore state saved by `persist`, returning false if there was none
+    ///
+    /// Missing or corrupt state leaves the engine's state untouched.
+    pub async fn restore(&self, storage: &dyn Storage) -> Result<bool> {
+        let Some(content) = storage.get(STORAGE_KEY).await? else {
+            log::info!("No stored credibility state, starting fresh");
+            return Ok(false);
+        };
+        Ok(self.apply_state_json(&content, &format!("storage key {}", STORAGE_KEY)).await)
+    }
+
+    async fn state_json(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(&self.snapshot().await)?)
+    }
+
+    /// Apply serialized state, warning and returning false if it is corrupt
+    async fn apply_state_json(&self, content: &[u8], origin: &str) -> bool {
+        match serde_json::from_slice(content) {
+            Ok(state) => {
+                self.apply_state(state).await;
+                true
+            }
+            Err(e) => {
+                log::warn!("Ignoring corrupt credibility state at {}: {}", origin, e);
+                false
+            }
+        }
+    }
+
+    async fn snapshot(&self) -> CredibilityState {
+        CredibilityState {
+            source_reputation: self.source_reputation.read().await.clone(),
+            ip_reputation: self.ip_reputation.read().await.clone(),
+            threat_type_accuracy: self.threat_type_accuracy.read().await.clone(),
+        }
+    }
+
+    async fn apply_state(&self, state: CredibilityState) {
+        *self.source_reputation.write().await = state.source_reputation;
+        *self.ip_reputation.write().await = state.ip_reputation;
+        *self.threat_type_accuracy.write().await = state.threat_type_accuracy;
+    }
+
+    /// Reputation of every tracked source, most trusted first
+    pub async fn source_reputations(&self) -> Vec<(String, f64)> {
+        let snapshot: Vec<(String, f64)> = self.source_reputation.read().await
+            .iter()
+            .map(|(source, reputation)| (source.clone(), *reputation))
+            .collect();
+        sort_by_reputation(snapshot)
+    }
+
+    /// The `top_n` most reputable tracked IPs, most trusted first
+    pub async fn ip_reputations(&self, top_n: usize) -> Vec<(String, f64)> {
+        let snapshot: Vec<(String, f64)> = self.ip_reputation.read().await
+            .iter()
+            .map(|(ip, reputation)| (ip.clone(), *reputation))
+            .collect();
+        let mut sorted = sort_by_reputation(snapshot);
+        sorted.truncate(top_n);
+        sorted
+    }
+
     /// Get current credibility metrics
     pub async fn get_metrics(&self) -> CredibilityMetrics {
         let source_reputation = self.source_reputation.read().await;
@@ -272,6 +422,23 @@ impl CredibilityEngine {
     }
 }
 
+/// Sort a reputation snapshot descending, breaking ties by name
+fn sort_by_reputation(mut reputations: Vec<(String, f64)>) -> Vec<(String, f64)> {
+    reputations.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    reputations
+}
+
+/// Storage key for persisted credibility state
+const STORAGE_KEY: &str = "credibility/state";
+
+/// Persisted form of the engine's learned state
+#[derive(Serialize, Deserialize)]
+struct CredibilityState {
+    source_reputation: HashMap<String, f64>,
+    ip_reputation: HashMap<String, f64>,
+    threat_type_accuracy: HashMap<String, (u64, u64)>,
+}
+
 /// Credibility metrics for monitoring
 #[derive(Debug, Clone)]
 pub struct CredibilityMetrics {
@@ -286,6 +453,7 @@ pub struct CredibilityMetrics {
 mod tests {
     use super::*;
     use crate::{ThreatEvidence, ThreatType, ThreatLevel};
+    use crate::test_evidence;
 
     #[tokio::test]
     async fn test_credibility_engine_creation() {
@@ -318,6 +486,13 @@ mod tests {
             reputation: 0.8,
             compliance_tag: "global".to_string(),
             region: "test".to_string(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
         };
 
         let score = engine.calculate_credibility_score(&evidence, Some(0.9)).await.unwrap();
@@ -347,6 +522,13 @@ mod tests {
             reputation: 0.8,
             compliance_tag: "global".to_string(),
             region: "test".to_string(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
         };
 
         // Initially should have default reputation
@@ -360,4 +542,180 @@ mod tests {
         let updated_rep = engine.get_source_reputation("test-agent-2").await;
         assert!(updated_rep > 0.7);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_reputation_asymptotes_to_ceiling() {
+        let config = CredibilityConfig {
+            reputation_floor: 0.1,
+            reputation_ceiling: 0.95,
+            source_learning_rate: 0.2,
+            ..CredibilityConfig::default()
+        };
+        let engine = CredibilityEngine::new(config);
+
+        let mut evidence = ThreatEvidence {
+            id: "test".to_string(),
+            timestamp: 1_700_000_000,
+            source_ip: "192.168.1.102".to_string(),
+            target_ip: "10.0.0.1".to_string(),
+            threat_type: ThreatType::Malware,
+            threat_level: ThreatLevel::Critical,
+            context: "Test threat".to_string(),
+            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"test"),
+            geolocation: "unknown".to_string(),
+            network_flow: "TCP".to_string(),
+            agent_id: "test-agent-3".to_string(),
+            reputation: 0.8,
+            compliance_tag: "global".to_string(),
+            region: "test".to_string(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        };
+
+        for _ in 0..200 {
+            engine.update_credibility(&evidence, true).await.unwrap();
+        }
+        let trusted = engine.get_source_reputation("test-agent-3").await;
+        assert!(trusted <= 0.95);
+        assert!(trusted > 0.94);
+        assert!(engine.get_ip_reputation("192.168.1.102").await <= 0.95);
+
+        evidence.agent_id = "test-agent-4".to_string();
+        for _ in 0..200 {
+            engine.update_credibility(&evidence, false).await.unwrap();
+        }
+        let distrusted = engine.get_source_reputation("test-agent-4").await;
+        assert!(distrusted >= 0.1);
+        assert!(distrusted < 0.11);
+    }
+
+    #[tokio::test]
+    async fn test_persist_and_restore() {
+        let storage = crate::storage::MemoryStorage::new();
+        let engine = CredibilityEngine::new(CredibilityConfig::default());
+        assert!(!engine.restore(&storage).await.unwrap());
+
+        let evidence = ThreatEvidence {
+            id: "test".to_string(),
+            timestamp: 1_700_000_000,
+            source_ip: "192.168.1.103".to_string(),
+            target_ip: "10.0.0.1".to_string(),
+            threat_type: ThreatType::Malware,
+            threat_level: ThreatLevel::Critical,
+            context: "Test threat".to_string(),
+            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(b"test"),
+            geolocation: "unknown".to_string(),
+            network_flow: "TCP".to_string(),
+            agent_id: "test-agent-5".to_string(),
+            reputation: 0.8,
+            compliance_tag: "global".to_string(),
+            region: "test".to_string(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        };
+        engine.update_credibility(&evidence, false).await.unwrap();
+        engine.persist(&storage).await.unwrap();
+
+        let restored = CredibilityEngine::new(CredibilityConfig::default());
+        assert!(restored.restore(&storage).await.unwrap());
+        assert_eq!(
+            restored.get_source_reputation("test-agent-5").await,
+            engine.get_source_reputation("test-agent-5").await
+        );
+        assert_eq!(restored.get_metrics().await.total_threat_types_tracked, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reputation_decays_toward_baseline() {
+        let config = CredibilityConfig {
+            reputation_decay_factor: 0.5,
+            ..CredibilityConfig::default()
+        };
+        let engine = CredibilityEngine::new(config);
+        engine.set_source_reputation("trusted", 1.0).await;
+        engine.set_source_reputation("distrusted", 0.0).await;
+        engine.ip_reputation.write().await.insert("203.0.113.9".to_string(), 0.9);
+
+        engine.apply_decay().await;
+        assert!((engine.get_source_reputation("trusted").await - 0.85).abs() < 1e-9);
+
+        for _ in 0..20 {
+            engine.apply_decay().await;
+        }
+        assert!((engine.get_source_reputation("trusted").await - DEFAULT_SOURCE_REPUTATION).abs() < 1e-3);
+        assert!((engine.get_source_reputation("distrusted").await - DEFAULT_SOURCE_REPUTATION).abs() < 1e-3);
+        assert!((engine.get_ip_reputation("203.0.113.9").await - DEFAULT_IP_REPUTATION).abs() < 1e-3);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_state_is_not_restored() {
+        let storage = crate::storage::MemoryStorage::new();
+        storage.put(STORAGE_KEY, b"{not json".to_vec()).await.unwrap();
+
+        let engine = CredibilityEngine::new(CredibilityConfig::default());
+        engine.update_credibility(&test_evidence("203.0.113.60"), true).await.unwrap();
+        let before = engine.get_source_reputation("test-agent").await;
+
+        assert!(!engine.restore(&storage).await.unwrap());
+        assert_eq!(engine.get_source_reputation("test-agent").await, before);
+        assert_eq!(engine.get_metrics().await.total_sources_tracked, 1);
+    }
+
+    #[tokio::test]
+    async fn test_json_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("orasrs-credibility-{}.json", uuid::Uuid::new_v4()));
+
+        let engine = CredibilityEngine::new(CredibilityConfig::default());
+        let mut evidence = test_evidence("203.0.113.60");
+        evidence.agent_id = "peer-agent".to_string();
+        engine.update_credibility(&evidence, true).await.unwrap();
+        engine.update_credibility(&evidence, false).await.unwrap();
+        engine.save_to(&path).await.unwrap();
+
+        let loaded = CredibilityEngine::new(CredibilityConfig::default());
+        assert!(loaded.load_from(&path).await.unwrap());
+        assert!((loaded.get_source_reputation("peer-agent").await - engine.get_source_reputation("peer-agent").await).abs() < 1e-12);
+        assert!((loaded.get_ip_reputation("203.0.113.60").await - engine.get_ip_reputation("203.0.113.60").await).abs() < 1e-12);
+        assert_eq!(loaded.get_threat_type_accuracy(&ThreatType::Malware).await, engine.get_threat_type_accuracy(&ThreatType::Malware).await);
+
+        // Corrupt or missing files leave a fresh engine
+        std::fs::write(&path, b"{not json").unwrap();
+        let fresh = CredibilityEngine::new(CredibilityConfig::default());
+        assert!(!fresh.load_from(&path).await.unwrap());
+        assert_eq!(fresh.get_metrics().await.total_sources_tracked, 0);
+        let _ = std::fs::remove_file(&path);
+        assert!(!fresh.load_from(&path).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reputations_are_listed_most_trusted_first() {
+        let engine = CredibilityEngine::new(CredibilityConfig::default());
+        for (agent_id, ip, accurate_reports) in [("feed-b", "198.51.100.2", 1), ("feed-a", "198.51.100.1", 3), ("feed-c", "198.51.100.3", 0)] {
+            let mut evidence = test_evidence(ip);
+            evidence.agent_id = agent_id.to_string();
+            for _ in 0..accurate_reports {
+                engine.update_credibility(&evidence, true).await.unwrap();
+            }
+            engine.update_credibility(&evidence, false).await.unwrap();
+        }
+
+        let sources: Vec<String> = engine.source_reputations().await.into_iter().map(|(source, _)| source).collect();
+        assert_eq!(sources, vec!["feed-a", "feed-b", "feed-c"]);
+
+        let ips = engine.ip_reputations(2).await;
+        assert_eq!(ips.len(), 2);
+        assert_eq!(ips[0].0, "198.51.100.1");
+        assert_eq!(ips[1].0, "198.51.100.2");
+        assert!(ips[0].1 > ips[1].1);
+    }
+}
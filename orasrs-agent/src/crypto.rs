@@ -1,6 +1,24 @@
+use crate::config::CryptoConfig;
 use crate::error::{AgentError, Result};
+use crate::storage::Storage;
+use crate::ThreatEvidence;
 use blake3;
+use libp2p::identity;
 use ring::{digest, rand, aead};
+use ring::rand::SecureRandom;
+
+/// Length of the random nonce prepended to AES-256-GCM ciphertext
+const NONCE_LEN: usize = 12;
+
+/// Storage key of the agent's Ed25519 identity key
+const AGENT_KEY: &str = "crypto/agent_key";
+
+/// Storage key of the evidence context encryption key used when none is configured
+const CONTEXT_KEY: &str = "crypto/context_key";
+
+/// Signer identity hashed into SM2 signatures, the GB/T 35276 default
+#[cfg(feature = "sm_crypto")]
+const SM2_SIGNER_ID: &str = "1234567812345678";
 
 /// Cryptographic utilities for OraSRS Agent
 pub struct CryptoProvider;
@@ -12,15 +30,11 @@ impl CryptoProvider {
         hash.to_hex().as_str()[..16].to_string() // Use first 16 chars for brevity
     }
     
-    /// Generate SM3 hash (placeholder - in real implementation would use actual SM3)
+    /// Generate SM3 hash (GB/T 32905) as 64 lowercase hex chars
     #[cfg(feature = "sm_crypto")]
     pub fn sm3_hash(data: &[u8]) -> String {
-        // Placeholder implementation - would use actual SM3 in real implementation
-        use sm_crypto::sm3::{Sm3, Hash};
-        let mut sm3 = Sm3::new();
-        sm3.update(data);
-        let result = sm3.finalize();
-        format!("{:x}", result)
+        use sm3::{Digest, Sm3};
+        Self::hex_encode(&Sm3::digest(data))
     }
     
     /// Generate SM3 hash (fallback without sm_crypto feature)
@@ -31,6 +45,8 @@ impl CryptoProvider {
     }
     
     /// Encrypt data using AES-256-GCM (or SM4 if enabled)
+    ///
+    /// A fresh random nonce is generated per call and prepended to the ciphertext.
     pub fn encrypt_data(data: &[u8], key: &[u8]) -> Result<Vec<u8>> {
         // Use AES-256-GCM for now
         let rng = rand::SystemRandom::new();
@@ -45,8 +61,10 @@ impl CryptoProvider {
                 .map_err(|e| AgentError::CryptoError(format!("Invalid key: {}", e)))?
         );
         
-        let nonce = aead::Nonce::try_assume_unique_for_key(&[0u8; 12][..])
-            .map_err(|e| AgentError::CryptoError(format!("Invalid nonce: {}", e)))?;
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        rng.fill(&mut nonce_bytes)
+            .map_err(|e| AgentError::CryptoError(format!("Nonce generation failed: {}", e)))?;
+        let nonce = aead::Nonce::assume_unique_for_key(nonce_bytes);
         
         let aad = aead::Aad::empty();
         
@@ -54,13 +72,14 @@ impl CryptoProvider {
         key.seal_in_place_append_tag(nonce, aad, &mut data_vec)
             .map_err(|e| AgentError::CryptoError(format!("Encryption failed: {}", e)))?;
         
-        Ok(data_vec)
+        let mut output = nonce_bytes.to_vec();
+        output.append(&mut data_vec);
+        Ok(output)
     }
     
-    /// Decrypt data using AES-256-GCM (or SM4 if enabled)
+    /// Decrypt data produced by `encrypt_data`, reading the nonce from its first 12 bytes
     pub fn decrypt_data(encrypted_data: &[u8], key: &[u8]) -> Result<Vec<u8>> {
         // Use AES-256-GCM for now
-        let rng = rand::SystemRandom::new();
         let key_bytes = if key.len() >= 32 {
             &key[..32]
         } else {
@@ -72,18 +91,112 @@ impl CryptoProvider {
                 .map_err(|e| AgentError::CryptoError(format!("Invalid key: {}", e)))?
         );
         
-        let nonce = aead::Nonce::try_assume_unique_for_key(&[0u8; 12][..])
+        if encrypted_data.len() < NONCE_LEN {
+            return Err(AgentError::CryptoError("Ciphertext too short".to_string()));
+        }
+        let (nonce_bytes, ciphertext) = encrypted_data.split_at(NONCE_LEN);
+        let nonce = aead::Nonce::try_assume_unique_for_key(nonce_bytes)
             .map_err(|e| AgentError::CryptoError(format!("Invalid nonce: {}", e)))?;
         
         let aad = aead::Aad::empty();
         
-        let mut data_vec = encrypted_data.to_vec();
+        let mut data_vec = ciphertext.to_vec();
         let decrypted = key.open_in_place(nonce, aad, &mut data_vec)
             .map_err(|e| AgentError::CryptoError(format!("Decryption failed: {}", e)))?;
         
         Ok(decrypted.to_vec())
     }
     
+    /// Encode bytes as lowercase hex
+    pub fn hex_encode(data: &[u8]) -> String {
+        hex::encode(data)
+    }
+    
+    /// Decode a hex string produced by `hex_encode`
+    pub fn hex_decode(encoded: &str) -> Result<Vec<u8>> {
+        hex::decode(encoded).map_err(|e| AgentError::CryptoError(format!("Invalid hex: {}", e)))
+    }
+
+    /// Decode a hex-encoded 32-byte context encryption key
+    pub fn parse_context_key(encoded: &str) -> Result<Vec<u8>> {
+        let key = Self::hex_decode(encoded.trim())?;
+        if key.len() != 32 {
+            return Err(AgentError::CryptoError(format!("Expected a 32-byte key, got {} bytes", key.len())));
+        }
+        Ok(key)
+    }
+
+    /// Load the context encryption key kept in `storage`, creating it on first use
+    ///
+    /// Used when none is configured, so contexts encrypted before a restart
+    /// can still be decrypted after it.
+    pub async fn stored_context_key(storage: &dyn Storage) -> Result<Vec<u8>> {
+        match storage.get(CONTEXT_KEY).await? {
+            Some(key) if key.len() == 32 => Ok(key),
+            Some(key) => Err(AgentError::CryptoError(format!("{} is {} bytes, expected 32", CONTEXT_KEY, key.len()))),
+            None => {
+                let key = Self::generate_key()?;
+                storage.put(CONTEXT_KEY, key.clone()).await?;
+                Ok(key)
+            }
+        }
+    }
+    
+    /// Signature scheme selected by config: SM2 when SM crypto is requested, Ed25519 otherwise
+    ///
+    /// `keypair` is the agent identity key used for Ed25519 signatures.
+    pub fn signer(config: &CryptoConfig, keypair: identity::Keypair) -> Result<Box<dyn Signer>> {
+        if Self::uses_sm2(config) {
+            Ok(Box::new(Sm2Signer::from_config(config)?))
+        } else {
+            Ok(Box::new(Ed25519Signer::new(keypair)))
+        }
+    }
+
+    /// Whether `signer` signs with the configured SM2 key rather than the agent's own key pair
+    pub fn uses_sm2(config: &CryptoConfig) -> bool {
+        config.use_sm_crypto || config.encryption_algorithm.eq_ignore_ascii_case("sm4")
+    }
+
+    /// Sign evidence as its emitter, setting `signer_key` and `signature`
+    ///
+    /// Must be the last change before the evidence leaves the agent.
+    pub fn sign_evidence(evidence: &mut ThreatEvidence, signer: &dyn Signer) -> Result<()> {
+        evidence.signer_key = Some(Self::hex_encode(&signer.public_key()));
+        let signature = signer.sign(&Self::evidence_signing_payload(evidence)?)?;
+        evidence.signature = Some(Self::hex_encode(&signature));
+        Ok(())
+    }
+
+    /// Whether evidence carries a valid signature by `pubkey` under `scheme`
+    ///
+    /// Unsigned evidence and evidence signed by another key are not valid.
+    pub fn verify_evidence_signature(evidence: &ThreatEvidence, scheme: &dyn Signer, pubkey: &[u8]) -> Result<bool> {
+        let (Some(signer_key), Some(signature)) = (&evidence.signer_key, &evidence.signature) else {
+            return Ok(false);
+        };
+        if Self::hex_decode(signer_key)? != pubkey {
+            return Ok(false);
+        }
+        scheme.verify(&Self::evidence_signing_payload(evidence)?, &Self::hex_decode(signature)?, pubkey)
+    }
+
+    /// Whether evidence is validly signed by the key it names in `signer_key`
+    pub fn has_valid_evidence_signature(evidence: &ThreatEvidence, scheme: &dyn Signer) -> bool {
+        evidence.signer_key
+            .as_deref()
+            .and_then(|key| Self::hex_decode(key).ok())
+            .map(|key| Self::verify_evidence_signature(evidence, scheme, &key).unwrap_or(false))
+            .unwrap_or(false)
+    }
+
+    /// Bytes covered by an evidence signature: the evidence serialized without it
+    fn evidence_signing_payload(evidence: &ThreatEvidence) -> Result<Vec<u8>> {
+        let mut unsigned = evidence.clone();
+        unsigned.signature = None;
+        Ok(serde_json::to_vec(&unsigned)?)
+    }
+
     /// Generate a secure random key
     pub fn generate_key() -> Result<Vec<u8>> {
         let rng = rand::SystemRandom::new();
@@ -93,17 +206,376 @@ impl CryptoProvider {
         Ok(key.to_vec())
     }
     
-    /// Sign data with SM2 (placeholder implementation)
+    /// Load the agent identity key kept in `storage`, creating it on first use
+    ///
+    /// The key names the agent on the network and signs its evidence, batches
+    /// and manifests, so it must stay the same across restarts.
+    pub async fn agent_keypair(storage: &dyn Storage) -> Result<identity::Keypair> {
+        if let Some(encoded) = storage.get(AGENT_KEY).await? {
+            return identity::Keypair::from_protobuf_encoding(&encoded)
+                .map_err(|e| AgentError::CryptoError(format!("Invalid {}: {}", AGENT_KEY, e)));
+        }
+        let keypair = identity::Keypair::generate_ed25519();
+        let encoded = keypair.to_protobuf_encoding()
+            .map_err(|e| AgentError::CryptoError(format!("Failed to encode agent key: {}", e)))?;
+        storage.put(AGENT_KEY, encoded).await?;
+        Ok(keypair)
+    }
+
+    /// Load the SM2 key pair from config as (private key, SEC1 public key)
+    ///
+    /// Both keys are hex-encoded; the public key is derived from the private
+    /// key when unset and must match it when set.
+    pub fn sm2_keys(config: &CryptoConfig) -> Result<(Vec<u8>, Vec<u8>)> {
+        let private_key = match &config.sm2_private_key {
+            Some(hex) => Self::hex_decode(hex.trim())?,
+            None => return Err(AgentError::CryptoError("sm2_private_key is not set".to_string())),
+        };
+        let derived = Self::sm2_public_key(&private_key)?;
+        if let Some(hex) = &config.sm2_public_key {
+            if Self::sm2_normalize_public_key(&Self::hex_decode(hex.trim())?)? != derived {
+                return Err(AgentError::CryptoError("sm2_public_key does not match sm2_private_key".to_string()));
+            }
+        }
+        Ok((private_key, derived))
+    }
+
+    /// Derive the uncompressed SEC1 public key for an SM2 private key
     #[cfg(feature = "sm_crypto")]
-    pub fn sm2_sign(data: &[u8], private_key: &[u8]) -> Result<String> {
-        // Placeholder implementation
-        Ok(format!("sm2_signature_placeholder_{}", Self::blake3_hash(data)))
+    pub fn sm2_public_key(private_key: &[u8]) -> Result<Vec<u8>> {
+        Ok(Self::sm2_secret_key(private_key)?.public_key().to_sec1_bytes().to_vec())
     }
-    
-    /// Sign data with SM2 (fallback without sm_crypto feature)
+
+    /// Sign data with SM2 over its SM3 digest, returning the 64-byte r||s signature
+    #[cfg(feature = "sm_crypto")]
+    pub fn sm2_sign(data: &[u8], private_key: &[u8]) -> Result<Vec<u8>> {
+        use sm2::dsa::{signature::Signer, Signature, SigningKey};
+
+        let signing_key = SigningKey::new(SM2_SIGNER_ID, &Self::sm2_secret_key(private_key)?)
+            .map_err(|e| AgentError::CryptoError(format!("Invalid SM2 private key: {}", e)))?;
+        let signature: Signature = signing_key
+            .try_sign(data)
+            .map_err(|e| AgentError::CryptoError(format!("SM2 signing failed: {}", e)))?;
+        Ok(signature.to_bytes().to_vec())
+    }
+
+    /// Verify an r||s SM2 signature from `sm2_sign` against a SEC1 public key
+    #[cfg(feature = "sm_crypto")]
+    pub fn sm2_verify(data: &[u8], signature: &[u8], public_key: &[u8]) -> Result<bool> {
+        use sm2::dsa::{signature::Verifier, Signature, VerifyingKey};
+
+        let verifying_key = VerifyingKey::from_sec1_bytes(SM2_SIGNER_ID, public_key)
+            .map_err(|e| AgentError::CryptoError(format!("Invalid SM2 public key: {}", e)))?;
+        let Ok(signature) = Signature::from_slice(signature) else {
+            return Ok(false);
+        };
+        Ok(verifying_key.verify(data, &signature).is_ok())
+    }
+
+    #[cfg(feature = "sm_crypto")]
+    fn sm2_secret_key(private_key: &[u8]) -> Result<sm2::SecretKey> {
+        sm2::SecretKey::from_slice(private_key)
+            .map_err(|e| AgentError::CryptoError(format!("Invalid SM2 private key: {}", e)))
+    }
+
+    /// Re-encode a compressed or uncompressed SEC1 public key as uncompressed
+    #[cfg(feature = "sm_crypto")]
+    fn sm2_normalize_public_key(public_key: &[u8]) -> Result<Vec<u8>> {
+        sm2::PublicKey::from_sec1_bytes(public_key)
+            .map(|key| key.to_sec1_bytes().to_vec())
+            .map_err(|e| AgentError::CryptoError(format!("Invalid SM2 public key: {}", e)))
+    }
+
+    /// SM2 is unavailable without the sm_crypto feature
+    #[cfg(not(feature = "sm_crypto"))]
+    pub fn sm2_public_key(_private_key: &[u8]) -> Result<Vec<u8>> {
+        Err(Self::sm2_unavailable())
+    }
+
+    /// SM2 is unavailable without the sm_crypto feature
+    #[cfg(not(feature = "sm_crypto"))]
+    pub fn sm2_sign(_data: &[u8], _private_key: &[u8]) -> Result<Vec<u8>> {
+        Err(Self::sm2_unavailable())
+    }
+
+    /// SM2 is unavailable without the sm_crypto feature
+    #[cfg(not(feature = "sm_crypto"))]
+    pub fn sm2_verify(_data: &[u8], _signature: &[u8], _public_key: &[u8]) -> Result<bool> {
+        Err(Self::sm2_unavailable())
+    }
+
+    #[cfg(not(feature = "sm_crypto"))]
+    fn sm2_normalize_public_key(_public_key: &[u8]) -> Result<Vec<u8>> {
+        Err(Self::sm2_unavailable())
+    }
+
+    #[cfg(not(feature = "sm_crypto"))]
+    fn sm2_unavailable() -> AgentError {
+        AgentError::CryptoError("SM2 requires the sm_crypto feature".to_string())
+    }
+}
+
+/// Signature scheme used for evidence, consensus responses and blocklist manifests
+pub trait Signer: Send + Sync {
+    /// Short name of the scheme, e.g. "ed25519"
+    fn scheme(&self) -> &'static str;
+
+    /// Public key in the encoding `verify` expects
+    fn public_key(&self) -> Vec<u8>;
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>>;
+
+    /// Whether `sig` over `data` was made by the holder of `pubkey`
+    fn verify(&self, data: &[u8], sig: &[u8], pubkey: &[u8]) -> Result<bool>;
+}
+
+/// Ed25519 signatures with the agent's libp2p identity key
+///
+/// Public keys are protobuf-encoded libp2p keys.
+pub struct Ed25519Signer {
+    keypair: identity::Keypair,
+}
+
+impl Ed25519Signer {
+    pub fn new(keypair: identity::Keypair) -> Self {
+        Self { keypair }
+    }
+}
+
+impl Signer for Ed25519Signer {
+    fn scheme(&self) -> &'static str {
+        "ed25519"
+    }
+
+    fn public_key(&self) -> Vec<u8> {
+        self.keypair.public().encode_protobuf()
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.keypair
+            .sign(data)
+            .map_err(|e| AgentError::CryptoError(format!("Signing failed: {}", e)))
+    }
+
+    fn verify(&self, data: &[u8], sig: &[u8], pubkey: &[u8]) -> Result<bool> {
+        let public_key = identity::PublicKey::try_decode_protobuf(pubkey)
+            .map_err(|e| AgentError::CryptoError(format!("Invalid public key: {}", e)))?;
+        Ok(public_key.verify(data, sig))
+    }
+}
+
+/// SM2 signatures with the key pair from `CryptoConfig`
+///
+/// Public keys are uncompressed SEC1 points.
+pub struct Sm2Signer {
+    private_key: Vec<u8>,
+    public_key: Vec<u8>,
+}
+
+impl Sm2Signer {
+    pub fn from_config(config: &CryptoConfig) -> Result<Self> {
+        let (private_key, public_key) = CryptoProvider::sm2_keys(config)?;
+        Ok(Self { private_key, public_key })
+    }
+}
+
+impl Signer for Sm2Signer {
+    fn scheme(&self) -> &'static str {
+        "sm2"
+    }
+
+    fn public_key(&self) -> Vec<u8> {
+        self.public_key.clone()
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        CryptoProvider::sm2_sign(data, &self.private_key)
+    }
+
+    fn verify(&self, data: &[u8], sig: &[u8], pubkey: &[u8]) -> Result<bool> {
+        CryptoProvider::sm2_verify(data, sig, pubkey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_evidence;
+
+    #[tokio::test]
+    async fn test_context_key_is_kept_in_storage() {
+        let storage = crate::storage::MemoryStorage::new();
+        let key = CryptoProvider::stored_context_key(&storage).await.unwrap();
+        assert_eq!(key.len(), 32);
+        assert_eq!(CryptoProvider::stored_context_key(&storage).await.unwrap(), key);
+
+        assert_eq!(CryptoProvider::parse_context_key(&format!(" {} ", CryptoProvider::hex_encode(&key))).unwrap(), key);
+        assert!(CryptoProvider::parse_context_key("0102").is_err());
+        assert!(CryptoProvider::parse_context_key("not-hex").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_agent_keypair_is_kept_in_storage() {
+        let storage = crate::storage::MemoryStorage::new();
+        let first = CryptoProvider::agent_keypair(&storage).await.unwrap();
+        let second = CryptoProvider::agent_keypair(&storage).await.unwrap();
+        assert_eq!(first.public(), second.public());
+
+        let other = CryptoProvider::agent_keypair(&crate::storage::MemoryStorage::new()).await.unwrap();
+        assert_ne!(first.public(), other.public());
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let key = CryptoProvider::generate_key().unwrap();
+        let ciphertext = CryptoProvider::encrypt_data(b"threat context", &key).unwrap();
+        assert_eq!(CryptoProvider::decrypt_data(&ciphertext, &key).unwrap(), b"threat context");
+
+        // Tampering, a wrong key, or a truncated message all fail
+        let mut tampered = ciphertext.clone();
+        *tampered.last_mut().unwrap() ^= 0xff;
+        assert!(CryptoProvider::decrypt_data(&tampered, &key).is_err());
+        let other_key = CryptoProvider::generate_key().unwrap();
+        assert!(CryptoProvider::decrypt_data(&ciphertext, &other_key).is_err());
+        assert!(CryptoProvider::decrypt_data(&ciphertext[..NONCE_LEN - 1], &key).is_err());
+    }
+
+    #[test]
+    fn test_encryption_uses_fresh_nonce() {
+        let key = CryptoProvider::generate_key().unwrap();
+        let first = CryptoProvider::encrypt_data(b"same plaintext", &key).unwrap();
+        let second = CryptoProvider::encrypt_data(b"same plaintext", &key).unwrap();
+        assert_ne!(first[..NONCE_LEN], second[..NONCE_LEN]);
+        assert_ne!(first, second);
+    }
+
+    #[cfg(feature = "sm_crypto")]
+    #[test]
+    fn test_sm3_known_answer() {
+        // GB/T 32905-2016 appendix A, example 1
+        assert_eq!(
+            CryptoProvider::sm3_hash(b"abc"),
+            "66c7f0f462eeedd9d1f2d46bdc10e4e24167c4875cf2f7a2297da02b8f4ba8e0"
+        );
+    }
+
+    #[cfg(feature = "sm_crypto")]
+    fn sm2_config() -> CryptoConfig {
+        CryptoConfig {
+            use_sm_crypto: true,
+            sm2_private_key: Some(CryptoProvider::hex_encode(&[7u8; 32])),
+            ..CryptoConfig::default()
+        }
+    }
+
+    #[cfg(feature = "sm_crypto")]
+    #[test]
+    fn test_sm2_sign_verify_round_trip() {
+        let (private_key, public_key) = CryptoProvider::sm2_keys(&sm2_config()).unwrap();
+        let signature = CryptoProvider::sm2_sign(b"threat evidence", &private_key).unwrap();
+
+        assert_eq!(signature.len(), 64);
+        assert!(CryptoProvider::sm2_verify(b"threat evidence", &signature, &public_key).unwrap());
+
+        // A configured public key must belong to the private key
+        let mut config = sm2_config();
+        config.sm2_public_key = Some(CryptoProvider::hex_encode(&public_key));
+        assert_eq!(CryptoProvider::sm2_keys(&config).unwrap().1, public_key);
+        let other_key = CryptoProvider::sm2_public_key(&[9u8; 32]).unwrap();
+        config.sm2_public_key = Some(CryptoProvider::hex_encode(&other_key));
+        assert!(CryptoProvider::sm2_keys(&config).is_err());
+    }
+
+    #[cfg(feature = "sm_crypto")]
+    #[test]
+    fn test_sm2_detects_tampering() {
+        let (private_key, public_key) = CryptoProvider::sm2_keys(&sm2_config()).unwrap();
+        let signature = CryptoProvider::sm2_sign(b"threat evidence", &private_key).unwrap();
+
+        assert!(!CryptoProvider::sm2_verify(b"threat evidencE", &signature, &public_key).unwrap());
+        let mut tampered = signature.clone();
+        tampered[10] ^= 0xff;
+        assert!(!CryptoProvider::sm2_verify(b"threat evidence", &tampered, &public_key).unwrap());
+        assert!(!CryptoProvider::sm2_verify(b"threat evidence", &signature[..63], &public_key).unwrap());
+        let other_key = CryptoProvider::sm2_public_key(&[9u8; 32]).unwrap();
+        assert!(!CryptoProvider::sm2_verify(b"threat evidence", &signature, &other_key).unwrap());
+    }
+
     #[cfg(not(feature = "sm_crypto"))]
-    pub fn sm2_sign(data: &[u8], _private_key: &[u8]) -> Result<String> {
-        // Fallback to regular signature
-        Ok(format!("signature_placeholder_{}", Self::blake3_hash(data)))
+    #[test]
+    fn test_sm2_requires_feature() {
+        assert!(matches!(CryptoProvider::sm2_sign(b"data", &[7u8; 32]), Err(AgentError::CryptoError(_))));
+        assert!(matches!(CryptoProvider::sm2_verify(b"data", &[0u8; 64], &[4u8; 65]), Err(AgentError::CryptoError(_))));
+    }
+
+    fn assert_round_trip(signer: &dyn Signer) {
+        let signature = signer.sign(b"threat evidence").unwrap();
+        assert!(signer.verify(b"threat evidence", &signature, &signer.public_key()).unwrap());
+        assert!(!signer.verify(b"other evidence", &signature, &signer.public_key()).unwrap());
+    }
+
+    #[test]
+    fn test_ed25519_signer_round_trip() {
+        let signer = Ed25519Signer::new(identity::Keypair::generate_ed25519());
+        assert_round_trip(&signer);
+
+        let other = Ed25519Signer::new(identity::Keypair::generate_ed25519());
+        let signature = signer.sign(b"threat evidence").unwrap();
+        assert!(!other.verify(b"threat evidence", &signature, &other.public_key()).unwrap());
+    }
+
+    #[cfg(feature = "sm_crypto")]
+    #[test]
+    fn test_sm2_signer_round_trip() {
+        assert_round_trip(&Sm2Signer::from_config(&sm2_config()).unwrap());
+    }
+
+    #[test]
+    fn test_signer_selected_from_config() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let signer = CryptoProvider::signer(&CryptoConfig::default(), keypair.clone()).unwrap();
+        assert_eq!(signer.scheme(), "ed25519");
+        assert_eq!(signer.public_key(), keypair.public().encode_protobuf());
+
+        for config in [
+            CryptoConfig { use_sm_crypto: true, ..CryptoConfig::default() },
+            CryptoConfig { encryption_algorithm: "sm4".to_string(), ..CryptoConfig::default() },
+        ] {
+            let config = CryptoConfig { sm2_private_key: Some(CryptoProvider::hex_encode(&[7u8; 32])), ..config };
+            let selected = CryptoProvider::signer(&config, keypair.clone());
+            if cfg!(feature = "sm_crypto") {
+                assert_eq!(selected.unwrap().scheme(), "sm2");
+            } else {
+                assert!(matches!(selected, Err(AgentError::CryptoError(_))));
+            }
+        }
+    }
+
+    #[test]
+    fn test_evidence_signature() {
+        let signer = Ed25519Signer::new(identity::Keypair::generate_ed25519());
+        let public_key = signer.public_key();
+        let mut evidence = test_evidence("203.0.113.7");
+        CryptoProvider::sign_evidence(&mut evidence, &signer).unwrap();
+
+        assert!(CryptoProvider::verify_evidence_signature(&evidence, &signer, &public_key).unwrap());
+        assert!(CryptoProvider::has_valid_evidence_signature(&evidence, &signer));
+
+        // Another agent's key is not the signer
+        let other = Ed25519Signer::new(identity::Keypair::generate_ed25519()).public_key();
+        assert!(!CryptoProvider::verify_evidence_signature(&evidence, &signer, &other).unwrap());
+
+        // Reattributing signed evidence invalidates it
+        let mut tampered = evidence.clone();
+        tampered.agent_id = "other-agent".to_string();
+        assert!(!CryptoProvider::verify_evidence_signature(&tampered, &signer, &public_key).unwrap());
+        assert!(!CryptoProvider::has_valid_evidence_signature(&tampered, &signer));
+
+        // Unsigned evidence, or a signature without its key, is rejected
+        let unsigned = test_evidence("203.0.113.7");
+        assert!(!CryptoProvider::verify_evidence_signature(&unsigned, &signer, &public_key).unwrap());
+        assert!(!CryptoProvider::has_valid_evidence_signature(&unsigned, &signer));
+        let keyless = ThreatEvidence { signer_key: None, ..evidence };
+        assert!(!CryptoProvider::has_valid_evidence_signature(&keyless, &signer));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,235 @@
+use crate::{ThreatEvidence, clock::{Clock, SystemClock}};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Lookups used to enrich evidence about a source IP
+///
+/// Implementations return `None` when a lookup fails so enrichment never
+/// blocks evidence processing.
+#[async_trait::async_trait]
+pub trait Enricher: Send + Sync {
+    /// Reverse DNS hostname for the IP
+    async fn reverse_dns(&self, ip: IpAddr) -> Option<String>;
+
+    /// Owner of the netblock containing the IP (WHOIS/RDAP)
+    async fn netblock_owner(&self, ip: IpAddr) -> Option<String>;
+}
+
+/// Enricher backed by the system resolver and RDAP
+pub struct NetworkEnricher {
+    client: reqwest::Client,
+    rdap_url: String,
+}
+
+impl NetworkEnricher {
+    pub fn new() -> Self {
+        Self::with_rdap_url("https://rdap.org/ip")
+    }
+
+    /// Use a custom RDAP base URL (the IP is appended as a path segment)
+    pub fn with_rdap_url(rdap_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+            rdap_url: rdap_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl Default for NetworkEnricher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl Enricher for NetworkEnricher {
+    async fn reverse_dns(&self, ip: IpAddr) -> Option<String> {
+        match tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&ip)).await {
+            Ok(Ok(hostname)) if hostname != ip.to_string() => Some(hostname),
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => {
+                log::debug!("Reverse DNS lookup for {} failed: {}", ip, e);
+                None
+            }
+            Err(e) => {
+                log::warn!("Reverse DNS task for {} failed: {}", ip, e);
+                None
+            }
+        }
+    }
+
+    async fn netblock_owner(&self, ip: IpAddr) -> Option<String> {
+        let url = format!("{}/{}", self.rdap_url, ip);
+        let response = match self.client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                log::debug!("RDAP lookup for {} returned HTTP {}", ip, response.status());
+                return None;
+            }
+            Err(e) => {
+                log::debug!("RDAP lookup for {} failed: {}", ip, e);
+                return None;
+            }
+        };
+
+        let body: serde_json::Value = response.json().await.ok()?;
+        body.get("name")
+            .and_then(|v| v.as_str())
+            .map(|name| name.to_string())
+    }
+}
+
+/// Enricher that answers from fixed tables, for tests and offline use
+#[derive(Debug, Default)]
+pub struct MockEnricher {
+    hostnames: HashMap<IpAddr, String>,
+    owners: HashMap<IpAddr, String>,
+    lookups: std::sync::atomic::AtomicUsize,
+}
+
+impl MockEnricher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_hostname(mut self, ip: IpAddr, hostname: &str) -> Self {
+        self.hostnames.insert(ip, hostname.to_string());
+        self
+    }
+
+    pub fn with_owner(mut self, ip: IpAddr, owner: &str) -> Self {
+        self.owners.insert(ip, owner.to_string());
+        self
+    }
+
+    /// Number of reverse DNS lookups performed
+    pub fn lookups(&self) -> usize {
+        self.lookups.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+#[async_trait::async_trait]
+impl Enricher for MockEnricher {
+    async fn reverse_dns(&self, ip: IpAddr) -> Option<String> {
+        self.lookups.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        self.hostnames.get(&ip).cloned()
+    }
+
+    async fn netblock_owner(&self, ip: IpAddr) -> Option<String> {
+        self.owners.get(&ip).cloned()
+    }
+}
+
+/// Result of enriching a single IP
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Enrichment {
+    pub hostname: Option<String>,
+    pub owner: Option<String>,
+}
+
+/// Enrichment stage that caches lookups per IP for a TTL
+pub struct EvidenceEnricher {
+    enricher: Arc<dyn Enricher>,
+    cache_ttl: i64,
+    cache: RwLock<HashMap<IpAddr, (Enrichment, i64)>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl EvidenceEnricher {
+    pub fn new(enricher: Arc<dyn Enricher>, cache_ttl: u64) -> Self {
+        Self {
+            enricher,
+            cache_ttl: cache_ttl as i64,
+            cache: RwLock::new(HashMap::new()),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Use a custom clock for cache expiry
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Look up enrichment for an IP, using the cache when fresh
+    pub async fn lookup(&self, ip: IpAddr) -> Enrichment {
+        let now = self.clock.now();
+
+        if let Some((enrichment, cached_at)) = self.cache.read().unwrap().get(&ip) {
+            if now - cached_at < self.cache_ttl {
+                return enrichment.clone();
+            }
+        }
+
+        let (hostname, owner) = tokio::join!(
+            self.enricher.reverse_dns(ip),
+            self.enricher.netblock_owner(ip)
+        );
+        let enrichment = Enrichment { hostname, owner };
+
+        self.cache.write().unwrap().insert(ip, (enrichment.clone(), now));
+        enrichment
+    }
+
+    /// Attach hostname and netblock owner of the source IP to the evidence
+    pub async fn enrich(&self, evidence: &mut ThreatEvidence) {
+        let Ok(ip) = evidence.source_ip.parse::<IpAddr>() else {
+            return;
+        };
+
+        let enrichment = self.lookup(ip).await;
+        evidence.source_hostname = enrichment.hostname;
+        evidence.source_owner = enrichment.owner;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::test_evidence;
+
+    #[tokio::test]
+    async fn test_mock_enricher_populates_fields() {
+        let ip: IpAddr = "203.0.113.20".parse().unwrap();
+        let mock = Arc::new(
+            MockEnricher::new()
+                .with_hostname(ip, "scanner.example.net")
+                .with_owner(ip, "EXAMPLE-NET"),
+        );
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let enricher = EvidenceEnricher::new(mock.clone(), 600).with_clock(clock.clone());
+
+        let mut evidence = test_evidence("203.0.113.20");
+        enricher.enrich(&mut evidence).await;
+        assert_eq!(evidence.source_hostname.as_deref(), Some("scanner.example.net"));
+        assert_eq!(evidence.source_owner.as_deref(), Some("EXAMPLE-NET"));
+
+        // Cached within the TTL, looked up again after it
+        enricher.enrich(&mut test_evidence("203.0.113.20")).await;
+        assert_eq!(mock.lookups(), 1);
+        clock.advance(601);
+        enricher.enrich(&mut test_evidence("203.0.113.20")).await;
+        assert_eq!(mock.lookups(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_lookups_leave_fields_empty() {
+        let enricher = EvidenceEnricher::new(Arc::new(MockEnricher::new()), 600);
+
+        let mut evidence = test_evidence("198.51.100.30");
+        enricher.enrich(&mut evidence).await;
+        assert_eq!(evidence.source_hostname, None);
+        assert_eq!(evidence.source_owner, None);
+
+        // Non-IP sources are skipped
+        let mut evidence = test_evidence("unknown");
+        enricher.enrich(&mut evidence).await;
+        assert_eq!(evidence.source_hostname, None);
+    }
+}
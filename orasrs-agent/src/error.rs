@@ -22,6 +22,10 @@ pub enum AgentError {
     #[error("P2P network error: {0}")]
     P2pError(String),
     
+    /// None of the configured bootstrap nodes could be reached
+    #[error("P2P network error: could not reach any of {tried} bootstrap nodes")]
+    BootstrapFailed { tried: usize },
+    
     /// Threat detection error
     #[error("Threat detection error: {0}")]
     ThreatDetectionError(String),
@@ -38,6 +42,30 @@ pub enum AgentError {
     #[error("System error: {0}")]
     SystemError(String),
     
+    /// Invalid evidence error
+    #[error("Invalid evidence: {0}")]
+    InvalidEvidence(String),
+    
+    /// Upstream source is rate limiting us or failing, and should be backed off
+    #[error("Upstream {name} unavailable: HTTP {status}")]
+    UpstreamUnavailable {
+        name: String,
+        status: u16,
+        retry_after: Option<u64>, // Seconds the server asked us to wait
+    },
+    
+    /// Upstream source answered with an error status not worth retrying
+    #[error("Upstream {name} returned HTTP {status}")]
+    UpstreamHttp { name: String, status: u16 },
+    
+    /// Operation did not finish in time
+    #[error("Timed out after {secs}s: {operation}")]
+    Timeout { operation: String, secs: u64 },
+    
+    /// No verification request with this ID is known
+    #[error("Verification request {0} not found")]
+    VerificationNotFound(String),
+    
     /// Compliance error
     #[error("Compliance error: {0}")]
     ComplianceError(String),
@@ -45,4 +73,62 @@ pub enum AgentError {
     /// Internal error
     #[error("Internal error: {0}")]
     InternalError(String),
-}
\ No newline at end of file
+}
+
+impl AgentError {
+    /// Stable machine-readable code of the error kind
+    pub fn code(&self) -> &'static str {
+        match self {
+            AgentError::ConfigError(_) => "config_error",
+            AgentError::NetworkError(_) => "network_error",
+            AgentError::CryptoError(_) => "crypto_error",
+            AgentError::P2pError(_) => "p2p_error",
+            AgentError::BootstrapFailed { .. } => "bootstrap_failed",
+            AgentError::ThreatDetectionError(_) => "threat_detection_error",
+            AgentError::IoError(_) => "io_error",
+            AgentError::SerializationError(_) => "serialization_error",
+            AgentError::SystemError(_) => "system_error",
+            AgentError::InvalidEvidence(_) => "invalid_evidence",
+            AgentError::UpstreamUnavailable { .. } => "upstream_unavailable",
+            AgentError::UpstreamHttp { .. } => "upstream_http",
+            AgentError::Timeout { .. } => "timeout",
+            AgentError::VerificationNotFound(_) => "verification_not_found",
+            AgentError::ComplianceError(_) => "compliance_error",
+            AgentError::InternalError(_) => "internal_error",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_codes_are_stable_and_distinct() {
+        let errors = [
+            (AgentError::ConfigError(String::new()), "config_error"),
+            (AgentError::NetworkError(String::new()), "network_error"),
+            (AgentError::CryptoError(String::new()), "crypto_error"),
+            (AgentError::P2pError(String::new()), "p2p_error"),
+            (AgentError::BootstrapFailed { tried: 2 }, "bootstrap_failed"),
+            (AgentError::ThreatDetectionError(String::new()), "threat_detection_error"),
+            (AgentError::IoError(std::io::Error::other("disk")), "io_error"),
+            (AgentError::SerializationError(serde_json::from_str::<u8>("").unwrap_err()), "serialization_error"),
+            (AgentError::SystemError(String::new()), "system_error"),
+            (AgentError::InvalidEvidence(String::new()), "invalid_evidence"),
+            (AgentError::UpstreamUnavailable { name: String::new(), status: 429, retry_after: None }, "upstream_unavailable"),
+            (AgentError::UpstreamHttp { name: String::new(), status: 404 }, "upstream_http"),
+            (AgentError::Timeout { operation: String::new(), secs: 30 }, "timeout"),
+            (AgentError::VerificationNotFound(String::new()), "verification_not_found"),
+            (AgentError::ComplianceError(String::new()), "compliance_error"),
+            (AgentError::InternalError(String::new()), "internal_error"),
+        ];
+
+        for (error, code) in &errors {
+            assert_eq!(error.code(), *code);
+        }
+        let codes: HashSet<_> = errors.iter().map(|(error, _)| error.code()).collect();
+        assert_eq!(codes.len(), errors.len());
+    }
+}
@@ -0,0 +1,198 @@
+//! Append-only local record of submitted evidence
+
+use crate::{ThreatEvidence, crypto::CryptoProvider, error::{AgentError, Result}, storage::Storage};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Storage key of the secret used to hash data subjects
+const SUBJECT_KEY: &str = "evidence_store/subject_key";
+
+/// Evidence as published, with keyed hashes of the IPs it was reported for
+///
+/// The hashes let a data subject's records be found for erasure without
+/// keeping the IPs anonymization removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredEvidence {
+    pub subjects: Vec<String>, // Subject keys of the source and target IPs before anonymization
+    pub evidence: ThreatEvidence,
+}
+
+impl StoredEvidence {
+    /// Whether the record is about the subject with the given key
+    pub fn concerns(&self, subject: &str) -> bool {
+        self.subjects.iter().any(|key| key == subject)
+    }
+}
+
+/// Evidence records appended to a JSON-lines file
+///
+/// Records are only ever appended, except when erasing a data subject, which
+/// rewrites the file without their records.
+#[derive(Debug, Clone)]
+pub struct EvidenceStore {
+    path: PathBuf,
+    key: Arc<[u8; 32]>, // Secret for subject keys, so they cannot be brute-forced from the IP space
+    lock: Arc<Mutex<()>>, // Keeps appends out of an in-progress rewrite
+}
+
+impl EvidenceStore {
+    pub fn new<P: AsRef<Path>>(path: P, key: [u8; 32]) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            key: Arc::new(key),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Open a store whose subject key is kept in `storage`, creating the key on first use
+    pub async fn open<P: AsRef<Path>>(path: P, storage: &dyn Storage) -> Result<Self> {
+        let key = match storage.get(SUBJECT_KEY).await? {
+            Some(key) => key,
+            None => {
+                let key = CryptoProvider::generate_key()?;
+                storage.put(SUBJECT_KEY, key.clone()).await?;
+                key
+            }
+        };
+        let key = key.try_into()
+            .map_err(|_| AgentError::CryptoError(format!("{} is not a 32-byte key", SUBJECT_KEY)))?;
+        Ok(Self::new(path, key))
+    }
+
+    /// File the records are appended to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Key identifying the data subject an IP belongs to; values that are not IPs have none
+    pub fn subject_key(&self, ip: &str) -> Option<String> {
+        let ip = ip.trim().parse::<IpAddr>().ok()?;
+        Some(blake3::keyed_hash(&self.key, ip.to_string().as_bytes()).to_hex().to_string())
+    }
+
+    /// Record of published evidence reported for the given source and target IPs
+    pub fn record(&self, source_ip: &str, target_ip: &str, evidence: ThreatEvidence) -> StoredEvidence {
+        let mut subjects: Vec<String> = [source_ip, target_ip].iter().filter_map(|ip| self.subject_key(ip)).collect();
+        subjects.dedup();
+        StoredEvidence { subjects, evidence }
+    }
+
+    /// Append one record
+    pub async fn append(&self, record: &StoredEvidence) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().await;
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// All stored records, oldest first
+    pub async fn load(&self) -> Result<Vec<StoredEvidence>> {
+        let _guard = self.lock.lock().await;
+        self.read().await
+    }
+
+    /// Remove every record about `ip`, returning how many were removed
+    pub async fn remove_ip(&self, ip: &str) -> Result<usize> {
+        let Some(subject) = self.subject_key(ip) else {
+            return Ok(0);
+        };
+        self.remove_where(|record| record.concerns(&subject)).await
+    }
+
+    /// Remove every record whose evidence is timestamped before `cutoff`, returning how many were removed
+    pub async fn remove_older_than(&self, cutoff: i64) -> Result<usize> {
+        self.remove_where(|record| record.evidence.timestamp < cutoff).await
+    }
+
+    /// Rewrite the store without the records matching `remove`
+    async fn remove_where<F: Fn(&StoredEvidence) -> bool>(&self, remove: F) -> Result<usize> {
+        let _guard = self.lock.lock().await;
+        let records = self.read().await?;
+        let (removed, kept): (Vec<_>, Vec<_>) = records.into_iter().partition(|record| remove(record));
+        if removed.is_empty() {
+            return Ok(0);
+        }
+
+        let mut contents = Vec::new();
+        for record in &kept {
+            contents.extend(serde_json::to_vec(record)?);
+            contents.push(b'\n');
+        }
+
+        // Write to a temporary file first so a crash never leaves a partial store
+        let tmp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, contents).await?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+        Ok(removed.len())
+    }
+
+    async fn read(&self) -> Result<Vec<StoredEvidence>> {
+        let contents = match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(Into::into))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ThreatLevel, ThreatType};
+
+    fn record(store: &EvidenceStore, source_ip: &str, target_ip: &str) -> StoredEvidence {
+        let evidence = ThreatEvidence::builder("203.0.113.0", ThreatType::SuspiciousConnection, ThreatLevel::Warning).build();
+        store.record(source_ip, target_ip, evidence)
+    }
+
+    #[tokio::test]
+    async fn test_remove_ip_rewrites_without_subject() {
+        let dir = std::env::temp_dir().join(format!("orasrs-evidence-{}", uuid::Uuid::new_v4()));
+        let store = EvidenceStore::new(dir.join("evidence.jsonl"), [7; 32]);
+        assert!(store.load().await.unwrap().is_empty());
+
+        store.append(&record(&store, "203.0.113.7", "local")).await.unwrap();
+        store.append(&record(&store, "198.51.100.1", "2001:db8::1")).await.unwrap();
+        store.append(&record(&store, "192.0.2.9", "203.0.113.7")).await.unwrap();
+
+        // Only the published, anonymized evidence and subject keys reach the disk
+        let contents = std::fs::read_to_string(dir.join("evidence.jsonl")).unwrap();
+        assert!(!contents.contains("203.0.113.7") && !contents.contains("2001:db8::1"));
+
+        assert_eq!(store.remove_ip("2001:db8:0::1").await.unwrap(), 1);
+        assert_eq!(store.remove_ip("203.0.113.7").await.unwrap(), 2);
+        assert_eq!(store.remove_ip("203.0.113.7").await.unwrap(), 0);
+        assert!(store.load().await.unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_subject_key_is_kept_in_storage() {
+        let storage = crate::storage::MemoryStorage::new();
+        let first = EvidenceStore::open("evidence.jsonl", &storage).await.unwrap();
+        let second = EvidenceStore::open("evidence.jsonl", &storage).await.unwrap();
+        let other = EvidenceStore::open("evidence.jsonl", &crate::storage::MemoryStorage::new()).await.unwrap();
+
+        let key = first.subject_key("203.0.113.7").unwrap();
+        assert_eq!(second.subject_key("203.0.113.7"), Some(key.clone()));
+        assert_ne!(other.subject_key("203.0.113.7"), Some(key));
+        assert_eq!(first.subject_key("2001:db8:0::1"), first.subject_key("2001:db8::1"));
+        assert_eq!(first.subject_key("local"), None);
+    }
+}
@@ -0,0 +1,349 @@
+//! Network flow records built from captured packets
+
+use crate::error::{AgentError, Result};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+/// pcap link type of Ethernet frames
+pub const LINKTYPE_ETHERNET: u32 = 1;
+
+/// pcap link type of bare IPv4/IPv6 packets
+pub const LINKTYPE_RAW: u32 = 101;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: [u16; 2] = [0x8100, 0x88a8];
+/// IP protocol number of TCP
+pub const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+
+/// Flows a table holds before new flows are dropped until it is drained
+pub const MAX_FLOWS: usize = 65_536;
+
+/// Flow 5-tuple
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub src_ip: IpAddr,
+    pub dst_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub protocol: u8, // IP protocol number, 6 for TCP and 17 for UDP
+}
+
+/// Headers of one packet relevant to flow tracking
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PacketInfo {
+    pub key: FlowKey,
+    pub syn: bool, // TCP connection attempt: SYN without ACK
+    pub payload_offset: usize, // Start of the TCP or UDP payload in the frame
+}
+
+/// Traffic seen for one flow
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowRecord {
+    pub key: FlowKey,
+    pub packets: u64,
+    pub bytes: u64,       // Bytes on the wire, including link-layer headers
+    pub syn_packets: u64,
+    pub first_seen: f64,  // Capture time of the first packet, in seconds
+    pub last_seen: f64,
+}
+
+impl FlowRecord {
+    /// SYNs per second over the flow's lifetime, which counts as at least one second
+    pub fn syn_rate(&self) -> f64 {
+        self.syn_packets as f64 / (self.last_seen - self.first_seen).max(1.0)
+    }
+
+    /// One-line summary handed to the threat detector
+    pub fn summary(&self) -> String {
+        let protocol = match self.key.protocol {
+            PROTOCOL_TCP => "tcp",
+            PROTOCOL_UDP => "udp",
+            _ => "other",
+        };
+        format!(
+            "flow proto={} src={} sport={} dst={} dport={} packets={} bytes={} syn_rate={:.1}/sec",
+            protocol, self.key.src_ip, self.key.src_port, self.key.dst_ip, self.key.dst_port,
+            self.packets, self.bytes, self.syn_rate()
+        )
+    }
+}
+
+/// Parse an Ethernet (optionally VLAN-tagged) or raw IP frame carrying TCP or UDP
+pub fn parse_packet(link_type: u32, data: &[u8]) -> Option<PacketInfo> {
+    let ip_packet = match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?);
+            while ETHERTYPE_VLAN.contains(&ethertype) {
+                offset += 4;
+                ethertype = u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?);
+            }
+            if ethertype != ETHERTYPE_IPV4 && ethertype != ETHERTYPE_IPV6 {
+                return None;
+            }
+            data.get(offset + 2..)?
+        }
+        LINKTYPE_RAW => data,
+        _ => return None,
+    };
+
+    let (src_ip, dst_ip, protocol, transport) = match ip_packet.first()? >> 4 {
+        4 => {
+            let header_len = usize::from(ip_packet[0] & 0x0f) * 4;
+            let fragment_offset = u16::from_be_bytes(ip_packet.get(6..8)?.try_into().ok()?) & 0x1fff;
+            if header_len < 20 || fragment_offset != 0 {
+                return None; // Later fragments carry no transport header
+            }
+            let src: [u8; 4] = ip_packet.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = ip_packet.get(16..20)?.try_into().ok()?;
+            (IpAddr::V4(Ipv4Addr::from(src)), IpAddr::V4(Ipv4Addr::from(dst)), ip_packet[9], ip_packet.get(header_len..)?)
+        }
+        6 => {
+            // Extension headers are not followed; such packets are skipped
+            let src: [u8; 16] = ip_packet.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = ip_packet.get(24..40)?.try_into().ok()?;
+            (IpAddr::V6(Ipv6Addr::from(src)), IpAddr::V6(Ipv6Addr::from(dst)), ip_packet[6], ip_packet.get(40..)?)
+        }
+        _ => return None,
+    };
+
+    let (src_port, dst_port, syn, header_len) = match protocol {
+        PROTOCOL_TCP => {
+            let flags = *transport.get(13)?;
+            let header_len = usize::from(transport.get(12)? >> 4) * 4;
+            (port(transport, 0)?, port(transport, 2)?, flags & 0x02 != 0 && flags & 0x10 == 0, header_len)
+        }
+        PROTOCOL_UDP => (port(transport, 0)?, port(transport, 2)?, false, 8),
+        _ => return None,
+    };
+
+    // Every header slice is a suffix of the frame
+    let payload_offset = data.len() - transport.len() + header_len;
+    Some(PacketInfo {
+        key: FlowKey { src_ip, dst_ip, src_port, dst_port, protocol },
+        syn,
+        payload_offset: payload_offset.min(data.len()),
+    })
+}
+
+fn port(transport: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(transport.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+/// Flows aggregated from captured packets until drained
+///
+/// Holds at most `max_flows` flows: once full, packets of new flows are
+/// counted as dropped while existing flows keep accumulating.
+#[derive(Debug)]
+pub struct FlowTable {
+    flows: HashMap<FlowKey, FlowRecord>,
+    max_flows: usize,
+    dropped_packets: u64,
+}
+
+impl Default for FlowTable {
+    fn default() -> Self {
+        Self::with_max_flows(MAX_FLOWS)
+    }
+}
+
+impl FlowTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_flows(max_flows: usize) -> Self {
+        Self {
+            flows: HashMap::new(),
+            max_flows: max_flows.max(1),
+            dropped_packets: 0,
+        }
+    }
+
+    /// Account one captured packet, returning its headers unless it is not TCP or UDP over IP
+    ///
+    /// A packet of a new flow arriving while the table is full is parsed but not accounted.
+    pub fn record(&mut self, link_type: u32, data: &[u8], wire_len: u32, timestamp: f64) -> Option<PacketInfo> {
+        let packet = parse_packet(link_type, data)?;
+        if self.is_full() && !self.flows.contains_key(&packet.key) {
+            self.dropped_packets += 1;
+            return Some(packet);
+        }
+
+        let flow = self.flows.entry(packet.key).or_insert_with(|| FlowRecord {
+            key: packet.key,
+            packets: 0,
+            bytes: 0,
+            syn_packets: 0,
+            first_seen: timestamp,
+            last_seen: timestamp,
+        });
+        flow.packets += 1;
+        flow.bytes += u64::from(wire_len);
+        flow.syn_packets += u64::from(packet.syn);
+        flow.first_seen = flow.first_seen.min(timestamp);
+        flow.last_seen = flow.last_seen.max(timestamp);
+        Some(packet)
+    }
+
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Whether packets of new flows are being dropped
+    pub fn is_full(&self) -> bool {
+        self.flows.len() >= self.max_flows
+    }
+
+    /// Packets of new flows dropped since the last drain
+    pub fn dropped_packets(&self) -> u64 {
+        self.dropped_packets
+    }
+
+    /// Remove all flows, ordered by their first packet
+    pub fn drain(&mut self) -> Vec<FlowRecord> {
+        self.dropped_packets = 0;
+        let mut flows: Vec<FlowRecord> = self.flows.drain().map(|(_, flow)| flow).collect();
+        flows.sort_by(|a, b| a.first_seen.total_cmp(&b.first_seen));
+        flows
+    }
+}
+
+/// Packet read from a capture file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedPacket {
+    pub timestamp: f64,
+    pub wire_len: u32, // Original length, which may exceed the captured data
+    pub data: Vec<u8>,
+}
+
+/// Read a classic libpcap capture file
+///
+/// Returns the file's link type and its packets in order. Both byte orders
+/// and microsecond or nanosecond timestamps are supported; pcapng is not.
+pub fn read_pcap_file(path: impl AsRef<Path>) -> Result<(u32, Vec<CapturedPacket>)> {
+    let content = std::fs::read(path.as_ref())?;
+    let invalid = |reason: &str| {
+        AgentError::ThreatDetectionError(format!("Invalid pcap file {}: {}", path.as_ref().display(), reason))
+    };
+
+    let magic: [u8; 4] = content.get(..4).ok_or_else(|| invalid("truncated header"))?.try_into().unwrap();
+    let (little_endian, fraction_scale) = match magic {
+        [0xd4, 0xc3, 0xb2, 0xa1] => (true, 1e-6),
+        [0xa1, 0xb2, 0xc3, 0xd4] => (false, 1e-6),
+        [0x4d, 0x3c, 0xb2, 0xa1] => (true, 1e-9),
+        [0xa1, 0xb2, 0x3c, 0x4d] => (false, 1e-9),
+        _ => return Err(invalid("unknown magic number")),
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes: [u8; 4] = content.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    };
+
+    let link_type = read_u32(20).ok_or_else(|| invalid("truncated header"))?;
+    let mut packets = Vec::new();
+    let mut offset = 24;
+    while offset < content.len() {
+        let (Some(seconds), Some(fraction), Some(captured_len), Some(wire_len)) =
+            (read_u32(offset), read_u32(offset + 4), read_u32(offset + 8), read_u32(offset + 12))
+        else {
+            return Err(invalid("truncated record header"));
+        };
+        let start = offset + 16;
+        let data = content
+            .get(start..start + captured_len as usize)
+            .ok_or_else(|| invalid("truncated packet"))?;
+        packets.push(CapturedPacket {
+            timestamp: f64::from(seconds) + f64::from(fraction) * fraction_scale,
+            wire_len,
+            data: data.to_vec(),
+        });
+        offset = start + captured_len as usize;
+    }
+
+    Ok((link_type, packets))
+}
+
+/// Replay a capture file into flow records
+pub fn flows_from_pcap_file(path: impl AsRef<Path>) -> Result<Vec<FlowRecord>> {
+    let (link_type, packets) = read_pcap_file(path)?;
+    let mut table = FlowTable::new();
+    for packet in &packets {
+        table.record(link_type, &packet.data, packet.wire_len, packet.timestamp);
+    }
+    Ok(table.drain())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_CAPTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/flows.pcap");
+
+    #[test]
+    fn test_replayed_capture_yields_flows() {
+        let flows = flows_from_pcap_file(TEST_CAPTURE).unwrap();
+        assert_eq!(flows.len(), 3, "{:?}", flows);
+
+        // Three SYNs and a data segment over 1.5 seconds
+        let tcp = &flows[0];
+        assert_eq!(tcp.key.src_ip, "10.0.0.5".parse::<IpAddr>().unwrap());
+        assert_eq!((tcp.key.src_port, tcp.key.dst_port, tcp.key.protocol), (40000, 80, PROTOCOL_TCP));
+        assert_eq!((tcp.packets, tcp.bytes, tcp.syn_packets), (4, 234, 3));
+        assert!((tcp.syn_rate() - 2.0).abs() < 1e-9);
+        assert!(tcp.summary().contains("src=10.0.0.5 sport=40000 dst=192.0.2.10 dport=80"));
+
+        let udp = &flows[1];
+        assert_eq!((udp.key.dst_port, udp.key.protocol, udp.packets, udp.syn_packets), (53, PROTOCOL_UDP, 2, 0));
+
+        // The ARP frame is skipped and the IPv6 SYN forms its own flow
+        let ipv6 = &flows[2];
+        assert_eq!(ipv6.key.dst_ip, "2001:db8::2".parse::<IpAddr>().unwrap());
+        assert_eq!((ipv6.key.dst_port, ipv6.syn_packets), (443, 1));
+    }
+
+    #[test]
+    fn test_malformed_input_is_rejected() {
+        assert!(parse_packet(LINKTYPE_ETHERNET, &[0u8; 10]).is_none());
+        assert!(parse_packet(LINKTYPE_RAW, &[0x45, 0, 0]).is_none());
+
+        // A SYN carries no payload
+        let (_, packets) = read_pcap_file(TEST_CAPTURE).unwrap();
+        let syn = parse_packet(LINKTYPE_ETHERNET, &packets[0].data).unwrap();
+        assert!(syn.syn);
+        assert_eq!(syn.payload_offset, 14 + 20 + 20);
+
+        let path = std::env::temp_dir().join(format!("orasrs-not-a-pcap-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"not a capture").unwrap();
+        assert!(matches!(read_pcap_file(&path), Err(AgentError::ThreatDetectionError(_))));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_full_table_drops_new_flows_until_drained() {
+        let (link_type, packets) = read_pcap_file(TEST_CAPTURE).unwrap();
+        let mut table = FlowTable::with_max_flows(1);
+        for packet in &packets {
+            table.record(link_type, &packet.data, packet.wire_len, packet.timestamp);
+        }
+
+        // The first flow keeps counting while the UDP and IPv6 flows are dropped
+        assert!(table.is_full());
+        assert_eq!(table.dropped_packets(), 3);
+        let flows = table.drain();
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].packets, 4);
+
+        assert_eq!(table.dropped_packets(), 0);
+        let packet = packets.last().unwrap();
+        assert!(table.record(link_type, &packet.data, packet.wire_len, packet.timestamp).is_some());
+        assert_eq!(table.len(), 1);
+    }
+}
@@ -0,0 +1,86 @@
+//! Minimal HTTP server for the agent's local endpoints
+
+use crate::error::{AgentError, Result};
+use http_body_util::Full;
+use hyper::{body::{Bytes, Incoming}, server::conn::http1, service::service_fn, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Response returned by an endpoint handler
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// Response with the given status and body
+    pub fn new(status: StatusCode, content_type: &'static str, body: impl Into<String>) -> Self {
+        Self { status, content_type, body: body.into() }
+    }
+
+    /// 404 for paths the server does not handle
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "text/plain", "not found\n")
+    }
+}
+
+/// Bind `addr` and answer requests with `handler` until `shutdown` turns true
+///
+/// Returns the bound address, which differs from `addr` when port 0 is
+/// requested, and the accept loop's task.
+pub async fn serve<F, Fut>(
+    addr: SocketAddr,
+    mut shutdown: watch::Receiver<bool>,
+    handler: F,
+) -> Result<(SocketAddr, JoinHandle<()>)>
+where
+    F: Fn(Method, String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = HttpResponse> + Send + 'static,
+{
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| AgentError::NetworkError(format!("Failed to bind HTTP server to {}: {}", addr, e)))?;
+    let local_addr = listener.local_addr()?;
+    let handler = Arc::new(handler);
+
+    let task = tokio::spawn(async move {
+        loop {
+            let stream = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        log::warn!("Failed to accept HTTP connection on {}: {}", local_addr, e);
+                        continue;
+                    }
+                },
+                _ = shutdown.wait_for(|stopping| *stopping) => break,
+            };
+
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request: Request<Incoming>| {
+                    let handler = handler.clone();
+                    async move {
+                        let response = handler(request.method().clone(), request.uri().path().to_string()).await;
+                        Response::builder()
+                            .status(response.status)
+                            .header("content-type", response.content_type)
+                            .body(Full::new(Bytes::from(response.body)))
+                    }
+                });
+                if let Err(e) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+                    log::debug!("HTTP connection error: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok((local_addr, task))
+}
@@ -1,7 +1,6 @@
 #[cfg(test)]
 mod integration_tests {
     use orasrs_agent::{OrasrsAgent, AgentConfig, ThreatEvidence, ThreatType, ThreatLevel, ThreatIntelAggregator, ConsensusEngine, CredibilityEngine};
-    use std::time::{SystemTime, UNIX_EPOCH};
     use tokio;
 
     #[tokio::test]
@@ -21,7 +20,7 @@ mod integration_tests {
     async fn test_consensus_engine() {
         use orasrs_agent::consensus_verification::{ConsensusConfig, VerificationStatus};
         
-        let config = ConsensusConfig::default();
+        let config = ConsensusConfig { min_verifiers: 1, ..ConsensusConfig::default() };
         let engine = ConsensusEngine::new(config, "test-agent".to_string());
         
         let evidence = create_test_evidence();
@@ -87,25 +86,14 @@ mod integration_tests {
     }
 
     fn create_test_evidence() -> ThreatEvidence {
-        ThreatEvidence {
-            id: format!("test-evidence-{}", 
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()),
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64,
-            source_ip: "192.168.1.100".to_string(),
-            target_ip: "10.0.0.1".to_string(),
-            threat_type: ThreatType::Malware,
-            threat_level: ThreatLevel::Critical,
-            context: "Test threat evidence for integration testing".to_string(),
-            evidence_hash: orasrs_agent::crypto::CryptoProvider::blake3_hash(b"test-data"),
-            geolocation: "unknown".to_string(),
-            network_flow: "TCP".to_string(),
-            agent_id: "test-agent".to_string(),
-            reputation: 0.8,
-            compliance_tag: "global".to_string(),
-            region: "test-region".to_string(),
-        }
+        ThreatEvidence::builder("192.168.1.100", ThreatType::Malware, ThreatLevel::Critical)
+            .target_ip("10.0.0.1")
+            .context("Test threat evidence for integration testing")
+            .network_flow("TCP")
+            .agent_id("test-agent")
+            .reputation(0.8)
+            .location("test-region")
+            .hash_of(b"test-data")
+            .build()
     }
 }
\ No newline at end of file
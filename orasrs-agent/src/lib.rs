@@ -4,6 +4,8 @@
 //! as part of the OraSRS v2.0 coordinated defense framework.
 
 pub mod agent;
+pub mod allowlist;
+pub mod clock;
 pub mod config;
 pub mod monitor;
 pub mod analyzer;
@@ -13,21 +15,35 @@ pub mod p2p;
 pub mod threat_intel;
 pub mod threat_intel_upstream;
 pub mod consensus_verification;
+pub mod correlation;
 pub mod credibility_enhancement;
+pub mod enrichment;
+pub mod flow;
+pub mod tls;
 pub mod compliance;
 pub mod error;
+pub mod evidence_store;
+pub mod resources;
+pub mod notarization;
+pub mod storage;
 pub mod blocklist_exporter;
+pub mod tenant;
+pub mod http_server;
+pub mod metrics;
+pub mod control;
 
-pub use agent::OrasrsAgent;
+pub use agent::{OrasrsAgent, AgentBuilder, ShutdownReport, StatusHandle};
 pub use config::AgentConfig;
 pub use threat_intel_upstream::ThreatIntelAggregator;
 pub use consensus_verification::ConsensusEngine;
 pub use credibility_enhancement::CredibilityEngine;
 pub use error::{AgentError, Result};
-pub use blocklist_exporter::{BlocklistExporter, start_blocklist_exporter};
+pub use blocklist_exporter::{BlocklistExporter, BlocklistFormat, start_blocklist_exporter};
+pub use tenant::TenantManager;
+pub use storage::{Storage, FsStorage, MemoryStorage};
 
-/// Threat level enumeration
-#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// Threat level enumeration, ordered by severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub enum ThreatLevel {
     Info = 0,
     Warning = 1,
@@ -35,16 +51,111 @@ pub enum ThreatLevel {
     Emergency = 3,
 }
 
+impl ThreatLevel {
+    /// CEF severity (0-10): Info=3, Warning=5, Critical=8, Emergency=10
+    pub fn to_cef_severity(&self) -> u8 {
+        match self {
+            ThreatLevel::Info => 3,
+            ThreatLevel::Warning => 5,
+            ThreatLevel::Critical => 8,
+            ThreatLevel::Emergency => 10,
+        }
+    }
+
+    /// Common SIEM severity string: low, medium, high, or critical
+    pub fn to_common_string(&self) -> &'static str {
+        match self {
+            ThreatLevel::Info => "low",
+            ThreatLevel::Warning => "medium",
+            ThreatLevel::Critical => "high",
+            ThreatLevel::Emergency => "critical",
+        }
+    }
+}
+
+impl std::fmt::Display for ThreatLevel {
+    /// Upper-case level name, as written in blocklist comments
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ThreatLevel::Info => "INFO",
+            ThreatLevel::Warning => "WARNING",
+            ThreatLevel::Critical => "CRITICAL",
+            ThreatLevel::Emergency => "EMERGENCY",
+        })
+    }
+}
+
+impl std::str::FromStr for ThreatLevel {
+    type Err = AgentError;
+
+    /// Parse a level name or a common severity synonym, case-insensitively
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "info" | "low" | "informational" => Ok(ThreatLevel::Info),
+            "warning" | "medium" | "moderate" => Ok(ThreatLevel::Warning),
+            "critical" | "high" => Ok(ThreatLevel::Critical),
+            "emergency" | "severe" => Ok(ThreatLevel::Emergency),
+            _ => Err(AgentError::ConfigError(format!("Unknown threat level {:?}", s))),
+        }
+    }
+}
+
 /// Threat type enumeration
+///
+/// Serialized as the snake_case names of `as_ref`; the variant names are
+/// still accepted when reading data written before that.
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ThreatType {
+    #[serde(rename = "ddos", alias = "DDoS")]
     DDoS,
+    #[serde(alias = "Malware")]
     Malware,
+    #[serde(alias = "Phishing")]
     Phishing,
+    #[serde(alias = "BruteForce")]
     BruteForce,
+    #[serde(alias = "SuspiciousConnection")]
     SuspiciousConnection,
+    #[serde(alias = "AnomalousBehavior")]
     AnomalousBehavior,
+    #[serde(rename = "ioc_match", alias = "IoCMatch")]
     IoCMatch,
+    #[serde(rename = "apt", alias = "APT")]
+    APT,
+    #[serde(alias = "Exploit")]
+    Exploit,
+    #[serde(alias = "Unknown")]
+    Unknown,
+}
+
+impl std::fmt::Display for ThreatType {
+    /// Snake-case type name, the same as `as_ref`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl std::str::FromStr for ThreatType {
+    type Err = AgentError;
+
+    /// Parse a name produced by `as_ref` or a common feed category, case-insensitively
+    ///
+    /// Hyphens and spaces are treated as underscores.
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.trim().to_lowercase().replace(['-', ' '], "_");
+        if let Some(threat_type) = ThreatType::from_name(&name) {
+            return Ok(threat_type);
+        }
+        match name.as_str() {
+            "dos" => Ok(ThreatType::DDoS),
+            "bruteforce" => Ok(ThreatType::BruteForce),
+            "c2" | "command_and_control" | "scanner" | "malicious_activity" => Ok(ThreatType::SuspiciousConnection),
+            "anomaly" => Ok(ThreatType::AnomalousBehavior),
+            "ioc" => Ok(ThreatType::IoCMatch),
+            _ => Err(AgentError::ConfigError(format!("Unknown threat type {:?}", s))),
+        }
+    }
 }
 
 /// Threat evidence structure
@@ -64,6 +175,205 @@ pub struct ThreatEvidence {
     pub reputation: f64,
     pub compliance_tag: String,
     pub region: String,
+    /// Time after which the indicator is no longer valid, if the source gave one
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Reverse DNS hostname of the source IP, when enrichment is enabled
+    #[serde(default)]
+    pub source_hostname: Option<String>,
+    /// Owner of the source IP's netblock, when enrichment is enabled
+    #[serde(default)]
+    pub source_owner: Option<String>,
+    /// Campaign linking this evidence to related recent events, set by temporal correlation
+    #[serde(default)]
+    pub campaign_id: Option<String>,
+    /// CIDR range the indicator covers, for feeds listing networks rather than single IPs
+    #[serde(default)]
+    pub source_network: Option<String>,
+    /// Hex public key of the emitting agent, in its signature scheme's encoding
+    #[serde(default)]
+    pub signer_key: Option<String>,
+    /// Hex signature by `signer_key` over the rest of the evidence
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+/// Seconds evidence timestamps may lie in the future to allow for clock skew
+const MAX_FUTURE_SKEW_SECS: i64 = 300;
+
+/// Placeholder target IPs used by local detectors and upstream feeds
+const TARGET_IP_PLACEHOLDERS: &[&str] = &["local", "global"];
+
+/// Whether a target IP is a placeholder rather than an address
+pub(crate) fn is_target_placeholder(target_ip: &str) -> bool {
+    TARGET_IP_PLACEHOLDERS.contains(&target_ip)
+}
+
+impl ThreatEvidence {
+    /// Start building evidence about `source_ip`
+    pub fn builder(source_ip: &str, threat_type: ThreatType, threat_level: ThreatLevel) -> ThreatEvidenceBuilder {
+        ThreatEvidenceBuilder::new(source_ip, threat_type, threat_level)
+    }
+
+    /// Reject evidence with malformed fields before it is processed
+    pub fn validate(&self) -> Result<()> {
+        self.validate_at(chrono::Utc::now().timestamp())
+    }
+
+    fn validate_at(&self, now: i64) -> Result<()> {
+        let invalid = |reason: String| Err(AgentError::InvalidEvidence(reason));
+
+        if self.id.trim().is_empty() {
+            return invalid("empty evidence ID".to_string());
+        }
+        if self.agent_id.trim().is_empty() {
+            return invalid(format!("empty agent ID in evidence {}", self.id));
+        }
+        if self.source_ip.parse::<std::net::IpAddr>().is_err() {
+            return invalid(format!("invalid source IP {:?} in evidence {}", self.source_ip, self.id));
+        }
+        if self.target_ip.parse::<std::net::IpAddr>().is_err()
+            && !is_target_placeholder(&self.target_ip)
+        {
+            return invalid(format!("invalid target IP {:?} in evidence {}", self.target_ip, self.id));
+        }
+        if self.timestamp <= 0 || self.timestamp > now + MAX_FUTURE_SKEW_SECS {
+            return invalid(format!("timestamp {} out of range in evidence {}", self.timestamp, self.id));
+        }
+        if !(0.0..=1.0).contains(&self.reputation) {
+            return invalid(format!("reputation {} outside [0, 1] in evidence {}", self.reputation, self.id));
+        }
+
+        Ok(())
+    }
+
+    /// Whether the evidence's source or target is `ip`
+    pub fn concerns_ip(&self, ip: std::net::IpAddr) -> bool {
+        [&self.source_ip, &self.target_ip]
+            .into_iter()
+            .any(|field| field.parse::<std::net::IpAddr>() == Ok(ip))
+    }
+}
+
+/// Builds [`ThreatEvidence`] the way local detectors raise it
+///
+/// Defaults to a random ID, the current time and a `local` target; the agent
+/// ID, reputation and compliance tag are placeholders the agent sets on
+/// submission. Unless given data to hash, `build` hashes the network flow, or
+/// the source IP when there is none.
+pub struct ThreatEvidenceBuilder {
+    evidence: ThreatEvidence,
+    hashed_data: Option<Vec<u8>>,
+}
+
+impl ThreatEvidenceBuilder {
+    pub fn new(source_ip: &str, threat_type: ThreatType, threat_level: ThreatLevel) -> Self {
+        Self {
+            evidence: ThreatEvidence {
+                id: uuid::Uuid::new_v4().to_string(),
+                timestamp: chrono::Utc::now().timestamp(),
+                source_ip: source_ip.to_string(),
+                target_ip: "local".to_string(),
+                threat_type,
+                threat_level,
+                context: String::new(),
+                evidence_hash: String::new(),
+                geolocation: "unknown".to_string(),
+                network_flow: String::new(),
+                agent_id: "agent".to_string(),
+                reputation: 1.0,
+                compliance_tag: "global".to_string(),
+                region: "unknown".to_string(),
+                expires_at: None,
+                source_hostname: None,
+                source_owner: None,
+                campaign_id: None,
+                source_network: None,
+                signer_key: None,
+                signature: None,
+            },
+            hashed_data: None,
+        }
+    }
+
+    pub fn id(mut self, id: &str) -> Self {
+        self.evidence.id = id.to_string();
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.evidence.timestamp = timestamp;
+        self
+    }
+
+    pub fn target_ip(mut self, target_ip: &str) -> Self {
+        self.evidence.target_ip = target_ip.to_string();
+        self
+    }
+
+    pub fn context(mut self, context: impl Into<String>) -> Self {
+        self.evidence.context = context.into();
+        self
+    }
+
+    pub fn network_flow(mut self, network_flow: impl Into<String>) -> Self {
+        self.evidence.network_flow = network_flow.into();
+        self
+    }
+
+    /// Set the geolocation and region, which local detectors keep equal
+    pub fn location(mut self, location: &str) -> Self {
+        self.evidence.geolocation = location.to_string();
+        self.evidence.region = location.to_string();
+        self
+    }
+
+    pub fn agent_id(mut self, agent_id: &str) -> Self {
+        self.evidence.agent_id = agent_id.to_string();
+        self
+    }
+
+    pub fn reputation(mut self, reputation: f64) -> Self {
+        self.evidence.reputation = reputation;
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: i64) -> Self {
+        self.evidence.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Hash `data`, e.g. a packet payload, instead of the network flow
+    pub fn hash_of(mut self, data: &[u8]) -> Self {
+        self.hashed_data = Some(data.to_vec());
+        self
+    }
+
+    pub fn build(self) -> ThreatEvidence {
+        let mut evidence = self.evidence;
+        let data = match &self.hashed_data {
+            Some(data) => data.as_slice(),
+            None if !evidence.network_flow.is_empty() => evidence.network_flow.as_bytes(),
+            None => evidence.source_ip.as_bytes(),
+        };
+        evidence.evidence_hash = crypto::CryptoProvider::blake3_hash(data);
+        evidence
+    }
+}
+
+/// Evidence about `ip` shared by unit tests; override fields at the call site
+#[cfg(test)]
+pub(crate) fn test_evidence(ip: &str) -> ThreatEvidence {
+    ThreatEvidence::builder(ip, ThreatType::Malware, ThreatLevel::Critical)
+        .id(&format!("test-{}", ip))
+        .timestamp(1_700_000_000)
+        .target_ip("10.0.0.1")
+        .context("Test threat")
+        .network_flow("TCP")
+        .agent_id("test-agent")
+        .reputation(0.9)
+        .hash_of(ip.as_bytes())
+        .build()
 }
 
 /// Agent status structure
@@ -80,4 +390,203 @@ pub struct AgentStatus {
     pub last_threat_report: Option<i64>,
     pub p2p_connected: bool,
     pub compliance_mode: String,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threat_level_severity_mapping() {
+        assert_eq!(ThreatLevel::Info.to_cef_severity(), 3);
+        assert_eq!(ThreatLevel::Warning.to_cef_severity(), 5);
+        assert_eq!(ThreatLevel::Critical.to_cef_severity(), 8);
+        assert_eq!(ThreatLevel::Emergency.to_cef_severity(), 10);
+
+        assert_eq!(ThreatLevel::Info.to_common_string(), "low");
+        assert_eq!(ThreatLevel::Warning.to_common_string(), "medium");
+        assert_eq!(ThreatLevel::Critical.to_common_string(), "high");
+        assert_eq!(ThreatLevel::Emergency.to_common_string(), "critical");
+    }
+
+    #[test]
+    fn test_builder_defaults() {
+        let before = chrono::Utc::now().timestamp();
+        let evidence = ThreatEvidence::builder("203.0.113.7", ThreatType::BruteForce, ThreatLevel::Warning).build();
+
+        assert!(uuid::Uuid::parse_str(&evidence.id).is_ok());
+        assert!(evidence.timestamp >= before && evidence.timestamp <= chrono::Utc::now().timestamp());
+        assert_eq!(evidence.source_ip, "203.0.113.7");
+        assert_eq!(evidence.target_ip, "local");
+        assert_eq!(evidence.threat_type, ThreatType::BruteForce);
+        assert_eq!(evidence.threat_level, ThreatLevel::Warning);
+        assert_eq!(evidence.context, "");
+        assert_eq!(evidence.network_flow, "");
+        assert_eq!(evidence.reputation, 1.0);
+        assert_eq!(evidence.expires_at, None);
+        assert_eq!(evidence.source_hostname, None);
+        assert_eq!(evidence.campaign_id, None);
+        assert_eq!(evidence.signature, None);
+        assert_eq!(evidence.evidence_hash, crypto::CryptoProvider::blake3_hash(b"203.0.113.7"));
+        assert!(evidence.validate().is_ok());
+
+        // The flow is hashed once there is one, unless other data is given
+        let evidence = ThreatEvidence::builder("203.0.113.7", ThreatType::DDoS, ThreatLevel::Critical)
+            .network_flow("SYN 203.0.113.7 -> 10.0.0.1")
+            .build();
+        assert_eq!(evidence.evidence_hash, crypto::CryptoProvider::blake3_hash(b"SYN 203.0.113.7 -> 10.0.0.1"));
+        let evidence = ThreatEvidence::builder("203.0.113.7", ThreatType::Malware, ThreatLevel::Critical)
+            .network_flow("TCP")
+            .hash_of(b"payload")
+            .build();
+        assert_eq!(evidence.evidence_hash, crypto::CryptoProvider::blake3_hash(b"payload"));
+    }
+
+    #[test]
+    fn test_display_wire_strings() {
+        assert_eq!(ThreatLevel::Info.to_string(), "INFO");
+        assert_eq!(ThreatLevel::Warning.to_string(), "WARNING");
+        assert_eq!(ThreatLevel::Critical.to_string(), "CRITICAL");
+        assert_eq!(ThreatLevel::Emergency.to_string(), "EMERGENCY");
+
+        assert_eq!(ThreatType::DDoS.to_string(), "ddos");
+        assert_eq!(ThreatType::Malware.to_string(), "malware");
+        assert_eq!(ThreatType::Phishing.to_string(), "phishing");
+        assert_eq!(ThreatType::BruteForce.to_string(), "brute_force");
+        assert_eq!(ThreatType::SuspiciousConnection.to_string(), "suspicious_connection");
+        assert_eq!(ThreatType::AnomalousBehavior.to_string(), "anomalous_behavior");
+        assert_eq!(ThreatType::IoCMatch.to_string(), "ioc_match");
+        assert_eq!(ThreatType::APT.to_string(), "apt");
+        assert_eq!(ThreatType::Exploit.to_string(), "exploit");
+        assert_eq!(ThreatType::Unknown.to_string(), "unknown");
+
+        // Both parse back to the same value
+        for level in [ThreatLevel::Info, ThreatLevel::Warning, ThreatLevel::Critical, ThreatLevel::Emergency] {
+            assert_eq!(level.to_string().parse::<ThreatLevel>().unwrap(), level);
+        }
+        for threat_type in ThreatType::ALL {
+            assert_eq!(threat_type.to_string().parse::<ThreatType>().unwrap(), threat_type);
+        }
+    }
+
+    #[test]
+    fn test_threat_type_serde_matches_wire_strings() {
+        for threat_type in ThreatType::ALL {
+            let json = serde_json::to_string(&threat_type).unwrap();
+            assert_eq!(json, format!("\"{}\"", threat_type.as_ref()));
+            assert_eq!(serde_json::from_str::<ThreatType>(&json).unwrap(), threat_type);
+
+            // Data written with the variant names still loads
+            let legacy = serde_json::to_string(&format!("{:?}", threat_type)).unwrap();
+            assert_eq!(serde_json::from_str::<ThreatType>(&legacy).unwrap(), threat_type);
+        }
+        assert_eq!(serde_json::from_str::<ThreatType>("\"brute_force\"").unwrap(), ThreatType::BruteForce);
+        assert!(serde_json::from_str::<ThreatType>("\"brute-force\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_threat_levels() {
+        for (name, level) in [
+            ("info", ThreatLevel::Info),
+            ("low", ThreatLevel::Info),
+            ("informational", ThreatLevel::Info),
+            ("warning", ThreatLevel::Warning),
+            ("medium", ThreatLevel::Warning),
+            ("moderate", ThreatLevel::Warning),
+            ("critical", ThreatLevel::Critical),
+            ("high", ThreatLevel::Critical),
+            ("emergency", ThreatLevel::Emergency),
+            ("severe", ThreatLevel::Emergency),
+            (" HIGH ", ThreatLevel::Critical),
+        ] {
+            assert_eq!(name.parse::<ThreatLevel>().unwrap(), level, "{:?}", name);
+        }
+        assert!(matches!("urgent".parse::<ThreatLevel>(), Err(AgentError::ConfigError(_))));
+        assert!("".parse::<ThreatLevel>().is_err());
+    }
+
+    #[test]
+    fn test_parse_threat_types() {
+        for threat_type in ThreatType::ALL {
+            assert_eq!(threat_type.as_ref().parse::<ThreatType>().unwrap(), threat_type);
+        }
+        for (name, threat_type) in [
+            ("DDoS", ThreatType::DDoS),
+            ("dos", ThreatType::DDoS),
+            ("brute-force", ThreatType::BruteForce),
+            ("bruteforce", ThreatType::BruteForce),
+            ("c2", ThreatType::SuspiciousConnection),
+            ("command and control", ThreatType::SuspiciousConnection),
+            ("scanner", ThreatType::SuspiciousConnection),
+            ("malicious-activity", ThreatType::SuspiciousConnection),
+            ("anomaly", ThreatType::AnomalousBehavior),
+            ("ioc", ThreatType::IoCMatch),
+            ("APT", ThreatType::APT),
+        ] {
+            assert_eq!(name.parse::<ThreatType>().unwrap(), threat_type, "{:?}", name);
+        }
+        assert!(matches!("ransomware-ish".parse::<ThreatType>(), Err(AgentError::ConfigError(_))));
+        assert!("".parse::<ThreatType>().is_err());
+    }
+
+    #[test]
+    fn test_threat_level_ordering() {
+        let levels = [ThreatLevel::Info, ThreatLevel::Warning, ThreatLevel::Critical, ThreatLevel::Emergency];
+        for (i, lower) in levels.iter().enumerate() {
+            for higher in &levels[i + 1..] {
+                assert!(lower < higher, "{:?} < {:?}", lower, higher);
+                assert_eq!(std::cmp::max(*lower, *higher), *higher);
+                assert_eq!(lower.cmp(higher), (*lower as u8).cmp(&(*higher as u8)));
+            }
+        }
+    }
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn valid_evidence() -> ThreatEvidence {
+        ThreatEvidence { timestamp: NOW, ..test_evidence("203.0.113.10") }
+    }
+
+    fn assert_invalid(evidence: ThreatEvidence) {
+        assert!(matches!(evidence.validate_at(NOW), Err(AgentError::InvalidEvidence(_))));
+    }
+
+    #[test]
+    fn test_valid_evidence_passes() {
+        assert!(valid_evidence().validate_at(NOW).is_ok());
+
+        let mut evidence = valid_evidence();
+        evidence.source_ip = "2001:db8::1".to_string();
+        evidence.target_ip = "local".to_string();
+        evidence.timestamp = NOW + 60; // Within clock skew
+        assert!(evidence.validate_at(NOW).is_ok());
+    }
+
+    #[test]
+    fn test_empty_ids_are_rejected() {
+        assert_invalid(ThreatEvidence { id: String::new(), ..valid_evidence() });
+        assert_invalid(ThreatEvidence { id: "  ".to_string(), ..valid_evidence() });
+        assert_invalid(ThreatEvidence { agent_id: String::new(), ..valid_evidence() });
+    }
+
+    #[test]
+    fn test_invalid_ips_are_rejected() {
+        assert_invalid(ThreatEvidence { source_ip: "unknown".to_string(), ..valid_evidence() });
+        assert_invalid(ThreatEvidence { source_ip: "203.0.113.300".to_string(), ..valid_evidence() });
+        assert_invalid(ThreatEvidence { target_ip: "not-an-ip".to_string(), ..valid_evidence() });
+    }
+
+    #[test]
+    fn test_out_of_range_timestamps_are_rejected() {
+        assert_invalid(ThreatEvidence { timestamp: 0, ..valid_evidence() });
+        assert_invalid(ThreatEvidence { timestamp: -5, ..valid_evidence() });
+        assert_invalid(ThreatEvidence { timestamp: NOW + 86_400, ..valid_evidence() });
+    }
+
+    #[test]
+    fn test_out_of_range_reputation_is_rejected() {
+        assert_invalid(ThreatEvidence { reputation: -0.1, ..valid_evidence() });
+        assert_invalid(ThreatEvidence { reputation: 1.5, ..valid_evidence() });
+        assert_invalid(ThreatEvidence { reputation: f64::NAN, ..valid_evidence() });
+    }
+}
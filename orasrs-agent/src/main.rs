@@ -33,7 +33,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tokio::signal::ctrl_c().await?;
     log::info!("Received shutdown signal");
     
-    agent.stop()?;
+    agent.stop().await?;
     log::info!("OraSRS Agent stopped");
     
     Ok(())
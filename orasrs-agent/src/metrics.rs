@@ -0,0 +1,79 @@
+//! Prometheus metrics export
+
+use crate::{
+    agent::StatusHandle,
+    consensus_verification::ConsensusEngine,
+    error::Result,
+    http_server::{self, HttpResponse},
+    threat_intel_upstream::ThreatIntelAggregator,
+};
+use hyper::{Method, StatusCode};
+use std::fmt::{Display, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Content type of the Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Gathers the agent's metrics for export
+#[derive(Clone)]
+pub struct MetricsCollector {
+    status: StatusHandle,
+    aggregator: ThreatIntelAggregator,
+    consensus_engine: Arc<ConsensusEngine>,
+}
+
+impl MetricsCollector {
+    pub fn new(status: StatusHandle, aggregator: ThreatIntelAggregator, consensus_engine: Arc<ConsensusEngine>) -> Self {
+        Self { status, aggregator, consensus_engine }
+    }
+
+    /// Render current metrics in the Prometheus text format
+    pub async fn render(&self) -> String {
+        let status = self.status.snapshot();
+        let mut out = String::new();
+
+        write_metric(&mut out, "orasrs_threats_total", "counter",
+                     "Threat evidence submitted by this agent", status.threat_count);
+        write_metric(&mut out, "orasrs_reputation", "gauge",
+                     "Reputation of this agent as a reporter", status.reputation);
+        write_metric(&mut out, "orasrs_p2p_connected", "gauge",
+                     "Whether the agent is connected to the P2P network", status.p2p_connected as u8);
+        write_metric(&mut out, "orasrs_upstream_threats_fetched_total", "counter",
+                     "Indicators fetched from upstream threat intelligence sources", self.aggregator.threats_fetched());
+        write_metric(&mut out, "orasrs_consensus_requests", "gauge",
+                     "Verification requests awaiting consensus", self.consensus_engine.in_flight_requests().await);
+        write_metric(&mut out, "orasrs_memory_usage_bytes", "gauge",
+                     "Memory used by the agent", status.memory_usage);
+        write_metric(&mut out, "orasrs_cpu_usage_percent", "gauge",
+                     "CPU used by the agent", status.cpu_usage);
+        out
+    }
+}
+
+/// Append one metric with its HELP and TYPE lines
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl Display) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Serve `/metrics` on `addr` until `shutdown` turns true
+pub async fn serve_metrics(
+    addr: SocketAddr,
+    collector: MetricsCollector,
+    shutdown: watch::Receiver<bool>,
+) -> Result<(SocketAddr, JoinHandle<()>)> {
+    http_server::serve(addr, shutdown, move |method, path| {
+        let collector = collector.clone();
+        async move {
+            match (method, path.as_str()) {
+                (Method::GET, "/metrics") => HttpResponse::new(StatusCode::OK, CONTENT_TYPE, collector.render().await),
+                _ => HttpResponse::not_found(),
+            }
+        }
+    })
+    .await
+}
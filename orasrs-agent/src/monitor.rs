@@ -1,39 +1,222 @@
-use crate::{ThreatEvidence, ThreatType, ThreatLevel, error::{AgentError, Result}};
+#[cfg(feature = "yara")]
+use crate::analyzer::YaraMatcher;
+use crate::{ThreatEvidence, ThreatType, ThreatLevel, analyzer::{ThreatDetector, DEFAULT_SYN_RATE_THRESHOLD}, config::{GeoFenceConfig, GeoFenceMode, TlsInspectConfig}, error::{AgentError, Result}, flow::{FlowRecord, PacketInfo}, tls::parse_client_hello};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
 use tokio::time::{sleep, Duration};
 
-/// Network flow monitor using eBPF (simplified for this example)
+/// Seconds between runs of captured flows through the threat detector
+#[cfg(feature = "pcap")]
+const FLOW_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Network flow monitor capturing packets with libpcap (requires the pcap feature)
+#[derive(Debug, Clone)]
 pub struct NetflowMonitor {
     enabled: bool,
-    // In a real implementation, this would hold eBPF program and maps
+    interface: Option<String>, // Capture interface; the default device if unset
+    syn_rate_threshold: f64,   // SYNs per second from one source reported as a flood
+    tls_inspector: Option<TlsInspector>, // Inspects captured TLS handshakes
+    #[cfg(feature = "yara")]
+    yara: Option<Arc<YaraMatcher>>, // Scans captured payloads
 }
 
 impl NetflowMonitor {
     pub fn new(enabled: bool) -> Self {
-        Self { enabled }
+        Self {
+            enabled,
+            interface: None,
+            syn_rate_threshold: DEFAULT_SYN_RATE_THRESHOLD,
+            tls_inspector: None,
+            #[cfg(feature = "yara")]
+            yara: None,
+        }
     }
 
-    pub async fn start_monitoring(&mut self) -> Result<()> {
+    /// Capture on the given interface instead of the default device
+    pub fn with_interface(mut self, interface: Option<String>) -> Self {
+        self.interface = interface;
+        self
+    }
+
+    /// Report sources sending more than `syn_rate` SYNs per second
+    pub fn with_ddos_threshold(mut self, syn_rate: f64) -> Self {
+        self.syn_rate_threshold = syn_rate;
+        self
+    }
+
+    /// Pass captured TCP payloads to a TLS inspector
+    pub fn with_tls_inspector(mut self, tls_inspector: Option<TlsInspector>) -> Self {
+        self.tls_inspector = tls_inspector;
+        self
+    }
+
+    /// Scan captured payloads with YARA rules
+    #[cfg(feature = "yara")]
+    pub fn with_yara_matcher(mut self, matcher: Arc<YaraMatcher>) -> Self {
+        self.yara = Some(matcher);
+        self
+    }
+
+    /// Whether captured payloads are scanned with YARA rules
+    #[cfg(feature = "yara")]
+    fn scans_payloads(&self) -> bool {
+        self.yara.is_some()
+    }
+
+    #[cfg(not(feature = "yara"))]
+    fn scans_payloads(&self) -> bool {
+        false
+    }
+
+    /// Start capturing, sending detected threats to `threat_queue` until shutdown
+    ///
+    /// Returns the capture task's handle, which finishes within about a
+    /// second of shutdown. A capture that can't be opened, e.g. without
+    /// permission, is logged and leaves the rest of the agent running.
+    pub async fn start_monitoring(
+        &mut self,
+        threat_queue: mpsc::UnboundedSender<ThreatEvidence>,
+        shutdown: watch::Receiver<bool>,
+    ) -> Result<Option<JoinHandle<()>>> {
         if !self.enabled {
-            return Ok(());
+            return Ok(None);
         }
 
         log::info!("Starting network flow monitoring...");
-        // In a real implementation, this would attach eBPF programs
-        // For now, we'll simulate network flow detection
+        Ok(self.start_capture(threat_queue, shutdown))
+    }
+
+    #[cfg(feature = "pcap")]
+    fn start_capture(&self, threat_queue: mpsc::UnboundedSender<ThreatEvidence>, shutdown: watch::Receiver<bool>) -> Option<JoinHandle<()>> {
+        let monitor = self.clone();
+        Some(tokio::task::spawn_blocking(move || {
+            if let Err(e) = monitor.capture_flows(&threat_queue, &shutdown) {
+                log::warn!("Network flow capture on {} stopped: {}",
+                           monitor.interface.as_deref().unwrap_or("default device"), e);
+            }
+        }))
+    }
+
+    #[cfg(not(feature = "pcap"))]
+    fn start_capture(&self, _threat_queue: mpsc::UnboundedSender<ThreatEvidence>, _shutdown: watch::Receiver<bool>) -> Option<JoinHandle<()>> {
+        log::warn!("Network flow monitoring needs packet capture, which requires the pcap feature");
+        None
+    }
+
+    /// Blocking capture loop, aggregating packets into flows and reporting them periodically
+    #[cfg(feature = "pcap")]
+    fn capture_flows(
+        &self,
+        threat_queue: &mpsc::UnboundedSender<ThreatEvidence>,
+        shutdown: &watch::Receiver<bool>,
+    ) -> std::result::Result<(), pcap::Error> {
+        let device = match &self.interface {
+            Some(name) => pcap::Device::from(name.as_str()),
+            None => pcap::Device::lookup()?
+                .ok_or_else(|| pcap::Error::PcapError("no capture device found".to_string()))?,
+        };
+        // Headers suffice for flows; TLS inspection and YARA need whole segments, since a
+        // ClientHello with many extensions or a post-quantum key share can exceed 2KB.
+        // A ClientHello split across segments is still missed.
+        let snaplen = if self.scans_payloads() || self.tls_inspector.is_some() { 65535 } else { 256 };
+        let mut capture = pcap::Capture::from_device(device)?.snaplen(snaplen).timeout(1000).open()?;
+        let link_type = capture.get_datalink().0 as u32;
+
+        let mut detector = ThreatDetector::new().with_ddos_threshold(self.syn_rate_threshold);
+        #[cfg(feature = "yara")]
+        if let Some(matcher) = &self.yara {
+            detector = detector.with_yara_matcher(matcher.clone());
+        }
+        let mut flows = crate::flow::FlowTable::new();
+        let mut last_report = Instant::now();
+        while !*shutdown.borrow() {
+            match capture.next_packet() {
+                Ok(packet) => {
+                    let timestamp = packet.header.ts.tv_sec as f64 + packet.header.ts.tv_usec as f64 * 1e-6;
+                    let Some(info) = flows.record(link_type, packet.data, packet.header.len, timestamp) else {
+                        continue;
+                    };
+                    let threats = self.inspect_packet(&info, packet.data).into_iter()
+                        .chain(self.scan_payload(&mut detector, &info, packet.data));
+                    for threat in threats {
+                        if threat_queue.send(threat).is_err() {
+                            return Ok(()); // The agent is gone
+                        }
+                    }
+                }
+                Err(pcap::Error::TimeoutExpired) => {}
+                Err(e) => return Err(e),
+            }
+
+            // A full table is reported early rather than dropping new flows for the rest of the interval
+            if last_report.elapsed() >= FLOW_REPORT_INTERVAL || flows.is_full() {
+                if flows.dropped_packets() > 0 {
+                    log::warn!("Flow table full: dropped {} packets of new flows", flows.dropped_packets());
+                }
+                for threat in self.detect_threats(&mut detector, &flows.drain()) {
+                    if threat_queue.send(threat).is_err() {
+                        return Ok(()); // The agent is gone
+                    }
+                }
+                last_report = Instant::now();
+            }
+        }
         Ok(())
     }
 
-    pub fn detect_threats(&self, _flow_data: &str) -> Vec<ThreatEvidence> {
-        // Simulated threat detection
-        // In real implementation, this would analyze actual network flows
-        vec![]
+    /// Inspect a captured TCP packet's payload for a flagged TLS handshake
+    pub fn inspect_packet(&self, info: &PacketInfo, data: &[u8]) -> Option<ThreatEvidence> {
+        let inspector = self.tls_inspector.as_ref()?;
+        if info.key.protocol != crate::flow::PROTOCOL_TCP {
+            return None;
+        }
+        let payload = data.get(info.payload_offset..).filter(|payload| !payload.is_empty())?;
+        inspector.inspect_tls(&info.key.src_ip.to_string(), &info.key.dst_ip.to_string(), payload)
+    }
+
+    /// Scan a captured packet's payload with the detector's YARA rules
+    pub fn scan_payload(&self, detector: &mut ThreatDetector, info: &PacketInfo, data: &[u8]) -> Vec<ThreatEvidence> {
+        if !self.scans_payloads() {
+            return Vec::new();
+        }
+        match data.get(info.payload_offset..).filter(|payload| !payload.is_empty()) {
+            Some(payload) => detector.detect_threats_from_payload(&info.key.src_ip.to_string(), &info.key.dst_ip.to_string(), payload),
+            None => Vec::new(),
+        }
+    }
+
+    /// Run flow summaries through the detector, attributing evidence to each flow's endpoints
+    ///
+    /// SYNs are also counted per source across flows, since a flood spreads
+    /// over many source ports; they count as observed now, when the flows are reported.
+    pub fn detect_threats(&self, detector: &mut ThreatDetector, flows: &[FlowRecord]) -> Vec<ThreatEvidence> {
+        let now = Instant::now();
+        let mut threats = Vec::new();
+        for flow in flows {
+            let source_ip = flow.key.src_ip.to_string();
+            let target_ip = flow.key.dst_ip.to_string();
+            for mut threat in detector.detect_threats_from_flow(&flow.summary()) {
+                threat.source_ip = source_ip.clone();
+                threat.target_ip = target_ip.clone();
+                threats.push(threat);
+            }
+            if flow.syn_packets > 0 {
+                threats.extend(detector.observe_syns(&source_ip, &target_ip, flow.syn_packets, now));
+            }
+        }
+        detector.prune_syn_rates(now);
+        threats
     }
 }
 
 /// System call monitor (simplified for this example)
+#[derive(Debug, Clone)]
 pub struct SyscallMonitor {
     enabled: bool,
 }
@@ -59,15 +242,32 @@ impl SyscallMonitor {
     }
 }
 
+/// Seconds during which a repeated flagged handshake is not reported again
+const TLS_REPEAT_WINDOW: Duration = Duration::from_secs(300);
+
+/// Most flagged handshakes remembered for suppressing repeats
+const MAX_TLS_REPORTED: usize = 4096;
+
 /// TLS inspection monitor (simplified for this example)
 #[derive(Debug, Clone)]
 pub struct TlsInspector {
     enabled: bool,
+    indicators: Arc<RwLock<TlsInspectConfig>>, // Shared with clones so runtime updates reach the capture loop
+    reported: Arc<Mutex<HashMap<String, Instant>>>, // When each flagged handshake was last reported
 }
 
 impl TlsInspector {
-    pub fn new(enabled: bool) -> Self {
-        Self { enabled }
+    pub fn new(enabled: bool, config: TlsInspectConfig) -> Self {
+        Self {
+            enabled,
+            indicators: Arc::new(RwLock::new(normalize_indicators(config))),
+            reported: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Replace the flagged server names and fingerprints
+    pub fn update_config(&self, config: TlsInspectConfig) {
+        *self.indicators.write().unwrap() = normalize_indicators(config);
     }
 
     pub async fn start_monitoring(&mut self) -> Result<()> {
@@ -79,28 +279,167 @@ impl TlsInspector {
         Ok(())
     }
 
-    pub fn inspect_tls(&self, _sni: &str, _cert_fingerprint: &str) -> Option<ThreatEvidence> {
-        // Simulated TLS threat detection
-        None
+    /// Check a TCP payload sent from `source_ip` to `target_ip`
+    ///
+    /// Returns evidence if the payload is a ClientHello whose server name or
+    /// JA3 fingerprint is flagged, unless the same handshake between the same
+    /// hosts was reported within the last five minutes.
+    pub fn inspect_tls(&self, source_ip: &str, target_ip: &str, payload: &[u8]) -> Option<ThreatEvidence> {
+        let hello = parse_client_hello(payload)?;
+        let ja3 = hello.ja3();
+        let sni = hello.sni.unwrap_or_default();
+
+        let indicators = self.indicators.read().unwrap();
+        let reason = if let Some(name) = indicators.blocked_sni.iter().find(|name| sni_matches(&sni, name)) {
+            format!("server name {}", name)
+        } else if indicators.blocked_ja3.contains(&ja3) {
+            "JA3 fingerprint".to_string()
+        } else {
+            return None;
+        };
+        drop(indicators);
+
+        let context = format!("TLS connection matched flagged {}: sni={} ja3={}", reason, sni, ja3);
+        if !self.first_report(&format!("{} {} {}", source_ip, target_ip, context)) {
+            return None;
+        }
+        Some(
+            ThreatEvidence::builder(source_ip, ThreatType::SuspiciousConnection, ThreatLevel::Warning)
+                .target_ip(target_ip)
+                .context(context)
+                .network_flow(format!("TLS {} -> {} sni={} ja3={}", source_ip, target_ip, sni, ja3))
+                .hash_of(payload)
+                .build(),
+        )
+    }
+
+    /// Record a report of `handshake`, returning false if it was reported within the repeat window
+    fn first_report(&self, handshake: &str) -> bool {
+        let now = Instant::now();
+        let mut reported = self.reported.lock().unwrap();
+        if reported.get(handshake).is_some_and(|at| now.duration_since(*at) < TLS_REPEAT_WINDOW) {
+            return false;
+        }
+        if reported.len() >= MAX_TLS_REPORTED {
+            reported.retain(|_, at| now.duration_since(*at) < TLS_REPEAT_WINDOW);
+            if reported.len() >= MAX_TLS_REPORTED {
+                let oldest = reported.iter().min_by_key(|(_, at)| **at).map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    reported.remove(&oldest);
+                }
+            }
+        }
+        reported.insert(handshake.to_string(), now);
+        true
+    }
+}
+
+/// Whether `sni` is `name` or one of its subdomains
+fn sni_matches(sni: &str, name: &str) -> bool {
+    sni == name || sni.strip_suffix(name).is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+fn normalize_indicators(mut config: TlsInspectConfig) -> TlsInspectConfig {
+    for name in &mut config.blocked_sni {
+        *name = name.trim().trim_end_matches('.').to_lowercase();
+    }
+    for fingerprint in &mut config.blocked_ja3 {
+        *fingerprint = fingerprint.trim().to_lowercase();
     }
+    config
 }
 
 /// Geographic fence monitor
+#[derive(Debug, Clone)]
 pub struct GeoFenceMonitor {
     enabled: bool,
-    blocked_regions: Vec<String>,
-    suspicious_asns: Vec<u32>,
+    rules: Arc<RwLock<GeoFenceConfig>>, // Shared with clones so runtime updates reach the monitoring loop
+    #[cfg(feature = "geoip")]
+    database: Option<Arc<maxminddb::Reader<Vec<u8>>>>, // Resolves IPs to country and ASN
+}
+
+/// Fields read from a GeoIP2 Country, City or ASN database record
+#[cfg(feature = "geoip")]
+#[derive(Deserialize)]
+struct GeoRecord<'a> {
+    #[serde(borrow)]
+    country: Option<maxminddb::geoip2::country::Country<'a>>,
+    autonomous_system_number: Option<u32>,
 }
 
 impl GeoFenceMonitor {
-    pub fn new(enabled: bool) -> Self {
+    pub fn new(enabled: bool, config: GeoFenceConfig) -> Self {
         Self {
             enabled,
-            blocked_regions: vec!["RU".to_string(), "KP".to_string()], // Example blocked regions
-            suspicious_asns: vec![12345, 67890], // Example suspicious ASNs
+            rules: Arc::new(RwLock::new(normalize_rules(config))),
+            #[cfg(feature = "geoip")]
+            database: None,
         }
     }
 
+    /// Replace the blocked regions and suspicious ASNs, including in running clones
+    pub fn update_config(&self, config: GeoFenceConfig) {
+        *self.rules.write().unwrap() = normalize_rules(config);
+    }
+
+    /// Resolve locations from a MaxMind `.mmdb` database
+    ///
+    /// If the database can't be opened, callers must keep supplying country
+    /// and ASN to `check_ip_location`.
+    #[cfg(feature = "geoip")]
+    pub fn with_database(mut self, path: impl AsRef<Path>) -> Self {
+        match maxminddb::Reader::open_readfile(path.as_ref()) {
+            Ok(reader) => self.database = Some(Arc::new(reader)),
+            Err(e) => log::warn!("Failed to open GeoIP database {}: {}", path.as_ref().display(), e),
+        }
+        self
+    }
+
+    /// GeoIP lookups require the geoip feature; locations stay caller-supplied
+    #[cfg(not(feature = "geoip"))]
+    pub fn with_database(self, path: impl AsRef<Path>) -> Self {
+        log::warn!("Ignoring GeoIP database {}: built without the geoip feature", path.as_ref().display());
+        self
+    }
+
+    /// Whether a GeoIP database was opened
+    #[cfg(feature = "geoip")]
+    pub fn has_database(&self) -> bool {
+        self.database.is_some()
+    }
+
+    /// Without the geoip feature no database is ever opened
+    #[cfg(not(feature = "geoip"))]
+    pub fn has_database(&self) -> bool {
+        false
+    }
+
+    /// Country code and ASN of an IP, if the database knows it
+    ///
+    /// The ASN is 0 for databases without ASN data.
+    #[cfg(feature = "geoip")]
+    pub fn lookup(&self, ip: &str) -> Option<(String, u32)> {
+        let ip = ip.parse::<IpAddr>().ok()?;
+        let record: GeoRecord = self.database.as_ref()?.lookup(ip).ok()?;
+        let country = record.country.and_then(|country| country.iso_code).map(str::to_string);
+        if country.is_none() && record.autonomous_system_number.is_none() {
+            return None;
+        }
+        Some((country.unwrap_or_default(), record.autonomous_system_number.unwrap_or(0)))
+    }
+
+    /// Without the geoip feature no IP can be resolved
+    #[cfg(not(feature = "geoip"))]
+    pub fn lookup(&self, _ip: &str) -> Option<(String, u32)> {
+        None
+    }
+
+    /// Check an IP against the fence using the database to locate it
+    pub fn check_ip(&self, ip: &str) -> Option<ThreatEvidence> {
+        let (country, asn) = self.lookup(ip)?;
+        self.check_ip_location(ip, &country, asn)
+    }
+
     pub async fn start_monitoring(&mut self) -> Result<()> {
         if !self.enabled {
             return Ok(());
@@ -115,50 +454,50 @@ impl GeoFenceMonitor {
             return None;
         }
 
-        // Check if IP is from blocked region
-        if self.blocked_regions.contains(&country.to_uppercase()) {
-            return Some(ThreatEvidence {
-                id: uuid::Uuid::new_v4().to_string(),
-                timestamp: chrono::Utc::now().timestamp(),
-                source_ip: ip.to_string(),
-                target_ip: "local".to_string(), // Placeholder
-                threat_type: ThreatType::SuspiciousConnection,
-                threat_level: ThreatLevel::Warning,
-                context: format!("Connection from blocked region: {}", country),
-                evidence_hash: crate::crypto::CryptoProvider::blake3_hash(ip.as_bytes()),
-                geolocation: country.to_string(),
-                network_flow: "".to_string(),
-                agent_id: "agent".to_string(), // Will be set by agent
-                reputation: 1.0, // Will be set by agent
-                compliance_tag: "global".to_string(), // Will be set by agent
-                region: country.to_string(),
-            });
+        let rules = self.rules.read().unwrap();
+        let region = country.to_uppercase();
+
+        // In allowlist mode every known country outside the allowed regions is flagged
+        match rules.mode {
+            GeoFenceMode::Blocklist if rules.blocked_regions.contains(&region) => {
+                return Some(fence_evidence(ip, country, ThreatLevel::Warning,
+                                           format!("Connection from blocked region: {}", country)));
+            }
+            GeoFenceMode::Allowlist
+                if !rules.allowed_regions.is_empty() && !region.is_empty() && !rules.allowed_regions.contains(&region) =>
+            {
+                return Some(fence_evidence(ip, country, ThreatLevel::Warning,
+                                           format!("Connection from outside allowed regions: {}", country)));
+            }
+            _ => {}
         }
 
         // Check if IP is from suspicious ASN
-        if self.suspicious_asns.contains(&asn) {
-            return Some(ThreatEvidence {
-                id: uuid::Uuid::new_v4().to_string(),
-                timestamp: chrono::Utc::now().timestamp(),
-                source_ip: ip.to_string(),
-                target_ip: "local".to_string(), // Placeholder
-                threat_type: ThreatType::SuspiciousConnection,
-                threat_level: ThreatLevel::Info,
-                context: format!("Connection from suspicious ASN: {}", asn),
-                evidence_hash: crate::crypto::CryptoProvider::blake3_hash(ip.as_bytes()),
-                geolocation: country.to_string(),
-                network_flow: "".to_string(),
-                agent_id: "agent".to_string(), // Will be set by agent
-                reputation: 1.0, // Will be set by agent
-                compliance_tag: "global".to_string(), // Will be set by agent
-                region: country.to_string(),
-            });
+        if rules.suspicious_asns.contains(&asn) {
+            return Some(fence_evidence(ip, country, ThreatLevel::Info,
+                                       format!("Connection from suspicious ASN: {}", asn)));
         }
 
         None
     }
 }
 
+/// Suspicious connection evidence raised by the geographic fence
+fn fence_evidence(ip: &str, country: &str, threat_level: ThreatLevel, context: String) -> ThreatEvidence {
+    ThreatEvidence::builder(ip, ThreatType::SuspiciousConnection, threat_level)
+        .context(context)
+        .location(country)
+        .build()
+}
+
+/// Country codes are compared in upper case
+fn normalize_rules(mut config: GeoFenceConfig) -> GeoFenceConfig {
+    for region in config.blocked_regions.iter_mut().chain(config.allowed_regions.iter_mut()) {
+        *region = region.trim().to_uppercase();
+    }
+    config
+}
+
 /// Main monitor coordinator
 pub struct AgentMonitor {
     pub netflow: NetflowMonitor,
@@ -174,35 +513,76 @@ impl AgentMonitor {
         syscall_enabled: bool,
         tls_inspect_enabled: bool,
         geo_fence_enabled: bool,
+        netflow_interface: Option<String>,
+        geo_fence_config: GeoFenceConfig,
         threat_queue: tokio::sync::mpsc::UnboundedSender<ThreatEvidence>,
     ) -> Self {
+        let tls_inspector = TlsInspector::new(tls_inspect_enabled, TlsInspectConfig::default());
         Self {
-            netflow: NetflowMonitor::new(netflow_enabled),
+            netflow: NetflowMonitor::new(netflow_enabled)
+                .with_interface(netflow_interface)
+                .with_tls_inspector(tls_inspect_enabled.then(|| tls_inspector.clone())),
             syscall: SyscallMonitor::new(syscall_enabled),
-            tls_inspector: TlsInspector::new(tls_inspect_enabled),
-            geo_fence: GeoFenceMonitor::new(geo_fence_enabled),
+            tls_inspector,
+            geo_fence: GeoFenceMonitor::new(geo_fence_enabled, geo_fence_config),
             threat_queue,
         }
     }
 
+    /// Report sources sending more than `syn_rate` SYNs per second in captured traffic
+    pub fn with_ddos_threshold(mut self, syn_rate: f64) -> Self {
+        self.netflow = self.netflow.with_ddos_threshold(syn_rate);
+        self
+    }
+
+    /// Locate IPs checked by the geographic fence with a MaxMind `.mmdb` database
+    pub fn with_geoip_database(mut self, path: impl AsRef<Path>) -> Self {
+        self.geo_fence = self.geo_fence.with_database(path);
+        self
+    }
+
+    /// Scan captured payloads with the YARA rules of a file or directory
+    #[cfg(feature = "yara")]
+    pub fn with_yara_rules(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        self.netflow = self.netflow.with_yara_matcher(Arc::new(YaraMatcher::from_path(path)?));
+        Ok(self)
+    }
+
+    /// Payload scanning requires the yara feature; the rules are ignored without it
+    #[cfg(not(feature = "yara"))]
+    pub fn with_yara_rules(self, path: impl AsRef<Path>) -> Result<Self> {
+        log::warn!("Ignoring YARA rules {}: built without the yara feature", path.as_ref().display());
+        Ok(self)
+    }
+
+    /// Flag TLS connections matching these indicators
+    pub fn with_tls_indicators(self, config: TlsInspectConfig) -> Self {
+        self.tls_inspector.update_config(config); // Shared with the netflow monitor's inspector
+        self
+    }
+
     /// Get a clone of the threat queue sender
     pub fn get_threat_sender(&self) -> tokio::sync::mpsc::UnboundedSender<ThreatEvidence> {
         self.threat_queue.clone()
     }
 
-    pub async fn start_monitoring(&mut self) -> Result<()> {
+    /// Start all enabled monitors, returning the monitoring loop's handle
+    ///
+    /// The loop exits once `true` is sent on the shutdown channel.
+    pub async fn start_monitoring(&mut self, shutdown: watch::Receiver<bool>) -> Result<JoinHandle<()>> {
         log::info!("Starting agent monitoring modules...");
 
         // Start all enabled monitors
-        if self.netflow.enabled {
-            self.netflow.start_monitoring().await?;
-        }
+        let capture = self.netflow.start_monitoring(self.threat_queue.clone(), shutdown.clone()).await?;
 
         if self.syscall.enabled {
             self.syscall.start_monitoring().await?;
         }
 
         if self.tls_inspector.enabled {
+            if !self.netflow.enabled {
+                log::warn!("TLS inspection sees no traffic unless netflow monitoring is enabled");
+            }
             self.tls_inspector.start_monitoring().await?;
         }
 
@@ -211,10 +591,11 @@ impl AgentMonitor {
         }
 
         // Start monitoring loop
-        self.start_monitoring_loop().await
+        Ok(self.start_monitoring_loop(shutdown, capture))
     }
 
-    async fn start_monitoring_loop(&self) -> Result<()> {
+    /// Run the monitoring loop, which waits for the packet capture to stop before exiting
+    fn start_monitoring_loop(&self, mut shutdown: watch::Receiver<bool>, capture: Option<JoinHandle<()>>) -> JoinHandle<()> {
         // In a real implementation, this would continuously monitor
         // For now, we'll just run a simple loop
         let netflow_monitor = self.netflow.clone();
@@ -226,7 +607,10 @@ impl AgentMonitor {
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(5)); // Check every 5 seconds
             loop {
-                interval.tick().await;
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = shutdown.wait_for(|stopping| *stopping) => break,
+                }
 
                 // Simulate monitoring activities
                 // In real implementation, this would check actual system state
@@ -244,8 +628,225 @@ impl AgentMonitor {
                     }
                 }
             }
+
+            if let Some(capture) = capture {
+                if let Err(e) = capture.await {
+                    log::warn!("Network flow capture task failed: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "geoip")]
+    const TEST_DATABASE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/geoip-test.mmdb");
+
+    #[cfg(feature = "geoip")]
+    #[test]
+    fn test_database_resolves_country_and_asn() {
+        let monitor = GeoFenceMonitor::new(true, GeoFenceConfig::default()).with_database(TEST_DATABASE);
+
+        assert_eq!(monitor.lookup("81.2.69.142"), Some(("GB".to_string(), 20712)));
+        assert_eq!(monitor.lookup("175.16.199.1"), Some(("CN".to_string(), 4837)));
+        assert_eq!(monitor.lookup("192.0.2.1"), None);
+        assert_eq!(monitor.lookup("not-an-ip"), None);
+    }
+
+    #[test]
+    fn test_missing_database_falls_back_to_caller_location() {
+        let config = GeoFenceConfig { blocked_regions: vec!["GB".to_string()], ..GeoFenceConfig::default() };
+        let monitor = GeoFenceMonitor::new(true, config).with_database("/nonexistent/GeoLite2-Country.mmdb");
+
+        assert_eq!(monitor.lookup("81.2.69.142"), None);
+        assert!(monitor.check_ip("81.2.69.142").is_none());
+        let threat = monitor.check_ip_location("81.2.69.142", "gb", 0).unwrap();
+        assert_eq!(threat.geolocation, "gb");
+    }
+
+    #[test]
+    fn test_fence_rules_come_from_config() {
+        let mut config = crate::AgentConfig::default();
+        assert!(GeoFenceMonitor::new(true, config.geo_fence.clone()).check_ip_location("192.0.2.1", "RU", 12345).is_none());
+
+        config.geo_fence = GeoFenceConfig {
+            blocked_regions: vec!["xx".to_string()],
+            suspicious_asns: vec![64512],
+            ..GeoFenceConfig::default()
+        };
+        let monitor = GeoFenceMonitor::new(true, config.geo_fence);
+        let blocked = monitor.check_ip_location("192.0.2.1", "XX", 1).unwrap();
+        assert_eq!(blocked.threat_level, ThreatLevel::Warning);
+        let suspicious = monitor.check_ip_location("192.0.2.2", "YY", 64512).unwrap();
+        assert_eq!(suspicious.threat_level, ThreatLevel::Info);
+        assert!(monitor.check_ip_location("192.0.2.3", "YY", 1).is_none());
+
+        // Runtime updates also apply to clones held by the monitoring loop
+        let running = monitor.clone();
+        monitor.update_config(GeoFenceConfig { blocked_regions: vec!["YY".to_string()], ..GeoFenceConfig::default() });
+        assert!(running.check_ip_location("192.0.2.1", "XX", 1).is_none());
+        assert!(running.check_ip_location("192.0.2.3", "YY", 1).is_some());
+    }
+
+    #[test]
+    fn test_allowlist_mode_flags_other_regions() {
+        let monitor = GeoFenceMonitor::new(true, GeoFenceConfig {
+            mode: GeoFenceMode::Allowlist,
+            allowed_regions: vec!["de".to_string()],
+            blocked_regions: vec!["DE".to_string()],
+            ..GeoFenceConfig::default()
         });
 
-        Ok(())
+        // The blocklist is ignored in allowlist mode
+        assert!(monitor.check_ip_location("192.0.2.1", "DE", 1).is_none());
+
+        let flagged = monitor.check_ip_location("192.0.2.2", "FR", 1).unwrap();
+        assert_eq!(flagged.threat_type, ThreatType::SuspiciousConnection);
+        assert!(flagged.context.contains("outside allowed regions"));
+
+        // An unresolved country is not evidence of being outside
+        assert!(monitor.check_ip_location("192.0.2.3", "", 1).is_none());
+    }
+
+    #[test]
+    fn test_captured_flows_feed_the_detector() {
+        let store = Arc::new(crate::threat_intel::IndicatorStore::new());
+        store.add(crate::threat_intel::Indicator::new(
+            "192.0.2.10", crate::threat_intel::IndicatorType::Ip, ThreatType::Malware, ThreatLevel::Critical, "test",
+        ));
+        let mut detector = ThreatDetector::new().with_indicator_store(store);
+        let flows = crate::flow::flows_from_pcap_file(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/flows.pcap")).unwrap();
+
+        let threats = NetflowMonitor::new(true).detect_threats(&mut detector, &flows);
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].source_ip, "10.0.0.5");
+        assert_eq!(threats[0].target_ip, "192.0.2.10");
+        assert!(threats[0].network_flow.contains("dport=80"));
+    }
+
+    #[test]
+    fn test_syn_flood_across_flows_is_detected() {
+        let flows: Vec<FlowRecord> = (0..20u16)
+            .map(|i| FlowRecord {
+                key: crate::flow::FlowKey {
+                    src_ip: "203.0.113.7".parse().unwrap(),
+                    dst_ip: "192.0.2.10".parse().unwrap(),
+                    src_port: 40000 + i,
+                    dst_port: 80,
+                    protocol: 6,
+                },
+                packets: 10,
+                bytes: 600,
+                syn_packets: 10,
+                first_seen: 1.0,
+                last_seen: 2.0,
+            })
+            .collect();
+
+        // 200 SYNs within the window is 20/sec
+        let monitor = NetflowMonitor::new(true).with_ddos_threshold(15.0);
+        let threats = monitor.detect_threats(&mut ThreatDetector::new().with_ddos_threshold(15.0), &flows);
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].threat_type, ThreatType::DDoS);
+        assert_eq!(threats[0].target_ip, "192.0.2.10");
+
+        let threats = monitor.detect_threats(&mut ThreatDetector::new().with_ddos_threshold(25.0), &flows);
+        assert!(threats.is_empty());
+    }
+
+    /// Ethernet frame carrying a TCP segment from 10.0.0.5:40001 to 192.0.2.10:443
+    fn tcp_frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&[0x08, 0x00]);
+        frame.extend_from_slice(&[0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, 6, 0, 0, 10, 0, 0, 5, 192, 0, 2, 10]);
+        frame.extend_from_slice(&[0x9c, 0x41, 0x01, 0xbb, 0, 0, 0, 1, 0, 0, 0, 1, 0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn test_captured_client_hello_is_inspected() {
+        let hello = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/client_hello.bin"));
+        let frame = tcp_frame(hello);
+        let info = crate::flow::parse_packet(crate::flow::LINKTYPE_ETHERNET, &frame).unwrap();
+
+        let inspector = TlsInspector::new(true, TlsInspectConfig {
+            blocked_sni: vec!["Evil.Example.".to_string()],
+            blocked_ja3: Vec::new(),
+        });
+        let monitor = NetflowMonitor::new(true).with_tls_inspector(Some(inspector.clone()));
+        let threat = monitor.inspect_packet(&info, &frame).unwrap();
+        assert_eq!(threat.threat_type, ThreatType::SuspiciousConnection);
+        assert_eq!((threat.source_ip.as_str(), threat.target_ip.as_str()), ("10.0.0.5", "192.0.2.10"));
+        assert!(threat.context.contains("server name evil.example"), "{}", threat.context);
+        assert!(threat.context.contains("ja3=93c7d42c0df602fb91589311534831f5"), "{}", threat.context);
+
+        // Updates reach the monitor's inspector; a lookalike domain does not match
+        inspector.update_config(TlsInspectConfig { blocked_sni: vec!["vil.example".to_string()], blocked_ja3: Vec::new() });
+        assert!(monitor.inspect_packet(&info, &frame).is_none());
+
+        inspector.update_config(TlsInspectConfig {
+            blocked_sni: Vec::new(),
+            blocked_ja3: vec!["93C7D42C0DF602FB91589311534831F5".to_string()],
+        });
+        let threat = monitor.inspect_packet(&info, &frame).unwrap();
+        assert!(threat.context.contains("JA3 fingerprint"), "{}", threat.context);
+        assert_eq!(threat.evidence_hash, crate::crypto::CryptoProvider::blake3_hash(&frame[54..]));
+
+        // The same handshake between the same hosts is reported once per window
+        assert!(monitor.inspect_packet(&info, &frame).is_none());
+        assert!(inspector.inspect_tls("10.0.0.6", "192.0.2.10", &frame[54..]).is_some());
+
+        // Other payloads are not handshakes
+        let frame = tcp_frame(b"GET / HTTP/1.1\r\n\r\n");
+        let info = crate::flow::parse_packet(crate::flow::LINKTYPE_ETHERNET, &frame).unwrap();
+        assert!(monitor.inspect_packet(&info, &frame).is_none());
+    }
+
+    #[cfg(feature = "yara")]
+    #[test]
+    fn test_captured_payload_is_scanned_with_yara() {
+        let matcher = Arc::new(YaraMatcher::from_source(r#"
+rule test_marker {
+    strings:
+        $marker = "ORASRS-TEST"
+    condition:
+        $marker
+}
+"#).unwrap());
+        let monitor = NetflowMonitor::new(true).with_yara_matcher(matcher.clone());
+        let mut detector = ThreatDetector::new().with_yara_matcher(matcher);
+
+        let frame = tcp_frame(b"GET /ORASRS-TEST HTTP/1.1");
+        let info = crate::flow::parse_packet(crate::flow::LINKTYPE_ETHERNET, &frame).unwrap();
+        let threats = monitor.scan_payload(&mut detector, &info, &frame);
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].threat_type, ThreatType::Malware);
+        assert_eq!((threats[0].source_ip.as_str(), threats[0].target_ip.as_str()), ("10.0.0.5", "192.0.2.10"));
+        assert!(threats[0].context.contains("test_marker"));
+
+        let frame = tcp_frame(b"GET / HTTP/1.1");
+        let info = crate::flow::parse_packet(crate::flow::LINKTYPE_ETHERNET, &frame).unwrap();
+        assert!(monitor.scan_payload(&mut detector, &info, &frame).is_empty());
+
+        // A missing rules path fails agent startup rather than scanning nothing
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let agent_monitor = AgentMonitor::new(true, false, false, false, None, GeoFenceConfig::default(), sender);
+        assert!(agent_monitor.with_yara_rules("/nonexistent/yara").is_err());
     }
-}
\ No newline at end of file
+
+    #[cfg(not(feature = "yara"))]
+    #[test]
+    fn test_yara_rules_are_ignored_without_the_feature() {
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let monitor = AgentMonitor::new(true, false, false, false, None, GeoFenceConfig::default(), sender);
+        let monitor = monitor.with_yara_rules("/nonexistent/yara").unwrap();
+
+        let frame = tcp_frame(b"GET /ORASRS-TEST HTTP/1.1");
+        let info = crate::flow::parse_packet(crate::flow::LINKTYPE_ETHERNET, &frame).unwrap();
+        assert!(monitor.netflow.scan_payload(&mut ThreatDetector::new(), &info, &frame).is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::{ThreatEvidence, clock::{Clock, SystemClock}, error::{AgentError, Result}, storage::Storage};
+use libp2p::identity;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Number of sealed batches kept in memory for inclusion proofs
+const MAX_SEALED_BATCHES: usize = 64;
+
+/// Storage key of the notary's chain and sealed batches
+const STATE_KEY: &str = "notary/state";
+
+/// Domain separation prefixes so a leaf can never be passed off as an inner node
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+type Hash = [u8; 32];
+
+/// Signed Merkle root over a batch of hash-chained evidence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotarizedBatch {
+    pub sequence: u64,
+    pub root: Hash,
+    pub previous_root: Hash, // Root of the previous batch, all zeros for the first
+    pub leaf_count: usize,
+    pub signed_at: i64,
+    pub signer: Vec<u8>, // protobuf-encoded public key
+    pub signature: Vec<u8>,
+}
+
+impl NotarizedBatch {
+    /// Bytes covered by the signature
+    fn signing_payload(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&(
+            self.sequence,
+            &self.root,
+            &self.previous_root,
+            self.leaf_count,
+            self.signed_at,
+            &self.signer,
+        ))?)
+    }
+
+    /// Check that the batch was signed by the key it names
+    pub fn verify_signature(&self) -> Result<()> {
+        let public_key = identity::PublicKey::try_decode_protobuf(&self.signer)
+            .map_err(|e| AgentError::CryptoError(format!("Invalid notary key: {}", e)))?;
+
+        if !public_key.verify(&self.signing_payload()?, &self.signature) {
+            return Err(AgentError::CryptoError("Invalid batch signature".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Proof that an evidence is included in a notarized batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub sequence: u64,
+    pub index: usize,
+    pub chain_previous: Hash, // Chain hash of the evidence recorded before this one
+    pub siblings: Vec<(Hash, bool)>, // Sibling hash and whether it sits on the left
+}
+
+/// Verify that an evidence is included in a signed batch
+pub fn verify_inclusion(evidence: &ThreatEvidence, proof: &InclusionProof, batch: &NotarizedBatch) -> Result<()> {
+    batch.verify_signature()?;
+
+    if proof.sequence != batch.sequence || proof.index >= batch.leaf_count {
+        return Err(AgentError::CryptoError("Proof does not belong to this batch".to_string()));
+    }
+
+    let mut hash = leaf_hash(&chain_hash(&proof.chain_previous, evidence)?);
+    for (sibling, sibling_on_left) in &proof.siblings {
+        hash = if *sibling_on_left {
+            node_hash(sibling, &hash)
+        } else {
+            node_hash(&hash, sibling)
+        };
+    }
+
+    if hash != batch.root {
+        return Err(AgentError::CryptoError("Inclusion proof does not match batch root".to_string()));
+    }
+    Ok(())
+}
+
+/// A sealed batch with the data needed to build inclusion proofs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SealedBatch {
+    batch: NotarizedBatch,
+    evidence_ids: Vec<String>,
+    chain_previous: Vec<Hash>,
+    leaves: Vec<Hash>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct NotaryState {
+    sequence: u64,
+    chain_head: Hash,
+    previous_root: Hash,
+    pending_ids: Vec<String>,
+    pending_previous: Vec<Hash>,
+    pending_leaves: Vec<Hash>,
+    sealed: VecDeque<SealedBatch>,
+}
+
+/// Hash-chains published evidence and periodically signs a Merkle root over each batch
+///
+/// Every evidence is chained to the one recorded before it, so reordering or
+/// dropping evidence changes every later root.
+pub struct Notary {
+    keypair: identity::Keypair,
+    batch_size: usize,
+    clock: Arc<dyn Clock>,
+    state: Mutex<NotaryState>,
+    storage: Option<Arc<dyn Storage>>,
+    persist_lock: tokio::sync::Mutex<()>, // Keeps an older state from overwriting a newer one
+}
+
+impl Notary {
+    pub fn new(keypair: identity::Keypair, batch_size: usize) -> Self {
+        Self {
+            keypair,
+            batch_size: batch_size.max(1),
+            clock: Arc::new(SystemClock),
+            state: Mutex::new(NotaryState::default()),
+            storage: None,
+            persist_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Keep the chain and sealed batches in a storage backend so they survive restarts
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Continue the chain saved by `persist`, returning false if there was none
+    pub async fn restore(&self) -> Result<bool> {
+        let Some(storage) = &self.storage else {
+            return Ok(false);
+        };
+        let Some(data) = storage.get(STATE_KEY).await? else {
+            return Ok(false);
+        };
+        *self.state.lock().unwrap() = serde_json::from_slice(&data)?;
+        Ok(true)
+    }
+
+    /// Save the chain, pending evidence and sealed batches to the storage backend
+    pub async fn persist(&self) -> Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        let _guard = self.persist_lock.lock().await;
+        let data = serde_json::to_vec(&*self.state.lock().unwrap())?;
+        storage.put(STATE_KEY, data).await
+    }
+
+    /// Use a custom clock for signing timestamps
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Add evidence to the current batch, sealing it once full
+    pub fn record(&self, evidence: &ThreatEvidence) -> Result<Option<NotarizedBatch>> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let chained = chain_hash(&state.chain_head, evidence)?;
+            let previous = std::mem::replace(&mut state.chain_head, chained);
+            state.pending_ids.push(evidence.id.clone());
+            state.pending_previous.push(previous);
+            state.pending_leaves.push(leaf_hash(&chained));
+
+            if state.pending_leaves.len() < self.batch_size {
+                return Ok(None);
+            }
+        }
+        self.seal()
+    }
+
+    /// Sign the root of the pending evidence, if there is any
+    pub fn seal(&self) -> Result<Option<NotarizedBatch>> {
+        let mut state = self.state.lock().unwrap();
+        if state.pending_leaves.is_empty() {
+            return Ok(None);
+        }
+
+        let leaves = std::mem::take(&mut state.pending_leaves);
+        let mut batch = NotarizedBatch {
+            sequence: state.sequence,
+            root: merkle_root(&leaves),
+            previous_root: state.previous_root,
+            leaf_count: leaves.len(),
+            signed_at: self.clock.now(),
+            signer: self.keypair.public().encode_protobuf(),
+            signature: Vec::new(),
+        };
+        batch.signature = self.keypair
+            .sign(&batch.signing_payload()?)
+            .map_err(|e| AgentError::CryptoError(format!("Failed to sign batch: {}", e)))?;
+
+        state.sequence += 1;
+        state.previous_root = batch.root;
+        let sealed = SealedBatch {
+            batch: batch.clone(),
+            evidence_ids: std::mem::take(&mut state.pending_ids),
+            chain_previous: std::mem::take(&mut state.pending_previous),
+            leaves,
+        };
+        state.sealed.push_back(sealed);
+        while state.sealed.len() > MAX_SEALED_BATCHES {
+            state.sealed.pop_front();
+        }
+
+        log::info!("Notarized batch {} of {} evidence", batch.sequence, batch.leaf_count);
+        Ok(Some(batch))
+    }
+
+    /// Signed batches still held in memory, oldest first
+    pub fn batches(&self) -> Vec<NotarizedBatch> {
+        self.state.lock().unwrap().sealed.iter().map(|sealed| sealed.batch.clone()).collect()
+    }
+
+    /// Build an inclusion proof for sealed evidence, with the batch it belongs to
+    pub fn prove(&self, evidence_id: &str) -> Option<(InclusionProof, NotarizedBatch)> {
+        let state = self.state.lock().unwrap();
+        state.sealed.iter().rev().find_map(|sealed| {
+            let index = sealed.evidence_ids.iter().position(|id| id == evidence_id)?;
+            let proof = InclusionProof {
+                sequence: sealed.batch.sequence,
+                index,
+                chain_previous: sealed.chain_previous[index],
+                siblings: merkle_path(&sealed.leaves, index),
+            };
+            Some((proof, sealed.batch.clone()))
+        })
+    }
+}
+
+/// Chain an evidence to the hash of the one recorded before it
+fn chain_hash(previous: &Hash, evidence: &ThreatEvidence) -> Result<Hash> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(previous);
+    hasher.update(&serde_json::to_vec(evidence)?);
+    Ok(*hasher.finalize().as_bytes())
+}
+
+fn leaf_hash(chained: &Hash) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(chained);
+    *hasher.finalize().as_bytes()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
+
+/// Hash one tree level into the next; an unpaired last node is carried up as-is
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+fn merkle_root(leaves: &[Hash]) -> Hash {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Sibling hashes from a leaf up to the root
+fn merkle_path(leaves: &[Hash], mut index: usize) -> Vec<(Hash, bool)> {
+    let mut path = Vec::new();
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            path.push((level[sibling], sibling < index));
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_evidence;
+
+    fn create_test_evidence(index: usize) -> ThreatEvidence {
+        ThreatEvidence { id: format!("test-{}", index), ..test_evidence(&format!("203.0.113.{}", index)) }
+    }
+
+    #[test]
+    fn test_inclusion_proof_verifies() {
+        let notary = Notary::new(identity::Keypair::generate_ed25519(), 5);
+
+        let mut sealed = None;
+        for index in 0..5 {
+            sealed = notary.record(&create_test_evidence(index)).unwrap();
+        }
+        let batch = sealed.expect("batch seals once full");
+        assert_eq!(batch.leaf_count, 5);
+
+        // Every evidence, including the unpaired last leaf, is provably included
+        for index in 0..5 {
+            let (proof, proof_batch) = notary.prove(&format!("test-{}", index)).unwrap();
+            assert_eq!(proof_batch, batch);
+            verify_inclusion(&create_test_evidence(index), &proof, &batch).unwrap();
+        }
+
+        // Evidence outside the batch does not verify
+        let (proof, _) = notary.prove("test-2").unwrap();
+        assert!(verify_inclusion(&create_test_evidence(3), &proof, &batch).is_err());
+    }
+
+    #[test]
+    fn test_tampered_proof_is_rejected() {
+        let notary = Notary::new(identity::Keypair::generate_ed25519(), 8);
+        for index in 0..4 {
+            notary.record(&create_test_evidence(index)).unwrap();
+        }
+        let batch = notary.seal().unwrap().unwrap();
+        let (proof, _) = notary.prove("test-1").unwrap();
+
+        let mut tampered = proof.clone();
+        tampered.siblings[0].0[0] ^= 0xff;
+        assert!(verify_inclusion(&create_test_evidence(1), &tampered, &batch).is_err());
+
+        let mut reordered = proof.clone();
+        reordered.chain_previous = [0; 32];
+        assert!(verify_inclusion(&create_test_evidence(1), &reordered, &batch).is_err());
+
+        // A forged root fails the signature check
+        let mut forged = batch.clone();
+        forged.root[0] ^= 0xff;
+        assert!(verify_inclusion(&create_test_evidence(1), &proof, &forged).is_err());
+    }
+
+    #[test]
+    fn test_batches_are_chained() {
+        let notary = Notary::new(identity::Keypair::generate_ed25519(), 2);
+        for index in 0..4 {
+            notary.record(&create_test_evidence(index)).unwrap();
+        }
+
+        let batches = notary.batches();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].previous_root, [0; 32]);
+        assert_eq!(batches[1].previous_root, batches[0].root);
+        assert_eq!(notary.seal().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_chain_continues_after_restore() {
+        let storage: Arc<dyn Storage> = Arc::new(crate::storage::MemoryStorage::new());
+        let keypair = identity::Keypair::generate_ed25519();
+        let notary = Notary::new(keypair.clone(), 2).with_storage(storage.clone());
+        for index in 0..3 {
+            notary.record(&create_test_evidence(index)).unwrap();
+        }
+        notary.persist().await.unwrap();
+
+        let restored = Notary::new(keypair, 2).with_storage(storage);
+        assert!(restored.restore().await.unwrap());
+        let (proof, first) = restored.prove("test-1").unwrap();
+        verify_inclusion(&create_test_evidence(1), &proof, &first).unwrap();
+
+        // The evidence pending before the restart seals into the next batch of the same chain
+        let second = restored.record(&create_test_evidence(3)).unwrap().unwrap();
+        assert_eq!((second.sequence, second.previous_root), (1, first.root));
+        let (proof, _) = restored.prove("test-2").unwrap();
+        verify_inclusion(&create_test_evidence(2), &proof, &second).unwrap();
+
+        assert!(!Notary::new(identity::Keypair::generate_ed25519(), 2).restore().await.unwrap());
+    }
+}
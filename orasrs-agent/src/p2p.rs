@@ -1,26 +1,196 @@
-use crate::{ThreatEvidence, AgentConfig, crypto::CryptoProvider, error::{AgentError, Result}};
+use crate::{ThreatEvidence, AgentConfig, config::P2pConfig, crypto::{CryptoProvider, Signer}, error::{AgentError, Result}};
+use crate::consensus_verification::{VerificationRequest, VerificationResponse};
 use serde::{Deserialize, Serialize};
 use libp2p::{
-    gossipsub, identity, PeerId, StreamProtocol,
+    futures::StreamExt,
+    gossipsub, identify, identity, mdns, noise, tcp, yamux,
+    multiaddr::Protocol,
+    swarm::{dial_opts::{DialOpts, PeerCondition}, SwarmEvent},
+    Multiaddr, PeerId, StreamProtocol, Swarm, SwarmBuilder,
 };
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
 use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Shared check of whether a network is connected, for use outside the agent
+pub type ConnectionProbe = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/// Current version of the evidence wire protocol
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Prefix of the versioned evidence protocol names
+const PROTOCOL_PREFIX: &str = "/orasrs/evidence/";
+
+/// Gossipsub topic carrying signed threat evidence
+///
+/// Versioned with the message encoding: v3 carries snake_case threat types,
+/// which agents on v2 cannot parse.
+pub const THREAT_INTEL_TOPIC: &str = "orasrs/threat-intel/v3";
+
+/// Gossipsub topic carrying consensus verification requests and responses
+///
+/// v2 carries snake_case threat types, which agents on v1 cannot parse.
+pub const VERIFICATION_TOPIC: &str = "orasrs/verification/v2";
+
+/// How long `connect_bootstrap` waits for the first bootstrap connection
+const BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long an idle connection is kept open
+const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Window over which verification requests are counted per peer
+const REQUEST_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Peers whose request counts are tracked at once
+const MAX_RATE_LIMITED_PEERS: usize = 1024;
+
+/// Protocol name advertised for a wire protocol version
+pub fn protocol_name(version: u32) -> StreamProtocol {
+    StreamProtocol::try_from_owned(format!("{}{}", PROTOCOL_PREFIX, version))
+        .expect("protocol name starts with a slash")
+}
+
+/// Wire protocol version named by a protocol, if it is an evidence protocol
+pub fn parse_protocol_version(protocol: &str) -> Option<u32> {
+    protocol.strip_prefix(PROTOCOL_PREFIX)?.parse().ok()
+}
+
+/// P2P network operations used by the agent
+///
+/// Implemented by [`P2pClient`]; embedders and tests can supply their own
+/// implementation through [`crate::AgentBuilder`].
+#[async_trait::async_trait]
+pub trait P2pNetwork: Send + Sync {
+    /// Connect to bootstrap nodes
+    async fn connect_bootstrap(&mut self) -> Result<()>;
+
+    /// Subscribe to the threat intelligence and verification topics
+    fn subscribe_threat_intel(&mut self) -> Result<()>;
+
+    /// Publish threat evidence to the network
+    async fn publish_threat_evidence(&self, evidence: &ThreatEvidence) -> Result<()>;
+
+    /// Tell peers that previously published evidence was a false positive
+    async fn publish_dispute(&self, evidence_id: &str, reason: &str) -> Result<()>;
+
+    /// Whether the client is connected to the network
+    fn is_connected(&self) -> bool;
+
+    /// Leave the network, waiting for its background tasks to finish
+    ///
+    /// The default does nothing.
+    async fn disconnect(&mut self) {}
+
+    /// Take the receiver of verified evidence published by peers
+    ///
+    /// Returns None if it was already taken or the network delivers nothing.
+    fn take_incoming_evidence(&mut self) -> Option<mpsc::UnboundedReceiver<ThreatEvidence>> {
+        None
+    }
+
+    /// Take the channel exchanging verification requests and responses with peers
+    ///
+    /// Returns None if it was already taken or the network carries no verifications.
+    fn take_verification_channel(&mut self) -> Option<VerificationChannel> {
+        None
+    }
+
+    /// Probe reporting [`P2pNetwork::is_connected`] from other tasks
+    ///
+    /// The default reports the state at the time of the call.
+    fn connection_probe(&self) -> ConnectionProbe {
+        let connected = self.is_connected();
+        Arc::new(move || connected)
+    }
+
+    /// Get network status
+    fn get_network_status(&self) -> NetworkStatus;
+}
+
+mod behaviour {
+    use libp2p::{gossipsub, identify, mdns, swarm::{behaviour::toggle::Toggle, NetworkBehaviour}};
+
+    /// Network behaviour driven by the swarm event loop
+    #[derive(NetworkBehaviour)]
+    pub(super) struct AgentBehaviour {
+        pub gossipsub: gossipsub::Behaviour,
+        pub identify: identify::Behaviour, // Carries the version handshake
+        pub mdns: Toggle<mdns::tokio::Behaviour>, // Only when `mdns_enabled` is set
+    }
+}
+
+use behaviour::{AgentBehaviour, AgentBehaviourEvent};
+
+/// Swarm state published by the event loop
+#[derive(Debug, Clone, Default)]
+struct SwarmState {
+    connections: usize,
+    failed_dials: usize,
+    listen_addrs: Vec<Multiaddr>,
+}
+
+/// Request handled by the swarm event loop
+enum SwarmCommand {
+    Subscribe(gossipsub::IdentTopic),
+    Publish {
+        topic: gossipsub::IdentTopic,
+        data: Vec<u8>,
+        reply: oneshot::Sender<Result<()>>,
+    },
+}
+
+/// Consensus verification traffic on [`VERIFICATION_TOPIC`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VerificationMessage {
+    Request(Box<VerificationRequest>), // Boxed as it carries the evidence
+    Response(VerificationResponse),
+}
+
+/// Verification messages received from peers, and a sender publishing ours
+///
+/// Messages sent before the swarm starts are published once it does.
+pub struct VerificationChannel {
+    pub incoming: mpsc::UnboundedReceiver<VerificationMessage>,
+    pub outgoing: mpsc::UnboundedSender<VerificationMessage>,
+}
 
 /// P2P network client for OraSRS Agent
 pub struct P2pClient {
     pub peer_id: PeerId,
-    _local_key: identity::Keypair,
-    _gossipsub: gossipsub::Behaviour,
+    local_key: identity::Keypair,
+    gossipsub: Option<gossipsub::Behaviour>, // moved into the swarm on connect
     config: AgentConfig,
-    pub connected: bool,
+    swarm_state: watch::Receiver<SwarmState>,
+    state_sender: Option<watch::Sender<SwarmState>>, // moved into the swarm on connect
+    commands: Option<mpsc::UnboundedSender<SwarmCommand>>,
+    swarm_task: Option<JoinHandle<()>>, // Runs the swarm until `commands` is dropped
+    next_nonce: Arc<AtomicU64>, // Shared with the swarm, which signs verification messages
+    replay_cache: Arc<Mutex<ReplayCache>>,
+    peer_versions: Arc<PeerVersions>, // Shared with the swarm, which negotiates with each peer
+    incoming_sender: Option<mpsc::UnboundedSender<ThreatEvidence>>, // moved into the swarm on connect
+    incoming: Option<mpsc::UnboundedReceiver<ThreatEvidence>>,
+    verification_sender: Option<mpsc::UnboundedSender<VerificationMessage>>, // moved into the swarm on connect
+    verifications: Option<mpsc::UnboundedReceiver<VerificationMessage>>,
+    outgoing_verifications: mpsc::UnboundedSender<VerificationMessage>,
+    outgoing_receiver: Option<mpsc::UnboundedReceiver<VerificationMessage>>, // moved into the swarm on connect
+    evidence_scheme: Option<Arc<dyn Signer>>, // Set when peer evidence must carry an emitter signature
 }
 
 impl P2pClient {
     pub fn new(config: AgentConfig) -> Result<Self> {
         // Create a random key for ourselves
-        let local_key = identity::Keypair::generate_ed25519();
+        Self::with_keypair(config, identity::Keypair::generate_ed25519())
+    }
+
+    /// Create a client using an existing identity key
+    pub fn with_keypair(config: AgentConfig, local_key: identity::Keypair) -> Result<Self> {
         let peer_id = PeerId::from(local_key.public());
 
         // Set up gossipsub
@@ -38,70 +208,335 @@ impl P2pClient {
 
         // build a gossipsub network behaviour
         let gossipsub = gossipsub::Behaviour::new(
-            local_key.clone(),
+            gossipsub::MessageAuthenticity::Signed(local_key.clone()),
             gossipsub_config,
         )
         .map_err(|e| AgentError::P2pError(format!("Gossipsub behavior error: {}", e)))?;
 
+        let replay_cache = ReplayCache::new(config.p2p_config.replay_cache_size);
+        let peer_versions = Arc::new(PeerVersions::new(&config.p2p_config));
+        let (incoming_sender, incoming) = mpsc::unbounded_channel();
+        let (verification_sender, verifications) = mpsc::unbounded_channel();
+        let (outgoing_verifications, outgoing_receiver) = mpsc::unbounded_channel();
+        let (state_sender, swarm_state) = watch::channel(SwarmState::default());
+        let evidence_scheme = if config.crypto_config.require_evidence_signatures {
+            Some(Arc::from(CryptoProvider::signer(&config.crypto_config, local_key.clone())?))
+        } else {
+            None
+        };
+
         Ok(Self {
             peer_id,
-            _local_key: local_key,
-            _gossipsub: gossipsub,
+            local_key,
+            gossipsub: Some(gossipsub),
             config,
-            connected: false,
+            swarm_state,
+            state_sender: Some(state_sender),
+            commands: None,
+            swarm_task: None,
+            next_nonce: Arc::new(AtomicU64::new(0)),
+            replay_cache: Arc::new(Mutex::new(replay_cache)),
+            peer_versions,
+            incoming_sender: Some(incoming_sender),
+            incoming: Some(incoming),
+            verification_sender: Some(verification_sender),
+            verifications: Some(verifications),
+            outgoing_verifications,
+            outgoing_receiver: Some(outgoing_receiver),
+            evidence_scheme,
         })
     }
 
-    /// Connect to bootstrap nodes
+    /// Start the swarm and dial the bootstrap nodes
+    ///
+    /// Waits until the first connection is established, every dial has
+    /// failed, or [`BOOTSTRAP_TIMEOUT`] passes; the client only counts as
+    /// connected once a connection is up. Fails with
+    /// [`AgentError::BootstrapFailed`] if bootstrap nodes are configured but
+    /// none was reached by then.
     pub async fn connect_bootstrap(&mut self) -> Result<()> {
+        let (Some(gossipsub), Some(state_sender), Some(outgoing)) =
+            (self.gossipsub.take(), self.state_sender.take(), self.outgoing_receiver.take())
+        else {
+            return Err(AgentError::P2pError("P2P swarm already started".to_string()));
+        };
+        let mut swarm = self.build_swarm(gossipsub)?;
+
+        let listen_ip: IpAddr = self.config.p2p_config.listen_address
+            .parse()
+            .map_err(|e| AgentError::P2pError(format!("Invalid listen address: {}", e)))?;
+        let listen_addr = Multiaddr::from(listen_ip).with(Protocol::Tcp(self.config.p2p_config.listen_port));
+        swarm
+            .listen_on(listen_addr)
+            .map_err(|e| AgentError::P2pError(format!("Failed to listen: {}", e)))?;
+
         log::info!("Connecting to bootstrap nodes...");
-        
-        // In a real implementation, this would connect to actual bootstrap nodes
-        // For now, we'll just simulate the connection
+        let mut dialed = 0;
+        let mut tried = 0;
         for bootstrap_node in &self.config.p2p_config.bootstrap_nodes {
-            log::info!("Connecting to bootstrap node: {}", bootstrap_node);
-            // Actual connection logic would go here
+            let addr = match bootstrap_node.parse::<Multiaddr>() {
+                Ok(addr) => addr,
+                Err(e) => {
+                    log::warn!("Skipping invalid bootstrap node {}: {}", bootstrap_node, e);
+                    continue;
+                }
+            };
+            tried += 1;
+            match swarm.dial(addr) {
+                Ok(()) => {
+                    log::info!("Dialing bootstrap node: {}", bootstrap_node);
+                    dialed += 1;
+                }
+                Err(e) => log::warn!("Failed to dial bootstrap node {}: {}", bootstrap_node, e),
+            }
         }
-        
-        self.connected = true;
-        log::info!("Connected to P2P network with peer ID: {}", self.peer_id);
-        
+
+        let (command_sender, command_receiver) = mpsc::unbounded_channel();
+        let inbox = self.incoming_sender.take().map(|sender| EvidenceInbox {
+            p2p_config: self.config.p2p_config.clone(),
+            replay_cache: self.replay_cache.clone(),
+            evidence_scheme: self.evidence_scheme.clone(),
+            sender,
+        });
+        let verifications = self.verification_relay(outgoing);
+        self.swarm_task = Some(tokio::spawn(run_swarm(
+            swarm, state_sender, command_receiver, inbox, verifications, self.peer_versions.clone(),
+        )));
+        self.commands = Some(command_sender);
+
+        let mut state = self.swarm_state.clone();
+        let timed_out = tokio::time::timeout(
+            BOOTSTRAP_TIMEOUT,
+            state.wait_for(|s| !s.listen_addrs.is_empty() && (s.connections > 0 || s.failed_dials >= dialed)),
+        )
+        .await
+        .is_err();
+
+        if timed_out {
+            log::warn!("Timed out waiting for bootstrap nodes");
+        }
+        if self.is_connected() {
+            log::info!("Connected to P2P network with peer ID: {}", self.peer_id);
+        } else if tried > 0 {
+            return Err(AgentError::BootstrapFailed { tried });
+        } else {
+            log::info!("No bootstrap nodes configured; waiting for peers to connect to {}", self.peer_id);
+        }
+
         Ok(())
     }
 
-    /// Subscribe to threat intelligence topic
+    /// Relay for the swarm, delivering to the verification channel unless it was never set up
+    fn verification_relay(&mut self, outgoing: mpsc::UnboundedReceiver<VerificationMessage>) -> VerificationRelay {
+        VerificationRelay {
+            local_key: self.local_key.clone(),
+            peer_signed_responses: !CryptoProvider::uses_sm2(&self.config.crypto_config),
+            next_nonce: self.next_nonce.clone(),
+            p2p_config: self.config.p2p_config.clone(),
+            replay_cache: self.replay_cache.clone(),
+            evidence_scheme: self.evidence_scheme.clone(),
+            request_limiter: RequestLimiter::new(self.config.p2p_config.verification_rate_limit),
+            incoming: self.verification_sender.take(),
+            outgoing,
+        }
+    }
+
+    fn build_swarm(&self, gossipsub: gossipsub::Behaviour) -> Result<Swarm<AgentBehaviour>> {
+        let mdns_enabled = self.config.p2p_config.mdns_enabled;
+        let handshake = self.handshake();
+        Ok(SwarmBuilder::with_existing_identity(self.local_key.clone())
+            .with_tokio()
+            .with_tcp(tcp::Config::default(), noise::Config::new, yamux::Config::default)
+            .map_err(|e| AgentError::P2pError(format!("Transport error: {}", e)))?
+            .with_dns()
+            .map_err(|e| AgentError::P2pError(format!("DNS transport error: {}", e)))?
+            .with_behaviour(|key| {
+                let mdns = if mdns_enabled {
+                    Some(mdns::tokio::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?)
+                } else {
+                    None
+                };
+                // Identify's protocol version names the evidence protocols we speak, its agent version our agent ID
+                let identify = identify::Behaviour::new(
+                    identify::Config::new(handshake.protocols.join(" "), key.public())
+                        .with_agent_version(handshake.agent_id),
+                );
+                Ok(AgentBehaviour { gossipsub, identify, mdns: mdns.into() })
+            })
+            .map_err(|e| AgentError::P2pError(format!("Behaviour error: {}", e)))?
+            .with_swarm_config(|c| c.with_idle_connection_timeout(IDLE_CONNECTION_TIMEOUT))
+            .build())
+    }
+
+    /// Stop the swarm, closing its connections and listeners
+    pub async fn disconnect(&mut self) {
+        self.commands = None; // The swarm exits once its command channel closes
+        if let Some(task) = self.swarm_task.take() {
+            if let Err(e) = task.await {
+                log::warn!("P2P swarm task failed: {}", e);
+            }
+        }
+    }
+
+    /// Whether at least one peer connection is open
+    pub fn is_connected(&self) -> bool {
+        self.connection_count() > 0
+    }
+
+    /// Number of open peer connections
+    pub fn connection_count(&self) -> usize {
+        self.swarm_state.borrow().connections
+    }
+
+    /// Addresses the swarm is listening on, once started
+    pub fn listen_addrs(&self) -> Vec<Multiaddr> {
+        self.swarm_state.borrow().listen_addrs.clone()
+    }
+
+    /// Subscribe to the threat intelligence and verification topics
     pub fn subscribe_threat_intel(&mut self) -> Result<()> {
-        // In a real implementation, this would subscribe to a gossipsub topic
-        // For now, we'll just log the subscription
-        log::info!("Subscribed to threat intelligence topic");
+        for name in [THREAT_INTEL_TOPIC, VERIFICATION_TOPIC] {
+            let topic = gossipsub::IdentTopic::new(name);
+
+            // Before the swarm starts the behaviour is still ours to configure
+            if let Some(gossipsub) = self.gossipsub.as_mut() {
+                gossipsub
+                    .subscribe(&topic)
+                    .map_err(|e| AgentError::P2pError(format!("Failed to subscribe to {}: {}", topic, e)))?;
+            } else {
+                self.send_command(SwarmCommand::Subscribe(topic))?;
+            }
+        }
+
+        log::info!("Subscribed to topics {} and {}", THREAT_INTEL_TOPIC, VERIFICATION_TOPIC);
         Ok(())
     }
 
+    fn send_command(&self, command: SwarmCommand) -> Result<()> {
+        self.commands
+            .as_ref()
+            .ok_or_else(|| AgentError::P2pError("P2P swarm not started".to_string()))?
+            .send(command)
+            .map_err(|_| AgentError::P2pError("P2P swarm stopped".to_string()))
+    }
+
     /// Publish threat evidence to the network
     pub async fn publish_threat_evidence(&self, evidence: &ThreatEvidence) -> Result<()> {
-        if !self.connected {
+        if !self.is_connected() {
             return Err(AgentError::P2pError("Not connected to P2P network".to_string()));
         }
 
-        // In a real implementation, this would publish to a gossipsub topic
-        // For now, we'll just log the publication
+        let message = self.sign_evidence(evidence)?;
+
         log::info!("Publishing threat evidence to network: {} - {}", 
                   evidence.threat_type.as_ref(), 
                   evidence.threat_level as u8);
-        
-        println!("Would publish to P2P network: {:?}", evidence);
-        
-        Ok(())
+
+        let (reply, result) = oneshot::channel();
+        self.send_command(SwarmCommand::Publish {
+            topic: gossipsub::IdentTopic::new(THREAT_INTEL_TOPIC),
+            data: serde_json::to_vec(&message)?,
+            reply,
+        })?;
+        result
+            .await
+            .map_err(|_| AgentError::P2pError("P2P swarm stopped".to_string()))?
+    }
+
+    /// Protocols this client speaks, highest version first
+    pub fn supported_protocols(&self) -> Vec<StreamProtocol> {
+        let p2p_config = &self.config.p2p_config;
+        (p2p_config.min_protocol_version..=p2p_config.protocol_version)
+            .rev()
+            .map(protocol_name)
+            .collect()
+    }
+
+    /// Whether this client speaks the given wire protocol version
+    pub fn supports_version(&self, version: u32) -> bool {
+        self.peer_versions.supports(version)
+    }
+
+    /// Handshake sent to a peer when a connection is established
+    pub fn handshake(&self) -> Handshake {
+        Handshake {
+            peer_id: self.peer_id.to_string(),
+            agent_id: self.config.agent_id.clone(),
+            protocols: self.supported_protocols().iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    /// Agree on the highest protocol version both sides speak
+    ///
+    /// The swarm negotiates with every peer once identify delivers its
+    /// handshake; peers without a common version are refused and no evidence
+    /// is exchanged with them.
+    pub fn negotiate(&self, remote: &Handshake) -> Result<u32> {
+        self.peer_versions.negotiate(remote)
+    }
+
+    /// Protocol version negotiated with a peer, if any
+    pub fn negotiated_version(&self, peer_id: &str) -> Option<u32> {
+        self.peer_versions.negotiated(peer_id)
+    }
+
+    /// Version to publish at so that every negotiated peer can read it
+    fn publish_version(&self) -> u32 {
+        self.peer_versions.publish_version()
+    }
+
+    /// Sign evidence for publication with a fresh nonce and the current time
+    pub fn sign_evidence(&self, evidence: &ThreatEvidence) -> Result<SignedEvidence> {
+        let signed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        self.sign_evidence_at(evidence, signed_at)
+    }
+
+    fn sign_evidence_at(&self, evidence: &ThreatEvidence, signed_at: i64) -> Result<SignedEvidence> {
+        let mut message = SignedEvidence {
+            evidence: evidence.clone(),
+            protocol_version: self.publish_version(),
+            nonce: self.next_nonce.fetch_add(1, Ordering::SeqCst),
+            signed_at,
+            signer: self.local_key.public().encode_protobuf(),
+            signature: Vec::new(),
+        };
+
+        message.signature = self.local_key
+            .sign(&message.signing_payload()?)
+            .map_err(|e| AgentError::CryptoError(format!("Signing failed: {}", e)))?;
+
+        Ok(message)
+    }
+
+    /// Verify a received message and reject stale or replayed ones
+    pub fn accept_evidence(&self, message: &SignedEvidence) -> Result<ThreatEvidence> {
+        verify_evidence(message, &self.config.p2p_config, &self.replay_cache, self.evidence_scheme.as_deref())
+    }
+
+    /// Take the receiver of verified evidence published by peers
+    pub fn take_incoming_evidence(&mut self) -> Option<mpsc::UnboundedReceiver<ThreatEvidence>> {
+        self.incoming.take()
+    }
+
+    /// Take the channel exchanging verification requests and responses with peers
+    pub fn take_verification_channel(&mut self) -> Option<VerificationChannel> {
+        Some(VerificationChannel {
+            incoming: self.verifications.take()?,
+            outgoing: self.outgoing_verifications.clone(),
+        })
     }
 
-    /// Request threat verification from peers
-    pub async fn request_verification(&self, evidence_id: &str) -> Result<()> {
-        if !self.connected {
+    /// Tell peers that previously published evidence was a false positive
+    pub async fn publish_dispute(&self, evidence_id: &str, reason: &str) -> Result<()> {
+        if !self.is_connected() {
             return Err(AgentError::P2pError("Not connected to P2P network".to_string()));
         }
 
-        // In a real implementation, this would send a verification request to peers
-        log::info!("Requesting verification for evidence: {}", evidence_id);
+        // In a real implementation, this would publish to a gossipsub topic
+        log::info!("Publishing dispute for evidence {}: {}", evidence_id, reason);
         
         Ok(())
     }
@@ -109,9 +544,9 @@ impl P2pClient {
     /// Get network status
     pub fn get_network_status(&self) -> NetworkStatus {
         NetworkStatus {
-            connected: self.connected,
+            connected: self.is_connected(),
             peer_id: self.peer_id.to_string(),
-            connections: if self.connected { 5 } else { 0 }, // Simulated
+            connections: self.connection_count(),
             reputation: 0.95, // Simulated
             last_seen: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
@@ -121,6 +556,537 @@ impl P2pClient {
     }
 }
 
+#[async_trait::async_trait]
+impl P2pNetwork for P2pClient {
+    async fn connect_bootstrap(&mut self) -> Result<()> {
+        P2pClient::connect_bootstrap(self).await
+    }
+
+    fn subscribe_threat_intel(&mut self) -> Result<()> {
+        P2pClient::subscribe_threat_intel(self)
+    }
+
+    async fn publish_threat_evidence(&self, evidence: &ThreatEvidence) -> Result<()> {
+        P2pClient::publish_threat_evidence(self, evidence).await
+    }
+
+    async fn publish_dispute(&self, evidence_id: &str, reason: &str) -> Result<()> {
+        P2pClient::publish_dispute(self, evidence_id, reason).await
+    }
+
+    fn is_connected(&self) -> bool {
+        P2pClient::is_connected(self)
+    }
+
+    async fn disconnect(&mut self) {
+        P2pClient::disconnect(self).await
+    }
+
+    fn take_incoming_evidence(&mut self) -> Option<mpsc::UnboundedReceiver<ThreatEvidence>> {
+        P2pClient::take_incoming_evidence(self)
+    }
+
+    fn take_verification_channel(&mut self) -> Option<VerificationChannel> {
+        P2pClient::take_verification_channel(self)
+    }
+
+    fn connection_probe(&self) -> ConnectionProbe {
+        let state = self.swarm_state.clone();
+        Arc::new(move || state.borrow().connections > 0)
+    }
+
+    fn get_network_status(&self) -> NetworkStatus {
+        P2pClient::get_network_status(self)
+    }
+}
+
+/// Verify a signed message against the protocol versions, age limit and replay cache
+///
+/// With an `evidence_scheme`, the evidence itself must also be signed by its emitter.
+fn verify_evidence(
+    message: &SignedEvidence,
+    p2p_config: &P2pConfig,
+    replay_cache: &Mutex<ReplayCache>,
+    evidence_scheme: Option<&dyn Signer>,
+) -> Result<ThreatEvidence> {
+    verify_envelope(&message.envelope()?, p2p_config, replay_cache)?;
+    check_peer_evidence(&message.evidence, evidence_scheme, &message.signer)?;
+    Ok(message.evidence.clone())
+}
+
+/// Check an envelope's protocol version, signature, age and nonce, returning the signing peer
+fn verify_envelope(envelope: &Envelope, p2p_config: &P2pConfig, replay_cache: &Mutex<ReplayCache>) -> Result<PeerId> {
+    if !(p2p_config.min_protocol_version..=p2p_config.protocol_version).contains(&envelope.protocol_version) {
+        return Err(AgentError::P2pError(format!(
+            "Unsupported protocol version {}", envelope.protocol_version
+        )));
+    }
+
+    let public_key = identity::PublicKey::try_decode_protobuf(envelope.signer)
+        .map_err(|e| AgentError::P2pError(format!("Invalid signer key: {}", e)))?;
+
+    if !public_key.verify(&envelope.payload, envelope.signature) {
+        return Err(AgentError::P2pError("Invalid message signature".to_string()));
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let max_age = p2p_config.max_message_age as i64;
+    if (now - envelope.signed_at).abs() > max_age {
+        return Err(AgentError::P2pError(format!(
+            "Stale message signed at {}", envelope.signed_at
+        )));
+    }
+
+    let signer = PeerId::from(public_key);
+    if !replay_cache.lock().unwrap().insert(signer.to_string(), envelope.nonce) {
+        return Err(AgentError::P2pError(format!(
+            "Replayed message from {} with nonce {}", signer, envelope.nonce
+        )));
+    }
+    Ok(signer)
+}
+
+/// Validate evidence from a peer and, with an `evidence_scheme`, require its emitter signature
+///
+/// When evidence is signed with agent key pairs, the emitter must also be the
+/// peer whose key, `sender_key`, signed the message carrying it.
+fn check_peer_evidence(evidence: &ThreatEvidence, evidence_scheme: Option<&dyn Signer>, sender_key: &[u8]) -> Result<()> {
+    evidence.validate()?;
+    if let Some(scheme) = evidence_scheme {
+        if !CryptoProvider::has_valid_evidence_signature(evidence, scheme) {
+            return Err(AgentError::P2pError(format!(
+                "Evidence {} has a missing or invalid emitter signature", evidence.id
+            )));
+        }
+        if scheme.scheme() == "ed25519" && evidence.signer_key != Some(CryptoProvider::hex_encode(sender_key)) {
+            return Err(AgentError::P2pError(format!(
+                "Evidence {} was signed by a key other than its sender's", evidence.id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Verifies evidence received on the threat topic and hands it to the agent
+struct EvidenceInbox {
+    p2p_config: P2pConfig,
+    replay_cache: Arc<Mutex<ReplayCache>>,
+    evidence_scheme: Option<Arc<dyn Signer>>,
+    sender: mpsc::UnboundedSender<ThreatEvidence>,
+}
+
+impl EvidenceInbox {
+    fn deliver(&self, data: &[u8]) -> Result<()> {
+        let message: SignedEvidence = serde_json::from_slice(data)?;
+        let evidence = verify_evidence(&message, &self.p2p_config, &self.replay_cache, self.evidence_scheme.as_deref())?;
+        // Nobody listening is not an error; the evidence is simply dropped
+        let _ = self.sender.send(evidence);
+        Ok(())
+    }
+}
+
+/// Carries verification messages between the verification topic and the agent
+///
+/// Messages are signed like evidence and checked against the same replay
+/// cache; requests are also rate limited per peer, since each one makes the
+/// receiver verify and answer. Responses signed with agent key pairs must be
+/// signed by the peer that sent them, so a peer can only vote as itself.
+struct VerificationRelay {
+    local_key: identity::Keypair,
+    peer_signed_responses: bool, // False when responses are signed with configured SM2 keys instead
+    next_nonce: Arc<AtomicU64>,
+    p2p_config: P2pConfig,
+    replay_cache: Arc<Mutex<ReplayCache>>,
+    evidence_scheme: Option<Arc<dyn Signer>>,
+    request_limiter: RequestLimiter,
+    incoming: Option<mpsc::UnboundedSender<VerificationMessage>>,
+    outgoing: mpsc::UnboundedReceiver<VerificationMessage>,
+}
+
+impl VerificationRelay {
+    /// Sign one of our messages for publishing
+    fn seal(&self, message: VerificationMessage) -> Result<Vec<u8>> {
+        let mut signed = SignedVerification {
+            message,
+            protocol_version: self.p2p_config.protocol_version,
+            nonce: self.next_nonce.fetch_add(1, Ordering::SeqCst),
+            signed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64,
+            signer: self.local_key.public().encode_protobuf(),
+            signature: Vec::new(),
+        };
+        signed.signature = self.local_key
+            .sign(&signed.signing_payload()?)
+            .map_err(|e| AgentError::CryptoError(format!("Signing failed: {}", e)))?;
+        Ok(serde_json::to_vec(&signed)?)
+    }
+
+    fn deliver(&mut self, data: &[u8]) -> Result<()> {
+        let signed: SignedVerification = serde_json::from_slice(data)?;
+        let peer = verify_envelope(&signed.envelope()?, &self.p2p_config, &self.replay_cache)?;
+        match &signed.message {
+            VerificationMessage::Request(request) => {
+                if !self.request_limiter.allow(peer, Instant::now()) {
+                    return Err(AgentError::P2pError(format!(
+                        "Peer {} exceeded {} verification requests per minute", peer, self.request_limiter.limit
+                    )));
+                }
+                check_peer_evidence(&request.evidence, self.evidence_scheme.as_deref(), &signed.signer)?;
+            }
+            VerificationMessage::Response(response) => {
+                if self.peer_signed_responses && response.signer != signed.signer {
+                    return Err(AgentError::P2pError(format!(
+                        "Verification response from {} is signed by another key", peer
+                    )));
+                }
+            }
+        }
+        if let Some(sender) = &self.incoming {
+            let _ = sender.send(signed.message);
+        }
+        Ok(())
+    }
+}
+
+/// Counts of verification requests per peer over [`REQUEST_RATE_WINDOW`]
+struct RequestLimiter {
+    limit: u32,
+    windows: HashMap<PeerId, (Instant, u32)>, // Window start and requests seen in it
+}
+
+impl RequestLimiter {
+    fn new(limit: u32) -> Self {
+        Self { limit, windows: HashMap::new() }
+    }
+
+    /// Count a request from `peer`, returning false if it is over the limit
+    ///
+    /// Once [`MAX_RATE_LIMITED_PEERS`] peers are tracked, requests from new
+    /// peers are refused until older windows end.
+    fn allow(&mut self, peer: PeerId, now: Instant) -> bool {
+        if !self.windows.contains_key(&peer) && self.windows.len() >= MAX_RATE_LIMITED_PEERS {
+            self.windows.retain(|_, (start, _)| now.duration_since(*start) < REQUEST_RATE_WINDOW);
+            if self.windows.len() >= MAX_RATE_LIMITED_PEERS {
+                return false;
+            }
+        }
+        let (start, count) = self.windows.entry(peer).or_insert((now, 0));
+        if now.duration_since(*start) >= REQUEST_RATE_WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count <= self.limit
+    }
+}
+
+/// Protocol versions spoken locally and those negotiated with connected peers
+struct PeerVersions {
+    min_version: u32,
+    max_version: u32,
+    negotiated: Mutex<HashMap<String, u32>>,
+}
+
+impl PeerVersions {
+    fn new(p2p_config: &P2pConfig) -> Self {
+        Self {
+            min_version: p2p_config.min_protocol_version,
+            max_version: p2p_config.protocol_version,
+            negotiated: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn supports(&self, version: u32) -> bool {
+        (self.min_version..=self.max_version).contains(&version)
+    }
+
+    /// Record the highest version both sides speak, or refuse the peer
+    fn negotiate(&self, remote: &Handshake) -> Result<u32> {
+        let version = remote.protocols
+            .iter()
+            .filter_map(|p| parse_protocol_version(p))
+            .filter(|v| self.supports(*v))
+            .max();
+
+        match version {
+            Some(version) => {
+                log::info!("Negotiated protocol version {} with peer {}", version, remote.peer_id);
+                self.negotiated.lock().unwrap().insert(remote.peer_id.clone(), version);
+                Ok(version)
+            }
+            None => {
+                log::warn!(
+                    "Refusing peer {} ({}): it offers {:?}, we support versions {} to {}",
+                    remote.peer_id, remote.agent_id, remote.protocols, self.min_version, self.max_version
+                );
+                Err(AgentError::P2pError(format!(
+                    "No common protocol version with peer {}", remote.peer_id
+                )))
+            }
+        }
+    }
+
+    fn negotiated(&self, peer_id: &str) -> Option<u32> {
+        self.negotiated.lock().unwrap().get(peer_id).copied()
+    }
+
+    /// Forget a peer that is no longer connected
+    fn forget(&self, peer_id: &str) {
+        self.negotiated.lock().unwrap().remove(peer_id);
+    }
+
+    /// Lowest negotiated version, which every connected peer can read
+    fn publish_version(&self) -> u32 {
+        self.negotiated.lock().unwrap().values().copied().min().unwrap_or(self.max_version)
+    }
+}
+
+/// Drive the swarm until the client is dropped
+async fn run_swarm(
+    mut swarm: Swarm<AgentBehaviour>,
+    state: watch::Sender<SwarmState>,
+    mut commands: mpsc::UnboundedReceiver<SwarmCommand>,
+    inbox: Option<EvidenceInbox>,
+    mut verifications: VerificationRelay,
+    peer_versions: Arc<PeerVersions>,
+) {
+    let threat_topic = gossipsub::IdentTopic::new(THREAT_INTEL_TOPIC).hash();
+    let verification_topic = gossipsub::IdentTopic::new(VERIFICATION_TOPIC);
+    loop {
+        tokio::select! {
+            Some(message) = verifications.outgoing.recv() => {
+                let published = verifications.seal(message)
+                    .and_then(|data| {
+                        swarm.behaviour_mut()
+                            .gossipsub
+                            .publish(verification_topic.clone(), data)
+                            .map_err(|e| AgentError::P2pError(e.to_string()))
+                    });
+                if let Err(e) = published {
+                    log::debug!("Failed to publish verification message: {}", e);
+                }
+            }
+            command = commands.recv() => match command {
+                Some(SwarmCommand::Subscribe(topic)) => {
+                    if let Err(e) = swarm.behaviour_mut().gossipsub.subscribe(&topic) {
+                        log::error!("Failed to subscribe to {}: {}", topic, e);
+                    }
+                }
+                Some(SwarmCommand::Publish { topic, data, reply }) => {
+                    let result = swarm.behaviour_mut()
+                        .gossipsub
+                        .publish(topic.clone(), data)
+                        .map(|_| ())
+                        .map_err(|e| AgentError::P2pError(format!("Failed to publish to {}: {}", topic, e)));
+                    let _ = reply.send(result);
+                }
+                None => break,
+            },
+            event = swarm.select_next_some() => match event {
+                SwarmEvent::NewListenAddr { address, .. } => {
+                    log::info!("Listening on {}", address);
+                    state.send_modify(|s| s.listen_addrs.push(address));
+                }
+                SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                    log::info!("Connected to peer {} at {}", peer_id, endpoint.get_remote_address());
+                    state.send_modify(|s| s.connections += 1);
+                }
+                SwarmEvent::ConnectionClosed { peer_id, cause, num_established, .. } => {
+                    log::info!("Connection to peer {} closed: {:?}", peer_id, cause);
+                    state.send_modify(|s| s.connections = s.connections.saturating_sub(1));
+                    if num_established == 0 {
+                        peer_versions.forget(&peer_id.to_string());
+                    }
+                }
+                SwarmEvent::Behaviour(AgentBehaviourEvent::Identify(identify::Event::Received { peer_id, info })) => {
+                    let handshake = Handshake::from_identify(peer_id, &info);
+                    if peer_versions.negotiate(&handshake).is_err() {
+                        // Ignore its gossip from now on, even if it reconnects
+                        swarm.behaviour_mut().gossipsub.blacklist_peer(&peer_id);
+                        let _ = swarm.disconnect_peer_id(peer_id);
+                    }
+                }
+                SwarmEvent::Behaviour(AgentBehaviourEvent::Mdns(mdns::Event::Discovered(peers))) => {
+                    for (peer_id, address) in peers {
+                        log::info!("Discovered peer {} at {} via mDNS", peer_id, address);
+                        swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+                        let dial = DialOpts::peer_id(peer_id)
+                            .addresses(vec![address])
+                            .condition(PeerCondition::DisconnectedAndNotDialing)
+                            .build();
+                        if let Err(e) = swarm.dial(dial) {
+                            log::debug!("Not dialing discovered peer {}: {}", peer_id, e);
+                        }
+                    }
+                }
+                SwarmEvent::Behaviour(AgentBehaviourEvent::Mdns(mdns::Event::Expired(peers))) => {
+                    for (peer_id, _) in peers {
+                        swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
+                    }
+                }
+                SwarmEvent::Behaviour(AgentBehaviourEvent::Gossipsub(gossipsub::Event::Message { propagation_source, message, .. })) => {
+                    if message.topic == verification_topic.hash() {
+                        if let Err(e) = verifications.deliver(&message.data) {
+                            log::warn!("Dropping verification message from peer {}: {}", propagation_source, e);
+                        }
+                        continue;
+                    }
+                    if message.topic != threat_topic {
+                        continue;
+                    }
+                    if let Some(inbox) = &inbox {
+                        if let Err(e) = inbox.deliver(&message.data) {
+                            log::warn!("Dropping malformed evidence from peer {}: {}", propagation_source, e);
+                        }
+                    }
+                }
+                SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                    log::warn!("Failed to connect to peer {:?}: {}", peer_id, error);
+                    state.send_modify(|s| s.failed_dials += 1);
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+/// Threat evidence as published on the network, signed by its sender
+///
+/// The nonce and signing timestamp are covered by the signature so receivers
+/// can reject replayed or stale messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedEvidence {
+    pub evidence: ThreatEvidence,
+    #[serde(default = "legacy_protocol_version")]
+    pub protocol_version: u32,
+    pub nonce: u64,
+    pub signed_at: i64,
+    pub signer: Vec<u8>, // protobuf-encoded public key
+    pub signature: Vec<u8>,
+}
+
+impl SignedEvidence {
+    /// Bytes covered by the signature
+    fn signing_payload(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&(&self.evidence, self.protocol_version, self.nonce, self.signed_at, &self.signer))?)
+    }
+
+    fn envelope(&self) -> Result<Envelope<'_>> {
+        Ok(Envelope {
+            protocol_version: self.protocol_version,
+            nonce: self.nonce,
+            signed_at: self.signed_at,
+            signer: &self.signer,
+            signature: &self.signature,
+            payload: self.signing_payload()?,
+        })
+    }
+}
+
+/// Verification message as published on [`VERIFICATION_TOPIC`], signed by its sender
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedVerification {
+    pub message: VerificationMessage,
+    pub protocol_version: u32,
+    pub nonce: u64,
+    pub signed_at: i64,
+    pub signer: Vec<u8>, // protobuf-encoded public key
+    pub signature: Vec<u8>,
+}
+
+impl SignedVerification {
+    /// Bytes covered by the signature
+    fn signing_payload(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&(&self.message, self.protocol_version, self.nonce, self.signed_at, &self.signer))?)
+    }
+
+    fn envelope(&self) -> Result<Envelope<'_>> {
+        Ok(Envelope {
+            protocol_version: self.protocol_version,
+            nonce: self.nonce,
+            signed_at: self.signed_at,
+            signer: &self.signer,
+            signature: &self.signature,
+            payload: self.signing_payload()?,
+        })
+    }
+}
+
+/// Signed fields shared by the messages peers exchange
+struct Envelope<'a> {
+    protocol_version: u32,
+    nonce: u64,
+    signed_at: i64,
+    signer: &'a [u8],
+    signature: &'a [u8],
+    payload: Vec<u8>, // Bytes the signature covers
+}
+
+fn legacy_protocol_version() -> u32 {
+    1
+}
+
+/// Protocol handshake exchanged when peers connect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Handshake {
+    pub peer_id: String,
+    pub agent_id: String,
+    pub protocols: Vec<String>, // e.g. "/orasrs/evidence/1", highest first
+}
+
+impl Handshake {
+    /// Handshake a peer sent as its identify info
+    fn from_identify(peer_id: PeerId, info: &identify::Info) -> Self {
+        Self {
+            peer_id: peer_id.to_string(),
+            agent_id: info.agent_version.clone(),
+            protocols: info.protocol_version.split_whitespace().map(str::to_string).collect(),
+        }
+    }
+}
+
+/// Bounded set of recently seen (signer, nonce) pairs
+struct ReplayCache {
+    capacity: usize,
+    seen: HashSet<(String, u64)>,
+    order: VecDeque<(String, u64)>,
+}
+
+impl ReplayCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Record a nonce, returning false if it was already seen
+    fn insert(&mut self, signer: String, nonce: u64) -> bool {
+        let key = (signer, nonce);
+        if self.seen.contains(&key) {
+            return false;
+        }
+
+        self.seen.insert(key.clone());
+        self.order.push_back(key);
+
+        // Evict the oldest entries once over capacity
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+
+        true
+    }
+}
+
 /// Network status structure
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NetworkStatus {
@@ -131,24 +1097,417 @@ pub struct NetworkStatus {
     pub last_seen: i64,
 }
 
-/// Threat verification request
-#[derive(Debug, Serialize, Deserialize)]
-pub struct VerificationRequest {
-    pub request_id: String,
-    pub evidence_id: String,
-    pub requesting_agent: String,
-    pub timestamp: i64,
-    pub verification_threshold: u8, // Number of confirmations needed
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Ed25519Signer;
+    use crate::{ThreatLevel, test_evidence};
 
-/// Verification response
-#[derive(Debug, Serialize, Deserialize)]
-pub struct VerificationResponse {
-    pub request_id: String,
-    pub evidence_id: String,
-    pub verifying_agent: String,
-    pub verdict: bool, // true for confirmed, false for disputed
-    pub confidence: f64,
-    pub timestamp: i64,
-    pub signature: String, // cryptographic signature
-}
\ No newline at end of file
+    #[test]
+    fn test_replayed_message_is_rejected() {
+        let sender = P2pClient::new(AgentConfig::default()).unwrap();
+        let receiver = P2pClient::new(AgentConfig::default()).unwrap();
+
+        let message = sender.sign_evidence(&test_evidence("203.0.113.5")).unwrap();
+        assert_eq!(receiver.accept_evidence(&message).unwrap().id, "test-203.0.113.5");
+        assert!(receiver.accept_evidence(&message).is_err());
+
+        // A new message from the same sender gets a fresh nonce
+        let next = sender.sign_evidence(&test_evidence("203.0.113.5")).unwrap();
+        assert!(next.nonce > message.nonce);
+        assert!(receiver.accept_evidence(&next).is_ok());
+    }
+
+    #[test]
+    fn test_stale_or_tampered_message_is_rejected() {
+        let sender = P2pClient::new(AgentConfig::default()).unwrap();
+        let receiver = P2pClient::new(AgentConfig::default()).unwrap();
+
+        let stale = sender.sign_evidence_at(&test_evidence("203.0.113.5"), 1_000).unwrap();
+        assert!(receiver.accept_evidence(&stale).is_err());
+
+        // Rewriting the timestamp invalidates the signature
+        let mut tampered = sender.sign_evidence(&test_evidence("203.0.113.5")).unwrap();
+        tampered.signed_at += 1;
+        assert!(receiver.accept_evidence(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_enforced_evidence_signatures() {
+        let sender = P2pClient::new(AgentConfig::default()).unwrap();
+        let mut config = AgentConfig::default();
+        config.crypto_config.require_evidence_signatures = true;
+        let receiver = P2pClient::new(config).unwrap();
+
+        // Unsigned evidence is refused even though the message itself is signed
+        let unsigned = sender.sign_evidence(&test_evidence("203.0.113.5")).unwrap();
+        assert!(receiver.accept_evidence(&unsigned).is_err());
+
+        // Evidence signed by another agent's key is refused even when correctly signed
+        let mut evidence = test_evidence("203.0.113.5");
+        CryptoProvider::sign_evidence(&mut evidence, &Ed25519Signer::new(identity::Keypair::generate_ed25519())).unwrap();
+        assert!(receiver.accept_evidence(&sender.sign_evidence(&evidence).unwrap()).is_err());
+
+        CryptoProvider::sign_evidence(&mut evidence, &Ed25519Signer::new(sender.local_key.clone())).unwrap();
+        assert!(receiver.accept_evidence(&sender.sign_evidence(&evidence).unwrap()).is_ok());
+
+        evidence.threat_level = ThreatLevel::Emergency;
+        assert!(receiver.accept_evidence(&sender.sign_evidence(&evidence).unwrap()).is_err());
+    }
+
+    fn verification_request(evidence: ThreatEvidence) -> VerificationMessage {
+        VerificationMessage::Request(Box::new(VerificationRequest {
+            request_id: format!("consensus-{}", uuid::Uuid::new_v4()),
+            evidence_id: evidence.id.clone(),
+            evidence,
+            requesting_agent: "sender".to_string(),
+            timestamp: 1_700_000_000,
+            verification_threshold: 3,
+            verifiers: Vec::new(),
+            responses: Vec::new(),
+            status: crate::consensus_verification::VerificationStatus::Pending,
+        }))
+    }
+
+    /// Relay of a client whose swarm is not running, with the receiver of what it delivers
+    fn relay(config: AgentConfig) -> (VerificationRelay, mpsc::UnboundedReceiver<VerificationMessage>) {
+        let mut client = P2pClient::new(config).unwrap();
+        let delivered = client.verifications.take().unwrap();
+        let relay = client.verification_relay(mpsc::unbounded_channel().1);
+        (relay, delivered)
+    }
+
+    #[test]
+    fn test_verification_messages_are_signed_and_replay_checked() {
+        let (sender, _) = relay(AgentConfig::default());
+        let (mut receiver, mut delivered) = relay(AgentConfig::default());
+
+        let sealed = sender.seal(verification_request(test_evidence("203.0.113.5"))).unwrap();
+        receiver.deliver(&sealed).unwrap();
+        assert!(matches!(delivered.try_recv(), Ok(VerificationMessage::Request(_))));
+        assert!(receiver.deliver(&sealed).is_err());
+
+        // Tampering breaks the signature
+        let mut tampered: SignedVerification = serde_json::from_slice(&sender.seal(verification_request(test_evidence("203.0.113.5"))).unwrap()).unwrap();
+        tampered.nonce += 100;
+        assert!(receiver.deliver(&serde_json::to_vec(&tampered).unwrap()).is_err());
+
+        // Unsigned messages are not accepted at all
+        let unsigned = serde_json::to_vec(&verification_request(test_evidence("203.0.113.5"))).unwrap();
+        assert!(receiver.deliver(&unsigned).is_err());
+        assert!(delivered.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_verification_requests_are_rate_limited_per_peer() {
+        let mut config = AgentConfig::default();
+        config.p2p_config.verification_rate_limit = 2;
+        let (mut receiver, mut delivered) = relay(config);
+        let (flooder, _) = relay(AgentConfig::default());
+        let (other, _) = relay(AgentConfig::default());
+
+        for _ in 0..2 {
+            receiver.deliver(&flooder.seal(verification_request(test_evidence("203.0.113.5"))).unwrap()).unwrap();
+        }
+        assert!(receiver.deliver(&flooder.seal(verification_request(test_evidence("203.0.113.5"))).unwrap()).is_err());
+        receiver.deliver(&other.seal(verification_request(test_evidence("203.0.113.5"))).unwrap()).unwrap();
+        assert_eq!(std::iter::from_fn(|| delivered.try_recv().ok()).count(), 3);
+
+        // The window resets after a minute
+        let peer = PeerId::random();
+        let mut limiter = RequestLimiter::new(1);
+        let start = Instant::now();
+        assert!(limiter.allow(peer, start));
+        assert!(!limiter.allow(peer, start + Duration::from_secs(30)));
+        assert!(limiter.allow(peer, start + REQUEST_RATE_WINDOW));
+    }
+
+    #[test]
+    fn test_verification_requests_need_emitter_signatures_when_enforced() {
+        let mut config = AgentConfig::default();
+        config.crypto_config.require_evidence_signatures = true;
+        let (mut receiver, _delivered) = relay(config);
+        let (sender, _) = relay(AgentConfig::default());
+
+        assert!(receiver.deliver(&sender.seal(verification_request(test_evidence("203.0.113.5"))).unwrap()).is_err());
+
+        let mut evidence = test_evidence("203.0.113.5");
+        CryptoProvider::sign_evidence(&mut evidence, &Ed25519Signer::new(sender.local_key.clone())).unwrap();
+        receiver.deliver(&sender.seal(verification_request(evidence)).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_verification_responses_must_be_signed_by_their_sender() {
+        let (mut receiver, mut delivered) = relay(AgentConfig::default());
+        let (sender, _) = relay(AgentConfig::default());
+        let response = |signer: Vec<u8>| VerificationMessage::Response(VerificationResponse {
+            request_id: "consensus-1".to_string(),
+            evidence_id: "evidence-1".to_string(),
+            verifying_agent: "verifier".to_string(),
+            verdict: true,
+            confidence: 0.9,
+            justification: String::new(),
+            timestamp: 1_700_000_000,
+            signer,
+            signature: Vec::new(),
+        });
+
+        let foreign_key = identity::Keypair::generate_ed25519().public().encode_protobuf();
+        assert!(receiver.deliver(&sender.seal(response(foreign_key)).unwrap()).is_err());
+        receiver.deliver(&sender.seal(response(sender.local_key.public().encode_protobuf())).unwrap()).unwrap();
+        assert!(matches!(delivered.try_recv(), Ok(VerificationMessage::Response(_))));
+        assert!(delivered.try_recv().is_err());
+    }
+
+    fn client_with_versions(min: u32, max: u32) -> P2pClient {
+        let mut config = AgentConfig::default();
+        config.p2p_config.min_protocol_version = min;
+        config.p2p_config.protocol_version = max;
+        P2pClient::new(config).unwrap()
+    }
+
+    #[test]
+    fn test_protocol_version_negotiation() {
+        let old = client_with_versions(1, 2);
+        let new = client_with_versions(2, 3);
+        let too_old = client_with_versions(1, 1);
+
+        assert_eq!(new.handshake().protocols, vec!["/orasrs/evidence/3", "/orasrs/evidence/2"]);
+        assert_eq!(old.negotiate(&new.handshake()).unwrap(), 2);
+        assert_eq!(new.negotiate(&old.handshake()).unwrap(), 2);
+        assert_eq!(new.negotiated_version(&old.peer_id.to_string()), Some(2));
+
+        // Evidence flows at the common version
+        let message = new.sign_evidence(&test_evidence("203.0.113.5")).unwrap();
+        assert_eq!(message.protocol_version, 2);
+        assert!(old.accept_evidence(&message).is_ok());
+
+        // No common version: the peer is refused and its evidence rejected
+        assert!(new.negotiate(&too_old.handshake()).is_err());
+        assert!(too_old.negotiate(&new.handshake()).is_err());
+        assert_eq!(new.negotiated_version(&too_old.peer_id.to_string()), None);
+        let message = too_old.sign_evidence(&test_evidence("203.0.113.5")).unwrap();
+        assert!(new.accept_evidence(&message).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_two_swarms_connect() {
+        let config = AgentConfig {
+            p2p_config: crate::config::P2pConfig {
+                bootstrap_nodes: Vec::new(),
+                listen_address: "127.0.0.1".to_string(),
+                listen_port: 0,
+                ..Default::default()
+            },
+            ..AgentConfig::default()
+        };
+
+        let mut first = P2pClient::new(config.clone()).unwrap();
+        first.connect_bootstrap().await.unwrap();
+        assert!(!first.is_connected());
+
+        // Only the configured address is listened on
+        let addrs = first.listen_addrs();
+        assert!(addrs.iter().all(|addr| addr.to_string().starts_with("/ip4/127.0.0.1/")), "{:?}", addrs);
+        let addr = addrs.into_iter().next().expect("listening on loopback");
+
+        let mut second_config = config;
+        second_config.p2p_config.bootstrap_nodes = vec![format!("{}/p2p/{}", addr, first.peer_id)];
+        let mut second = P2pClient::new(second_config).unwrap();
+        second.connect_bootstrap().await.unwrap();
+        assert!(second.is_connected());
+        assert_eq!(second.get_network_status().connections, 1);
+
+        // The listening side sees the inbound connection too
+        for _ in 0..50 {
+            if first.is_connected() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(first.is_connected());
+        assert!(second.connect_bootstrap().await.is_err());
+
+        // Disconnecting stops the swarm, closing its connection
+        second.disconnect().await;
+        assert!(second.swarm_task.is_none());
+        for _ in 0..50 {
+            if !first.is_connected() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(!first.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_swarms_negotiate_versions_on_connect() {
+        let config = |min_protocol_version, protocol_version| AgentConfig {
+            p2p_config: crate::config::P2pConfig {
+                bootstrap_nodes: Vec::new(),
+                listen_address: "127.0.0.1".to_string(),
+                listen_port: 0,
+                min_protocol_version,
+                protocol_version,
+                ..Default::default()
+            },
+            ..AgentConfig::default()
+        };
+        let mut listener = P2pClient::new(config(2, 3)).unwrap();
+        listener.connect_bootstrap().await.unwrap();
+        let bootstrap = format!("{}/p2p/{}", listener.listen_addrs()[0], listener.peer_id);
+
+        let dial = |mut config: AgentConfig| {
+            config.p2p_config.bootstrap_nodes = vec![bootstrap.clone()];
+            P2pClient::new(config).unwrap()
+        };
+        // Identify carries the handshake, and both sides settle on version 2
+        let mut old = dial(config(1, 2));
+        old.connect_bootstrap().await.unwrap();
+        for _ in 0..50 {
+            if old.negotiated_version(&listener.peer_id.to_string()).is_some()
+                && listener.negotiated_version(&old.peer_id.to_string()).is_some()
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(old.negotiated_version(&listener.peer_id.to_string()), Some(2));
+        assert_eq!(listener.negotiated_version(&old.peer_id.to_string()), Some(2));
+
+        // A peer without a common version is disconnected
+        let mut too_old = dial(config(1, 1));
+        let _ = too_old.connect_bootstrap().await; // May already be refused by the time it returns
+        for _ in 0..50 {
+            if !too_old.is_connected() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(!too_old.is_connected());
+        assert_eq!(listener.negotiated_version(&too_old.peer_id.to_string()), None);
+    }
+
+    #[tokio::test]
+    async fn test_published_evidence_reaches_subscriber() {
+        let config = AgentConfig {
+            p2p_config: crate::config::P2pConfig {
+                bootstrap_nodes: Vec::new(),
+                listen_port: 0,
+                ..Default::default()
+            },
+            ..AgentConfig::default()
+        };
+
+        let mut publisher = P2pClient::new(config.clone()).unwrap();
+        publisher.connect_bootstrap().await.unwrap();
+        publisher.subscribe_threat_intel().unwrap();
+        let addr = publisher.listen_addrs()
+            .into_iter()
+            .find(|addr| addr.to_string().starts_with("/ip4/127.0.0.1/"))
+            .expect("listening on loopback");
+
+        // Drive the subscriber's swarm by hand to observe raw messages
+        let mut subscriber = P2pClient::new(config).unwrap();
+        subscriber.subscribe_threat_intel().unwrap();
+        let gossipsub = subscriber.gossipsub.take().unwrap();
+        let mut swarm = subscriber.build_swarm(gossipsub).unwrap();
+        swarm.dial(format!("{}/p2p/{}", addr, publisher.peer_id).parse::<Multiaddr>().unwrap()).unwrap();
+
+        let evidence = test_evidence("203.0.113.5");
+        let received = tokio::time::timeout(Duration::from_secs(20), async {
+            loop {
+                tokio::select! {
+                    event = swarm.select_next_some() => {
+                        if let SwarmEvent::Behaviour(AgentBehaviourEvent::Gossipsub(gossipsub::Event::Message { message, .. })) = event {
+                            return serde_json::from_slice::<SignedEvidence>(&message.data).unwrap();
+                        }
+                    }
+                    // Publishing fails until the subscription has reached the publisher
+                    _ = tokio::time::sleep(Duration::from_millis(200)) => {
+                        let _ = publisher.publish_threat_evidence(&evidence).await;
+                    }
+                }
+            }
+        })
+        .await
+        .expect("evidence delivered");
+
+        let accepted = subscriber.accept_evidence(&received).unwrap();
+        assert_eq!(accepted.id, evidence.id);
+        assert_eq!(accepted.source_ip, evidence.source_ip);
+    }
+
+    #[tokio::test]
+    async fn test_received_evidence_is_delivered() {
+        let config = AgentConfig {
+            p2p_config: crate::config::P2pConfig {
+                bootstrap_nodes: Vec::new(),
+                listen_port: 0,
+                ..Default::default()
+            },
+            ..AgentConfig::default()
+        };
+
+        let mut publisher = P2pClient::new(config.clone()).unwrap();
+        publisher.connect_bootstrap().await.unwrap();
+        publisher.subscribe_threat_intel().unwrap();
+        let addr = publisher.listen_addrs()
+            .into_iter()
+            .find(|addr| addr.to_string().starts_with("/ip4/127.0.0.1/"))
+            .expect("listening on loopback");
+
+        let mut receiver_config = config;
+        receiver_config.p2p_config.bootstrap_nodes = vec![format!("{}/p2p/{}", addr, publisher.peer_id)];
+        let mut receiver = P2pClient::new(receiver_config).unwrap();
+        let mut incoming = receiver.take_incoming_evidence().unwrap();
+        assert!(receiver.take_incoming_evidence().is_none());
+        receiver.connect_bootstrap().await.unwrap();
+        receiver.subscribe_threat_intel().unwrap();
+
+        let evidence = test_evidence("203.0.113.5");
+        let delivered = tokio::time::timeout(Duration::from_secs(20), async {
+            loop {
+                tokio::select! {
+                    delivered = incoming.recv() => return delivered.unwrap(),
+                    // Publishing fails until the subscription has reached the publisher
+                    _ = tokio::time::sleep(Duration::from_millis(200)) => {
+                        let _ = publisher.publish_threat_evidence(&evidence).await;
+                    }
+                }
+            }
+        })
+        .await
+        .expect("evidence delivered");
+
+        assert_eq!(delivered.id, evidence.id);
+        assert_eq!(delivered.threat_level, evidence.threat_level);
+    }
+
+    #[tokio::test]
+    async fn test_mdns_discovers_peers_without_bootstrap() {
+        let config = AgentConfig {
+            p2p_config: crate::config::P2pConfig {
+                bootstrap_nodes: Vec::new(),
+                listen_port: 0,
+                mdns_enabled: true,
+                ..Default::default()
+            },
+            ..AgentConfig::default()
+        };
+
+        let mut first = P2pClient::new(config.clone()).unwrap();
+        let mut second = P2pClient::new(config).unwrap();
+        first.connect_bootstrap().await.unwrap();
+        second.connect_bootstrap().await.unwrap();
+
+        for _ in 0..100 {
+            if first.is_connected() && second.is_connected() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(first.is_connected());
+        assert!(second.is_connected());
+    }
+}
@@ -1,9 +1,73 @@
-use crate::{ThreatEvidence, AgentConfig, crypto::CryptoProvider, error::{AgentError, Result}};
+use crate::{ThreatEvidence, ThreatType, AgentConfig, allowlist::SharedAllowlist, clock::{Clock, SystemClock}, compliance::{anonymization_prefixes, anonymize_ip_field, is_anonymization_exempt, parse_anonymization_exemptions}, crypto::{CryptoProvider, Signer}, notarization::Notary, error::{AgentError, Result}};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Seconds between trial publications while publishing is paused
+const PAUSE_PROBE_INTERVAL: i64 = 60;
+
+/// Reporter metrics shared between the reporter task and the agent
+#[derive(Debug)]
+pub struct ReputationMetrics {
+    reputation_bits: AtomicU64,
+    below_threshold: AtomicBool,
+    low_reputation_alerts: AtomicU64,
+    withheld_evidence: AtomicU64,
+    published_evidence: AtomicU64,
+}
+
+impl ReputationMetrics {
+    fn new(reputation: f64) -> Self {
+        Self {
+            reputation_bits: AtomicU64::new(reputation.to_bits()),
+            below_threshold: AtomicBool::new(false),
+            low_reputation_alerts: AtomicU64::new(0),
+            withheld_evidence: AtomicU64::new(0),
+            published_evidence: AtomicU64::new(0),
+        }
+    }
+
+    /// Current agent reputation
+    pub fn reputation(&self) -> f64 {
+        f64::from_bits(self.reputation_bits.load(Ordering::SeqCst))
+    }
+
+    /// Whether reputation is currently below `reputation_threshold`
+    pub fn is_below_threshold(&self) -> bool {
+        self.below_threshold.load(Ordering::SeqCst)
+    }
+
+    /// Number of times reputation has dropped below the threshold
+    pub fn low_reputation_alerts(&self) -> u64 {
+        self.low_reputation_alerts.load(Ordering::SeqCst)
+    }
+
+    /// Evidence not published because publishing was paused
+    pub fn withheld_evidence(&self) -> u64 {
+        self.withheld_evidence.load(Ordering::SeqCst)
+    }
+
+    /// Count evidence held back while publishing is paused
+    pub(crate) fn record_withheld(&self) {
+        self.withheld_evidence.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_below_threshold(&self, below: bool) {
+        self.below_threshold.store(below, Ordering::SeqCst);
+    }
+
+    /// Evidence successfully published by the reporter
+    pub fn published_evidence(&self) -> u64 {
+        self.published_evidence.load(Ordering::SeqCst)
+    }
+}
+
 /// Threat evidence collector and reporter
 pub struct EvidenceCollector {
     agent_id: String,
@@ -11,6 +75,14 @@ pub struct EvidenceCollector {
     evidence_queue: tokio::sync::mpsc::UnboundedReceiver<ThreatEvidence>,
     blocklist_sender: Option<tokio::sync::mpsc::UnboundedSender<ThreatEvidence>>,
     reputation: f64,
+    metrics: Arc<ReputationMetrics>,
+    clock: Arc<dyn Clock>,
+    last_probe: i64,
+    notary: Option<Arc<Notary>>,
+    signer: Option<Arc<dyn Signer>>, // Signs evidence as its emitter once it is processed
+    allowlist: SharedAllowlist,      // Addresses never reported
+    context_key: Option<Vec<u8>>, // None if no usable key, so encryption fails closed
+    anonymization_exemptions: HashSet<IpAddr>, // Own infrastructure IPs kept intact
 }
 
 impl EvidenceCollector {
@@ -20,13 +92,65 @@ impl EvidenceCollector {
         evidence_queue: tokio::sync::mpsc::UnboundedReceiver<ThreatEvidence>,
         blocklist_sender: Option<tokio::sync::mpsc::UnboundedSender<ThreatEvidence>>,
     ) -> Self {
+        let context_key = Self::load_context_key(&config);
+        let anonymization_exemptions = parse_anonymization_exemptions(&config.anonymization_exemptions);
         Self {
             agent_id,
             config,
             evidence_queue,
             blocklist_sender,
             reputation: 1.0, // Start with good reputation
+            metrics: Arc::new(ReputationMetrics::new(1.0)),
+            clock: Arc::new(SystemClock),
+            last_probe: 0,
+            notary: None,
+            signer: None,
+            allowlist: SharedAllowlist::default(),
+            context_key,
+            anonymization_exemptions,
+        }
+    }
+
+    /// Read the configured context key, or generate one for this run
+    fn load_context_key(config: &AgentConfig) -> Option<Vec<u8>> {
+        let key = match &config.crypto_config.context_encryption_key {
+            Some(hex) => CryptoProvider::parse_context_key(hex),
+            None => CryptoProvider::generate_key(),
+        };
+
+        key.map_err(|e| log::error!("No usable context encryption key: {}", e)).ok()
+    }
+
+    /// Encrypt contexts with `key` when none is configured, instead of a key generated for this run
+    pub fn with_context_key(mut self, key: Vec<u8>) -> Self {
+        if self.config.crypto_config.context_encryption_key.is_none() {
+            self.context_key = Some(key);
         }
+        self
+    }
+
+    /// Use a custom clock for pause probes
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Notarize published evidence in signed Merkle batches
+    pub fn with_notary(mut self, notary: Arc<Notary>) -> Self {
+        self.notary = Some(notary);
+        self
+    }
+
+    /// Sign published evidence as its emitter
+    pub fn with_signer(mut self, signer: Arc<dyn Signer>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Drop evidence about IPs covered by the allowlist, following updates made through the shared handle
+    pub fn with_allowlist(mut self, allowlist: SharedAllowlist) -> Self {
+        self.allowlist = allowlist;
+        self
     }
 
     /// Start collecting and processing evidence
@@ -34,6 +158,12 @@ impl EvidenceCollector {
         log::info!("Starting evidence collection...");
         
         while let Some(mut evidence) = self.evidence_queue.recv().await {
+            // Never report our own critical infrastructure
+            if self.allowlist.read().unwrap().contains(&evidence.source_ip) {
+                log::warn!("Dropping evidence {} about allowlisted IP {}", evidence.id, evidence.source_ip);
+                continue;
+            }
+            
             // Set agent-specific fields
             evidence.agent_id = self.agent_id.clone();
             evidence.reputation = self.reputation;
@@ -41,16 +171,36 @@ impl EvidenceCollector {
             evidence.region = self.config.region.clone();
             
             // Process the evidence based on privacy and compliance settings
-            let processed_evidence = self.process_evidence(evidence.clone())?; // Clone for blocklist
+            let processed_evidence = match self.process_evidence(evidence.clone()) { // Clone for blocklist
+                Ok(processed_evidence) => processed_evidence,
+                Err(e) => {
+                    log::warn!("Dropping evidence {}: {}", evidence.id, e);
+                    continue;
+                }
+            };
+            
+            // The blocklist is local, so it keeps the full IP unless configured otherwise
+            let internal_evidence = if self.config.internal_full_ip {
+                evidence
+            } else {
+                processed_evidence.clone()
+            };
             
             // Send to blocklist exporter if enabled
             if let Some(ref sender) = self.blocklist_sender {
                 // Only send to blocklist if threat level is high enough
-                if processed_evidence.threat_level as u8 >= self.config.blocklist_min_threat_level.unwrap_or(crate::ThreatLevel::Warning) as u8 {
-                    let _ = sender.send(processed_evidence.clone());
+                if internal_evidence.threat_level as u8 >= self.config.blocklist_min_threat_level.unwrap_or(crate::ThreatLevel::Warning) as u8 {
+                    let _ = sender.send(internal_evidence);
                 }
             }
             
+            // Hold back evidence while paused for low reputation
+            if !self.should_publish() {
+                self.metrics.record_withheld();
+                log::debug!("Withholding evidence {} while reputation is below threshold", processed_evidence.id);
+                continue;
+            }
+            
             // Submit evidence to the threat intelligence fabric
             if let Err(e) = self.submit_evidence(&processed_evidence).await {
                 log::error!("Failed to submit evidence: {}", e);
@@ -58,40 +208,52 @@ impl EvidenceCollector {
                 self.update_reputation(false);
             } else {
                 log::debug!("Evidence submitted successfully");
+                self.metrics.published_evidence.fetch_add(1, Ordering::SeqCst);
                 // Update reputation based on success
                 self.update_reputation(true);
+                
+                if let Some(ref notary) = self.notary {
+                    match notary.record(&processed_evidence) {
+                        // Save each sealed batch so its root survives a restart
+                        Ok(Some(_)) => {
+                            if let Err(e) = notary.persist().await {
+                                log::warn!("Failed to persist notary state: {}", e);
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => log::error!("Failed to notarize evidence {}: {}", processed_evidence.id, e),
+                    }
+                }
             }
         }
         
+        // Sign whatever is left of the last batch
+        if let Some(ref notary) = self.notary {
+            notary.seal()?;
+            notary.persist().await?;
+        }
+        
         Ok(())
     }
 
     /// Process evidence according to privacy and compliance settings
     fn process_evidence(&self, mut evidence: ThreatEvidence) -> Result<ThreatEvidence> {
         // Apply privacy settings based on privacy level
-        match self.config.privacy_level {
-            1 => { // GDPR: anonymize to /24
-                evidence.source_ip = self.anonymize_ip(&evidence.source_ip, 24);
-                evidence.target_ip = self.anonymize_ip(&evidence.target_ip, 24);
-            },
-            2 => { // CCPA: anonymize to /16
-                evidence.source_ip = self.anonymize_ip(&evidence.source_ip, 16);
-                evidence.target_ip = self.anonymize_ip(&evidence.target_ip, 16);
-            },
-            3 => { // China: full IP allowed
-                // No anonymization needed
-            },
-            _ => { // Global: anonymize to /16
-                evidence.source_ip = self.anonymize_ip(&evidence.source_ip, 16);
-                evidence.target_ip = self.anonymize_ip(&evidence.target_ip, 16);
+        if let Some((ipv4_prefix, ipv6_prefix)) = anonymization_prefixes(self.config.privacy_level) {
+            if self.config.anonymize_fields.source() && !is_anonymization_exempt(&self.anonymization_exemptions, &evidence.source_ip) {
+                evidence.source_ip = anonymize_ip_field(&evidence.source_ip, ipv4_prefix, ipv6_prefix);
+                // Reverse DNS and netblock owner would identify the address the prefix hides
+                evidence.source_hostname = None;
+                evidence.source_owner = None;
+            }
+            if self.config.anonymize_fields.target() && !is_anonymization_exempt(&self.anonymization_exemptions, &evidence.target_ip) {
+                evidence.target_ip = anonymize_ip_field(&evidence.target_ip, ipv4_prefix, ipv6_prefix);
             }
         }
 
         // Encrypt sensitive fields if required
         if self.config.storage_config.encryption_enabled {
-            evidence.context = CryptoProvider::encrypt_data(evidence.context.as_bytes(), &[0u8; 32])
-                .map(|v| format!("{:?}", v))  // Simplified representation
-                .unwrap_or(evidence.context);
+            evidence.context = self.encrypt_context(&evidence.context)?;
         }
 
         // Update evidence hash after processing
@@ -102,50 +264,28 @@ impl EvidenceCollector {
             evidence.context);
         evidence.evidence_hash = CryptoProvider::blake3_hash(evidence_str.as_bytes());
 
+        // Signing covers everything above, so it comes last
+        if let Some(ref signer) = self.signer {
+            CryptoProvider::sign_evidence(&mut evidence, signer.as_ref())?;
+        }
+
         Ok(evidence)
     }
 
-    /// Anonymize IP address to specified subnet size
-    fn anonymize_ip(&self, ip: &str, subnet_bits: u8) -> String {
-        // This is a simplified IP anonymization
-        // In a real implementation, we'd use proper IP address manipulation
-        if subnet_bits >= 32 {
-            return ip.to_string(); // No anonymization
-        }
-
-        // For IPv4, anonymize the last octet(s) based on subnet_bits
-        if ip.contains('.') {
-            let octets: Vec<&str> = ip.split('.').collect();
-            if octets.len() == 4 {
-                let keep_octets = match subnet_bits {
-                    0..=8 => 1,
-                    9..=16 => 2,
-                    17..=24 => 3,
-                    _ => 4, // Don't anonymize if >= 24
-                };
-                
-                if keep_octets >= 4 {
-                    return ip.to_string(); // No anonymization needed
-                }
-                
-                let mut result = String::new();
-                for i in 0..4 {
-                    if i < keep_octets {
-                        result.push_str(octets[i]);
-                    } else {
-                        result.push_str("0");
-                    }
-                    
-                    if i < 3 {
-                        result.push('.');
-                    }
-                }
-                return result;
-            }
-        }
+    /// Encrypt an evidence context into hex-encoded nonce and ciphertext
+    fn encrypt_context(&self, context: &str) -> Result<String> {
+        let key = self.context_key.as_ref()
+            .ok_or_else(|| AgentError::CryptoError("No context encryption key".to_string()))?;
+        Ok(CryptoProvider::hex_encode(&CryptoProvider::encrypt_data(context.as_bytes(), key)?))
+    }
 
-        // For IPv6 or malformed IPs, return a placeholder
-        "0.0.0.0".to_string()
+    /// Recover an evidence context encrypted by this collector
+    pub fn decrypt_context(&self, encrypted: &str) -> Result<String> {
+        let key = self.context_key.as_ref()
+            .ok_or_else(|| AgentError::CryptoError("No context encryption key".to_string()))?;
+        let plaintext = CryptoProvider::decrypt_data(&CryptoProvider::hex_decode(encrypted)?, key)?;
+        String::from_utf8(plaintext)
+            .map_err(|e| AgentError::CryptoError(format!("Decrypted context is not UTF-8: {}", e)))
     }
 
     /// Submit evidence to the threat intelligence fabric
@@ -169,12 +309,50 @@ impl EvidenceCollector {
             // Larger reputation decrease for failed submission
             self.reputation = (self.reputation - 0.05).max(0.0);
         }
+        
+        self.metrics.reputation_bits.store(self.reputation.to_bits(), Ordering::SeqCst);
+        self.check_reputation_threshold();
+    }
+
+    /// Alert when reputation crosses below `reputation_threshold`, and note recovery
+    fn check_reputation_threshold(&self) {
+        let threshold = self.config.reputation_threshold;
+        let below = self.reputation < threshold;
+        let was_below = self.metrics.below_threshold.swap(below, Ordering::SeqCst);
+        
+        if below && !was_below {
+            self.metrics.low_reputation_alerts.fetch_add(1, Ordering::SeqCst);
+            log::warn!("Agent reputation {:.2} fell below threshold {:.2}{}",
+                      self.reputation, threshold,
+                      if self.config.pause_publishing_below_threshold { "; pausing publishing" } else { "" });
+        } else if !below && was_below {
+            log::info!("Agent reputation {:.2} recovered above threshold {:.2}", self.reputation, threshold);
+        }
+    }
+
+    /// Whether to publish now; while paused only one trial per probe interval goes out
+    fn should_publish(&mut self) -> bool {
+        if !self.config.pause_publishing_below_threshold || !self.metrics.is_below_threshold() {
+            return true;
+        }
+        
+        let now = self.clock.now();
+        if now - self.last_probe >= PAUSE_PROBE_INTERVAL {
+            self.last_probe = now;
+            return true;
+        }
+        false
     }
 
     /// Get current reputation
     pub fn get_reputation(&self) -> f64 {
         self.reputation
     }
+
+    /// Get reporter metrics shared with the agent
+    pub fn metrics(&self) -> Arc<ReputationMetrics> {
+        self.metrics.clone()
+    }
 }
 
 /// Threat reporter that coordinates with P2P network
@@ -198,6 +376,36 @@ impl ThreatReporter {
         }
     }
 
+    /// Use a custom clock for pause probes
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.evidence_collector = self.evidence_collector.with_clock(clock);
+        self
+    }
+
+    /// Notarize published evidence in signed Merkle batches
+    pub fn with_notary(mut self, notary: Arc<Notary>) -> Self {
+        self.evidence_collector = self.evidence_collector.with_notary(notary);
+        self
+    }
+
+    /// Sign published evidence as its emitter
+    pub fn with_signer(mut self, signer: Arc<dyn Signer>) -> Self {
+        self.evidence_collector = self.evidence_collector.with_signer(signer);
+        self
+    }
+
+    /// Drop evidence about IPs covered by the allowlist
+    pub fn with_allowlist(mut self, allowlist: SharedAllowlist) -> Self {
+        self.evidence_collector = self.evidence_collector.with_allowlist(allowlist);
+        self
+    }
+
+    /// Encrypt contexts with a persistent key when none is configured
+    pub fn with_context_key(mut self, key: Vec<u8>) -> Self {
+        self.evidence_collector = self.evidence_collector.with_context_key(key);
+        self
+    }
+
     /// Start the reporting service
     pub async fn start_reporting(&mut self) -> Result<()> {
         log::info!("Starting threat reporting service...");
@@ -208,9 +416,38 @@ impl ThreatReporter {
     pub fn get_reputation(&self) -> f64 {
         self.evidence_collector.get_reputation()
     }
+
+    /// Get reporter metrics, which stay live after the reporter is started
+    pub fn metrics(&self) -> Arc<ReputationMetrics> {
+        self.evidence_collector.metrics()
+    }
+
+    /// Recover an evidence context encrypted by this reporter
+    pub fn decrypt_context(&self, encrypted: &str) -> Result<String> {
+        self.evidence_collector.decrypt_context(encrypted)
+    }
 }
 
 impl ThreatType {
+    /// Every threat type, in declaration order
+    pub const ALL: [ThreatType; 10] = [
+        ThreatType::DDoS,
+        ThreatType::Malware,
+        ThreatType::Phishing,
+        ThreatType::BruteForce,
+        ThreatType::SuspiciousConnection,
+        ThreatType::AnomalousBehavior,
+        ThreatType::IoCMatch,
+        ThreatType::APT,
+        ThreatType::Exploit,
+        ThreatType::Unknown,
+    ];
+
+    /// Parse a string produced by `as_ref`
+    pub fn from_name(name: &str) -> Option<ThreatType> {
+        Self::ALL.into_iter().find(|threat_type| threat_type.as_ref() == name)
+    }
+
     /// Get string representation of threat type
     pub fn as_ref(&self) -> &'static str {
         match self {
@@ -221,6 +458,175 @@ impl ThreatType {
             ThreatType::SuspiciousConnection => "suspicious_connection",
             ThreatType::AnomalousBehavior => "anomalous_behavior",
             ThreatType::IoCMatch => "ioc_match",
+            ThreatType::APT => "apt",
+            ThreatType::Exploit => "exploit",
+            ThreatType::Unknown => "unknown",
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreatLevel;
+    use crate::clock::MockClock;
+    use crate::test_evidence;
+
+    fn create_collector(pause: bool, clock: Arc<MockClock>) -> EvidenceCollector {
+        let mut config = AgentConfig::default();
+        config.reputation_threshold = 0.6;
+        config.pause_publishing_below_threshold = pause;
+        let (_sender, receiver) = mpsc::unbounded_channel();
+        EvidenceCollector::new("test-agent".to_string(), config, receiver, None).with_clock(clock)
+    }
+
+    #[test]
+    fn test_low_reputation_alert_fires_once() {
+        let mut collector = create_collector(false, Arc::new(MockClock::new(1_700_000_000)));
+        let metrics = collector.metrics();
+
+        while collector.get_reputation() >= 0.6 {
+            assert_eq!(metrics.low_reputation_alerts(), 0);
+            collector.update_reputation(false);
+        }
+        assert!(metrics.is_below_threshold());
+        assert_eq!(metrics.low_reputation_alerts(), 1);
+        assert_eq!(metrics.reputation(), collector.get_reputation());
+
+        // Staying below does not re-alert; recovering clears the state
+        collector.update_reputation(false);
+        assert_eq!(metrics.low_reputation_alerts(), 1);
+        while collector.get_reputation() < 0.6 {
+            collector.update_reputation(true);
+        }
+        assert!(!metrics.is_below_threshold());
+        assert!(collector.should_publish());
+    }
+
+    #[test]
+    fn test_publishing_paused_below_threshold() {
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let mut collector = create_collector(true, clock.clone());
+
+        while collector.get_reputation() >= 0.6 {
+            assert!(collector.should_publish());
+            collector.update_reputation(false);
+        }
+
+        // One trial publication per probe interval while paused
+        assert!(collector.should_publish());
+        assert!(!collector.should_publish());
+        clock.advance(PAUSE_PROBE_INTERVAL);
+        assert!(collector.should_publish());
+        assert!(!collector.should_publish());
+    }
+
+    #[test]
+    fn test_threat_type_names_round_trip() {
+        let mut names = std::collections::HashSet::new();
+        for threat_type in ThreatType::ALL {
+            let name = threat_type.as_ref();
+            assert!(names.insert(name), "duplicate name {}", name);
+            assert_eq!(ThreatType::from_name(name), Some(threat_type));
+        }
+        assert_eq!(ThreatType::from_name("not-a-threat"), None);
+    }
+
+    #[test]
+    fn test_context_encryption_round_trip() {
+        let mut config = AgentConfig::default();
+        config.storage_config.encryption_enabled = true;
+        config.crypto_config.context_encryption_key = Some(CryptoProvider::hex_encode(&[7u8; 32]));
+        let (_sender, receiver) = mpsc::unbounded_channel();
+        let collector = EvidenceCollector::new("test-agent".to_string(), config.clone(), receiver, None);
+
+        let processed = collector.process_evidence(test_evidence("203.0.113.5")).unwrap();
+        assert_ne!(processed.context, test_evidence("203.0.113.5").context);
+        assert!(processed.context.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(collector.decrypt_context(&processed.context).unwrap(), test_evidence("203.0.113.5").context);
+
+        // Another collector with the same configured key can decrypt it; a stored key does not replace it
+        let (_sender, receiver) = mpsc::unbounded_channel();
+        let other = EvidenceCollector::new("other-agent".to_string(), config.clone(), receiver, None).with_context_key(vec![9u8; 32]);
+        assert_eq!(other.decrypt_context(&processed.context).unwrap(), test_evidence("203.0.113.5").context);
+
+        // Without a configured key, a collector given the same stored key after a restart can decrypt it
+        config.crypto_config.context_encryption_key = None;
+        let (_sender, receiver) = mpsc::unbounded_channel();
+        let stored = EvidenceCollector::new("test-agent".to_string(), config.clone(), receiver, None).with_context_key(vec![9u8; 32]);
+        let encrypted = stored.process_evidence(test_evidence("203.0.113.5")).unwrap();
+        let (_sender, receiver) = mpsc::unbounded_channel();
+        let restarted = EvidenceCollector::new("test-agent".to_string(), config, receiver, None).with_context_key(vec![9u8; 32]);
+        assert_eq!(restarted.decrypt_context(&encrypted.context).unwrap(), test_evidence("203.0.113.5").context);
+    }
+
+    #[test]
+    fn test_processed_evidence_is_signed_last() {
+        let (_sender, receiver) = mpsc::unbounded_channel();
+        let signer: Arc<dyn Signer> = Arc::new(crate::crypto::Ed25519Signer::new(libp2p::identity::Keypair::generate_ed25519()));
+        let collector = EvidenceCollector::new("test-agent".to_string(), AgentConfig::default(), receiver, None)
+            .with_signer(signer.clone());
+
+        let processed = collector.process_evidence(test_evidence("203.0.113.5")).unwrap();
+        assert_eq!(processed.signer_key, Some(CryptoProvider::hex_encode(&signer.public_key())));
+        assert!(CryptoProvider::has_valid_evidence_signature(&processed, signer.as_ref()));
+    }
+
+    #[test]
+    fn test_anonymized_source_drops_hostname_and_owner() {
+        let (_sender, receiver) = mpsc::unbounded_channel();
+        let collector = EvidenceCollector::new("test-agent".to_string(), AgentConfig::default(), receiver, None);
+
+        let mut evidence = test_evidence("203.0.113.5");
+        evidence.source_hostname = Some("scanner.example.net".to_string());
+        evidence.source_owner = Some("EXAMPLE-NET".to_string());
+        let processed = collector.process_evidence(evidence).unwrap();
+        assert_ne!(processed.source_ip, test_evidence("203.0.113.5").source_ip);
+        assert_eq!(processed.source_hostname, None);
+        assert_eq!(processed.source_owner, None);
+    }
+
+    #[test]
+    fn test_unknown_source_ip_is_kept_as_placeholder() {
+        let (_sender, receiver) = mpsc::unbounded_channel();
+        let collector = EvidenceCollector::new("test-agent".to_string(), AgentConfig::default(), receiver, None);
+
+        let mut evidence = test_evidence("203.0.113.5");
+        evidence.source_ip = "unknown".to_string();
+        let processed = collector.process_evidence(evidence).unwrap();
+        assert_eq!(processed.source_ip, "unknown");
+    }
+
+    #[test]
+    fn test_invalid_context_key_fails_closed() {
+        let mut config = AgentConfig::default();
+        config.storage_config.encryption_enabled = true;
+        config.crypto_config.context_encryption_key = Some("not-hex".to_string());
+        let (_sender, receiver) = mpsc::unbounded_channel();
+        let collector = EvidenceCollector::new("test-agent".to_string(), config, receiver, None);
+
+        assert!(matches!(collector.process_evidence(test_evidence("203.0.113.5")), Err(AgentError::CryptoError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_allowlisted_evidence_is_not_reported() {
+        let allowlist = SharedAllowlist::default();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (blocklist_sender, mut blocklist) = mpsc::unbounded_channel();
+        let mut collector = EvidenceCollector::new("test-agent".to_string(), AgentConfig::default(), receiver, Some(blocklist_sender))
+            .with_allowlist(allowlist.clone());
+        let metrics = collector.metrics();
+
+        // An update through the shared handle applies to evidence already queued
+        for source_ip in ["192.0.2.10", "203.0.113.7"] {
+            sender.send(ThreatEvidence::builder(source_ip, ThreatType::BruteForce, ThreatLevel::Critical).build()).unwrap();
+        }
+        drop(sender);
+        *allowlist.write().unwrap() = crate::allowlist::Allowlist::parse(&["192.0.2.0/24".to_string()]).unwrap();
+        collector.start_collection().await.unwrap();
+
+        assert_eq!(metrics.published_evidence(), 1);
+        assert!(blocklist.recv().await.unwrap().source_ip.starts_with("203.0.113."));
+        assert!(blocklist.try_recv().is_err());
+    }
+}
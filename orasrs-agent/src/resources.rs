@@ -0,0 +1,172 @@
+//! Sampling of the agent process's memory, CPU and network use
+
+use std::time::Instant;
+
+/// USER_HZ assumed where the system cannot report it
+const DEFAULT_CLOCK_TICKS_PER_SEC: f64 = 100.0;
+
+/// Resource use measured at one sample
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceUsage {
+    pub memory: usize, // Resident set size in bytes
+    pub cpu: f64,      // Percent of one core since the previous sample
+    pub network: u64,  // Bytes per second sent and received since the previous sample
+}
+
+/// Cumulative counters read at one sample
+#[derive(Debug, Clone, Copy)]
+struct Counters {
+    at: Instant,
+    cpu_ticks: Option<u64>,
+    network_bytes: Option<u64>,
+}
+
+/// Samples the process's resource use from procfs
+///
+/// CPU and network are rates over the time since the previous sample, so the
+/// first sample reports them as 0. Procfs has no per-process network
+/// counters, so network traffic is that of every non-loopback interface in
+/// the process's network namespace: on a host without its own namespace it
+/// includes every other process's traffic. Without procfs every field stays 0.
+#[derive(Debug)]
+pub struct ResourceSampler {
+    previous: Option<Counters>,
+    ticks_per_sec: f64, // Rate at which procfs reports CPU time (USER_HZ)
+}
+
+impl Default for ResourceSampler {
+    fn default() -> Self {
+        Self {
+            previous: None,
+            ticks_per_sec: clock_ticks_per_sec(),
+        }
+    }
+}
+
+impl ResourceSampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a sample
+    pub fn sample(&mut self) -> ResourceUsage {
+        let now = Counters {
+            at: Instant::now(),
+            cpu_ticks: read_proc("stat").and_then(|stat| parse_cpu_ticks(&stat)),
+            network_bytes: read_proc("net/dev").and_then(|dev| parse_network_bytes(&dev)),
+        };
+        let mut usage = ResourceUsage {
+            memory: read_proc("status").and_then(|status| parse_resident_memory(&status)).unwrap_or(0),
+            ..ResourceUsage::default()
+        };
+
+        if let Some(previous) = self.previous.replace(now) {
+            let elapsed = now.at.duration_since(previous.at).as_secs_f64();
+            if elapsed > 0.0 {
+                if let (Some(before), Some(after)) = (previous.cpu_ticks, now.cpu_ticks) {
+                    usage.cpu = after.saturating_sub(before) as f64 / self.ticks_per_sec / elapsed * 100.0;
+                }
+                if let (Some(before), Some(after)) = (previous.network_bytes, now.network_bytes) {
+                    usage.network = (after.saturating_sub(before) as f64 / elapsed) as u64;
+                }
+            }
+        }
+        usage
+    }
+}
+
+/// Clock ticks per second of procfs CPU times, as reported by sysconf(_SC_CLK_TCK)
+fn clock_ticks_per_sec() -> f64 {
+    #[cfg(unix)]
+    {
+        // SAFETY: sysconf only reads a system configuration value
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        if ticks > 0 {
+            return ticks as f64;
+        }
+    }
+    DEFAULT_CLOCK_TICKS_PER_SEC
+}
+
+fn read_proc(file: &str) -> Option<String> {
+    std::fs::read_to_string(format!("/proc/self/{}", file)).ok()
+}
+
+/// Resident set size in bytes from /proc/self/status
+fn parse_resident_memory(status: &str) -> Option<usize> {
+    let kib = status.lines().find_map(|line| line.strip_prefix("VmRSS:"))?;
+    let kib: usize = kib.trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// User plus system CPU time in clock ticks from /proc/self/stat
+fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    // The command name may contain spaces, so fields are counted from its closing parenthesis,
+    // where field 3 (state) starts; utime and stime are fields 14 and 15
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// Bytes received and sent on non-loopback interfaces from /proc/self/net/dev
+fn parse_network_bytes(dev: &str) -> Option<u64> {
+    let mut total = 0;
+    for line in dev.lines().skip(2) {
+        let (name, counters) = line.split_once(':')?;
+        if name.trim() == "lo" {
+            continue;
+        }
+        let counters: Vec<u64> = counters.split_whitespace().map(|value| value.parse().ok()).collect::<Option<_>>()?;
+        total += counters.first()? + counters.get(8)?;
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_files() {
+        let stat = "4242 (orasrs (agent) x) S 1 4242 4242 0 -1 4194560 900 0 0 0 250 75 0 0 20 0 9 0 100 0 0";
+        assert_eq!(parse_cpu_ticks(stat), Some(325));
+
+        let status = "Name:\torasrs-agent\nVmPeak:\t   20480 kB\nVmRSS:\t    8192 kB\nThreads:\t9\n";
+        assert_eq!(parse_resident_memory(status), Some(8 * 1024 * 1024));
+
+        let dev = "Inter-|   Receive                                                |  Transmit\n \
+                   face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n    \
+                   lo:  5000      50    0    0    0     0          0         0     5000      50    0    0    0     0       0          0\n  \
+                   eth0:  1200      10    0    0    0     0          0         0      800       8    0    0    0     0       0          0\n";
+        assert_eq!(parse_network_bytes(dev), Some(2000));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sample_measures_this_process() {
+        let mut sampler = ResourceSampler::new();
+        let first = sampler.sample();
+        assert!(first.memory > 0);
+        assert_eq!(first.cpu, 0.0);
+
+        // Burn enough CPU to span several clock ticks
+        let started = Instant::now();
+        let mut spins = 0u64;
+        while started.elapsed() < std::time::Duration::from_millis(100) {
+            spins = std::hint::black_box(spins.wrapping_add(1));
+        }
+
+        let second = sampler.sample();
+        assert!(second.memory > 0);
+        assert!(second.cpu > 0.0, "{:?}", second);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_clock_ticks_come_from_the_system() {
+        let ticks = clock_ticks_per_sec();
+        assert_eq!(ticks, unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f64);
+        assert!(ticks > 0.0);
+    }
+}
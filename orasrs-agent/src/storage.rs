@@ -0,0 +1,226 @@
+use crate::error::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::sync::RwLock;
+
+/// Key-value storage backing the agent's persistent state
+///
+/// Keys are `/`-separated paths such as `indicator/203.0.113.7`; values are
+/// opaque bytes. Implement this trait to plug in sled, RocksDB, or another
+/// store through [`crate::AgentBuilder::with_storage`].
+#[async_trait::async_trait]
+pub trait Storage: Send + Sync {
+    /// Get the value stored under a key
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Store a value, replacing any existing one
+    async fn put(&self, key: &str, value: Vec<u8>) -> Result<()>;
+
+    /// Delete a key, returning true if it existed
+    async fn delete(&self, key: &str) -> Result<bool>;
+
+    /// All entries whose key starts with the prefix, sorted by key
+    async fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>>;
+}
+
+/// In-memory storage, for tests and agents that need no persistence
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for MemoryStorage {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.read().await.get(key).cloned())
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.entries.write().await.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool> {
+        Ok(self.entries.write().await.remove(key).is_some())
+    }
+
+    async fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        Ok(self.entries
+            .read()
+            .await
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+}
+
+/// Storage keeping one file per key in a directory
+///
+/// Keys are escaped into flat file names, so they can never point outside
+/// the storage directory.
+#[derive(Debug, Clone)]
+pub struct FsStorage {
+    root: PathBuf,
+}
+
+impl FsStorage {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.root.join(encode_key(key))
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for FsStorage {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path_for(key)).await {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        tokio::fs::create_dir_all(&self.root).await?;
+
+        // Write to a temporary file first so readers never see a partial value
+        let path = self.path_for(key);
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, value).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool> {
+        match tokio::fs::remove_file(self.path_for(key)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut dir = match tokio::fs::read_dir(&self.root).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut entries = Vec::new();
+        while let Some(entry) = dir.next_entry().await? {
+            let Some(key) = entry.file_name().to_str().and_then(decode_key) else {
+                continue; // Temporary or foreign files
+            };
+            if key.starts_with(prefix) {
+                entries.push((key, tokio::fs::read(entry.path()).await?));
+            }
+        }
+
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
+}
+
+/// Escape a key into a file name, keeping only `[A-Za-z0-9_-]` and `.` as-is
+fn encode_key(key: &str) -> String {
+    let mut name = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' => name.push(byte as char),
+            // Escape dots too, so "." and ".." are never produced
+            _ => name.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    name
+}
+
+/// Reverse `encode_key`, returning None for names it could not have produced
+fn decode_key(name: &str) -> Option<String> {
+    let bytes = name.as_bytes();
+    let mut key = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                key.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' => {
+                key.push(bytes[i]);
+                i += 1;
+            }
+            _ => return None,
+        }
+    }
+    String::from_utf8(key).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Behaviour every backend must share
+    async fn exercise_storage(storage: &dyn Storage) {
+        assert_eq!(storage.get("missing").await.unwrap(), None);
+
+        storage.put("indicator/203.0.113.7", b"first".to_vec()).await.unwrap();
+        storage.put("indicator/203.0.113.7", b"second".to_vec()).await.unwrap();
+        storage.put("indicator/198.51.100.1", b"other".to_vec()).await.unwrap();
+        storage.put("reputation/source", b"{}".to_vec()).await.unwrap();
+        storage.put("../escape", b"contained".to_vec()).await.unwrap();
+
+        assert_eq!(storage.get("indicator/203.0.113.7").await.unwrap(), Some(b"second".to_vec()));
+        assert_eq!(storage.get("../escape").await.unwrap(), Some(b"contained".to_vec()));
+
+        let scanned = storage.scan_prefix("indicator/").await.unwrap();
+        let keys: Vec<&str> = scanned.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["indicator/198.51.100.1", "indicator/203.0.113.7"]);
+        assert_eq!(storage.scan_prefix("").await.unwrap().len(), 4);
+
+        assert!(storage.delete("indicator/203.0.113.7").await.unwrap());
+        assert!(!storage.delete("indicator/203.0.113.7").await.unwrap());
+        assert_eq!(storage.get("indicator/203.0.113.7").await.unwrap(), None);
+        assert_eq!(storage.scan_prefix("indicator/").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage() {
+        exercise_storage(&MemoryStorage::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_fs_storage() {
+        let dir = std::env::temp_dir().join(format!("orasrs-storage-{}", uuid::Uuid::new_v4()));
+        let storage = FsStorage::new(dir.join("state"));
+        exercise_storage(&storage).await;
+
+        // Nothing is written outside the storage directory
+        assert!(!dir.join("escape").exists());
+        assert_eq!(std::fs::read_dir(dir.join("state")).unwrap().count(), 3);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_key_encoding_round_trip() {
+        for key in ["indicator/203.0.113.7", "../escape", "2001:db8::1", "ünïcode key"] {
+            let name = encode_key(key);
+            assert!(!name.contains('/') && !name.contains('.'));
+            assert_eq!(decode_key(&name).as_deref(), Some(key));
+        }
+        assert_eq!(decode_key("name.tmp"), None);
+    }
+}
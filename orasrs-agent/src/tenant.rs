@@ -0,0 +1,187 @@
+use crate::{
+    AgentConfig,
+    agent::{AgentBuilder, OrasrsAgent},
+    error::{AgentError, Result},
+};
+use std::collections::BTreeMap;
+
+/// Hosts several isolated agents, one per tenant, in a single process
+///
+/// Each tenant gets its own [`OrasrsAgent`] with its own config, reputation,
+/// blocklist, and region; they only share the tokio runtime.
+pub struct TenantManager {
+    tenants: BTreeMap<String, OrasrsAgent>,
+}
+
+impl TenantManager {
+    pub fn new() -> Self {
+        Self {
+            tenants: BTreeMap::new(),
+        }
+    }
+
+    /// Create and register an agent for a tenant from its config
+    pub async fn add_tenant(&mut self, tenant_id: &str, config: AgentConfig) -> Result<()> {
+        self.add_tenant_with(tenant_id, AgentBuilder::new(config)).await
+    }
+
+    /// Create and register an agent for a tenant from a configured builder
+    pub async fn add_tenant_with(&mut self, tenant_id: &str, builder: AgentBuilder) -> Result<()> {
+        if self.tenants.contains_key(tenant_id) {
+            return Err(AgentError::ConfigError(format!("Tenant already exists: {}", tenant_id)));
+        }
+
+        self.check_isolation(tenant_id, builder.config())?;
+        let agent = builder.build().await?;
+
+        log::info!("Added tenant {} with agent ID {}", tenant_id, agent.config.agent_id);
+        self.tenants.insert(tenant_id.to_string(), agent);
+        Ok(())
+    }
+
+    /// Reject a tenant that would share on-disk state or a P2P port with an existing one
+    fn check_isolation(&self, tenant_id: &str, config: &AgentConfig) -> Result<()> {
+        for (other_id, other) in &self.tenants {
+            if other.config.agent_id == config.agent_id {
+                return Err(AgentError::ConfigError(format!(
+                    "Tenant {} reuses agent ID of tenant {}", tenant_id, other_id
+                )));
+            }
+            if other.config.storage_config.data_dir == config.storage_config.data_dir {
+                return Err(AgentError::ConfigError(format!(
+                    "Tenant {} shares data directory with tenant {}", tenant_id, other_id
+                )));
+            }
+            if other.config.p2p_config.listen_port == config.p2p_config.listen_port {
+                return Err(AgentError::ConfigError(format!(
+                    "Tenant {} shares P2P listen port with tenant {}", tenant_id, other_id
+                )));
+            }
+            if config.blocklist_export_enabled
+                && other.config.blocklist_export_enabled
+                && other.config.blocklist_file == config.blocklist_file
+            {
+                return Err(AgentError::ConfigError(format!(
+                    "Tenant {} shares blocklist file with tenant {}", tenant_id, other_id
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stop and remove a tenant's agent
+    pub async fn remove_tenant(&mut self, tenant_id: &str) -> Result<OrasrsAgent> {
+        let mut agent = self.tenants.remove(tenant_id)
+            .ok_or_else(|| AgentError::ConfigError(format!("Unknown tenant: {}", tenant_id)))?;
+        agent.stop().await?;
+        Ok(agent)
+    }
+
+    /// Get a tenant's agent
+    pub fn get(&self, tenant_id: &str) -> Option<&OrasrsAgent> {
+        self.tenants.get(tenant_id)
+    }
+
+    /// Get a tenant's agent mutably
+    pub fn get_mut(&mut self, tenant_id: &str) -> Option<&mut OrasrsAgent> {
+        self.tenants.get_mut(tenant_id)
+    }
+
+    /// IDs of all registered tenants, in sorted order
+    pub fn tenant_ids(&self) -> Vec<String> {
+        self.tenants.keys().cloned().collect()
+    }
+
+    /// Start every tenant's agent
+    pub async fn start_all(&mut self) -> Result<()> {
+        for (tenant_id, agent) in self.tenants.iter_mut() {
+            agent.start().await?;
+            log::info!("Started tenant {}", tenant_id);
+        }
+        Ok(())
+    }
+
+    /// Stop every tenant's agent
+    pub async fn stop_all(&mut self) -> Result<()> {
+        for (tenant_id, agent) in self.tenants.iter_mut() {
+            if let Err(e) = agent.stop().await {
+                log::error!("Failed to stop tenant {}: {}", tenant_id, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for TenantManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ThreatEvidence, test_evidence};
+
+    fn tenant_config(name: &str, listen_port: u16, dir: &std::path::Path) -> AgentConfig {
+        let mut config = AgentConfig::default();
+        config.p2p_config.bootstrap_nodes = Vec::new();
+        config.p2p_config.listen_port = listen_port;
+        config.agent_id = format!("agent-{}", name);
+        config.region = format!("region-{}", name);
+        config.blocklist_export_enabled = true;
+        config.blocklist_file = Some(dir.join(format!("{}-blocklist.txt", name)).to_string_lossy().to_string());
+        config.storage_config.data_dir = dir.join(name);
+        config
+    }
+
+    #[tokio::test]
+    async fn test_tenants_are_isolated() {
+        let dir = std::env::temp_dir().join(format!("orasrs-tenants-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut manager = TenantManager::new();
+        manager.add_tenant("alpha", tenant_config("alpha", 40411, &dir)).await.unwrap();
+        manager.add_tenant("beta", tenant_config("beta", 40412, &dir)).await.unwrap();
+        assert_eq!(manager.tenant_ids(), vec!["alpha", "beta"]);
+
+        // Sharing a blocklist file with another tenant is rejected
+        let mut clash = tenant_config("gamma", 40413, &dir);
+        clash.blocklist_file = tenant_config("alpha", 40411, &dir).blocklist_file;
+        assert!(manager.add_tenant("gamma", clash).await.is_err());
+        assert!(manager.add_tenant("gamma", tenant_config("gamma", 40411, &dir)).await.is_err());
+
+        // Keep the test offline
+        for tenant_id in manager.tenant_ids() {
+            let aggregator = &manager.get(&tenant_id).unwrap().threat_intel_aggregator;
+            for source in aggregator.get_sources_config() {
+                aggregator.set_source_enabled(&source.name, false).unwrap();
+            }
+        }
+
+        manager.start_all().await.unwrap();
+
+        let alpha = manager.get_mut("alpha").unwrap();
+        // Recent evidence, so the blocklist does not treat it as expired
+        let evidence = ThreatEvidence { timestamp: chrono::Utc::now().timestamp(), ..test_evidence("203.0.113.77") };
+        alpha.submit_threat_evidence(evidence.clone()).await.unwrap();
+        alpha.credibility_engine.update_credibility(&evidence, true).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        let alpha_blocklist = std::fs::read_to_string(dir.join("alpha-blocklist.txt")).unwrap();
+        let beta_blocklist = std::fs::read_to_string(dir.join("beta-blocklist.txt")).unwrap();
+        assert!(alpha_blocklist.contains("203.0.113.77"));
+        assert!(!beta_blocklist.contains("203.0.113.77"));
+
+        let alpha_metrics = manager.get("alpha").unwrap().credibility_engine.get_metrics().await;
+        let beta_metrics = manager.get("beta").unwrap().credibility_engine.get_metrics().await;
+        assert_eq!(alpha_metrics.total_sources_tracked, 1);
+        assert_eq!(beta_metrics.total_sources_tracked, 0);
+        assert_eq!(manager.get("alpha").unwrap().get_status().threat_count, 1);
+        assert_eq!(manager.get("beta").unwrap().get_status().threat_count, 0);
+
+        manager.stop_all().await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
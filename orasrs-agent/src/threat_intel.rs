@@ -0,0 +1,504 @@
+use crate::{ThreatEvidence, ThreatType, ThreatLevel, error::Result, storage::Storage};
+use ipnetwork::IpNetwork;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::{LazyLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Kind of indicator of compromise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndicatorType {
+    Ip,
+    Domain,
+    Url,
+    FileHash,
+}
+
+static URL_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?i)\bhttps?://[^\s"'<>|,]+"#).unwrap());
+static DOMAIN_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.)+[a-z]{2,24}\b").unwrap()
+});
+static HASH_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:[0-9a-f]{64}|[0-9a-f]{40}|[0-9a-f]{32})\b").unwrap() // SHA-256, SHA-1, MD5
+});
+
+/// Extract URL, domain and file hash indicators mentioned in free text, lowercased
+pub fn extract_indicators(text: &str) -> HashSet<(IndicatorType, String)> {
+    let text = text.to_lowercase();
+    let urls = URL_PATTERN
+        .find_iter(&text)
+        .map(|url| (IndicatorType::Url, url.as_str().trim_end_matches(['.', ')', ';']).to_string()));
+    let domains = DOMAIN_PATTERN.find_iter(&text).map(|domain| (IndicatorType::Domain, domain.as_str().to_string()));
+    let hashes = HASH_PATTERN.find_iter(&text).map(|hash| (IndicatorType::FileHash, hash.as_str().to_string()));
+    urls.chain(domains).chain(hashes).collect()
+}
+
+/// A locally known indicator of compromise
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Indicator {
+    pub value: String,
+    pub indicator_type: IndicatorType,
+    pub threat_type: ThreatType,
+    pub threat_level: ThreatLevel,
+    pub source: String,
+    pub added_at: i64,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+impl Indicator {
+    pub fn new(value: &str, indicator_type: IndicatorType, threat_type: ThreatType, threat_level: ThreatLevel, source: &str) -> Self {
+        Self {
+            value: value.trim().to_string(),
+            indicator_type,
+            threat_type,
+            threat_level,
+            source: source.to_string(),
+            added_at: now(),
+            expires_at: None,
+        }
+    }
+
+    /// Set the time after which the indicator is dropped
+    pub fn with_expiry(mut self, expires_at: Option<i64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    /// Build an IP indicator from upstream evidence, if it names a valid IP or network
+    pub fn from_evidence(evidence: &ThreatEvidence) -> Option<Self> {
+        let value = match &evidence.source_network {
+            Some(network) => {
+                network.parse::<IpNetwork>().ok()?;
+                network
+            }
+            None => {
+                evidence.source_ip.parse::<IpAddr>().ok()?;
+                &evidence.source_ip
+            }
+        };
+        Some(
+            Self::new(value, IndicatorType::Ip, evidence.threat_type.clone(), evidence.threat_level, &evidence.agent_id)
+                .with_expiry(evidence.expires_at),
+        )
+    }
+
+    /// Whether the indicator has expired at the given time
+    pub fn is_expired(&self, now: i64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if expires_at <= now)
+    }
+}
+
+/// Storage key prefix for persisted indicators
+const STORAGE_PREFIX: &str = "indicator/";
+
+/// Local IoC database consulted by the analyzer and consensus engine
+///
+/// Distinct from the upstream fetcher in `threat_intel_upstream`: this store
+/// holds the indicators the agent currently knows about and can persist them
+/// to a [`Storage`] backend between restarts.
+pub struct IndicatorStore {
+    indicators: RwLock<HashMap<String, Indicator>>,
+    network_prefixes: RwLock<HashSet<(bool, u8)>>, // (IPv6, prefix length) of CIDR indicators ever added
+}
+
+impl IndicatorStore {
+    /// Create an empty in-memory store
+    pub fn new() -> Self {
+        Self {
+            indicators: RwLock::new(HashMap::new()),
+            network_prefixes: RwLock::new(HashSet::new()),
+        }
+    }
+
+    /// Load the indicators persisted in a storage backend
+    ///
+    /// Corrupt entries are skipped with a warning.
+    pub async fn load(storage: &dyn Storage) -> Result<Self> {
+        let store = Self::new();
+        for (key, value) in storage.scan_prefix(STORAGE_PREFIX).await? {
+            match serde_json::from_slice(&value) {
+                Ok(indicator) => {
+                    store.add(indicator);
+                }
+                Err(e) => log::warn!("Ignoring corrupt indicator at storage key {}: {}", key, e),
+            }
+        }
+        Ok(store)
+    }
+
+    /// Write all indicators to a storage backend, dropping ones no longer held
+    pub async fn persist(&self, storage: &dyn Storage) -> Result<()> {
+        let indicators: Vec<(String, Indicator)> = self.snapshot()
+            .into_iter()
+            .map(|(key, indicator)| (format!("{}{}", STORAGE_PREFIX, key), indicator))
+            .collect();
+
+        for (key, _) in storage.scan_prefix(STORAGE_PREFIX).await? {
+            if !indicators.iter().any(|(current, _)| *current == key) {
+                storage.delete(&key).await?;
+            }
+        }
+        for (key, indicator) in indicators {
+            storage.put(&key, serde_json::to_vec(&indicator)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Load indicators saved by `save_to`
+    ///
+    /// A missing or corrupt file gives an empty store.
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("No indicators at {}, starting fresh", path.display());
+                return Ok(Self::new());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let store = Self::new();
+        match serde_json::from_slice::<Vec<Indicator>>(&content) {
+            Ok(indicators) => {
+                for indicator in indicators {
+                    store.add(indicator);
+                }
+            }
+            Err(e) => log::warn!("Ignoring corrupt indicators at {}: {}", path.display(), e),
+        }
+        Ok(store)
+    }
+
+    /// Save the same indicators as `persist` to a JSON file
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let indicators: Vec<Indicator> = self.snapshot().into_iter().map(|(_, indicator)| indicator).collect();
+
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&indicators)?)?;
+        Ok(())
+    }
+
+    /// Every held indicator with its lookup key, sorted by key
+    fn snapshot(&self) -> Vec<(String, Indicator)> {
+        let mut indicators: Vec<(String, Indicator)> = self.indicators
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(key, indicator)| (key.clone(), indicator.clone()))
+            .collect();
+        indicators.sort_by(|a, b| a.0.cmp(&b.0));
+        indicators
+    }
+
+    /// Add or replace an indicator, returning true if it was not already known
+    ///
+    /// Indicators that have already expired are not stored.
+    pub fn add(&self, indicator: Indicator) -> bool {
+        if indicator.is_expired(now()) {
+            return false;
+        }
+
+        let key = Self::normalize(&indicator.value);
+        if indicator.indicator_type == IndicatorType::Ip {
+            if let Ok(network) = key.parse::<IpNetwork>() {
+                if key.contains('/') {
+                    self.network_prefixes.write().unwrap().insert((network.is_ipv6(), network.prefix()));
+                }
+            }
+        }
+        self.indicators.write().unwrap().insert(key, indicator).is_none()
+    }
+
+    /// Add indicators derived from upstream evidence, returning how many were new
+    pub fn ingest(&self, evidence: &[ThreatEvidence]) -> usize {
+        evidence
+            .iter()
+            .filter_map(Indicator::from_evidence)
+            .filter(|indicator| self.add(indicator.clone()))
+            .count()
+    }
+
+    /// Drop all expired indicators, returning how many were removed
+    pub fn purge_expired(&self) -> usize {
+        let now = now();
+        let mut indicators = self.indicators.write().unwrap();
+        let before = indicators.len();
+        indicators.retain(|_, indicator| !indicator.is_expired(now));
+        before - indicators.len()
+    }
+
+    /// Remove an indicator by value
+    pub fn remove(&self, value: &str) -> Option<Indicator> {
+        self.indicators.write().unwrap().remove(&Self::normalize(value))
+    }
+
+    /// Look up an unexpired indicator by value
+    pub fn get(&self, value: &str) -> Option<Indicator> {
+        self.indicators
+            .read()
+            .unwrap()
+            .get(&Self::normalize(value))
+            .filter(|indicator| !indicator.is_expired(now()))
+            .cloned()
+    }
+
+    /// Check whether a value is a known, unexpired indicator
+    pub fn contains(&self, value: &str) -> bool {
+        self.get(value).is_some()
+    }
+
+    /// Check whether an IP address is a known threat or inside a known network
+    ///
+    /// An exact IP is one hash lookup; networks add one lookup per distinct
+    /// prefix length held.
+    pub fn is_known_ip(&self, ip: &str) -> bool {
+        ip.trim().parse::<IpAddr>().is_ok_and(|ip| !self.ip_indicators(ip).is_empty())
+    }
+
+    /// Find all unexpired indicators mentioned in the given data
+    ///
+    /// IP indicators match addresses in the data that equal them or fall
+    /// inside their network; other indicators match as substrings.
+    pub fn matches_in(&self, data: &str) -> Vec<Indicator> {
+        let data = data.to_lowercase();
+        let now = now();
+        let mut matches: HashMap<String, Indicator> = self.indicators
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(key, indicator)| {
+                indicator.indicator_type != IndicatorType::Ip && !indicator.is_expired(now) && data.contains(key.as_str())
+            })
+            .map(|(key, indicator)| (key.clone(), indicator.clone()))
+            .collect();
+
+        let addresses: HashSet<IpAddr> = data
+            .split(|c: char| !(c.is_ascii_hexdigit() || c == '.' || c == ':'))
+            .filter_map(parse_address)
+            .collect();
+        for ip in addresses {
+            matches.extend(self.ip_indicators(ip));
+        }
+        matches.into_values().collect()
+    }
+
+    /// Unexpired IP indicators, keyed by value, for an address and every known network containing it
+    fn ip_indicators(&self, ip: IpAddr) -> Vec<(String, Indicator)> {
+        let prefixes: Vec<u8> = self.network_prefixes
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(ipv6, _)| *ipv6 == ip.is_ipv6())
+            .map(|&(_, prefix)| prefix)
+            .collect();
+        let networks = prefixes
+            .into_iter()
+            .filter_map(|prefix| IpNetwork::new(ip, prefix).ok())
+            .map(|network| format!("{}/{}", network.network(), network.prefix()));
+
+        std::iter::once(ip.to_string())
+            .chain(networks)
+            .filter_map(|key| {
+                self.get(&key)
+                    .filter(|indicator| indicator.indicator_type == IndicatorType::Ip)
+                    .map(|indicator| (key, indicator))
+            })
+            .collect()
+    }
+
+    /// Number of indicators in the store
+    pub fn len(&self) -> usize {
+        self.indicators.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Lookup key of a value; networks are keyed by their network address and single hosts as plain IPs
+    fn normalize(value: &str) -> String {
+        let value = value.trim().to_lowercase();
+        match value.parse::<IpNetwork>() {
+            Ok(network) => {
+                let host_prefix = if network.is_ipv4() { 32 } else { 128 };
+                if network.prefix() == host_prefix {
+                    network.ip().to_string()
+                } else {
+                    format!("{}/{}", network.network(), network.prefix())
+                }
+            }
+            _ => value,
+        }
+    }
+}
+
+/// Parse an address token from free text, allowing a trailing full stop or IPv4 port
+fn parse_address(token: &str) -> Option<IpAddr> {
+    let token = token.trim_end_matches('.');
+    token
+        .parse::<IpAddr>()
+        .or_else(|_| token.parse::<SocketAddr>().map(|address| address.ip()))
+        .ok()
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+impl Default for IndicatorStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip_indicator(ip: &str) -> Indicator {
+        Indicator::new(ip, IndicatorType::Ip, ThreatType::IoCMatch, ThreatLevel::Warning, "test")
+    }
+
+    #[test]
+    fn test_add_query_remove() {
+        let store = IndicatorStore::new();
+        assert!(store.add(ip_indicator("203.0.113.7")));
+        assert!(!store.add(ip_indicator("203.0.113.7")));
+        assert!(store.add(Indicator::new("Evil.Example.com", IndicatorType::Domain, ThreatType::Phishing, ThreatLevel::Critical, "test")));
+
+        assert_eq!(store.len(), 2);
+        assert!(store.is_known_ip("203.0.113.7"));
+        assert!(!store.is_known_ip("203.0.113.8"));
+        assert!(store.contains("evil.example.com"));
+        assert!(!store.is_known_ip("evil.example.com"));
+
+        let matches = store.matches_in("GET http://evil.example.com/login from 198.51.100.1");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].threat_type, ThreatType::Phishing);
+
+        assert!(store.remove("203.0.113.7").is_some());
+        assert!(!store.is_known_ip("203.0.113.7"));
+        assert!(store.remove("203.0.113.7").is_none());
+    }
+
+    #[test]
+    fn test_extract_indicators_from_text() {
+        let indicators = extract_indicators(
+            "Beacon to https://C2.Evil.example/gate.php, dropped 44D88612FEA8A8F36DE82E1278ABB02F from 203.0.113.9",
+        );
+        assert!(indicators.contains(&(IndicatorType::Url, "https://c2.evil.example/gate.php".to_string())));
+        assert!(indicators.contains(&(IndicatorType::Domain, "c2.evil.example".to_string())));
+        assert!(indicators.contains(&(IndicatorType::FileHash, "44d88612fea8a8f36de82e1278abb02f".to_string())));
+        assert!(!indicators.iter().any(|(_, value)| value.contains("203.0.113")));
+
+        assert!(extract_indicators("").is_empty());
+        assert!(extract_indicators("SSH brute force login attempts").is_empty());
+    }
+
+    #[test]
+    fn test_networks_cover_their_addresses() {
+        let store = IndicatorStore::new();
+        store.add(ip_indicator("203.0.113.77/24"));
+        store.add(ip_indicator("2001:db8::/32"));
+        store.add(ip_indicator("198.51.100.9/32"));
+
+        assert!(store.is_known_ip("203.0.113.1"));
+        assert!(store.is_known_ip("203.0.113.255"));
+        assert!(!store.is_known_ip("203.0.114.1"));
+        assert!(store.is_known_ip("2001:db8:1::7"));
+        assert!(!store.is_known_ip("2001:db9::7"));
+        assert!(store.is_known_ip("198.51.100.9"));
+        assert!(store.contains("203.0.113.0/24"));
+
+        assert!(store.remove("203.0.113.0/24").is_some());
+        assert!(!store.is_known_ip("203.0.113.1"));
+    }
+
+    #[test]
+    fn test_ip_indicators_match_whole_addresses() {
+        let store = IndicatorStore::new();
+        store.add(ip_indicator("10.0.0.1"));
+        store.add(ip_indicator("2001:DB8:0::7"));
+        store.add(ip_indicator("192.0.2.0/24"));
+
+        assert!(store.matches_in("TCP 10.0.0.10:443 -> 10.0.0.100:22").is_empty());
+        assert_eq!(store.matches_in("TCP 10.0.0.1:443 -> 10.0.0.100:22").len(), 1);
+        assert_eq!(store.matches_in("Blocked 10.0.0.1.").len(), 1);
+        assert_eq!(store.matches_in("UDP [2001:db8::7]:53").len(), 1);
+        assert!(store.matches_in("UDP [2001:db8::70]:53").is_empty());
+        assert!(store.is_known_ip("2001:db8::7"));
+
+        let matches = store.matches_in("192.0.2.10 -> 192.0.2.20");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].value, "192.0.2.0/24");
+        assert!(store.matches_in("192.0.20.1").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_persistence_round_trip() {
+        let storage = crate::storage::MemoryStorage::new();
+
+        let store = IndicatorStore::load(&storage).await.unwrap();
+        assert!(store.is_empty());
+        store.add(ip_indicator("198.51.100.23"));
+        store.add(ip_indicator("198.51.100.24"));
+        store.add(Indicator::new("d41d8cd98f00b204e9800998ecf8427e", IndicatorType::FileHash, ThreatType::Malware, ThreatLevel::Critical, "test"));
+        store.persist(&storage).await.unwrap();
+
+        // Removed indicators are dropped from storage on the next persist
+        store.remove("198.51.100.24");
+        store.persist(&storage).await.unwrap();
+
+        let reopened = IndicatorStore::load(&storage).await.unwrap();
+        assert_eq!(reopened.len(), 2);
+        assert!(reopened.is_known_ip("198.51.100.23"));
+        assert!(!reopened.is_known_ip("198.51.100.24"));
+        assert_eq!(reopened.get("d41d8cd98f00b204e9800998ecf8427e").unwrap().indicator_type, IndicatorType::FileHash);
+
+        // A corrupt entry is skipped rather than failing the load
+        storage.put(&format!("{}203.0.113.1", STORAGE_PREFIX), b"{not json".to_vec()).await.unwrap();
+        assert_eq!(IndicatorStore::load(&storage).await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_json_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("orasrs-indicators-{}.json", uuid::Uuid::new_v4()));
+
+        let store = IndicatorStore::new();
+        store.add(ip_indicator("198.51.100.23"));
+        store.save_to(&path).unwrap();
+
+        let loaded = IndicatorStore::load_from(&path).unwrap();
+        assert!(loaded.is_known_ip("198.51.100.23"));
+
+        // Corrupt or missing files give an empty store
+        fs::write(&path, b"{not json").unwrap();
+        assert!(IndicatorStore::load_from(&path).unwrap().is_empty());
+        let _ = fs::remove_file(&path);
+        assert!(IndicatorStore::load_from(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_expired_indicators_are_dropped() {
+        let store = IndicatorStore::new();
+        let now = now();
+
+        assert!(!store.add(ip_indicator("198.51.100.1").with_expiry(Some(now - 60))));
+        assert!(store.add(ip_indicator("198.51.100.2").with_expiry(Some(now + 3600))));
+        assert_eq!(store.len(), 1);
+        assert!(store.is_known_ip("198.51.100.2"));
+        assert_eq!(store.purge_expired(), 0);
+    }
+}
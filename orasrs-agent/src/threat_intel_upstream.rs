@@ -1,10 +1,12 @@
-use crate::{ThreatEvidence, ThreatType, ThreatLevel, error::{AgentError, Result}};
+use crate::{AgentConfig, ThreatEvidence, ThreatType, ThreatLevel, config::UpstreamSourceConfigFile, clock::{Clock, SystemClock}, compliance::mask_ip, error::{AgentError, Result}, storage::{MemoryStorage, Storage}, threat_intel::IndicatorType};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::time::{sleep, Duration};
-use reqwest;
-use url::Url;
+use reqwest::{self, Url};
 
 /// Upstream threat intelligence source configuration
 #[derive(Debug, Clone)]
@@ -15,24 +17,362 @@ pub struct UpstreamSourceConfig {
     pub enabled: bool,
     pub update_interval: u64, // in seconds
     pub threat_level_mapping: HashMap<String, ThreatLevel>,
+    pub taxii_collection: Option<String>, // TAXII 2.1 collection ID to poll under `url`
+}
+
+impl From<&UpstreamSourceConfigFile> for UpstreamSourceConfig {
+    fn from(source: &UpstreamSourceConfigFile) -> Self {
+        Self {
+            name: source.name.clone(),
+            url: source.url.clone(),
+            auth_token: source.auth_token.clone(),
+            enabled: source.enabled,
+            update_interval: source.update_interval,
+            threat_level_mapping: source.threat_level_mapping.clone(),
+            taxii_collection: source.taxii_collection.clone(),
+        }
+    }
+}
+
+/// Maximum TAXII pages fetched per poll; the rest resume on the next poll
+const MAX_TAXII_PAGES: usize = 100;
+
+/// Maximum number of upstream indicators remembered for de-duplication
+const MAX_CACHED_INDICATORS: usize = 50_000;
+
+/// Seconds an upstream request may take before it is abandoned
+const UPSTREAM_TIMEOUT: u64 = 30;
+
+/// Snapshot of the remembered upstream indicators, reused for the aggregator's cache TTL
+struct ThreatSnapshot {
+    taken_at: i64, // Clock time the snapshot was built
+    version: u64,  // Version of the indicator cache it was built from
+    threats: Arc<Vec<ThreatEvidence>>,
+}
+
+/// Media type of TAXII 2.1 requests and responses
+const TAXII_MEDIA_TYPE: &str = "application/taxii+json;version=2.1";
+
+/// Where polling of a TAXII collection resumes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaxiiCursor {
+    /// Only objects added after this time are requested
+    pub added_after: Option<String>,
+    /// Pagination token of the next page in an unfinished poll
+    pub next: Option<String>,
+    /// Newest `date_added` seen in an unfinished poll, promoted to `added_after` once it completes
+    pub pending_added_after: Option<String>,
+}
+
+/// State of a per-source circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Source is fetched normally
+    Closed,
+    /// Source is skipped until the cooldown elapses
+    Open,
+    /// Cooldown elapsed; the next fetch is a trial
+    HalfOpen,
+}
+
+/// Circuit breaker tracking consecutive failures of one source
+#[derive(Debug, Clone)]
+struct CircuitBreaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: i64,
+}
+
+impl CircuitBreaker {
+    fn new() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            opened_at: 0,
+        }
+    }
+
+    /// Whether the source may be fetched now, half-opening after the cooldown
+    fn allow(&mut self, now: i64, cooldown: i64) -> bool {
+        if self.state == BreakerState::Open && now - self.opened_at >= cooldown {
+            self.state = BreakerState::HalfOpen;
+        }
+        self.state != BreakerState::Open
+    }
+
+    fn record_success(&mut self) {
+        self.state = BreakerState::Closed;
+        self.consecutive_failures = 0;
+    }
+
+    fn record_failure(&mut self, now: i64, threshold: u32) {
+        self.consecutive_failures += 1;
+        if self.state == BreakerState::HalfOpen || self.consecutive_failures >= threshold {
+            self.state = BreakerState::Open;
+            self.opened_at = now;
+        }
+    }
+}
+
+/// Fetch timing of one source, used for rate limiting and backoff
+#[derive(Debug, Clone, Default)]
+struct SourceSchedule {
+    last_success: Option<i64>,
+    consecutive_failures: u32, // Rate-limited or server-error responses in a row
+    retry_at: i64,
+}
+
+impl SourceSchedule {
+    /// Why the source must not be fetched yet, if it must not
+    fn check(&self, now: i64, update_interval: u64) -> Option<FetchOutcome> {
+        if now < self.retry_at {
+            return Some(FetchOutcome::BackingOff { until: self.retry_at });
+        }
+        match self.last_success {
+            Some(last_success) if now - last_success < update_interval as i64 => Some(FetchOutcome::NotDue),
+            _ => None,
+        }
+    }
+
+    fn record_success(&mut self, now: i64) {
+        self.last_success = Some(now);
+        self.consecutive_failures = 0;
+        self.retry_at = 0;
+    }
+
+    /// Double the delay on every failure up to `max_delay`, waiting at least
+    /// as long as the server asked; returns the delay applied
+    fn record_unavailable(&mut self, now: i64, retry_after: Option<u64>, base_delay: u64, max_delay: u64) -> u64 {
+        self.consecutive_failures += 1;
+        let exponential = base_delay.saturating_mul(1 << (self.consecutive_failures - 1).min(32));
+        let delay = exponential.max(retry_after.unwrap_or(0)).min(max_delay);
+        self.retry_at = now + delay as i64;
+        delay
+    }
+}
+
+/// An upstream indicator remembered across fetch cycles
+#[derive(Debug, Clone)]
+struct CachedIndicator {
+    evidence: ThreatEvidence,
+    first_seen: i64,
+    last_used: u64,
+}
+
+/// How an observed indicator compares to the cached one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Observation {
+    New,
+    Refreshed, // Already known, with a different expiry
+    Unchanged,
+}
+
+/// Least-recently-used cache of upstream indicators keyed by a stable identity
+#[derive(Debug, Default)]
+struct IndicatorCache {
+    entries: HashMap<String, CachedIndicator>,
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    version: u64, // Bumped on every change to the cached indicators
+}
+
+impl IndicatorCache {
+    /// Identity of an indicator that stays the same across fetches
+    fn identity(source: &UpstreamSourceConfig, evidence: &ThreatEvidence) -> String {
+        format!("{}|{}|{}", source.name, evidence.source_ip, evidence.network_flow)
+    }
+
+    /// Remember an indicator
+    ///
+    /// A known indicator keeps its ID and first-seen time but takes the
+    /// latest level, context and expiry.
+    fn observe(&mut self, key: String, mut evidence: ThreatEvidence, now: i64) -> Observation {
+        self.tick += 1;
+        self.version += 1;
+        let (first_seen, observation) = match self.entries.get(&key) {
+            Some(cached) => {
+                self.recency.remove(&cached.last_used);
+                evidence.id = cached.evidence.id.clone();
+                let observation = if cached.evidence.expires_at == evidence.expires_at {
+                    Observation::Unchanged
+                } else {
+                    Observation::Refreshed
+                };
+                (cached.first_seen, observation)
+            }
+            None => (now, Observation::New),
+        };
+
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, CachedIndicator { evidence, first_seen, last_used: self.tick });
+
+        while self.entries.len() > MAX_CACHED_INDICATORS {
+            let Some((_, oldest)) = self.recency.pop_first() else { break };
+            self.entries.remove(&oldest);
+        }
+        observation
+    }
+
+    /// Cached evidence first seen after `since`, oldest first
+    fn first_seen_after(&self, since: i64) -> Vec<ThreatEvidence> {
+        let mut indicators: Vec<&CachedIndicator> = self.entries
+            .values()
+            .filter(|cached| cached.first_seen > since)
+            .collect();
+        indicators.sort_by_key(|cached| (cached.first_seen, cached.last_used));
+        indicators.into_iter().map(|cached| cached.evidence.clone()).collect()
+    }
+
+    /// Drop cached indicators about `ip`, returning how many were dropped
+    fn forget_ip(&mut self, ip: IpAddr) -> usize {
+        let keys: Vec<String> = self.entries
+            .iter()
+            .filter(|(_, cached)| cached.evidence.concerns_ip(ip))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &keys {
+            if let Some(cached) = self.entries.remove(key) {
+                self.recency.remove(&cached.last_used);
+            }
+        }
+        if !keys.is_empty() {
+            self.version += 1;
+        }
+        keys.len()
+    }
+}
+
+/// Outcome of fetching one source in a fetch cycle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchOutcome {
+    Fetched { threats: usize },
+    Failed { error: String },
+    /// Skipped because the source's circuit breaker is open
+    Skipped,
+    /// Skipped because the source was fetched less than its update interval ago
+    NotDue,
+    /// Skipped while backing off after a rate limit or server error
+    BackingOff { until: i64 },
+}
+
+/// Per-source result of a fetch cycle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceFetchStatus {
+    pub name: String,
+    pub outcome: FetchOutcome,
+    pub breaker_state: BreakerState,
+    pub consecutive_failures: u32,
+}
+
+/// Result of fetching all enabled sources
+#[derive(Debug, Clone, Default)]
+pub struct FetchReport {
+    pub threats: Vec<ThreatEvidence>,   // Only indicators not seen in an earlier fetch
+    pub refreshed: Vec<ThreatEvidence>, // Indicators seen in an earlier fetch whose expiry changed
+    pub sources: Vec<SourceFetchStatus>,
 }
 
 /// Upstream threat intelligence aggregator
+///
+/// Clones share the same source list, so runtime toggles made through one
+/// handle are seen by the periodic fetch running on another.
+#[derive(Clone)]
 pub struct ThreatIntelAggregator {
-    sources: Vec<UpstreamSourceConfig>,
+    sources: Arc<RwLock<Vec<UpstreamSourceConfig>>>,
     client: reqwest::Client,
-    last_update_times: HashMap<String, i64>,
+    schedules: Arc<RwLock<HashMap<String, SourceSchedule>>>,
+    seen: Arc<RwLock<IndicatorCache>>,
+    backoff_base: u64,
+    backoff_max: u64,
+    breakers: Arc<RwLock<HashMap<String, CircuitBreaker>>>,
+    failure_threshold: u32,
+    cooldown: i64,
+    clock: Arc<dyn Clock>,
+    storage: Arc<dyn Storage>,
+    simulated: bool, // Serve canned CISA AIS objects instead of contacting the server
+    threats_fetched: Arc<AtomicU64>,
+    snapshot: Arc<Mutex<Option<ThreatSnapshot>>>,
+    snapshot_ttl: i64,
 }
 
 impl ThreatIntelAggregator {
     pub fn new() -> Self {
         Self {
-            sources: vec![
+            sources: Arc::new(RwLock::new(vec![
                 Self::create_cisa_ais_config(),  // CISA AIS as primary source
-            ],
-            client: reqwest::Client::new(),
-            last_update_times: HashMap::new(),
+            ])),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(UPSTREAM_TIMEOUT))
+                .build()
+                .unwrap_or_default(),
+            schedules: Arc::new(RwLock::new(HashMap::new())),
+            seen: Arc::new(RwLock::new(IndicatorCache::default())),
+            backoff_base: 30,
+            backoff_max: 3600, // 1 hour
+            breakers: Arc::new(RwLock::new(HashMap::new())),
+            failure_threshold: 3,
+            cooldown: 600, // 10 minutes
+            clock: Arc::new(SystemClock),
+            storage: Arc::new(MemoryStorage::new()),
+            simulated: false,
+            threats_fetched: Arc::new(AtomicU64::new(0)),
+            snapshot: Arc::new(Mutex::new(None)),
+            snapshot_ttl: 0,
+        }
+    }
+
+    /// Serve canned CISA AIS objects instead of contacting the TAXII server
+    #[cfg(test)]
+    pub(crate) fn with_simulated_feeds(mut self) -> Self {
+        self.simulated = true;
+        self
+    }
+
+    /// Create an aggregator polling the sources declared in the config
+    ///
+    /// Falls back to the built-in CISA AIS source when none are declared.
+    pub fn from_config(config: &AgentConfig) -> Self {
+        let aggregator = Self::new().with_snapshot_cache(config.upstream_cache_ttl);
+        if !config.upstream_sources.is_empty() {
+            *aggregator.sources.write().unwrap() = config.upstream_sources.iter().map(UpstreamSourceConfig::from).collect();
         }
+        aggregator
+    }
+
+    /// Persist TAXII polling cursors in a storage backend so restarts resume
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Reuse the snapshot of known threats for `ttl` seconds instead of rebuilding it on every read
+    pub fn with_snapshot_cache(mut self, ttl: u64) -> Self {
+        self.snapshot_ttl = ttl as i64;
+        self
+    }
+
+    /// Open a source's breaker after `failure_threshold` consecutive failures
+    /// and skip it for `cooldown` seconds before retrying
+    pub fn with_circuit_breaker(mut self, failure_threshold: u32, cooldown: u64) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self.cooldown = cooldown as i64;
+        self
+    }
+
+    /// Back off a source for `base_delay` seconds after a rate limit or server
+    /// error, doubling on each further one up to `max_delay`
+    pub fn with_backoff(mut self, base_delay: u64, max_delay: u64) -> Self {
+        self.backoff_base = base_delay.max(1);
+        self.backoff_max = max_delay.max(self.backoff_base);
+        self
+    }
+
+    /// Use a custom clock for breaker cooldowns
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
     }
 
     /// Create default CISA AIS configuration
@@ -50,32 +390,125 @@ impl ThreatIntelAggregator {
             enabled: false,   // Disabled by default, requires proper credentials
             update_interval: 300, // 5 minutes
             threat_level_mapping,
+            taxii_collection: None,
         }
     }
 
     /// Add an upstream source
     pub fn add_source(&mut self, config: UpstreamSourceConfig) {
-        self.sources.push(config);
+        self.sources.write().unwrap().push(config);
+    }
+
+    /// Enable or disable a source at runtime, taking effect on the next fetch cycle
+    pub fn set_source_enabled(&self, name: &str, enabled: bool) -> Result<()> {
+        let mut sources = self.sources.write().unwrap();
+        let source = sources
+            .iter_mut()
+            .find(|source| source.name == name)
+            .ok_or_else(|| AgentError::ConfigError(format!("Unknown upstream source: {}", name)))?;
+
+        if source.enabled != enabled {
+            log::info!("Upstream source '{}' {}", name, if enabled { "enabled" } else { "disabled" });
+        }
+        source.enabled = enabled;
+
+        Ok(())
     }
 
     /// Fetch threat intelligence from all enabled sources
+    ///
+    /// Indicators already returned by an earlier fetch are left out unless
+    /// their expiry changed, so stores keyed on them can extend it.
     pub async fn fetch_all_sources(&self) -> Result<Vec<ThreatEvidence>> {
-        let mut all_threats = Vec::new();
+        let report = self.fetch_with_report().await?;
+        Ok(report.threats.into_iter().chain(report.refreshed).collect())
+    }
 
-        for source in &self.sources {
-            if !source.enabled {
-                continue;
-            }
+    /// Fetch from all enabled sources, reporting each source's outcome and breaker state
+    pub async fn fetch_with_report(&self) -> Result<FetchReport> {
+        let mut report = FetchReport::default();
 
-            match self.fetch_source(source).await {
-                Ok(threats) => all_threats.extend(threats),
-                Err(e) => {
-                    log::warn!("Failed to fetch from upstream source '{}': {}", source.name, e);
+        // Snapshot the enabled sources so the lock isn't held across fetches
+        let enabled_sources: Vec<UpstreamSourceConfig> = self.sources
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|source| source.enabled)
+            .cloned()
+            .collect();
+
+        for source in &enabled_sources {
+            let allowed = self.breakers
+                .write()
+                .unwrap()
+                .entry(source.name.clone())
+                .or_insert_with(CircuitBreaker::new)
+                .allow(self.clock.now(), self.cooldown);
+
+            let not_ready = self.schedules
+                .read()
+                .unwrap()
+                .get(&source.name)
+                .and_then(|schedule| schedule.check(self.clock.now(), source.update_interval));
+
+            let outcome = if !allowed {
+                log::debug!("Skipping upstream source '{}': circuit breaker open", source.name);
+                FetchOutcome::Skipped
+            } else if let Some(outcome) = not_ready {
+                log::debug!("Skipping upstream source '{}': {:?}", source.name, outcome);
+                outcome
+            } else {
+                let result = self.fetch_source(source).await;
+
+                let mut schedules = self.schedules.write().unwrap();
+                let schedule = schedules.entry(source.name.clone()).or_default();
+                let mut breakers = self.breakers.write().unwrap();
+                let breaker = breakers.entry(source.name.clone()).or_insert_with(CircuitBreaker::new);
+                match result {
+                    Ok(threats) => {
+                        schedule.record_success(self.clock.now());
+                        breaker.record_success();
+                        let count = threats.len();
+                        self.threats_fetched.fetch_add(count as u64, Ordering::Relaxed);
+                        let mut seen = self.seen.write().unwrap();
+                        for threat in threats {
+                            match seen.observe(IndicatorCache::identity(source, &threat), threat.clone(), self.clock.now()) {
+                                Observation::New => report.threats.push(threat),
+                                Observation::Refreshed => report.refreshed.push(threat),
+                                Observation::Unchanged => {}
+                            }
+                        }
+                        FetchOutcome::Fetched { threats: count }
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to fetch from upstream source '{}': {}", source.name, e);
+                        if let AgentError::UpstreamUnavailable { retry_after, .. } = e {
+                            let delay = schedule.record_unavailable(self.clock.now(), retry_after, self.backoff_base, self.backoff_max);
+                            log::warn!("Backing off upstream source '{}' for {}s", source.name, delay);
+                        }
+                        breaker.record_failure(self.clock.now(), self.failure_threshold);
+                        if breaker.state == BreakerState::Open {
+                            log::warn!(
+                                "Circuit breaker opened for upstream source '{}' after {} consecutive failures",
+                                source.name, breaker.consecutive_failures
+                            );
+                        }
+                        FetchOutcome::Failed { error: e.to_string() }
+                    }
                 }
-            }
+            };
+
+            let breakers = self.breakers.read().unwrap();
+            let breaker = &breakers[&source.name];
+            report.sources.push(SourceFetchStatus {
+                name: source.name.clone(),
+                outcome,
+                breaker_state: breaker.state,
+                consecutive_failures: breaker.consecutive_failures,
+            });
         }
 
-        Ok(all_threats)
+        Ok(report)
     }
 
     /// Fetch threat intelligence from a specific source
@@ -88,9 +521,10 @@ impl ThreatIntelAggregator {
             .unwrap()
             .as_secs());
 
-        // For CISA AIS (TAXII), we would need to implement proper TAXII client functionality
-        // For now, we'll simulate the data fetch for demonstration purposes
-        if source.name == "CISA_AIS" {
+        if let Some(collection) = &source.taxii_collection {
+            self.poll_taxii_collection(source, &source.url, collection, &fetch_id).await
+        } else if source.name == "CISA_AIS" {
+            // Without a configured collection, discover the CISA AIS collections
             self.fetch_cisa_ais_data(source, &fetch_id).await
         } else {
             // For other sources, we'll implement a generic fetch mechanism
@@ -98,53 +532,184 @@ impl ThreatIntelAggregator {
         }
     }
 
-    /// Fetch data from CISA AIS (TAXII 2.1 compatible implementation)
+    /// Fetch data from CISA AIS over TAXII 2.1
+    ///
+    /// Discovers the default API root from `url`, then polls every readable
+    /// collection under it.
     async fn fetch_cisa_ais_data(&self, source: &UpstreamSourceConfig, fetch_id: &str) -> Result<Vec<ThreatEvidence>> {
         log::info!("Fetching CISA AIS data for fetch ID: {}", fetch_id);
 
-        // In a real implementation, this would be a proper TAXII 2.1 client
-        // For demonstration, we'll simulate a TAXII response with STIX objects
+        if self.simulated {
+            return Ok(self.simulated_cisa_ais_data(source, fetch_id));
+        }
+
+        let api_root = self.discover_api_root(source).await?;
         let mut threats = Vec::new();
+        for collection in self.list_collections(source, &api_root).await? {
+            threats.extend(self.poll_taxii_collection(source, api_root.as_str(), &collection, fetch_id).await?);
+        }
 
-        // Simulate STIX objects that would be received from CISA AIS
-        // This is a simplified version - real STIX objects are more complex
+        log::info!("Retrieved {} threats from CISA AIS", threats.len());
+        Ok(threats)
+    }
+
+    /// Canned STIX objects standing in for a CISA AIS response
+    fn simulated_cisa_ais_data(&self, source: &UpstreamSourceConfig, fetch_id: &str) -> Vec<ThreatEvidence> {
         let simulated_stix_threats = [
-            r#"{
+            serde_json::json!({
                 "type": "indicator",
                 "id": "indicator--12345",
                 "pattern": "[ipv4-addr:value = '192.168.1.100']",
                 "pattern_type": "stix",
                 "labels": ["malicious-activity"],
-                "created": "2023-01-01T00:00:00.000Z",
-                "modified": "2023-01-01T00:00:00.000Z",
                 "name": "CISA Alert: Malicious IP",
                 "description": "IP address associated with known malicious activity",
                 "confidence": 85
-            }"#,
-            r#"{
-                "type": "indicator", 
+            }),
+            serde_json::json!({
+                "type": "indicator",
                 "id": "indicator--67890",
                 "pattern": "[file:hashes.'SHA-256' = 'abc123...']",
                 "pattern_type": "stix",
                 "labels": ["malware"],
-                "created": "2023-01-01T00:00:00.000Z",
-                "modified": "2023-01-01T00:00:00.000Z", 
                 "name": "CISA Alert: Malware Hash",
                 "description": "Malware hash associated with recent threat campaign",
                 "confidence": 90
-            }"#
+            }),
         ];
 
-        for stix_str in &simulated_stix_threats {
-            // Parse the STIX object and convert to ThreatEvidence
-            if let Ok(stix_obj) = serde_json::from_str::<serde_json::Value>(stix_str) {
-                if let Some(threat_evidence) = self.convert_stix_to_threat_evidence(&stix_obj, source, fetch_id) {
-                    threats.push(threat_evidence);
+        simulated_stix_threats
+            .iter()
+            .filter_map(|stix_obj| self.convert_stix_to_threat_evidence(stix_obj, source, fetch_id))
+            .collect()
+    }
+
+    /// GET a TAXII endpoint with the TAXII media type and the source's bearer token
+    async fn taxii_get(&self, source: &UpstreamSourceConfig, url: &str, query: &[(&str, String)]) -> Result<reqwest::Response> {
+        let mut request = self.client
+            .get(url)
+            .header(reqwest::header::ACCEPT, TAXII_MEDIA_TYPE)
+            .query(query);
+        if let Some(token) = &source.auth_token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| request_error(format!("Failed to poll {}", source.name), e))?;
+        check_response_status(source, response)
+    }
+
+    /// Read a TAXII response body as JSON
+    async fn taxii_json(&self, source: &UpstreamSourceConfig, response: reqwest::Response) -> Result<serde_json::Value> {
+        response
+            .json()
+            .await
+            .map_err(|e| AgentError::NetworkError(format!("Invalid TAXII response from {}: {}", source.name, e)))
+    }
+
+    /// Resolve the default API root from the source's discovery endpoint
+    async fn discover_api_root(&self, source: &UpstreamSourceConfig) -> Result<Url> {
+        let discovery_url = Url::parse(&source.url)
+            .map_err(|e| AgentError::ConfigError(format!("Invalid TAXII URL for {}: {}", source.name, e)))?;
+        let response = self.taxii_get(source, discovery_url.as_str(), &[]).await?;
+        let discovery = self.taxii_json(source, response).await?;
+
+        // Fall back to the first listed root when no default is advertised
+        let api_root = discovery.get("default")
+            .and_then(|v| v.as_str())
+            .or_else(|| discovery.get("api_roots")?.as_array()?.first()?.as_str())
+            .ok_or_else(|| AgentError::NetworkError(format!("No TAXII API root advertised by {}", source.name)))?;
+
+        // API roots may be relative to the discovery URL and must end in a slash
+        let mut api_root = discovery_url
+            .join(api_root)
+            .map_err(|e| AgentError::NetworkError(format!("Invalid TAXII API root from {}: {}", source.name, e)))?;
+        if !api_root.path().ends_with('/') {
+            let path = format!("{}/", api_root.path());
+            api_root.set_path(&path);
+        }
+        Ok(api_root)
+    }
+
+    /// IDs of the collections under an API root that can be read
+    async fn list_collections(&self, source: &UpstreamSourceConfig, api_root: &Url) -> Result<Vec<String>> {
+        let collections_url = api_root
+            .join("collections/")
+            .map_err(|e| AgentError::NetworkError(format!("Invalid TAXII API root from {}: {}", source.name, e)))?;
+        let response = self.taxii_get(source, collections_url.as_str(), &[]).await?;
+        let collections = self.taxii_json(source, response).await?;
+
+        Ok(collections.get("collections")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter(|collection| collection.get("can_read").and_then(|v| v.as_bool()).unwrap_or(true))
+            .filter_map(|collection| collection.get("id").and_then(|v| v.as_str()))
+            .map(|id| id.to_string())
+            .collect())
+    }
+
+    /// Poll a TAXII 2.1 collection, resuming from the persisted cursor
+    ///
+    /// The cursor is saved after every page, so an interrupted poll continues
+    /// from its last page and a finished one only asks for newer objects.
+    async fn poll_taxii_collection(&self, source: &UpstreamSourceConfig, api_root: &str, collection: &str, fetch_id: &str) -> Result<Vec<ThreatEvidence>> {
+        let cursor_key = format!("taxii_cursor/{}/{}", source.name, collection);
+        let mut cursor: TaxiiCursor = match self.storage.get(&cursor_key).await? {
+            Some(value) => serde_json::from_slice(&value)?,
+            None => TaxiiCursor::default(),
+        };
+
+        let objects_url = format!("{}/collections/{}/objects/", api_root.trim_end_matches('/'), collection);
+        let mut threats = Vec::new();
+
+        for _ in 0..MAX_TAXII_PAGES {
+            let mut query = Vec::new();
+            if let Some(added_after) = &cursor.added_after {
+                query.push(("added_after", added_after.clone()));
+            }
+            if let Some(next) = &cursor.next {
+                query.push(("next", next.clone()));
+            }
+
+            let response = self.taxii_get(source, &objects_url, &query).await?;
+            let date_added_last = response
+                .headers()
+                .get("X-TAXII-Date-Added-Last")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
+            let envelope = self.taxii_json(source, response).await?;
+
+            for object in envelope.get("objects").and_then(|v| v.as_array()).into_iter().flatten() {
+                if let Some(threat) = self.convert_stix_to_threat_evidence(object, source, fetch_id) {
+                    threats.push(threat);
                 }
             }
+
+            if date_added_last.is_some() {
+                cursor.pending_added_after = date_added_last;
+            }
+            let more = envelope.get("more").and_then(|v| v.as_bool()).unwrap_or(false);
+            let next = envelope.get("next").and_then(|v| v.as_str()).map(|v| v.to_string());
+            match next {
+                Some(next) if more => cursor.next = Some(next),
+                _ => {
+                    cursor.next = None;
+                    if let Some(pending) = cursor.pending_added_after.take() {
+                        cursor.added_after = Some(pending);
+                    }
+                }
+            }
+
+            self.storage.put(&cursor_key, serde_json::to_vec(&cursor)?).await?;
+            if cursor.next.is_none() {
+                break;
+            }
         }
 
-        log::info!("Retrieved {} threats from CISA AIS", threats.len());
+        log::info!("Retrieved {} threats from TAXII collection {} of {}", threats.len(), collection, source.name);
         Ok(threats)
     }
 
@@ -157,7 +722,7 @@ impl ThreatIntelAggregator {
             headers.insert(
                 reqwest::header::AUTHORIZATION,
                 reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token))
-                    .map_err(|e| AgentError::IoError(format!("Invalid auth token: {}", e)))?,
+                    .map_err(|e| AgentError::ConfigError(format!("Invalid auth token: {}", e)))?,
             );
         }
 
@@ -167,20 +732,13 @@ impl ThreatIntelAggregator {
             .headers(headers)
             .send()
             .await
-            .map_err(|e| AgentError::IoError(format!("Failed to fetch from {}: {}", source.name, e)))?;
-
-        if !response.status().is_success() {
-            return Err(AgentError::IoError(format!(
-                "HTTP error {} from {}",
-                response.status(),
-                source.name
-            )));
-        }
+            .map_err(|e| request_error(format!("Failed to fetch from {}", source.name), e))?;
+        let response = check_response_status(source, response)?;
 
         let text = response
             .text()
             .await
-            .map_err(|e| AgentError::IoError(format!("Failed to read response from {}: {}", source.name, e)))?;
+            .map_err(|e| request_error(format!("Failed to read response from {}", source.name), e))?;
 
         // Parse the response based on the content type
         let threats = self.parse_generic_threat_feed(&text, source, fetch_id)?;
@@ -191,23 +749,25 @@ impl ThreatIntelAggregator {
 
     /// Convert STIX object to internal ThreatEvidence format
     fn convert_stix_to_threat_evidence(&self, stix_obj: &serde_json::Value, source: &UpstreamSourceConfig, fetch_id: &str) -> Option<ThreatEvidence> {
-        let threat_type = match stix_obj.get("labels").and_then(|v| v.as_array()) {
-            Some(labels) => {
-                for label in labels {
-                    if let Some(label_str) = label.as_str() {
-                        match label_str {
-                            "malicious-activity" => ThreatType::SuspiciousConnection,
-                            "malware" => ThreatType::Malware,
-                            "apt" => ThreatType::APT,
-                            "ddos" => ThreatType::DDoS,
-                            _ => ThreatType::IoCMatch,
-                        }
-                    }
-                }
-                ThreatType::IoCMatch // default
-            },
-            None => ThreatType::IoCMatch,
-        };
+        let pattern = stix_obj.get("pattern").and_then(|v| v.as_str()).unwrap_or("");
+        let observables = parse_stix_pattern(pattern);
+
+        // Use the first recognized label; without one, only a file hash implies
+        // a threat type (a malicious file), while domains, URLs and IPs do not
+        let threat_type = stix_obj.get("labels")
+            .and_then(|v| v.as_array())
+            .and_then(|labels| {
+                labels.iter()
+                    .filter_map(|label| label.as_str()?.parse::<ThreatType>().ok())
+                    .find(|threat_type| *threat_type != ThreatType::Unknown)
+            })
+            .or_else(|| {
+                observables
+                    .iter()
+                    .any(|observable| observable.indicator_type == IndicatorType::FileHash)
+                    .then_some(ThreatType::Malware)
+            })
+            .unwrap_or(ThreatType::IoCMatch); // default
 
         let threat_level = match stix_obj.get("confidence").and_then(|v| v.as_number()) {
             Some(conf) => {
@@ -225,16 +785,19 @@ impl ThreatIntelAggregator {
             None => ThreatLevel::Warning,
         };
 
-        // Extract indicator pattern to identify the threat
-        let pattern = stix_obj.get("pattern").and_then(|v| v.as_str()).unwrap_or("");
         let description = stix_obj.get("description").and_then(|v| v.as_str()).unwrap_or("");
         
-        // Extract IP address if present in the pattern
-        let source_ip = if pattern.contains("ipv4-addr:value") {
-            // This is a simplified extraction - in reality, STIX patterns are more complex
-            extract_ip_from_pattern(pattern).unwrap_or("unknown".to_string())
+        let source_ip = extract_ip_from_pattern(pattern).unwrap_or_else(|| "unknown".to_string());
+
+        // Keep every observable, typed, so non-IP indicators are not lost
+        let network_flow = if observables.is_empty() {
+            pattern.to_string()
         } else {
-            "unknown".to_string()
+            observables
+                .iter()
+                .map(|observable| format!("{}:{}", observable.kind, observable.value))
+                .collect::<Vec<_>>()
+                .join(" ")
         };
 
         let timestamp = SystemTime::now()
@@ -260,50 +823,134 @@ impl ThreatIntelAggregator {
                 format!("{}-{}", fetch_id, pattern).as_bytes()
             ),
             geolocation: "unknown".to_string(),
-            network_flow: pattern.to_string(),
+            network_flow,
             agent_id: format!("upstream-{}", source.name),
             reputation: 0.95, // Upstream sources typically have high reputation
             compliance_tag: "upstream".to_string(),
             region: "global".to_string(),
+            expires_at: parse_expiry(stix_obj.get("valid_until")),
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
         })
     }
 
-    /// Parse generic threat feed (JSON format)
+    /// Parse generic threat feed (JSON array/object, NDJSON, CSV, or one indicator per line)
+    ///
+    /// A leading UTF-8 BOM and surrounding whitespace are ignored, and data
+    /// trailing a complete JSON array is skipped with a warning.
     fn parse_generic_threat_feed(&self, content: &str, source: &UpstreamSourceConfig, fetch_id: &str) -> Result<Vec<ThreatEvidence>> {
-        let mut threats = Vec::new();
+        let content = content.trim_start_matches('\u{feff}').trim();
 
-        // Attempt to parse as JSON array
-        match serde_json::from_str::<Vec<serde_json::Value>>(content) {
-            Ok(threat_objects) => {
-                for threat_obj in threat_objects {
-                    if let Some(threat_evidence) = self.convert_generic_to_threat_evidence(&threat_obj, source, fetch_id) {
-                        threats.push(threat_evidence);
+        if content.starts_with('[') || content.starts_with('{') {
+            let mut values = Vec::new();
+            let mut trailing_error = None;
+            for value in serde_json::Deserializer::from_str(content).into_iter::<serde_json::Value>() {
+                match value {
+                    Ok(value) => values.push(value),
+                    Err(e) => {
+                        trailing_error = Some(e);
+                        break;
                     }
                 }
             }
-            Err(_) => {
-                // If not an array, try as single object
-                if let Ok(threat_obj) = serde_json::from_str::<serde_json::Value>(content) {
+
+            // A complete JSON document, possibly followed by junk
+            if !values.is_empty() && (trailing_error.is_none() || content.starts_with('[')) {
+                if let Some(e) = trailing_error {
+                    log::warn!("Ignoring trailing data in feed from {}: {}", source.name, e);
+                }
+                return Ok(self.convert_json_values(values, source, fetch_id));
+            }
+        }
+
+        if let Some(threats) = self.parse_csv_feed(content, source, fetch_id) {
+            return Ok(threats);
+        }
+
+        // Otherwise treat it line by line: NDJSON objects or plain indicators
+        let mut threats = Vec::new();
+        for line in content.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            if trimmed.starts_with('{') {
+                if let Ok(threat_obj) = serde_json::from_str::<serde_json::Value>(trimmed) {
                     if let Some(threat_evidence) = self.convert_generic_to_threat_evidence(&threat_obj, source, fetch_id) {
                         threats.push(threat_evidence);
                     }
-                } else {
-                    // If not JSON, try parsing as newline-delimited indicators
-                    for line in content.lines() {
-                        let trimmed = line.trim();
-                        if !trimmed.is_empty() && !trimmed.starts_with('#') {
-                            if let Some(threat_evidence) = self.parse_line_as_indicator(trimmed, source, fetch_id) {
-                                threats.push(threat_evidence);
-                            }
-                        }
-                    }
+                    continue;
                 }
             }
+
+            if let Some(threat_evidence) = self.parse_line_as_indicator(trimmed, source, fetch_id) {
+                threats.push(threat_evidence);
+            }
         }
 
         Ok(threats)
     }
 
+    /// Parse a CSV feed whose header row names an IP column
+    ///
+    /// The `type` and `level` columns are optional. Returns None when the
+    /// content does not start with such a header. Quoted fields may not
+    /// contain commas.
+    fn parse_csv_feed(&self, content: &str, source: &UpstreamSourceConfig, fetch_id: &str) -> Option<Vec<ThreatEvidence>> {
+        let mut lines = content
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let header = lines.next()?;
+        if !header.contains(',') || header.starts_with('{') {
+            return None;
+        }
+        let columns: Vec<String> = split_csv_row(header).map(|column| column.to_lowercase()).collect();
+        let find_column = |names: &[&str]| columns.iter().position(|column| names.contains(&column.as_str()));
+
+        let ip_column = find_column(&["ip", "ip_address", "source_ip", "address", "cidr", "network"])?;
+        let fields = [
+            ("ip", Some(ip_column)),
+            ("type", find_column(&["type", "threat_type", "category"])),
+            ("level", find_column(&["level", "threat_level", "severity"])),
+            ("description", find_column(&["description", "comment"])),
+        ];
+
+        let threats = lines
+            .filter_map(|line| {
+                let row: Vec<&str> = split_csv_row(line).collect();
+                let mut threat_obj = serde_json::Map::new();
+                for (key, column) in fields {
+                    if let Some(value) = column.and_then(|column| row.get(column)).filter(|value| !value.is_empty()) {
+                        threat_obj.insert(key.to_string(), serde_json::Value::String(value.to_lowercase()));
+                    }
+                }
+                threat_obj.contains_key("ip").then_some(serde_json::Value::Object(threat_obj))
+            })
+            .filter_map(|threat_obj| self.convert_generic_to_threat_evidence(&threat_obj, source, fetch_id))
+            .collect();
+
+        Some(threats)
+    }
+
+    /// Convert parsed JSON values, flattening arrays, to ThreatEvidence
+    fn convert_json_values(&self, values: Vec<serde_json::Value>, source: &UpstreamSourceConfig, fetch_id: &str) -> Vec<ThreatEvidence> {
+        values
+            .into_iter()
+            .flat_map(|value| match value {
+                serde_json::Value::Array(items) => items,
+                other => vec![other],
+            })
+            .filter_map(|threat_obj| self.convert_generic_to_threat_evidence(&threat_obj, source, fetch_id))
+            .collect()
+    }
+
     /// Convert generic threat object to ThreatEvidence
     fn convert_generic_to_threat_evidence(&self, threat_obj: &serde_json::Value, source: &UpstreamSourceConfig, fetch_id: &str) -> Option<ThreatEvidence> {
         // Extract fields based on common threat feed formats
@@ -312,23 +959,8 @@ impl ThreatIntelAggregator {
         let threat_level_str = threat_obj.get("level").and_then(|v| v.as_str()).unwrap_or("warning");
         let description = threat_obj.get("description").and_then(|v| v.as_str()).unwrap_or("").to_string();
 
-        let threat_type = match threat_type_str {
-            "malware" => ThreatType::Malware,
-            "c2" => ThreatType::SuspiciousConnection,
-            "phishing" => ThreatType::SuspiciousConnection,
-            "scanner" => ThreatType::SuspiciousConnection,
-            "exploit" => ThreatType::Exploit,
-            "apt" => ThreatType::APT,
-            _ => ThreatType::IoCMatch,
-        };
-
-        let threat_level = match threat_level_str {
-            "info" | "low" => ThreatLevel::Info,
-            "warning" | "medium" => ThreatLevel::Warning,
-            "critical" | "high" => ThreatLevel::Critical,
-            "emergency" | "severe" => ThreatLevel::Emergency,
-            _ => ThreatLevel::Warning,
-        };
+        let threat_type = threat_type_str.parse().unwrap_or(ThreatType::IoCMatch);
+        let threat_level = threat_level_str.parse().unwrap_or(ThreatLevel::Warning);
 
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -337,7 +969,7 @@ impl ThreatIntelAggregator {
 
         let threat_id = format!("{}_{}_{}", source.name, threat_obj.get("id").and_then(|v| v.as_str()).unwrap_or("unknown"), timestamp);
 
-        Some(ThreatEvidence {
+        let mut evidence = ThreatEvidence {
             id: threat_id,
             timestamp,
             source_ip: source_ip.clone(),
@@ -354,40 +986,74 @@ impl ThreatIntelAggregator {
             reputation: 0.90, // High reputation for upstream sources
             compliance_tag: "upstream".to_string(),
             region: "global".to_string(),
-        })
+            expires_at: ["expiration", "expires_at", "valid_until"]
+                .iter()
+                .find_map(|key| parse_expiry(threat_obj.get(*key))),
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network: None,
+            signer_key: None,
+            signature: None,
+        };
+
+        // Entries naming a whole network block the range rather than one address
+        if let Some((network, prefix)) = parse_cidr(&source_ip) {
+            evidence.source_ip = network.to_string();
+            evidence.source_network = Some(format!("{}/{}", network, prefix));
+        }
+        Some(evidence)
     }
 
     /// Parse a single line as an indicator (common format for threat feeds)
+    ///
+    /// Accepts an IP or a CIDR range, optionally followed by a `;` or `#`
+    /// comment as in Spamhaus DROP lists.
     fn parse_line_as_indicator(&self, line: &str, source: &UpstreamSourceConfig, fetch_id: &str) -> Option<ThreatEvidence> {
-        // Check if it's an IP address
-        if is_valid_ip(line) {
-            let timestamp = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64;
-
-            return Some(ThreatEvidence {
-                id: format!("upstream-{}-{}-{}", source.name, line, timestamp),
-                timestamp,
-                source_ip: line.to_string(),
-                target_ip: "global".to_string(),
-                threat_type: ThreatType::IoCMatch,
-                threat_level: ThreatLevel::Warning,
-                context: format!("Upstream source: {} - Known malicious IP", source.name),
-                evidence_hash: crate::crypto::CryptoProvider::blake3_hash(
-                    format!("{}-{}", fetch_id, line).as_bytes()
-                ),
-                geolocation: "unknown".to_string(),
-                network_flow: line.to_string(),
-                agent_id: format!("upstream-{}", source.name),
-                reputation: 0.85,
-                compliance_tag: "upstream".to_string(),
-                region: "global".to_string(),
-            });
-        }
+        let indicator = line
+            .split(|c: char| c == ';' || c == '#' || c.is_whitespace())
+            .next()
+            .unwrap_or("");
+
+        let (source_ip, source_network, context) = if is_valid_ip(indicator) {
+            (indicator.to_string(), None, "Known malicious IP")
+        } else if let Some((network, prefix)) = parse_cidr(indicator) {
+            (network.to_string(), Some(format!("{}/{}", network, prefix)), "Known malicious network")
+        } else {
+            // Could add more parsing logic for other indicator types (URLs, hashes, etc.)
+            return None;
+        };
 
-        // Could add more parsing logic for other indicator types (URLs, hashes, etc.)
-        None
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        Some(ThreatEvidence {
+            id: format!("upstream-{}-{}-{}", source.name, indicator, timestamp),
+            timestamp,
+            source_ip,
+            target_ip: "global".to_string(),
+            threat_type: ThreatType::IoCMatch,
+            threat_level: ThreatLevel::Warning,
+            context: format!("Upstream source: {} - {}", source.name, context),
+            evidence_hash: crate::crypto::CryptoProvider::blake3_hash(
+                format!("{}-{}", fetch_id, indicator).as_bytes()
+            ),
+            geolocation: "unknown".to_string(),
+            network_flow: source_network.clone().unwrap_or_else(|| indicator.to_string()),
+            agent_id: format!("upstream-{}", source.name),
+            reputation: 0.85,
+            compliance_tag: "upstream".to_string(),
+            region: "global".to_string(),
+            expires_at: None,
+            source_hostname: None,
+            source_owner: None,
+            campaign_id: None,
+            source_network,
+            signer_key: None,
+            signature: None,
+        })
     }
 
     /// Start periodic fetching of threat intelligence
@@ -409,25 +1075,204 @@ impl ThreatIntelAggregator {
         }
     }
 
+    /// Upstream evidence first seen after the given time, oldest first
+    pub fn new_indicators_since(&self, last: i64) -> Vec<ThreatEvidence> {
+        self.seen.read().unwrap().first_seen_after(last)
+    }
+
+    /// Every upstream indicator currently remembered, oldest first
+    ///
+    /// Callers share one snapshot until the remembered indicators change.
+    /// After a change it is still reused for the snapshot cache TTL, so
+    /// indicators fetched since it was built show up once that expires.
+    /// Concurrent callers wait for a single rebuild.
+    pub fn known_threats(&self) -> Arc<Vec<ThreatEvidence>> {
+        let now = self.clock.now();
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let (version, threats) = {
+            let seen = self.seen.read().unwrap();
+            if let Some(cached) = snapshot.as_ref() {
+                if cached.version == seen.version || now - cached.taken_at < self.snapshot_ttl {
+                    return cached.threats.clone();
+                }
+            }
+            (seen.version, Arc::new(seen.first_seen_after(i64::MIN)))
+        };
+        *snapshot = Some(ThreatSnapshot { taken_at: now, version, threats: threats.clone() });
+        threats
+    }
+
+    /// Forget upstream indicators about `ip`, including the cached snapshot of known threats
+    pub fn forget_ip(&self, ip: IpAddr) -> usize {
+        let forgotten = self.seen.write().unwrap().forget_ip(ip);
+        *self.snapshot.lock().unwrap() = None;
+        forgotten
+    }
+
     /// Get the current configuration of upstream sources
     pub fn get_sources_config(&self) -> Vec<UpstreamSourceConfig> {
-        self.sources.clone()
+        self.sources.read().unwrap().clone()
+    }
+
+    /// Total indicators returned by successful source fetches, duplicates included
+    pub fn threats_fetched(&self) -> u64 {
+        self.threats_fetched.load(Ordering::Relaxed)
     }
 }
 
-/// Helper function to extract IP address from STIX pattern
-fn extract_ip_from_pattern(pattern: &str) -> Option<String> {
-    // Simple pattern: [ipv4-addr:value = '192.168.1.100']
-    // In a real implementation, this would use a proper STIX pattern parser
-    if let Some(start) = pattern.find(''') {
-        if let Some(end) = pattern[start + 1..].find(''') {
-            let ip = &pattern[start + 1..start + 1 + end];
-            if is_valid_ip(ip) {
-                return Some(ip.to_string());
+/// An observable value pulled out of a STIX pattern
+#[derive(Debug, Clone, PartialEq)]
+struct StixObservable {
+    indicator_type: IndicatorType,
+    kind: String, // Typed prefix such as `ipv4`, `domain`, `url` or `sha256`
+    value: String,
+}
+
+/// Extract the observables compared with `=` in a STIX 2.1 pattern
+///
+/// Handles several comparisons joined by `AND`/`OR`, inside one or more
+/// observation brackets. Comparisons on unsupported object paths, and other
+/// operators such as `MATCHES` or `!=`, are skipped.
+fn parse_stix_pattern(pattern: &str) -> Vec<StixObservable> {
+    let mut observables = Vec::new();
+    let mut chars = pattern.chars().peekable();
+
+    loop {
+        // Skip separators between comparisons
+        while matches!(chars.peek(), Some(c) if c.is_whitespace() || "[]()".contains(*c)) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        // Object path, which may contain a quoted segment like hashes.'SHA-256'
+        let mut path = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || "=!<>[]()".contains(c) {
+                break;
+            }
+            chars.next();
+            if c == '\'' {
+                path.extend(chars.by_ref().take_while(|&c| c != '\''));
+            } else {
+                path.push(c);
+            }
+        }
+        if path == "AND" || path == "OR" {
+            continue;
+        }
+
+        while matches!(chars.peek(), Some(c) if c.is_whitespace()) {
+            chars.next();
+        }
+        let mut operator = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == '\'' || "[]()".contains(c) {
+                break;
+            }
+            operator.push(c);
+            chars.next();
+        }
+
+        while matches!(chars.peek(), Some(c) if c.is_whitespace()) {
+            chars.next();
+        }
+        let value = if chars.peek() == Some(&'\'') {
+            chars.next();
+            let mut value = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '\'' => break,
+                    _ => value.push(c),
+                }
             }
+            Some(value)
+        } else {
+            None
+        };
+
+        if path.is_empty() && operator.is_empty() && value.is_none() {
+            chars.next(); // Unexpected character, skip it so parsing always advances
+            continue;
         }
+        if operator == "=" {
+            if let Some(observable) = value.and_then(|value| classify_observable(&path, value)) {
+                observables.push(observable);
+            }
+        }
+    }
+
+    observables
+}
+
+/// Map a STIX object path and value to a typed observable
+fn classify_observable(path: &str, value: String) -> Option<StixObservable> {
+    let (indicator_type, kind) = match path {
+        "ipv4-addr:value" if value.parse::<std::net::Ipv4Addr>().is_ok() => (IndicatorType::Ip, "ipv4".to_string()),
+        "ipv6-addr:value" if value.parse::<std::net::Ipv6Addr>().is_ok() => (IndicatorType::Ip, "ipv6".to_string()),
+        "domain-name:value" => (IndicatorType::Domain, "domain".to_string()),
+        "url:value" => (IndicatorType::Url, "url".to_string()),
+        _ => {
+            // file:hashes.'SHA-256' has its quotes stripped by the parser
+            let algorithm = path.strip_prefix("file:hashes.")?;
+            (IndicatorType::FileHash, algorithm.replace('-', "").to_lowercase())
+        }
+    };
+
+    Some(StixObservable { indicator_type, kind, value })
+}
+
+/// Turn an unsuccessful response into an error, marking ones worth backing off from
+fn check_response_status(source: &UpstreamSourceConfig, response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        // Only the delay-seconds form of Retry-After is honored
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok());
+        return Err(AgentError::UpstreamUnavailable {
+            name: source.name.clone(),
+            status: status.as_u16(),
+            retry_after,
+        });
+    }
+    Err(AgentError::UpstreamHttp { name: source.name.clone(), status: status.as_u16() })
+}
+
+/// Error for a failed upstream request, telling timeouts apart from other failures
+fn request_error(operation: String, e: reqwest::Error) -> AgentError {
+    if e.is_timeout() {
+        AgentError::Timeout { operation, secs: UPSTREAM_TIMEOUT }
+    } else {
+        AgentError::NetworkError(format!("{}: {}", operation, e))
+    }
+}
+
+/// Helper function to extract the first IP address from a STIX pattern
+fn extract_ip_from_pattern(pattern: &str) -> Option<String> {
+    parse_stix_pattern(pattern)
+        .into_iter()
+        .find(|observable| observable.indicator_type == IndicatorType::Ip)
+        .map(|observable| observable.value)
+}
+
+/// Parse an expiry given as an RFC 3339 string or Unix timestamp
+fn parse_expiry(value: Option<&serde_json::Value>) -> Option<i64> {
+    match value? {
+        serde_json::Value::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|dt| dt.timestamp()),
+        serde_json::Value::Number(n) => n.as_i64(),
+        _ => None,
     }
-    None
 }
 
 /// Helper function to validate IP address
@@ -435,10 +1280,64 @@ fn is_valid_ip(ip_str: &str) -> bool {
     ip_str.parse::<std::net::IpAddr>().is_ok()
 }
 
+/// Parse `network/prefix` notation, masking off any host bits
+fn parse_cidr(value: &str) -> Option<(IpAddr, u8)> {
+    let (network, prefix) = value.split_once('/')?;
+    let network: IpAddr = network.parse().ok()?;
+    let prefix: u8 = prefix.parse().ok()?;
+
+    let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+    if prefix > max_prefix {
+        return None;
+    }
+    Some((mask_ip(network, prefix, prefix), prefix))
+}
+
+/// Split a CSV row into trimmed fields with surrounding quotes removed
+fn split_csv_row(row: &str) -> impl Iterator<Item = &str> {
+    row.split(',').map(|field| field.trim().trim_matches('"').trim())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_sources_from_config() {
+        let upstream_sources = serde_json::from_value(serde_json::json!([
+            {
+                "name": "firehol",
+                "url": "https://iplists.firehol.org/files/firehol_level1.netset",
+                "update_interval": 3600,
+            },
+            {
+                "name": "vendor",
+                "url": "https://taxii.example.com/api1/",
+                "auth_token": "secret",
+                "enabled": false,
+                "threat_level_mapping": { "high": "Critical" },
+                "taxii_collection": "indicators",
+            },
+        ]))
+        .unwrap();
+        let config = AgentConfig { upstream_sources, ..AgentConfig::default() };
+
+        let sources = ThreatIntelAggregator::from_config(&config).get_sources_config();
+        let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+        assert_eq!(names, vec!["firehol", "vendor"]);
+        assert!(sources[0].enabled);
+        assert_eq!(sources[0].update_interval, 3600);
+        assert!(!sources[1].enabled);
+        assert_eq!(sources[1].auth_token.as_deref(), Some("secret"));
+        assert_eq!(sources[1].threat_level_mapping["high"], ThreatLevel::Critical);
+        assert_eq!(sources[1].taxii_collection.as_deref(), Some("indicators"));
+
+        // Without declared sources the built-in CISA AIS source is kept
+        let defaults = ThreatIntelAggregator::from_config(&AgentConfig::default()).get_sources_config();
+        assert_eq!(defaults.len(), 1);
+        assert_eq!(defaults[0].name, "CISA_AIS");
+    }
+
     #[test]
     fn test_is_valid_ip() {
         assert!(is_valid_ip("192.168.1.1"));
@@ -453,4 +1352,577 @@ mod tests {
         let result = extract_ip_from_pattern(pattern);
         assert_eq!(result, Some("192.168.1.100".to_string()));
     }
-}
\ No newline at end of file
+
+    fn convert_pattern(pattern: &str, labels: serde_json::Value) -> ThreatEvidence {
+        let aggregator = ThreatIntelAggregator::new();
+        let source = aggregator.get_sources_config()[0].clone();
+        let stix_obj = serde_json::json!({
+            "type": "indicator",
+            "id": "indicator--pattern",
+            "pattern": pattern,
+            "labels": labels,
+        });
+        aggregator.convert_stix_to_threat_evidence(&stix_obj, &source, "test").unwrap()
+    }
+
+    #[test]
+    fn test_stix_domain_pattern() {
+        let evidence = convert_pattern("[domain-name:value = 'evil.example.com']", serde_json::json!([]));
+        assert_eq!(evidence.network_flow, "domain:evil.example.com");
+        assert_eq!(evidence.threat_type, ThreatType::IoCMatch);
+        assert_eq!(evidence.source_ip, "unknown");
+    }
+
+    #[test]
+    fn test_stix_url_pattern() {
+        let evidence = convert_pattern(
+            "[url:value = 'http://evil.example.com/it\\'s/login'] OR [ipv4-addr:value = '198.51.100.9']",
+            serde_json::json!(["malicious-activity"]),
+        );
+        assert_eq!(evidence.network_flow, "url:http://evil.example.com/it's/login ipv4:198.51.100.9");
+        assert_eq!(evidence.source_ip, "198.51.100.9");
+        // An explicit label takes precedence over the observable kind
+        assert_eq!(evidence.threat_type, ThreatType::SuspiciousConnection);
+    }
+
+    #[test]
+    fn test_stix_sha256_pattern() {
+        let hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let pattern = format!("[file:hashes.'SHA-256' = '{}' AND file:size > 1024 AND file:name MATCHES '^a.*']", hash);
+        let evidence = convert_pattern(&pattern, serde_json::json!([]));
+        assert_eq!(evidence.network_flow, format!("sha256:{}", hash));
+        assert_eq!(evidence.threat_type, ThreatType::Malware);
+
+        // Unsupported observables leave the raw pattern in place
+        let evidence = convert_pattern("[process:name = 'evil.exe']", serde_json::json!([]));
+        assert_eq!(evidence.network_flow, "[process:name = 'evil.exe']");
+        assert_eq!(evidence.threat_type, ThreatType::IoCMatch);
+    }
+
+    #[tokio::test]
+    async fn test_set_source_enabled_at_runtime() {
+        let aggregator = ThreatIntelAggregator::new().with_simulated_feeds();
+        let periodic = aggregator.clone();
+
+        aggregator.set_source_enabled("CISA_AIS", true).unwrap();
+        let threats = periodic.fetch_all_sources().await.unwrap();
+        assert!(!threats.is_empty());
+
+        // Disabling through one handle stops the next cycle on the other
+        aggregator.set_source_enabled("CISA_AIS", false).unwrap();
+        let threats = periodic.fetch_all_sources().await.unwrap();
+        assert!(threats.is_empty());
+        assert!(!periodic.get_sources_config()[0].enabled);
+
+        assert!(aggregator.set_source_enabled("missing", true).is_err());
+    }
+
+    #[test]
+    fn test_expired_stix_indicator_is_not_retained() {
+        let aggregator = ThreatIntelAggregator::new();
+        let source = aggregator.get_sources_config()[0].clone();
+        let store = crate::threat_intel::IndicatorStore::new();
+
+        let expired = serde_json::json!({
+            "type": "indicator",
+            "id": "indicator--expired",
+            "pattern": "[ipv4-addr:value = '198.51.100.10']",
+            "labels": ["malicious-activity"],
+            "valid_until": "2020-01-01T00:00:00Z",
+            "confidence": 80
+        });
+        let evidence = aggregator.convert_stix_to_threat_evidence(&expired, &source, "test").unwrap();
+        assert_eq!(evidence.expires_at, Some(1_577_836_800));
+        assert_eq!(store.ingest(&[evidence]), 0);
+        assert!(!store.is_known_ip("198.51.100.10"));
+
+        let current = serde_json::json!({
+            "type": "indicator",
+            "id": "indicator--current",
+            "pattern": "[ipv4-addr:value = '198.51.100.11']",
+            "labels": ["malicious-activity"],
+            "valid_until": "2999-01-01T00:00:00Z",
+            "confidence": 80
+        });
+        let evidence = aggregator.convert_stix_to_threat_evidence(&current, &source, "test").unwrap();
+        assert_eq!(store.ingest(&[evidence]), 1);
+        assert!(store.is_known_ip("198.51.100.11"));
+    }
+
+    #[test]
+    fn test_generic_feed_expiration_is_parsed() {
+        let aggregator = ThreatIntelAggregator::new();
+        let source = aggregator.get_sources_config()[0].clone();
+
+        let threats = aggregator
+            .parse_generic_threat_feed(r#"[{"ip": "198.51.100.12", "type": "malware", "expiration": 1700000000}]"#, &source, "test")
+            .unwrap();
+        assert_eq!(threats[0].expires_at, Some(1_700_000_000));
+    }
+
+    #[test]
+    fn test_parse_feed_with_bom_and_trailing_data() {
+        let aggregator = ThreatIntelAggregator::new();
+        let source = aggregator.get_sources_config()[0].clone();
+
+        let feed = "\u{feff}[{\"ip\": \"198.51.100.20\", \"type\": \"malware\"}, {\"ip\": \"198.51.100.21\"}]  \n\n";
+        let threats = aggregator.parse_generic_threat_feed(feed, &source, "test").unwrap();
+        assert_eq!(threats.len(), 2);
+        assert_eq!(threats[0].source_ip, "198.51.100.20");
+
+        let feed = "[{\"ip\": \"198.51.100.22\"}]\n<!-- generated by feed server -->";
+        let threats = aggregator.parse_generic_threat_feed(feed, &source, "test").unwrap();
+        assert_eq!(threats.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_ndjson_feed() {
+        let aggregator = ThreatIntelAggregator::new();
+        let source = aggregator.get_sources_config()[0].clone();
+
+        let feed = "\u{feff}# exported indicators\n\
+                    {\"ip\": \"198.51.100.30\", \"type\": \"c2\"}\n\
+                    {\"ip\": \"198.51.100.31\", \"level\": \"high\"}\n\
+                    \n\
+                    {\"ip\": \"198.51.100.32\"}\n";
+        let threats = aggregator.parse_generic_threat_feed(feed, &source, "test").unwrap();
+        let ips: Vec<&str> = threats.iter().map(|t| t.source_ip.as_str()).collect();
+        assert_eq!(ips, vec!["198.51.100.30", "198.51.100.31", "198.51.100.32"]);
+        assert_eq!(threats[1].threat_level, ThreatLevel::Critical);
+    }
+
+    #[test]
+    fn test_parse_drop_style_cidr_list() {
+        let aggregator = ThreatIntelAggregator::new();
+        let source = aggregator.get_sources_config()[0].clone();
+
+        let feed = "; Spamhaus DROP List 2024/01/01 - (c) 2024 The Spamhaus Project\n\
+                    ; Last-Modified: Mon, 01 Jan 2024 00:00:00 GMT\n\
+                    185.220.100.0/22 ; SBL123456\n\
+                    203.0.113.77/24 ; SBL654321\n\
+                    2001:db8::/32\n\
+                    198.51.100.5\n\
+                    10.0.0.0/33 ; invalid prefix\n\
+                    not-a-network/8\n";
+        let threats = aggregator.parse_generic_threat_feed(feed, &source, "test").unwrap();
+        let networks: Vec<Option<&str>> = threats.iter().map(|t| t.source_network.as_deref()).collect();
+        assert_eq!(networks, vec![Some("185.220.100.0/22"), Some("203.0.113.0/24"), Some("2001:db8::/32"), None]);
+
+        // Host bits are masked off and the range is kept in the flow
+        assert_eq!(threats[1].source_ip, "203.0.113.0");
+        assert_eq!(threats[1].network_flow, "203.0.113.0/24");
+        assert_eq!(threats[3].source_ip, "198.51.100.5");
+    }
+
+    #[tokio::test]
+    async fn test_ingested_feed_drives_known_threat_ips() {
+        let aggregator = ThreatIntelAggregator::new();
+        let source = aggregator.get_sources_config()[0].clone();
+        let feed = "198.51.100.5\n185.220.100.0/22 ; SBL123456\n";
+        let threats = aggregator.parse_generic_threat_feed(feed, &source, "test").unwrap();
+
+        let store = std::sync::Arc::new(crate::threat_intel::IndicatorStore::new());
+        assert_eq!(store.ingest(&threats), 2);
+        let engine = crate::consensus_verification::ConsensusEngine::new(Default::default(), "agent".to_string())
+            .with_indicator_store(store);
+
+        assert!(engine.is_known_threat_ip("198.51.100.5").await);
+        assert!(engine.is_known_threat_ip("185.220.103.9").await);
+        assert!(!engine.is_known_threat_ip("192.0.2.44").await);
+        assert!(!engine.is_known_threat_ip("8.8.8.8").await);
+    }
+
+    #[test]
+    fn test_parse_three_column_csv_feed() {
+        let aggregator = ThreatIntelAggregator::new();
+        let source = aggregator.get_sources_config()[0].clone();
+
+        let feed = "# exported indicators\n\
+                    severity,IP_Address,category\n\
+                    high,198.51.100.40,malware\n\
+                    low,\"198.51.100.41\",scanner\n\
+                    medium,,c2\n\
+                    critical,192.0.2.0/24,apt\n";
+        let threats = aggregator.parse_generic_threat_feed(feed, &source, "test").unwrap();
+        let ips: Vec<&str> = threats.iter().map(|t| t.source_ip.as_str()).collect();
+        assert_eq!(ips, vec!["198.51.100.40", "198.51.100.41", "192.0.2.0"]);
+        assert_eq!(threats[0].threat_type, ThreatType::Malware);
+        assert_eq!(threats[0].threat_level, ThreatLevel::Critical);
+        assert_eq!(threats[1].threat_type, ThreatType::SuspiciousConnection);
+        assert_eq!(threats[1].threat_level, ThreatLevel::Info);
+        assert_eq!(threats[2].threat_type, ThreatType::APT);
+        assert_eq!(threats[2].source_network.as_deref(), Some("192.0.2.0/24"));
+    }
+
+    /// Serve HTTP responses with the given status, counting requests
+    async fn spawn_feed_server(status: Arc<std::sync::atomic::AtomicU16>, hits: Arc<std::sync::atomic::AtomicUsize>) -> String {
+        use std::sync::atomic::Ordering;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                hits.fetch_add(1, Ordering::SeqCst);
+
+                let body = "203.0.113.5\n";
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status.load(Ordering::SeqCst), body.len(), body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}/feed", addr)
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_skips_then_recovers() {
+        use std::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+
+        let status = Arc::new(AtomicU16::new(500));
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = spawn_feed_server(status.clone(), hits.clone()).await;
+
+        let clock = Arc::new(crate::clock::MockClock::new(1_700_000_000));
+        let mut aggregator = ThreatIntelAggregator::new()
+            .with_circuit_breaker(2, 60)
+            .with_clock(clock.clone());
+        aggregator.add_source(UpstreamSourceConfig {
+            name: "flaky".to_string(),
+            url,
+            auth_token: None,
+            enabled: true,
+            update_interval: 60,
+            threat_level_mapping: HashMap::new(),
+            taxii_collection: None,
+        });
+
+        // Consecutive failures open the breaker, once each backoff has elapsed
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert_eq!(report.sources[0].breaker_state, BreakerState::Closed);
+        clock.advance(30);
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert!(matches!(report.sources[0].outcome, FetchOutcome::Failed { .. }));
+        assert_eq!(report.sources[0].breaker_state, BreakerState::Open);
+        assert_eq!(report.sources[0].consecutive_failures, 2);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // While open the source is skipped without a request
+        status.store(200, Ordering::SeqCst);
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert_eq!(report.sources[0].outcome, FetchOutcome::Skipped);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // After the cooldown a half-open trial succeeds and closes it
+        clock.advance(60);
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert_eq!(report.sources[0].outcome, FetchOutcome::Fetched { threats: 1 });
+        assert_eq!(report.sources[0].breaker_state, BreakerState::Closed);
+        assert_eq!(report.sources[0].consecutive_failures, 0);
+        assert_eq!(report.threats.len(), 1);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_source_backs_off_then_recovers() {
+        use std::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+
+        let status = Arc::new(AtomicU16::new(429));
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = spawn_feed_server(status.clone(), hits.clone()).await;
+
+        let start = 1_700_000_000;
+        let clock = Arc::new(crate::clock::MockClock::new(start));
+        let mut aggregator = ThreatIntelAggregator::new()
+            .with_circuit_breaker(10, 600)
+            .with_backoff(30, 100)
+            .with_clock(clock.clone());
+        aggregator.add_source(UpstreamSourceConfig {
+            name: "throttled".to_string(),
+            url,
+            auth_token: None,
+            enabled: true,
+            update_interval: 60,
+            threat_level_mapping: HashMap::new(),
+            taxii_collection: None,
+        });
+
+        // A 429 backs the source off, doubling the delay on each repeat
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert!(matches!(report.sources[0].outcome, FetchOutcome::Failed { .. }));
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert_eq!(report.sources[0].outcome, FetchOutcome::BackingOff { until: start + 30 });
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        clock.advance(30);
+        aggregator.fetch_with_report().await.unwrap();
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert_eq!(report.sources[0].outcome, FetchOutcome::BackingOff { until: start + 90 });
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // Once the backoff elapses the source recovers
+        status.store(200, Ordering::SeqCst);
+        clock.advance(60);
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert_eq!(report.sources[0].outcome, FetchOutcome::Fetched { threats: 1 });
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // A healthy source is still not fetched more often than its update interval
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert_eq!(report.sources[0].outcome, FetchOutcome::NotDue);
+        clock.advance(60);
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert_eq!(report.sources[0].outcome, FetchOutcome::Fetched { threats: 1 });
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_indicators_are_not_repeated() {
+        use std::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = spawn_feed_server(Arc::new(AtomicU16::new(200)), hits.clone()).await;
+
+        let start = 1_700_000_000;
+        let clock = Arc::new(crate::clock::MockClock::new(start));
+        let mut aggregator = ThreatIntelAggregator::new().with_clock(clock.clone());
+        aggregator.add_source(UpstreamSourceConfig {
+            name: "static".to_string(),
+            url,
+            auth_token: None,
+            enabled: true,
+            update_interval: 60,
+            threat_level_mapping: HashMap::new(),
+            taxii_collection: None,
+        });
+
+        let first = aggregator.fetch_all_sources().await.unwrap();
+        assert_eq!(first.len(), 1);
+
+        // The second fetch sees the same data and yields nothing new
+        clock.advance(60);
+        let report = aggregator.fetch_with_report().await.unwrap();
+        assert_eq!(report.sources[0].outcome, FetchOutcome::Fetched { threats: 1 });
+        assert!(report.threats.is_empty());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // The cached indicator keeps its original identity
+        assert_eq!(aggregator.new_indicators_since(start - 1).len(), 1);
+        assert!(aggregator.new_indicators_since(start).is_empty());
+        assert_eq!(aggregator.known_threats()[0].id, first[0].id);
+    }
+
+    /// Serve a TAXII collection of three indicators over two polls' worth of pages
+    async fn spawn_taxii_server(requests: Arc<std::sync::Mutex<Vec<String>>>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        fn indicator(ip: &str) -> serde_json::Value {
+            serde_json::json!({
+                "type": "indicator",
+                "id": format!("indicator--{}", ip),
+                "pattern": format!("[ipv4-addr:value = '{}']", ip),
+                "labels": ["malicious-activity"],
+            })
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                let request_line = String::from_utf8_lossy(&buf[..n]).lines().next().unwrap_or("").to_string();
+                requests.lock().unwrap().push(request_line.clone());
+
+                let (objects, more, next, added_last) = if request_line.contains("next=page-2") {
+                    (vec![indicator("198.51.100.2")], false, None, "2024-01-01T00:00:02Z")
+                } else if request_line.contains("added_after=2024-01-01T00%3A00%3A02Z") {
+                    (vec![indicator("198.51.100.3")], false, None, "2024-01-01T00:00:03Z")
+                } else {
+                    (vec![indicator("198.51.100.1")], true, Some("page-2"), "2024-01-01T00:00:01Z")
+                };
+                let body = serde_json::json!({ "objects": objects, "more": more, "next": next }).to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/taxii+json;version=2.1\r\nX-TAXII-Date-Added-Last: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    added_last, body.len(), body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}/taxii2/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_taxii_poll_resumes_from_persisted_cursor() {
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let url = spawn_taxii_server(requests.clone()).await;
+        let dir = std::env::temp_dir().join(format!("orasrs-taxii-{}", uuid::Uuid::new_v4()));
+        let source = UpstreamSourceConfig {
+            name: "vendor".to_string(),
+            url,
+            auth_token: None,
+            enabled: true,
+            update_interval: 60,
+            threat_level_mapping: HashMap::new(),
+            taxii_collection: Some("collection-1".to_string()),
+        };
+
+        // The first poll walks every page of the collection
+        let mut first = ThreatIntelAggregator::new().with_storage(Arc::new(crate::storage::FsStorage::new(&dir)));
+        first.add_source(source.clone());
+        let threats = first.fetch_all_sources().await.unwrap();
+        let ips: Vec<&str> = threats.iter().map(|t| t.source_ip.as_str()).collect();
+        assert_eq!(ips, vec!["198.51.100.1", "198.51.100.2"]);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        // After a restart only objects added since the saved cursor are fetched
+        let mut second = ThreatIntelAggregator::new().with_storage(Arc::new(crate::storage::FsStorage::new(&dir)));
+        second.add_source(source);
+        let threats = second.fetch_all_sources().await.unwrap();
+        let ips: Vec<&str> = threats.iter().map(|t| t.source_ip.as_str()).collect();
+        assert_eq!(ips, vec!["198.51.100.3"]);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].contains("/taxii2/collections/collection-1/objects/"));
+        assert!(requests[2].contains("added_after=2024-01-01T00%3A00%3A02Z"));
+        assert!(!requests[2].contains("next="));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Serve a TAXII discovery document, collection list and paged objects, recording each request
+    async fn spawn_taxii_discovery_server(requests: Arc<std::sync::Mutex<Vec<String>>>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let request_line = request.lines().next().unwrap_or("").to_string();
+                requests.lock().unwrap().push(request.clone());
+
+                let body = if request_line.starts_with("GET /taxii2/ ") {
+                    serde_json::json!({ "title": "Test TAXII", "default": "/api1/", "api_roots": ["/api1/"] })
+                } else if request_line.starts_with("GET /api1/collections/ ") {
+                    serde_json::json!({ "collections": [
+                        { "id": "readable", "can_read": true },
+                        { "id": "write-only", "can_read": false },
+                    ] })
+                } else if request_line.contains("/api1/collections/readable/objects/?next=page-2") {
+                    serde_json::json!({ "more": false, "objects": [{
+                        "type": "indicator",
+                        "id": "indicator--2",
+                        "pattern": "[ipv4-addr:value = '198.51.100.72']",
+                        "labels": ["malware"],
+                    }] })
+                } else if request_line.contains("/api1/collections/readable/objects/") {
+                    serde_json::json!({ "more": true, "next": "page-2", "objects": [{
+                        "type": "indicator",
+                        "id": "indicator--1",
+                        "pattern": "[ipv4-addr:value = '198.51.100.71']",
+                        "labels": ["malicious-activity"],
+                    }] })
+                } else {
+                    serde_json::json!({ "title": "Not found" })
+                }
+                .to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    TAXII_MEDIA_TYPE, body.len(), body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}/taxii2/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_cisa_ais_discovers_and_polls_collections() {
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let url = spawn_taxii_discovery_server(requests.clone()).await;
+
+        let aggregator = ThreatIntelAggregator::new();
+        {
+            let mut sources = aggregator.sources.write().unwrap();
+            sources[0].url = url;
+            sources[0].auth_token = Some("ais-token".to_string());
+            sources[0].enabled = true;
+        }
+
+        let threats = aggregator.fetch_all_sources().await.unwrap();
+        let ips: Vec<&str> = threats.iter().map(|t| t.source_ip.as_str()).collect();
+        assert_eq!(ips, vec!["198.51.100.71", "198.51.100.72"]);
+        assert_eq!(threats[1].threat_type, ThreatType::Malware);
+
+        // Discovery, collection list, then both pages of the only readable collection
+        let requests = requests.lock().unwrap();
+        let request_lines: Vec<&str> = requests.iter().map(|r| r.lines().next().unwrap()).collect();
+        assert_eq!(request_lines.len(), 4);
+        assert!(request_lines[0].starts_with("GET /taxii2/ "));
+        assert!(request_lines[1].starts_with("GET /api1/collections/ "));
+        assert!(request_lines[2].starts_with("GET /api1/collections/readable/objects/ "));
+        assert!(request_lines[3].contains("next=page-2"));
+        for request in requests.iter() {
+            let request = request.to_lowercase();
+            assert!(request.contains("accept: application/taxii+json;version=2.1"));
+            assert!(request.contains("authorization: bearer ais-token"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_known_threats_snapshot_is_reused_within_ttl() {
+        let clock = Arc::new(crate::clock::MockClock::new(1_700_000_000));
+        let aggregator = ThreatIntelAggregator::new().with_simulated_feeds().with_clock(clock.clone()).with_snapshot_cache(30);
+        aggregator.set_source_enabled("CISA_AIS", true).unwrap();
+        aggregator.fetch_all_sources().await.unwrap();
+
+        // Readers within the TTL share one snapshot
+        let first = aggregator.known_threats();
+        clock.advance(10);
+        assert!(Arc::ptr_eq(&first, &aggregator.known_threats()));
+        assert_eq!(first.len(), 2);
+
+        // Erasing an IP drops the snapshot at once
+        assert_eq!(aggregator.forget_ip("192.168.1.100".parse().unwrap()), 1);
+        let erased = aggregator.known_threats();
+        assert_eq!(erased.len(), 1);
+
+        // Unchanged indicators keep the snapshot past the TTL
+        clock.advance(30);
+        assert!(Arc::ptr_eq(&erased, &aggregator.known_threats()));
+
+        // Without a TTL a change is seen by the next read, and only a change rebuilds
+        let uncached = ThreatIntelAggregator::new();
+        let empty = uncached.known_threats();
+        assert!(Arc::ptr_eq(&empty, &uncached.known_threats()));
+        let source = ThreatIntelAggregator::create_cisa_ais_config();
+        let evidence = ThreatEvidence::builder("203.0.113.9", ThreatType::IoCMatch, ThreatLevel::Warning).build();
+        uncached.seen.write().unwrap().observe(IndicatorCache::identity(&source, &evidence), evidence, 1);
+        assert_eq!(uncached.known_threats().len(), 1);
+    }
+
+    #[test]
+    fn test_reobserved_indicator_with_new_expiry_is_refreshed() {
+        let source = ThreatIntelAggregator::create_cisa_ais_config();
+        let mut cache = IndicatorCache::default();
+        let evidence = ThreatEvidence::builder("203.0.113.9", ThreatType::IoCMatch, ThreatLevel::Warning)
+            .expires_at(1_700_000_100)
+            .build();
+        let key = IndicatorCache::identity(&source, &evidence);
+        assert_eq!(cache.observe(key.clone(), evidence.clone(), 1_700_000_000), Observation::New);
+        assert_eq!(cache.observe(key.clone(), evidence.clone(), 1_700_000_050), Observation::Unchanged);
+
+        let extended = ThreatEvidence { expires_at: Some(1_700_000_400), ..evidence.clone() };
+        assert_eq!(cache.observe(key, extended, 1_700_000_080), Observation::Refreshed);
+        let cached = cache.first_seen_after(i64::MIN);
+        assert_eq!(cached.len(), 1);
+        assert_eq!((cached[0].id.as_str(), cached[0].expires_at), (evidence.id.as_str(), Some(1_700_000_400)));
+    }
+}
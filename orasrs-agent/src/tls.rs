@@ -0,0 +1,209 @@
+//! TLS ClientHello parsing and JA3 fingerprints
+
+const CONTENT_TYPE_HANDSHAKE: u8 = 22;
+const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+const EXTENSION_SERVER_NAME: u16 = 0;
+const EXTENSION_SUPPORTED_GROUPS: u16 = 10;
+const EXTENSION_EC_POINT_FORMATS: u16 = 11;
+
+/// Fields of a ClientHello used for fingerprinting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientHello {
+    pub version: u16,
+    pub cipher_suites: Vec<u16>,
+    pub extensions: Vec<u16>, // Extension types in the order sent
+    pub elliptic_curves: Vec<u16>,
+    pub ec_point_formats: Vec<u8>,
+    pub sni: Option<String>,
+}
+
+impl ClientHello {
+    /// JA3 string: version, ciphers, extensions, curves and point formats, without GREASE values
+    pub fn ja3_string(&self) -> String {
+        let join = |values: &mut dyn Iterator<Item = u16>| {
+            values.filter(|&value| !is_grease(value)).map(|value| value.to_string()).collect::<Vec<_>>().join("-")
+        };
+        format!(
+            "{},{},{},{},{}",
+            self.version,
+            join(&mut self.cipher_suites.iter().copied()),
+            join(&mut self.extensions.iter().copied()),
+            join(&mut self.elliptic_curves.iter().copied()),
+            join(&mut self.ec_point_formats.iter().map(|&format| u16::from(format))),
+        )
+    }
+
+    /// JA3 fingerprint, the MD5 of the JA3 string in hex
+    pub fn ja3(&self) -> String {
+        format!("{:x}", md5::compute(self.ja3_string()))
+    }
+}
+
+/// GREASE values (RFC 8701) are random per connection and excluded from JA3
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+/// Parse a ClientHello from the start of a TCP payload
+///
+/// The handshake message must fit in the first TLS record; hellos split
+/// across records or segments are not parsed.
+pub fn parse_client_hello(payload: &[u8]) -> Option<ClientHello> {
+    let mut record = Reader::new(payload);
+    if record.u8()? != CONTENT_TYPE_HANDSHAKE {
+        return None;
+    }
+    record.skip(2)?; // Record version
+    let mut handshake = Reader::new(record.vec16()?);
+    if handshake.u8()? != HANDSHAKE_CLIENT_HELLO {
+        return None;
+    }
+    let length = handshake.u24()?;
+    let mut hello = Reader::new(handshake.take(length)?);
+
+    let version = hello.u16()?;
+    hello.skip(32)?; // Random
+    hello.vec8()?; // Session ID
+    let cipher_suites = Reader::new(hello.vec16()?).u16s()?;
+    hello.vec8()?; // Compression methods
+
+    let mut client_hello = ClientHello {
+        version,
+        cipher_suites,
+        extensions: Vec::new(),
+        elliptic_curves: Vec::new(),
+        ec_point_formats: Vec::new(),
+        sni: None,
+    };
+    if hello.is_empty() {
+        return Some(client_hello); // No extensions
+    }
+
+    let mut extensions = Reader::new(hello.vec16()?);
+    while !extensions.is_empty() {
+        let extension_type = extensions.u16()?;
+        let mut data = Reader::new(extensions.vec16()?);
+        client_hello.extensions.push(extension_type);
+        match extension_type {
+            EXTENSION_SERVER_NAME => client_hello.sni = server_name(&mut data),
+            EXTENSION_SUPPORTED_GROUPS => client_hello.elliptic_curves = Reader::new(data.vec16()?).u16s()?,
+            EXTENSION_EC_POINT_FORMATS => client_hello.ec_point_formats = data.vec8()?.to_vec(),
+            _ => {}
+        }
+    }
+    Some(client_hello)
+}
+
+/// First host name of a server_name extension
+fn server_name(data: &mut Reader) -> Option<String> {
+    let mut names = Reader::new(data.vec16()?);
+    while !names.is_empty() {
+        let name_type = names.u8()?;
+        let name = names.vec16()?;
+        if name_type == 0 {
+            return String::from_utf8(name.to_vec()).ok().map(|name| name.to_lowercase());
+        }
+    }
+    None
+}
+
+/// Bounds-checked big-endian reader
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(taken)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        let bytes = self.take(3)?;
+        Some(usize::from(bytes[0]) << 16 | usize::from(bytes[1]) << 8 | usize::from(bytes[2]))
+    }
+
+    /// Bytes prefixed by a one-byte length
+    fn vec8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()?;
+        self.take(usize::from(len))
+    }
+
+    /// Bytes prefixed by a two-byte length
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()?;
+        self.take(usize::from(len))
+    }
+
+    /// The rest as a list of u16 values
+    fn u16s(&mut self) -> Option<Vec<u16>> {
+        let mut values = Vec::with_capacity(self.data.len() / 2);
+        while !self.is_empty() {
+            values.push(self.u16()?);
+        }
+        Some(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_CLIENT_HELLO: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/client_hello.bin"));
+
+    #[test]
+    fn test_client_hello_ja3() {
+        let hello = parse_client_hello(TEST_CLIENT_HELLO).unwrap();
+        assert_eq!(hello.sni.as_deref(), Some("c2.evil.example"));
+        assert_eq!(
+            hello.ja3_string(),
+            "771,4866-4867-4865-49196-49200-49195-49199-52393-52392-49188-49192-49187-49191-159-158-107-103-255,\
+             0-11-10-35-22-23-13-43-45-51-21,29-23-30-25-24-256-257-258-259-260,0-1-2"
+        );
+        assert_eq!(hello.ja3(), "93c7d42c0df602fb91589311534831f5");
+    }
+
+    #[test]
+    fn test_grease_values_are_ignored() {
+        let hello = ClientHello {
+            version: 771,
+            cipher_suites: vec![0x0a0a, 4865],
+            extensions: vec![0x1a1a, 0, 0xfafa],
+            elliptic_curves: vec![0x2a2a, 29],
+            ec_point_formats: vec![0],
+            sni: None,
+        };
+        assert_eq!(hello.ja3_string(), "771,4865,0,29,0");
+    }
+
+    #[test]
+    fn test_other_payloads_are_not_client_hellos() {
+        assert!(parse_client_hello(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n").is_none());
+        assert!(parse_client_hello(&TEST_CLIENT_HELLO[..100]).is_none());
+        assert!(parse_client_hello(&[]).is_none());
+    }
+}
@@ -136,7 +136,8 @@ mod protocol_tests {
 
     #[tokio::test]
     async fn test_consensus_correlation() {
-        let config = ConsensusConfig::default();
+        // No peers answer, so requests expire at once and the correlation is decided
+        let config = ConsensusConfig { verification_timeout: 0, ..ConsensusConfig::default() };
         let engine = ConsensusEngine::new(config, "test-agent".to_string());
         
         let local_evidence = create_test_evidence_with_ip("192.168.1.100".to_string());
@@ -148,11 +149,11 @@ mod protocol_tests {
         // Process correlation
         let correlations = engine.process_evidence_correlation(&[local_evidence], &upstream_evidence).await.unwrap();
         
-        // Should have at least one correlation (the matching IP)
-        assert!(!correlations.is_empty());
+        // Only the matching IP correlates
+        assert_eq!(correlations.len(), 1);
         
         // The first correlation should have the combined evidence
-        let (combined_evidence, consensus_result) = &correlations[0];
+        let (_, combined_evidence, consensus_result) = &correlations[0];
         assert!(combined_evidence.context.contains("Combined with upstream"));
         assert!(consensus_result.confidence_score >= 0.0);
     }
@@ -173,48 +174,28 @@ mod protocol_tests {
     }
 
     fn create_test_evidence() -> ThreatEvidence {
-        ThreatEvidence {
-            id: format!("test-evidence-{}", 
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()),
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64,
-            source_ip: "192.168.1.100".to_string(),
-            target_ip: "10.0.0.1".to_string(),
-            threat_type: ThreatType::Malware,
-            threat_level: ThreatLevel::Critical,
-            context: "Test threat evidence for protocol testing".to_string(),
-            evidence_hash: orasrs_agent::crypto::CryptoProvider::blake3_hash(b"test-data"),
-            geolocation: "unknown".to_string(),
-            network_flow: "TCP".to_string(),
-            agent_id: "test-agent".to_string(),
-            reputation: 0.8,
-            compliance_tag: "global".to_string(),
-            region: "test-region".to_string(),
-        }
+        let evidence = ThreatEvidence::builder("192.168.1.100", ThreatType::Malware, ThreatLevel::Critical)
+            .id(&format!("test-evidence-{}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()))
+            .target_ip("10.0.0.1")
+            .context("Test threat evidence for protocol testing")
+            .network_flow("TCP")
+            .agent_id("test-agent")
+            .reputation(0.8)
+            .hash_of(b"test-data")
+            .build();
+        ThreatEvidence { region: "test-region".to_string(), ..evidence }
     }
 
     fn create_test_evidence_with_ip(ip: String) -> ThreatEvidence {
-        ThreatEvidence {
-            id: format!("test-evidence-{}", 
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()),
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64,
-            source_ip: ip,
-            target_ip: "10.0.0.1".to_string(),
-            threat_type: ThreatType::Malware,
-            threat_level: ThreatLevel::Critical,
-            context: "Test threat evidence for correlation".to_string(),
-            evidence_hash: orasrs_agent::crypto::CryptoProvider::blake3_hash(b"test-data"),
-            geolocation: "unknown".to_string(),
-            network_flow: "TCP".to_string(),
-            agent_id: "test-agent".to_string(),
-            reputation: 0.8,
-            compliance_tag: "global".to_string(),
-            region: "test-region".to_string(),
-        }
+        let evidence = ThreatEvidence::builder(&ip, ThreatType::Malware, ThreatLevel::Critical)
+            .id(&format!("test-evidence-{}", SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()))
+            .target_ip("10.0.0.1")
+            .context("Test threat evidence for correlation")
+            .network_flow("TCP")
+            .agent_id("test-agent")
+            .reputation(0.8)
+            .hash_of(b"test-data")
+            .build();
+        ThreatEvidence { region: "test-region".to_string(), ..evidence }
     }
 }
\ No newline at end of file